@@ -1,4 +1,4 @@
-from typing import List, Optional
+from typing import Any, Callable, Dict, Iterator, List, Optional, Union
 
 
 class UserProvidedConfig:
@@ -13,9 +13,28 @@ class UserProvidedConfig:
     :param disk_size: the disk size of the service
     :param cpu: the CPU upper bound of the service
     :param memory: the memory upper bound of the service
-    :param accelerators: the GPU upper bound of the service
+    :param accelerators: the GPU upper bound of the service; on the kubernetes cloud, slices of a
+        MIG partitioned GPU such as a100-1g.10gb or a100-1g.10gb:2
     :param setup: the setup command of the service
     :param run: the run command of the service
+    :param namespace: the Kubernetes namespace of the pods
+    :param labels: the labels of the pods on Kubernetes
+    :param node_selector: the node labels the pods must land on, e.g. {"gpu": "a100"}
+    :param tolerations: the tolerations of the pods, each with key, operator, value and effect
+    :param service_account: the Kubernetes service account of the pods
+    :param init_containers: Kubernetes containers run before the service, e.g. to download the
+        model, each with a name, an image and optionally a shell command
+    :param sidecars: Kubernetes containers run alongside the service, e.g. a metrics exporter,
+        declared like init_containers (native sidecars, Kubernetes 1.29+)
+    :param min_replicas: the fewest replicas the Kubernetes orchestrator autoscales the service
+        to, replicas by default
+    :param max_replicas: the most replicas the Kubernetes orchestrator autoscales the service to,
+        with a HorizontalPodAutoscaler
+    :param target_utilization: the average CPU utilization of the pods the autoscaler aims at, in
+        percent of their request, 80 by default
+    :param ttl_hours: the hours after its launch that monitor_ttl takes the service down
+    :param base: the name of the base configuration this one extends and overrides, see
+        Dispatcher.define_base
     """
 
     def __init__(self,
@@ -29,61 +48,426 @@ class UserProvidedConfig:
                  memory: Optional[str] = None,
                  accelerators: Optional[str] = None,
                  setup: Optional[str] = None,
-                 run: Optional[str] = None) -> None: ...
+                 run: Optional[str] = None,
+                 namespace: Optional[str] = None,
+                 labels: Optional[Dict[str, str]] = None,
+                 node_selector: Optional[Dict[str, str]] = None,
+                 tolerations: Optional[List[Dict[str, str]]] = None,
+                 service_account: Optional[str] = None,
+                 init_containers: Optional[List[Dict[str, str]]] = None,
+                 sidecars: Optional[List[Dict[str, str]]] = None,
+                 min_replicas: Optional[int] = None,
+                 max_replicas: Optional[int] = None,
+                 target_utilization: Optional[int] = None,
+                 ttl_hours: Optional[int] = None,
+                 base: Optional[str] = None) -> None: ...
+
+    def __repr__(self) -> str: ...
+
+    def __eq__(self, other: object) -> bool: ...
+
+    def __hash__(self) -> int: ...
+
+
+class Orchestrators:
+    """
+    Orchestrators are the backends a service can be launched with
+    """
+
+    Skypilot: Orchestrators
+    #: a local container, to try a configuration out before launching it with SkyPilot
+    Docker: Orchestrators
+    #: a Deployment behind a LoadBalancer Service, in the cluster of the current kubectl context
+    Kubernetes: Orchestrators
+
+    @staticmethod
+    def from_str(name: str) -> Orchestrators:
+        """
+        Parse an orchestrator name, e.g. "skypilot", "docker" or "kubernetes"
+        """
+
+    @staticmethod
+    def available() -> List[Orchestrators]:
+        """
+        List the orchestrators whose prerequisites are installed
+        """
+
+    def __hash__(self) -> int: ...
+
+
+class CompletionStream(Iterator[str]):
+    """
+    CompletionStream iterates over the events of a streamed completion, each item is the JSON
+    payload of one event. The stream lasts as long as the service sends events, failing only
+    when the service sends nothing for 300 seconds
+    """
+
+    def __repr__(self) -> str: ...
+
+    def __iter__(self) -> CompletionStream: ...
+
+    def __next__(self) -> str: ...
+
+
+class ServiceStatus:
+    """
+    Summary of the state of a service, rendered as a table in Jupyter and IPython
+    """
+    name: str
+    #: down, provisioning, starting (setup running), up, maintenance, unhealthy (ready since
+    #: its launch, failing its probes now) or paused
+    state: str
+    url: Optional[str]
+    replicas: int
+    #: replicas serving requests, 0 unless the service is up
+    ready_replicas: int
+    #: seconds since the service was launched, None when it is down
+    uptime: Optional[int]
+    #: estimated spend in dollars
+    cost: float
+    hourly_cost: Optional[float]
+    #: error of the last operation on the service, None if it succeeded
+    last_error: Optional[str]
+
+    def __repr__(self) -> str: ...
+    def _repr_html_(self) -> str: ...
+
+
+class OperationResult:
+    """
+    What add_service, up or down did, always truthy
+    """
+    #: add_service, up or down
+    operation: str
+    service: str
+    #: the configuration rendered for the orchestrator
+    filepath: Optional[str]
+    endpoint: Optional[str]
+    #: the seconds the operation took
+    duration: float
+    orchestrator: str
+    #: the name of the service in the orchestrator, once it knows about it
+    orchestrator_id: Optional[str]
+    #: the replica groups handled along with the service
+    groups: List[str]
+    #: the id of the up or down, None for add_service
+    operation_id: Optional[str]
+
+    def __repr__(self) -> str: ...
+    def __bool__(self) -> bool: ...
+
+
+class Deadline:
+    """
+    Deadline bounds the Dispatcher calls made within its with block, on the thread entering it,
+    as returned by Dispatcher.deadline. It does not reach the work of other threads, neither
+    the calls of other Python threads nor the gateway, the monitors and the other background
+    tasks of the dispatcher
+    """
+
+    def __enter__(self) -> Deadline: ...
+
+    def __exit__(self, exc_type: Any, exc_value: Any, traceback: Any) -> bool: ...
+
+    def remaining(self) -> float:
+        """
+        :return: the seconds left before the deadline, 0 once it passed
+        """
 
 
 class Dispatcher:
     """
     Dispatcher is a class that represents the service dispatcher, which is
     responsible for housing all the Servicing functionality
+
+    Errors are raised as RuntimeError with a `code` attribute, a stable machine-readable result
+    code such as PROVISION_CAPACITY, PROVISION_QUOTA, CREDENTIALS_EXPIRED, SERVICE_NOT_FOUND or
+    PROBE_FAILED (progress events only), for UIs to map to their own messages and runbooks
     """
 
-    def __init__(self, 
-                 skip_sky_validation: Optional[bool] = False) -> None: ...
+    def __init__(self,
+                 skip_sky_validation: Optional[bool] = False,
+                 worker_threads: Optional[int] = None,
+                 dry_run: Optional[bool] = False,
+                 plugins: Optional[bool] = True,
+                 thread_name: Optional[str] = None,
+                 max_blocking_threads: Optional[int] = None) -> None:
+        """
+        :param skip_sky_validation: whether to skip checking that SkyPilot is installed
+        :param worker_threads: the number of runtime worker threads, by default one per 8
+            managed services, at least one and at most one per core; the runtime and HTTP client
+            are shared by the live dispatchers so this and the other runtime options only apply
+            when none exists yet
+        :param dry_run: whether up and down only record the commands and manifests they would
+            run, see last_plan; also turned on by the SERVICING_DRY_RUN environment variable
+        :param plugins: whether to load the hooks of the installed plugins, see add_hook
+        :param thread_name: the name of the runtime threads, servicing by default
+        :param max_blocking_threads: the most threads running the commands, Python callbacks
+            and file I/O off the workers, 512 by default
+        """
+
+    def __repr__(self) -> str:
+        """
+        Summary of the dispatcher: the number of services, of services up and of aliases
+        """
+
+    def __str__(self) -> str:
+        """
+        The services of the dispatcher with their state, one per line
+        """
+
+    def _repr_html_(self) -> str:
+        """
+        The services of the dispatcher as a table of their state, URL, replicas, uptime and cost
+        """
 
     def add_service(self, name: str,
-                    config: Optional[UserProvidedConfig] = None) -> None:
+                    config: Optional[UserProvidedConfig] = None,
+                    orchestrator: Optional[Union[Orchestrators, str]] = None,
+                    auto_setup: Optional[bool] = None,
+                    cuda_setup: Optional[bool] = None) -> OperationResult:
         """
         Add a new service to the dispatcher
 
         :param name: the name of the service
         :param config: the configuration of the service
+        :param orchestrator: the backend of the service or its name, SkyPilot by default; Docker
+            builds an image from the setup and runs the service in a local container publishing
+            its port; Kubernetes applies a Deployment running the setup then the run command, with
+            the accelerators as node selector and limits, behind a LoadBalancer Service
+        :param auto_setup: whether to infer the setup and run commands and the accelerators not
+            set in config from the workdir (conda environment file, pyproject.toml,
+            requirements.txt, CUDA dependencies, entrypoint script)
+        :param cuda_setup: set to False to keep the setup as is, by default a setup not set in
+            config installs the CUDA toolkit only if the service has accelerators
+        :return: the rendered configuration file of the service
+        """
+
+    def register_external(self, name: str, url: str,
+                          readiness_probe: Optional[str] = None) -> None:
+        """
+        Register a service not managed by any orchestrator, e.g. a vendor API, so it can be
+        listed, health-checked, aliased and invoked without provisioning
+
+        :param name: the name of the service
+        :param url: the URL of the service, host:port or with a scheme such as https://
+        :param readiness_probe: the path checked by status, /health by default
+        """
+
+    def add_service_from_sky_yaml(self, name: str, path: str) -> OperationResult:
         """
+        Add a new service to the dispatcher from an existing SkyPilot serve YAML
 
-    def remove_service(self, name: str) -> None:
+        :param name: the name of the service
+        :param path: the path of the SkyPilot YAML, fields servicing does not manage are kept as is
+            and the fields it leaves out are not rendered until they are changed
+        """
+
+    def add_replica_group(self, name: str, group: str,
+                          config: Optional[UserProvidedConfig] = None) -> None:
         """
-        Remove a service from the dispatcher
+        Add a group of replicas with resources of their own to a service, e.g. CPU replicas for
+        preprocessing next to GPU ones; the group is managed along with the service
 
         :param name: the name of the service
+        :param group: the name of the group, launched as the service name-group
+        :param config: the changes to the configuration of the service for the group
         """
 
-    def up(self, name: str, skip_prompt: Optional[bool] = None) -> None:
+    def remove_service(self, name: str, purge: Optional[bool] = None,
+                       force: Optional[bool] = None) -> None:
         """
-        Start a service
+        Remove a service and its replica groups from the dispatcher, refused while the service
+        still has live resources or when its orchestrator cannot tell whether it has any
+
+        :param name: the name of the service
+        :param purge: whether to tear down the resources of the service first
+        :param force: whether to remove a service created by another user
+        """
+
+    def up(self, name: str, skip_prompt: Optional[bool] = None,
+           allow_drift: Optional[bool] = None, retries: Optional[int] = None,
+           fallback: Optional[List[str]] = None,
+           progress: Optional[Callable[[Dict[str, Any]], None]] = None,
+           confirm: Optional[Callable[[Dict[str, Any]], bool]] = None,
+           dry_run: Optional[bool] = None, env: Optional[str] = None,
+           override_policies: Optional[bool] = None) -> OperationResult:
+        """
+        Start a service, followed by its replica groups. The GPU quota of the target cloud is
+        checked first: a zero quota in the region the service is pinned to fails the start
+        unless fallback is provided
 
         :param name: the name of the service to start
+        :param allow_drift: set to False to refuse starting a service whose configuration file was
+            modified on disk, by default the modification is only logged
+        :param retries: the number of retries on quota or capacity failures, defaults to one per
+            fallback entry
+        :param fallback: the placements to retry with, a cloud ("gcp"), a region ("eu-west-1")
+            or both ("gcp:us-central1")
+        :param progress: called with a dict (service, phase, event, elapsed, message, code,
+            next_check) as the provision, endpoint and ready phases start, retry, wait, finish or
+            fail, code being the stable result code of a retry or failure; the
+            SkyPilot output is not echoed when set, e.g. to drive a rich progress display
+        :param confirm: called with the plan of the optimizer as a dict (service, cloud, instance,
+            region, accelerators, replicas, hourly_price, hourly_cost) before provisioning, the
+            service is only started if it returns True; SkyPilot is not prompted when set, e.g.
+            to show a confirmation dialog
+        :param env: the environment to start the service in, e.g. "staging": its overlay, see
+            set_overlay, is applied to its configuration and the environment is recorded in the
+            metadata of the service and as the servicing-environment label of its resources
+        :param override_policies: set to True to launch despite the violations of the policies,
+            see add_policy, which are then only logged
+        :param dry_run: whether to only record the commands and manifests of the first attempt,
+            see last_plan; defaults to the dry_run of the dispatcher
+        :return: the endpoint of the service, how long starting it took and its replica groups
+        """
+
+    def protect(self, name: str, on: Optional[bool] = None,
+                approvers: Optional[List[str]] = None, token: Optional[str] = None) -> None:
+        """
+        Require the ups of a service to be approved: up fails with code APPROVAL_REQUIRED, except
+        for dry runs, as does restore with reprovision, and request_up records a request that
+        approve or reject decides on. Only the owner of the service can protect it, and protecting
+        or lifting the protection is recorded in the approval trail along with the requests
+
+        :param name: the name of the service
+        :param on: set to False to lift the protection, dropping the pending request
+        :param approvers: the users allowed to approve, any user but the requester by default
+        :param token: a token whose holders may approve as well, the requester included
+        """
+
+    def request_up(self, name: str, env: Optional[str] = None,
+                   reason: Optional[str] = None) -> None:
+        """
+        Ask for a protected service to be started, recorded in its approval trail
+
+        :param name: the name of the service
+        :param env: the environment to start the service in, see up
+        :param reason: why the service is to be started
+        """
+
+    def approve(self, name: str, token: Optional[str] = None,
+                progress: Optional[Callable[[Dict[str, Any]], None]] = None) -> OperationResult:
+        """
+        Approve the up requested for a protected service and start it as requested; fails with
+        code APPROVAL_REFUSED for the requester or a user who is not an approver
+
+        :param name: the name of the service
+        :param token: the token of the service, to approve without being an approver
+        :param progress: called with the progress of the up, see up
+        :return: the endpoint of the service, how long starting it took and its replica groups
+        """
+
+    def reject(self, name: str, token: Optional[str] = None,
+               reason: Optional[str] = None) -> None:
         """
+        Turn down the up requested for a protected service, the same users as for approve can
 
-    def down(self, name: str, skip_prompt: Optional[bool] = None, force: Optional[bool] = None) -> None:
+        :param name: the name of the service
+        :param token: the token of the service, to reject without being an approver
+        :param reason: why the up is turned down
+        """
+
+    def pending_ups(self) -> List[Dict[str, Optional[str]]]:
+        """
+        :return: the ups waiting for approval as dicts (service, requester, requested,
+            environment, reason), oldest first
         """
-        Stop a service
+
+    def down(self, name: str, skip_prompt: Optional[bool] = None, force: Optional[bool] = None,
+             purge: Optional[bool] = None, wait: Optional[bool] = None,
+             timeout: Optional[int] = None,
+             dry_run: Optional[bool] = None) -> OperationResult:
+        """
+        Stop a service along with its replica groups
 
         :param name: the name of the service to stop
-        :param force: whether to force stop the service
+        :param force: whether to force stop the service, even if not up or created by another user
+        :param purge: whether to also purge the SkyPilot record of the service and, once it is
+            gone, drop the state cached for it and the configurations rendered for its
+            environments and scale to zero; implies wait
+        :param wait: whether to wait until SkyPilot no longer reports the service, so that it can
+            be started again under the same name right away
+        :param timeout: the seconds to wait for at most, 600 by default
+        :param dry_run: whether to only record the commands, see last_plan; defaults to the
+            dry_run of the dispatcher
+        :return: how long stopping the service took and its replica groups
+        """
+
+    def set_replicas(self, name: str, replicas: int) -> None:
+        """
+        Change the number of replicas of a service; the configuration is rendered again and a
+        running service is reported by status as needing an update
+
+        :param name: the name of the service
+        :param replicas: the number of replicas
+        """
+
+    def set_accelerators(self, name: str, accelerators: Optional[str] = None) -> None:
+        """
+        Change the accelerators of a service, like set_replicas
+
+        :param name: the name of the service
+        :param accelerators: the accelerators, e.g. "A100:1", None removes them
+        """
+
+    def set_env(self, name: str, key: str, value: Optional[str] = None) -> None:
+        """
+        Set an environment variable of a service, like set_replicas
+
+        :param name: the name of the service
+        :param key: the name of the variable
+        :param value: the value of the variable, None removes it
+        """
+
+    def last_plan(self, pretty: Optional[bool] = None) -> Optional[str]:
+        """
+        What the last dry-run up or down would have run
+
+        :param pretty: whether to pretty print the JSON
+        :return: the operation and its steps as JSON, each with the service, the command line,
+            the manifest fed to it and the content of the files it refers to; None if there was no
+            dry run yet
+        """
+
+    def last_output(self, name: str) -> str:
+        """
+        Output of the last commands run for the service by up and down
+
+        :param name: name of the service
+        :return: the last 64 KB of output, each command preceded by its command line; empty if
+            nothing ran for the service yet in this process
         """
 
-    def status(self, name: str, pretty: Optional[bool] = None) -> str:
+    def status_obj(self, name: str) -> ServiceStatus:
+        """
+        Poll the service like status and summarise it
+
+        :param name: the name of the service
+        :return: the state, URL, replicas, uptime and cost of the service, rendered as a table
+            in notebooks
+        """
+
+    def status(self, name: str, pretty: Optional[bool] = None, tz: Optional[str] = None) -> str:
         """
         Get the status of a service
 
         :param name: the name of the service
         :param pretty: whether to return the status in a pretty format
-        :return: the status of the service in string format
+        :param tz: the IANA timezone to render timestamps in, e.g. "Europe/Zurich", UTC by
+            default; timestamps are RFC 3339
+        :return: the status of the service in string format, with its state (provisioning,
+            starting, up, unhealthy...) and the status of its replica groups under groups; a
+            service is only up when all of its groups are; the row SkyPilot reports for it is
+            under sky while the status cache is running; a Kubernetes service has its replicas
+            and ready pods under kubernetes, with the current and desired replicas of its
+            autoscaler if it has one
         """
 
     def save(self, location: Optional[str] = None) -> None:
         """
-        Save the dispatcher's cache
+        Save the dispatcher's cache. Saves of other processes sharing the cache are merged into the
+        dispatcher as they happen, and before saving, rather than overwritten
 
         :param location: the location of the cache, defaults to home directory
         """
@@ -95,23 +479,870 @@ class Dispatcher:
         :return: the base64 string of the cache
         """
 
-    def load(self, location: Optional[str] = None, update_status: Optional[str] = None) -> None:
+    def load(self, location: Optional[str] = None, update_status: Optional[bool] = None) -> None:
         """
         Load the dispatcher's cache
 
         :param location: the location of the cache, defaults to home directory
         """
 
-    def load_as_b64(self, b64: str) -> None:
+    def load_from_b64(self, b64: str) -> None:
         """
         Load the dispatcher's cache from a base64 string
 
         :param b64: the base64 string of the cache
         """
 
-    def list(self) -> List[str]:
+    def export_endpoints(self, format: Optional[str] = None,
+                         prefix: Optional[str] = None) -> str:
+        """
+        Export the URLs of the services that are up
+
+        :param format: env for SVC_MY_SERVICE_URL=... lines (default), json for a map of
+            service name to URL
+        :param prefix: the prefix of the env variables, SVC_ by default
+        :return: the endpoints in the requested format
+        """
+
+    def openapi(self, name: str, path: Optional[str] = None, refresh: Optional[bool] = None) -> str:
+        """
+        Fetch and cache the OpenAPI document of a running service
+
+        :param name: the name of the service
+        :param path: the path of the OpenAPI document, defaults to /openapi.json
+        :param refresh: whether to fetch the document again even if it is cached
+        :return: the OpenAPI document in JSON format
+        """
+
+    def routes(self, name: str) -> List[str]:
+        """
+        List the routes of the cached OpenAPI document
+
+        :param name: the name of the service
+        :return: a list of routes in the form "METHOD /path"
+        """
+
+    def models(self, name: str) -> List[str]:
+        """
+        List the models (schemas) of the cached OpenAPI document
+
+        :param name: the name of the service
+        :return: a list of model names
+        """
+
+    def chat(self, name: str, messages: List[Dict[str, Any]], stream: Optional[bool] = None,
+             **params: Any) -> Union[str, CompletionStream]:
+        """
+        Send a chat completion request to an OpenAI-compatible service
+
+        :param name: the name of the service
+        :param messages: the chat messages, e.g. [{"role": "user", "content": "Hi"}]
+        :param stream: whether to stream the response
+        :param params: extra request parameters such as model, temperature or max_tokens
+        :return: the response in JSON format, or an iterator over the streamed events
+        """
+
+    def completions(self, name: str, prompt: Union[str, List[str]], stream: Optional[bool] = None,
+                    **params: Any) -> Union[str, CompletionStream]:
+        """
+        Send a completion request to an OpenAI-compatible service
+
+        :param name: the name of the service
+        :param prompt: the prompt to complete
+        :param stream: whether to stream the response
+        :param params: extra request parameters such as model, temperature or max_tokens
+        :return: the response in JSON format, or an iterator over the streamed events
+        """
+
+    def invoke(self, name: str, path: str, payload: Optional[Any] = None,
+               method: Optional[str] = None) -> str:
+        """
+        Send a request to a service, or to one of its members if name is an alias
+
+        :param name: the name of the service or alias
+        :param path: the path of the request, e.g. /predict
+        :param payload: a JSON serializable payload sent as the request body
+        :param method: the HTTP method, defaults to POST with a payload and GET without
+        :return: the body of the response
+        """
+
+    def create_alias(self, alias: str, services: List[str], policy: Optional[str] = None) -> None:
+        """
+        Put several services behind one name, traffic is spread across the members that are up
+
+        :param alias: the name of the alias
+        :param services: the names of the services behind the alias
+        :param policy: either "round_robin" (default) or "least_latency"
+        """
+
+    def remove_alias(self, alias: str) -> None:
+        """
+        Remove an alias, the services behind it are left untouched
+
+        :param alias: the name of the alias
+        """
+
+    def list_aliases(self) -> Dict[str, List[str]]:
+        """
+        List all the aliases
+
+        :return: a mapping of alias names to their services
+        """
+
+    def list(self, owner: Optional[str] = None, offset: Optional[int] = None,
+             limit: Optional[int] = None) -> List[str]:
+        """
+        List all the services, in alphabetical order
+
+        :param owner: only list the services created by this user
+        :param offset: the number of services to skip, to page through large registries
+        :param limit: the most services to return, all of them by default
+        :return: a list of the names of the services
+        """
+
+    def status_all(self, offset: Optional[int] = None, limit: Optional[int] = None,
+                   pretty: Optional[bool] = None) -> str:
+        """
+        Get the summaries of the services in alphabetical order, as polled last rather than
+        probing them; the registry is not held while they are serialized
+
+        :param offset: the number of services to skip, to page through large registries
+        :param limit: the most services to return, all of them by default
+        :param pretty: whether to indent the JSON
+        :return: a JSON list of {name, state, url, replicas, ready_replicas, uptime, cost,
+            hourly_cost, last_error}
+        """
+
+    def get_url(self, name: str) -> str:
+        """
+        Get the URL of a service, or of one of its members if name is an alias
+
+        :param name: the name of the service or alias
+        :return: the URL of the service, its ingress host and path once the ingress has an
+            address
+        """
+
+    def start_gateway(self, port: Optional[int] = None) -> int:
+        """
+        Start a local reverse proxy routing /svc/<name>/... to the service or alias called name,
+        answering 503 while the service is not up, unless it is scaled to zero: the request then
+        wakes it up and waits for it. /services.json serves the state, url, uptime,
+        replica counts and last error of every service, for a Grafana JSON datasource or a
+        dashboard to consume
+
+        :param port: the local port to listen on, defaults to 8000, 0 picks a free port
+        :return: the port the gateway listens on
+        """
+
+    def set_gateway_route(self, name: str, cors_origins: Optional[List[str]] = None,
+                          cors_methods: Optional[List[str]] = None,
+                          cors_headers: Optional[List[str]] = None,
+                          headers: Optional[Dict[str, str]] = None) -> None:
+        """
+        Set the options of the gateway route of a service or alias, no options reset the route
+
+        :param name: the name of the service or alias
+        :param cors_origins: the origins allowed to call the route from a browser, "*" for any;
+            preflight requests are answered by the gateway
+        :param cors_methods: the methods allowed, the common ones by default
+        :param cors_headers: the request headers allowed
+        :param headers: headers added to every request forwarded to the service
+        """
+
+    def stop_gateway(self) -> None:
+        """
+        Stop the local reverse proxy
+        """
+
+    def set_rate_limit(self, name: str, rate: Optional[float] = None,
+                       burst: Optional[int] = None) -> None:
+        """
+        Limit the requests sent to a service through invoke, chat, completions and the gateway,
+        requests over the limit wait for their turn
+
+        :param name: the name of the service
+        :param rate: the number of requests per second, at least 0.001; None removes the limit
+        :param burst: the number of requests that can be sent at once, at least and by default 1
+        """
+
+    def set_circuit_breaker(self, name: str, failures: Optional[int] = None,
+                            cooldown: Optional[int] = None) -> None:
+        """
+        Stop sending requests to a service after consecutive failures, a single probe request is
+        let through every cooldown until one succeeds
+
+        :param name: the name of the service
+        :param failures: the number of consecutive failures opening the circuit, None removes it
+        :param cooldown: the seconds to wait before probing the service again, defaults to 30
+        """
+
+    def benchmark(self, name: str, path: str, payload: Optional[Any] = None,
+                  concurrency: Optional[int] = None, duration: Optional[float] = None,
+                  method: Optional[str] = None, pretty: Optional[bool] = None) -> str:
+        """
+        Drive load against a running service and report throughput and latencies
+
+        :param name: the name of the service or alias
+        :param path: the path of the request, e.g. /predict
+        :param payload: the JSON payload, sent with POST unless method is given
+        :param concurrency: the number of concurrent workers, 8 by default
+        :param duration: how long to send requests for in seconds, 30 by default
+        :param method: the HTTP method
+        :param pretty: pretty-print the JSON report
+        :return: a JSON report with requests, errors, seconds, throughput, mean, p50, p90, p99
+            and max latencies in milliseconds, and the count of each status code
+        """
+
+    def add_middleware(self, before: Optional[Callable[[Dict[str, Any]], Optional[Dict[str, str]]]] = None,
+                       after: Optional[Callable[[Dict[str, Any], Dict[str, Any]], None]] = None) -> None:
+        """
+        Register hooks run around every request sent to a service through invoke, chat,
+        completions and the gateway
+
+        :param before: called with a request dict (service, method, url, headers, body_size), may
+            change its headers in place or return new ones, raising aborts the request
+        :param after: called with the request dict and a response dict (status, elapsed, error)
+        """
+
+    def clear_middleware(self) -> None:
+        """
+        Remove all the registered middleware hooks
+        """
+
+    def add_hook(self, before: Optional[Callable[[Dict[str, Any]], None]] = None,
+                 after: Optional[Callable[[Dict[str, Any], Optional[str]], None]] = None,
+                 operations: Optional[List[str]] = None) -> None:
+        """
+        Register hooks run around the operations on services. Installed packages can provide
+        hooks too: a `servicing.hooks` entry point naming an object, e.g. a module, with before
+        and after functions
+
+        :param before: called with a context dict (service, operation, orchestrator, owner, url,
+            config), raising aborts the operation
+        :param after: called with the context dict once the operation is done and its error,
+            None if it succeeded
+        :param operations: the operations hooked among add, up, down and remove, all by default
+        """
+
+    def clear_hooks(self, plugins: Optional[bool] = None) -> None:
+        """
+        Remove the hooks registered with add_hook
+
+        :param plugins: whether to remove the hooks of the plugins as well
+        """
+
+    def add_policy(self, check: Optional[Callable[[Dict[str, Any]], Any]] = None,
+                   bundle: Optional[str] = None, query: Optional[str] = None,
+                   name: Optional[str] = None) -> None:
+        """
+        Register a policy evaluated against the rendered configuration of every service before
+        it is launched, e.g. "no public clouds for datasets labelled confidential" or "at most 4
+        GPUs per service". Violations make up fail with code POLICY_VIOLATION unless
+        override_policies is set
+
+        :param check: called with a dict (service, orchestrator, environment, owner, config),
+            config being the rendered configuration; returns the violations as strings, None if
+            there are none; it cannot call back into the dispatcher while up evaluates it
+        :param bundle: the path of an OPA bundle, a directory or a .tar.gz, evaluated with the opa
+            CLI against the same dict as input
+        :param query: the query of the bundle returning its violations, as messages or objects with
+            a msg field, defaults to data.servicing.deny
+        :param name: the name of the policy in the violations, that of check or the path of bundle
+            by default
+        """
+
+    def clear_policies(self) -> None:
+        """
+        Remove the policies registered with add_policy
+        """
+
+    def policies(self) -> List[str]:
+        """
+        :return: the names of the policies, in the order they are evaluated
+        """
+
+    def check_policies(self, name: str, env: Optional[str] = None) -> List[Dict[str, str]]:
+        """
+        Evaluate the policies against a service without launching it
+
+        :param name: the name of the service
+        :param env: the environment the service would be launched in
+        :return: the violations, as dicts (policy, message)
+        """
+
+    def add_notification(self, url: str, kind: Optional[str] = None,
+                         template: Optional[str] = None,
+                         operations: Optional[List[str]] = None,
+                         name: Optional[str] = None) -> None:
+        """
+        Post the outcome of the operations on a service, or on every service, to a Slack or
+        Microsoft Teams incoming webhook
+
+        :param url: the url of the incoming webhook
+        :param kind: slack or teams, guessed from the url by default
+        :param template: the message, with {service}, {operation}, {state}, {url}, {cost} and
+            {error} placeholders
+        :param operations: the operations notified among add, up, down and remove, all by default
+        :param name: the name of the service, every service if None; the sinks of every service
+            are kept for the other dispatchers of the user
+        """
+
+    def clear_notifications(self, name: Optional[str] = None) -> None:
+        """
+        Remove the notification sinks of a service, or those of every service
+
+        :param name: the name of the service, every service if None
+        """
+
+    def plugins(self) -> List[str]:
+        """
+        The names of the servicing.hooks entry points the hooks were loaded from
+        """
+
+    def doctor(self, orchestrator: Optional[Union[Orchestrators, str]] = None,
+               pretty: Optional[bool] = None) -> str:
+        """
+        Check the prerequisites of an orchestrator, or of all of them: binaries, Python packages,
+        credentials and connectivity
+
+        :param orchestrator: the orchestrator to check, all of them by default
+        :param pretty: whether to pretty print the JSON
+        :return: a JSON list of checks (orchestrator, check, ok, detail, hint)
+        """
+
+    def describe(self, pretty: Optional[bool] = None) -> str:
+        """
+        Describe the API of the installed version, to generate completions and help text from
+
+        :param pretty: whether to pretty print the JSON
+        :return: a JSON object with the version, the methods (name, doc and parameters with their
+            defaults), the values of the choice parameters under enums and the orchestrators with
+            their package, whether it is installed and the clouds they can place services in
+        """
+
+    def validate(self, name: str, pretty: Optional[bool] = None) -> str:
+        """
+        Lint the setup and run scripts of the service before anything is provisioned
+
+        :param name: the name of the service
+        :param pretty: whether to pretty print the JSON
+        :return: the findings as a JSON list, each with the script (setup or run), the line and a
+            message: unquoted variables, package managers left to prompt for confirmation, files
+            missing from the workdir and accelerators the service does not seem to use
+        """
+
+    def set_default_template(self, orchestrator: Union[Orchestrators, str],
+                             yaml: Optional[str] = None) -> None:
+        """
+        Replace the configuration new services of the orchestrator start from, for every
+        dispatcher of the user, e.g. to bake in an organization's setup script
+
+        :param orchestrator: the orchestrator whose default is replaced
+        :param yaml: a YAML document overriding any part of the built-in default configuration;
+            None restores the built-in default
+        """
+
+    def define_base(self, name: str, config: Optional[UserProvidedConfig] = None) -> None:
+        """
+        Save a base configuration, e.g. `define_base("gpu-inference", UserProvidedConfig(...))`,
+        for every dispatcher of the user, for configurations to extend with
+        `UserProvidedConfig(base="gpu-inference", ...)`. When the service is added, the default
+        template comes first, then the bases from the furthest to the nearest and last the
+        configuration itself, each overriding the fields the previous ones set; labels and node
+        selectors are merged instead. Services already added keep their configuration
+
+        :param name: the name of the base, letters, digits, -, _ and .
+        :param config: the base configuration, which may extend another base; None removes it
+        """
+
+    def bases(self) -> List[str]:
+        """
+        :return: the names of the base configurations, sorted
+        """
+
+    def resolved_config(self, name: str, env: Optional[str] = None) -> UserProvidedConfig:
+        """
+        :param name: the name of the service
+        :param env: the environment whose overlay is applied on top, if any
+        :return: the configuration the service was rendered from, its bases applied
+        """
+
+    def set_overlay(self, name: str, env: str,
+                    config: Optional[UserProvidedConfig] = None) -> None:
+        """
+        Override parts of the configuration of a service in an environment, e.g. fewer replicas
+        and CPUs in "dev", applied when the service is started with `up(name, env="dev")`;
+        labels and node selectors are merged, the other fields set replace those of the service
+
+        :param name: the name of the service
+        :param env: the name of the environment, letters, digits, -, _ and .
+        :param config: the overrides, which may extend a base configuration; None removes them
+        """
+
+    def quote(self, config: Optional[UserProvidedConfig] = None,
+              pretty: Optional[bool] = None) -> str:
+        """
+        Compare the clouds and instance types able to run a configuration before launching it
+
+        :param config: the configuration to price, the default one if not provided; the cloud
+            is left to the optimizer unless the configuration sets one
+        :param pretty: pretty-print the JSON list
+        :return: a JSON list of cloud, instance, vcpus, memory, accelerators, region,
+            hourly_price and chosen, cheapest first
+        """
+
+    def set_ingress(self, name: str, host: Optional[str] = None, path: Optional[str] = None,
+                    tls_secret: Optional[str] = None, ingress_class: Optional[str] = None,
+                    namespace: Optional[str] = None) -> None:
+        """
+        Expose a service on Kubernetes through an ingress, created by up and removed by down
+
+        :param name: the name of the service
+        :param host: the external hostname, None removes the ingress
+        :param path: the path prefix routed to the service, / by default
+        :param tls_secret: the secret holding the TLS certificate of host
+        :param ingress_class: the ingress class, the cluster default if not provided
+        :param namespace: the namespace of the ingress, the one of the pods by default
+        """
+
+    def set_maintenance(self, name: str, on: Optional[bool] = None,
+                        reason: Optional[str] = None) -> None:
+        """
+        Mark a service as intentionally degraded: monitors do not alert, status shows the
+        maintenance, aliases skip the service and the gateway answers 503 with the reason
+
+        :param name: the name of the service
+        :param on: False to end the maintenance, True by default
+        :param reason: why the service is under maintenance
+        """
+
+    def pause(self, name: str) -> None:
+        """
+        Suspend a Docker or Kubernetes service without tearing it down: its container is frozen
+        with docker pause, keeping the state of its processes, or its Deployment scaled to zero.
+        It is not polled and shows as paused until resume, down still tears it down. SkyPilot
+        services cannot be paused, set_scale_to_zero lets them sleep instead
+
+        :param name: the name of the service
+        """
+
+    def resume(self, name: str) -> None:
+        """
+        Bring back a service suspended by pause, its Deployment scaled back to the replicas of
+        its configuration on Kubernetes, the minimum of its autoscaler if it has one. status
+        tells when it is ready again
+
+        :param name: the name of the service
+        """
+
+    def set_budget(self, name: str, max_hourly_cost: Optional[float] = None,
+                   max_total_cost: Optional[float] = None,
+                   auto_down: Optional[bool] = None) -> None:
+        """
+        Cap the cost of a service in dollars, no limits remove the budget
+
+        :param name: the name of the service
+        :param max_hourly_cost: up refuses configurations estimated above this hourly cost
+        :param max_total_cost: up refuses to launch once the estimated spend reached it
+        :param auto_down: take the service down when monitor_budget sees max_total_cost crossed
+        """
+
+    def spend(self, name: str) -> float:
+        """
+        Get the estimated spend of a service in dollars, across the launches its cost could be
+        estimated for
+
+        :param name: the name of the service
+        :return: the spend of the previous launches plus the running one
+        """
+
+    def cost_report(self, since: Optional[str] = None, until: Optional[str] = None,
+                    group_by: Optional[str] = None, label: Optional[str] = None,
+                    tz: Optional[str] = None, format: Optional[str] = None,
+                    pretty: Optional[bool] = None) -> str:
+        """
+        Sum the estimated spend of every service over a time window, for chargeback
+
+        :param since: RFC 3339 time or YYYY-MM-DD date the window starts at, the first day of
+            the current month by default
+        :param until: RFC 3339 time or YYYY-MM-DD date the window ends at, now by default
+        :param group_by: service (default), owner or label
+        :param label: the resource label whose values the services are grouped by with label
+        :param tz: the IANA timezone of the dates and of the times in the report, UTC by default
+        :param format: json (default) or csv
+        :param pretty: whether to indent the JSON
+        :return: a JSON {since, until, group_by, lines, total}, or the CSV of the lines, each
+            line with the group, services, hours and cost
+        """
+
+    def export(self, directory: str, format: Optional[str] = None,
+               tables: Optional[List[str]] = None) -> List[str]:
+        """
+        Dump tables of the services into files named after them, e.g. for pandas: services, the
+        status of every service; events, the ups, launches, teardowns, preemptions, maintenance
+        and approval trails of the services in time order; probes, the last readiness probe and
+        latency of every service
+
+        :param directory: the directory the files are written to, created if needed
+        :param format: csv (default) or parquet
+        :param tables: the tables to export, all of them by default
+        :return: the files written, empty tables being skipped
+        """
+
+    def set_workdir_packaging(self, name: str, include: Optional[List[str]] = None,
+                              exclude: Optional[List[str]] = None,
+                              gitignore: Optional[bool] = None,
+                              max_size_mb: Optional[int] = None) -> None:
+        """
+        Control what up ships from the workdir of a service; the selected files are staged in a
+        copy that is reused while they are unchanged. No arguments ship the workdir as is again
+
+        :param name: the name of the service
+        :param include: globs of the files to ship, all files by default
+        :param exclude: globs of the files not to ship, e.g. "data/"
+        :param gitignore: whether to skip the files ignored by .gitignore, True by default
+        :param max_size_mb: refuse to ship a workdir larger than this
+        """
+
+    def set_image_build(self, name: str, registry: Optional[str] = None,
+                        dockerfile: Optional[str] = None, context: Optional[str] = None,
+                        tag: Optional[str] = None) -> None:
+        """
+        Build and push a container image before every up, the service then runs from it
+
+        :param name: the name of the service
+        :param registry: where to push the image, e.g. "ghcr.io/acme"; None removes the build step
+        :param dockerfile: the Dockerfile, Dockerfile in the context by default
+        :param context: the build context, the current directory by default
+        :param tag: the image tag, the time of the build by default
+        """
+
+    def set_registry_credentials(self, registry: str, username: Optional[str] = None,
+                                 password: Optional[str] = None,
+                                 token_helper: Optional[str] = None) -> None:
+        """
+        Set the credentials of a container registry, used to push built images and by the
+        orchestrator to pull them; they are only kept in memory and never written to configurations
+
+        :param registry: the registry server, e.g. "ghcr.io"
+        :param username: the username, None removes the credentials
+        :param password: the password or token
+        :param token_helper: a shell command printing a token at every up instead of a password,
+            e.g. "aws ecr get-login-password --region us-east-1"
+        """
+
+    def set_stable_endpoint(self, name: str, endpoint: Optional[str] = None) -> None:
+        """
+        Use an endpoint that survives service updates, such as an elastic IP or an existing load
+        balancer, for requests and get_url instead of the one SkyPilot reports
+
+        :param name: the name of the service
+        :param endpoint: host[:port] of the stable endpoint, None goes back to the SkyPilot one
+        """
+
+    def set_api_key(self, name: str, enabled: Optional[bool] = None) -> None:
+        """
+        Issue a new API key to the service at every up, handed to it in the SERVICING_API_KEY
+        environment variable; the key is attached as a bearer token to the requests sent through
+        invoke, chat, completions and the gateway
+
+        :param name: the name of the service
+        :param enabled: whether to issue keys, True by default
+        """
+
+    def api_key(self, name: str) -> Optional[str]:
+        """
+        Get the API key issued to the service by the last up
+
+        :param name: the name of the service
+        :return: the key, None if the service has none
+        """
+
+    def set_subprocess_limit(self, limit: int) -> None:
+        """
+        Set how many orchestrator subprocesses (sky, docker, ...) may run at once across the
+        process, further ones wait for a slot in order
+
+        :param limit: the number of concurrent subprocesses, 8 by default
+        """
+
+    def set_interrupt_policy(self, policy: str) -> None:
+        """
+        Set what up and down do with the orchestrator subprocess they wait for on SIGINT (Ctrl-C,
+        or interrupting the kernel), across the process. Unless waiting, the call raises with code
+        INTERRUPTED and its operation is left for recover to finish or roll back
+
+        :param policy: "kill" to terminate the subprocess and its process group (the default),
+            "detach" to leave it running, or "wait" to let the call run to completion
+        """
+
+    def deadline(self, timeout: float) -> Deadline:
+        """
+        Bound the calls made within a with block, e.g. `with dispatcher.deadline(30): ...`:
+        the orchestrator subprocesses still running when the deadline passes are killed and the
+        HTTP requests dropped, the call raising with code DEADLINE_EXCEEDED. The deadline is
+        held by the thread entering the block: calls made from other threads, the requests the
+        gateway forwards and the probes of the monitors are not bounded by it
+
+        :param timeout: the seconds the calls of the block may take; a nested block cannot
+            extend the deadline of the enclosing one
+        :return: the context manager
+        """
+
+    def tasks(self, pretty: Optional[bool] = None) -> str:
+        """
+        List the running and queued orchestrator subprocesses
+
+        :param pretty: whether to pretty print the JSON
+        :return: a JSON list of tasks (id, command, state, seconds)
+        """
+
+    def timings(self, name: str, pretty: Optional[bool] = None) -> str:
+        """
+        Get how long each phase of the recent up invocations took, oldest first
+
+        :param name: the name of the service
+        :param pretty: whether to return the timings in a pretty format
+        :return: the timings in JSON format: render, policies, checks (budget and quota),
+            confirm, package, image and endpoint are the time spent in each phase, provision and
+            ready are counted from the start of up
+        """
+
+    def watch_configs(self, enable: bool = True,
+                      callback: Optional[Callable[[str, bool], None]] = None) -> None:
+        """
+        Follow the configuration files of the services on disk, for workflows editing the YAML
+        directly. An edited configuration is picked up by its service, which then needs an update
+        if it is running; an invalid one is reported as drift by up
+
+        :param enable: whether to watch the files, False stops watching
+        :param callback: called with the service name and whether the edit was picked up
+        """
+
+    def monitor_preemptions(self, name: str, interval: Optional[int] = None,
+                            callback: Optional[Callable[[str, int], None]] = None) -> None:
+        """
+        Poll SkyPilot while the service is up and record the spot replicas reported as preempted
+
+        :param name: the name of the service
+        :param interval: the seconds between two polls, defaults to 60
+        :param callback: called with the service name and replica id of every new preemption
+        """
+
+    def monitor_budget(self, name: str, interval: Optional[int] = None,
+                       callback: Optional[Callable[[str, float], None]] = None) -> None:
+        """
+        Watch the estimated spend of a running service against its total budget
+
+        :param name: the name of the service, which must have a budget
+        :param interval: seconds between checks, 60 by default
+        :param callback: called with the service name and the estimated spend when the budget
+            is crossed; the service is then taken down if the budget has auto_down
+        """
+
+    def monitor_ttl(self, name: str, interval: Optional[int] = None,
+                    callback: Optional[Callable[[str, str, int], None]] = None) -> None:
+        """
+        Take a running service down once it is older than the ttl_hours of its configuration,
+        warning an hour before
+
+        :param name: the name of the service, which must have a TTL
+        :param interval: seconds between checks, 60 by default
+        :param callback: called with the service name, the event, warning or expired, and the
+            seconds left
+        """
+
+    def operations(self, pretty: Optional[bool] = None) -> str:
+        """
+        List the up and down operations recorded for the services. Every up and down gets an id
+        and is journaled before it runs, along with the phase it enters; an operation interrupted
+        with its process is reconciled with what SkyPilot reports on the next load, and kept for
+        recover, or on the next operation on the service, while starting another one while it is
+        in flight fails
+
+        :param pretty: whether to indent the JSON
+        :return: a JSON list of {id, service, operation, started, pid, host, phase, in_flight}
+        """
+
+    def attach(self,
+               name: str,
+               progress: Optional[Callable[[Dict[str, Any]], None]] = None) -> str:
+        """
+        Pick up a service whose up was interrupted while the orchestrator kept provisioning it:
+        its URL is derived again from the orchestrator and its readiness monitored as up would,
+        instead of taking it down and up again. The interrupted operation is cleared
+
+        :param name: the name of the service
+        :param progress: called with the endpoint and ready phases, like the progress of up
+        :return: the URL of the service; raises with code SERVICE_NOT_UP if the orchestrator does
+            not run it
+        """
+
+    def recover(
+        self,
+        name: Optional[str] = None,
+        rollback: Optional[bool] = None,
+        pretty: Optional[bool] = None,
+    ) -> str:
+        """
+        Finish or roll back the up and down operations interrupted with their process, according
+        to what SkyPilot reports. An interrupted down is run again if the service still runs; an
+        interrupted up that got the service running is finished, the status checks taking over,
+        and one that did not is rolled back
+
+        :param name: the service to recover the operation of, all of them if None
+        :param rollback: whether to take down the services an interrupted up got running
+        :param pretty: whether to indent the JSON
+        :return: a JSON list of {id, service, operation, phase, outcome}, the outcome being
+            finished or rolled_back
+        """
+
+    def heartbeat_path(self) -> str:
+        """
+        Get the heartbeat file, rewritten at every tick of monitor_preemptions, monitor_budget and
+        monitor_ttl with the time, the process id, the monitor that ticked and a summary of the services, for
+        external watchdogs (systemd, Kubernetes liveness probes) to check the monitors are alive
+
+        :return: the path of the heartbeat file
+        """
+
+    def find_untagged(self, pretty: Optional[bool] = None) -> str:
+        """
+        Spot the resources that escaped management. The cloud resources of the services added
+        through servicing are labelled with managed-by: servicing, the service, its owner and its
+        creation time
+
+        :param pretty: whether to indent the JSON
+        :return: a JSON list of {name, reason}, reason being unmanaged for the SkyPilot services
+            not in the registry and untagged for the registered services lacking the labels
+        """
+
+    def leaks(self, pretty: Optional[bool] = None) -> str:
+        """
+        Find the resources that exist but are tracked by no service: SkyPilot services and
+        clusters, docker containers and Kubernetes objects labelled by servicing
+
+        :param pretty: whether to indent the JSON
+        :return: a JSON object with the leaks, a list of {kind, name, service}, and unchecked, the
+            sources that could not be listed, e.g. because docker is not installed
+        """
+
+    def start_status_cache(self, interval: Optional[int] = None) -> None:
+        """
+        Poll sky serve status for all the services in the background and cache its output, which
+        status, replicas, find_untagged and monitor_preemptions then answer from instead of
+        forking the CLI. A cache that missed a poll is not used; launches, teardowns and recover
+        always ask SkyPilot
+
+        :param interval: seconds between polls, 30 by default; starting again changes it
+        """
+
+    def stop_status_cache(self) -> bool:
+        """
+        Stop polling SkyPilot and drop the cached status
+
+        :return: whether the cache was running
+        """
+
+    def replicas(self, name: str, pretty: Optional[bool] = None) -> str:
+        """
+        Get the replicas SkyPilot reports for a service, from the status cache when it is fresh
+
+        :param name: the name of the service
+        :param pretty: whether to indent the JSON
+        :return: a JSON list of {service, id, status, region, endpoint}
+        """
+
+    def preemptions(self, name: str) -> str:
+        """
+        Get the preemptions recorded for a service, oldest first
+
+        :param name: the name of the service
+        :return: the preemptions in JSON format, each with the replica id and detection time
+        """
+
+    def set_health_check(self, name: str, expression: Optional[str] = None,
+                         expected: Optional[str] = None) -> None:
+        """
+        Validate the JSON body of the readiness endpoint on top of SkyPilot having a ready replica
+
+        :param name: the name of the service
+        :param expression: a JSONPath expression, optionally compared, e.g. "$.model_loaded == true";
+            None removes the check
+        :param expected: the JSON value the path must hold, by default it must be truthy
+        """
+
+    def set_probe_method(self, name: str, method: Optional[str] = None) -> None:
+        """
+        Set how the readiness endpoint of the service is polled, to spare services billing per
+        request
+
+        :param name: the name of the service
+        :param method: "get" (the default) fetches the body every time, "head" only the status
+            code, falling back to GET when HEAD is not supported or a health check needs the
+            body, and "conditional" only fetches the body when its ETag or Last-Modified changed
+        """
+
+    def set_ready_check(self, name: str,
+                        check: Optional[Callable[[str], bool]] = None) -> None:
+        """
+        Replace the readiness probe of the service with a Python callable, for services whose
+        health can only be judged by a domain-specific request. The check runs on a thread of
+        its own, an exception failing the probe; it is not saved with the services
+
+        :param name: the name of the service
+        :param check: called with the URL of the service, returning whether it is ready; None
+            restores the HTTP probe
+        """
+
+    def set_tunnel(self, name: str, host: Optional[str] = None, user: Optional[str] = None,
+                   key: Optional[str] = None, port: Optional[int] = None) -> None:
+        """
+        Route the health checks and requests of a service listening on a private network
+        through an SSH bastion
+
+        :param name: the name of the service
+        :param host: the bastion host, None removes the tunnel
+        :param user: the user on the bastion, the current user by default
+        :param key: the private key file, the SSH agent and configuration are used without
+        :param port: the SSH port of the bastion, 22 by default
+        """
+
+    def set_proxy(self, name: str, proxy: Optional[str] = None) -> None:
+        """
+        Send the health checks and requests of the service through a proxy, leaving the other
+        services alone
+
+        :param name: the name of the service
+        :param proxy: the proxy URL, SOCKS5 (socks5h://jump:1080) or HTTP(S); None removes it
+        """
+
+    def set_scale_to_zero(self, name: str, enabled: Optional[bool] = None,
+                          idle_timeout: Optional[int] = None) -> None:
+        """
+        Scale a running service down to zero replicas once it is idle, the next request sent
+        through invoke, chat, completions or the gateway scales it back up and waits until it is
+        ready
+
+        :param name: the name of the service
+        :param enabled: set to False to leave scale-to-zero mode
+        :param idle_timeout: the seconds without requests before scaling down, defaults to 600
+        """
+
+    def snapshot(self, path: str) -> None:
+        """
+        Write an archive of the whole dispatcher: every service with its configuration file, and
+        the aliases
+
+        :param path: the path of the archive
+        """
+
+    def restore(self, path: str, reprovision: Optional[bool] = None) -> None:
         """
-        List all the services
+        Recreate the services and aliases of an archive written by snapshot, services already
+        known to the dispatcher are refused
 
-        :return: a list of all the services
+        :param path: the path of the archive
+        :param reprovision: whether to launch every restored service again, refused with code
+            APPROVAL_REQUIRED for the protected services
         """
@@ -0,0 +1,104 @@
+//! Alias module houses the load balancing of traffic across several services behind one name.
+use std::{str::FromStr, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::ServicingError;
+
+/// Policy decides which member of an alias receives the next request.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum Policy {
+    RoundRobin,
+    LeastLatency,
+}
+
+impl Policy {
+    pub const NAMES: &'static [&'static str] = &["round_robin", "least_latency"];
+}
+
+impl FromStr for Policy {
+    type Err = ServicingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "round_robin" => Ok(Policy::RoundRobin),
+            "least_latency" => Ok(Policy::LeastLatency),
+            _ => Err(ServicingError::General(format!(
+                "Unknown load balancing policy {s}, expected round_robin or least_latency"
+            ))),
+        }
+    }
+}
+
+/// Alias spreads traffic across several services.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Alias {
+    pub members: Vec<String>,
+    pub policy: Policy,
+    next: usize,
+}
+
+/// Candidate is a member of an alias that is currently up, along with its last observed latency.
+pub struct Candidate<'a> {
+    pub name: &'a str,
+    pub latency: Option<Duration>,
+}
+
+impl Alias {
+    pub fn new(members: Vec<String>, policy: Policy) -> Self {
+        Alias {
+            members,
+            policy,
+            next: 0,
+        }
+    }
+
+    /// pick selects the member to route to among the healthy candidates. Members with no observed
+    /// latency are preferred by least_latency so that they get measured.
+    pub fn pick(&mut self, candidates: &[Candidate]) -> Option<String> {
+        if candidates.is_empty() {
+            return None;
+        }
+        let picked = match self.policy {
+            Policy::RoundRobin => {
+                let picked = &candidates[self.next % candidates.len()];
+                self.next = self.next.wrapping_add(1);
+                picked
+            }
+            Policy::LeastLatency => candidates
+                .iter()
+                .min_by_key(|c| c.latency.unwrap_or(Duration::ZERO))?,
+        };
+        Some(picked.name.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Alias, Candidate, Policy};
+
+    #[test]
+    fn test_alias_pick() {
+        let candidates = [
+            Candidate {
+                name: "aws",
+                latency: Some(Duration::from_millis(80)),
+            },
+            Candidate {
+                name: "gcp",
+                latency: Some(Duration::from_millis(20)),
+            },
+        ];
+
+        let mut alias = Alias::new(vec!["aws".into(), "gcp".into()], Policy::RoundRobin);
+        assert_eq!(alias.pick(&candidates).as_deref(), Some("aws"));
+        assert_eq!(alias.pick(&candidates).as_deref(), Some("gcp"));
+        assert_eq!(alias.pick(&candidates).as_deref(), Some("aws"));
+
+        let mut alias = Alias::new(vec!["aws".into(), "gcp".into()], Policy::LeastLatency);
+        assert_eq!(alias.pick(&candidates).as_deref(), Some("gcp"));
+        assert_eq!(alias.pick(&[]), None);
+    }
+}
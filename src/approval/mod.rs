@@ -0,0 +1,133 @@
+//! Approval module houses the two-step up of protected services: request_up records a pending
+//! request, which a second user, or anyone holding the approval token of the service, approves
+//! before the service is provisioned. Every request and decision is kept in the trail of the
+//! service, exported with its other events.
+use serde::{Deserialize, Serialize};
+
+use crate::{error::ServicingError, helper, models::Timestamp};
+
+/// Protection requires the ups of a service to be approved.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Protection {
+    /// users allowed to approve, any user but the requester if empty
+    pub approvers: Vec<String>,
+    /// hash of the token approving without being an approver
+    pub token_hash: Option<String>,
+}
+
+/// Request is an up waiting for approval.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Request {
+    /// user@host asking for the up
+    pub requester: String,
+    pub requested: Timestamp,
+    /// environment the service is to be launched in
+    pub environment: Option<String>,
+    pub reason: Option<String>,
+}
+
+/// Action is a step of the approval of an up, or a change of the protection of the service.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+pub enum Action {
+    Requested,
+    Approved,
+    Rejected,
+    Protected,
+    Unprotected,
+}
+
+impl Action {
+    /// event names the action in the events of the service.
+    pub fn event(&self) -> &'static str {
+        match self {
+            Action::Requested => "up requested",
+            Action::Approved => "up approved",
+            Action::Rejected => "up rejected",
+            Action::Protected => "protected",
+            Action::Unprotected => "unprotected",
+        }
+    }
+}
+
+/// Step is a request or decision in the approval trail of a service.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Step {
+    pub time: Timestamp,
+    pub action: Action,
+    /// user@host taking the step, or the token holder
+    pub by: String,
+    pub detail: Option<String>,
+}
+
+impl Protection {
+    pub fn new(approvers: Vec<String>, token: Option<&str>) -> Self {
+        Protection {
+            approvers,
+            token_hash: token.map(helper::content_hash),
+        }
+    }
+
+    /// check refuses to let user decide on request, unless user is an approver other than the
+    /// requester or token is the approval token of the service.
+    pub fn check(
+        &self,
+        name: &str,
+        request: &Request,
+        user: &str,
+        token: Option<&str>,
+    ) -> Result<(), ServicingError> {
+        let refused = |reason: &str| {
+            Err(ServicingError::ApprovalRefused(
+                name.to_string(),
+                reason.to_string(),
+            ))
+        };
+        if let Some(token) = token {
+            return match &self.token_hash {
+                Some(hash) if *hash == helper::content_hash(token) => Ok(()),
+                Some(_) => refused("invalid token"),
+                None => refused("the service has no approval token"),
+            };
+        }
+        let requester = request.requester.split('@').next().unwrap_or_default();
+        if requester == user {
+            return refused("the up must be approved by another user");
+        }
+        if !self.approvers.is_empty() && !self.approvers.iter().any(|a| a == user) {
+            return refused(&format!("{user} is not an approver"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Protection, Request};
+    use crate::models::Timestamp;
+
+    #[test]
+    fn test_approval_check() {
+        let request = Request {
+            requester: "alice@laptop".to_string(),
+            requested: Timestamp::now(),
+            environment: None,
+            reason: None,
+        };
+        let anyone = Protection::new(Vec::new(), None);
+        assert!(anyone.check("llm", &request, "bob", None).is_ok());
+        assert!(anyone.check("llm", &request, "alice", None).is_err());
+        assert!(anyone
+            .check("llm", &request, "alice", Some("t0k3n"))
+            .is_err());
+
+        let protected = Protection::new(vec!["carol".to_string()], Some("t0k3n"));
+        assert!(protected.check("llm", &request, "bob", None).is_err());
+        assert!(protected.check("llm", &request, "carol", None).is_ok());
+        // a token holder may be the requester
+        assert!(protected
+            .check("llm", &request, "alice", Some("t0k3n"))
+            .is_ok());
+        let wrong = protected.check("llm", &request, "carol", Some("guess"));
+        assert_eq!(wrong.unwrap_err().code(), "APPROVAL_REFUSED");
+    }
+}
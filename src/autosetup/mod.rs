@@ -0,0 +1,153 @@
+//! Autosetup module houses the detection of the setup and run commands of a Python project from
+//! the files of its workdir, so users do not have to adapt the default template by hand.
+use std::{fs, path::Path};
+
+use log::info;
+
+use crate::error::ServicingError;
+
+static CONDA_FILES: &[&str] = &["environment.yml", "environment.yaml", "conda.yml"];
+static ENTRYPOINTS: &[&str] = &["service.py", "app.py", "main.py", "server.py"];
+/// dependencies that need a GPU to be of use
+static CUDA_HINTS: &[&str] = &[
+    "torch",
+    "vllm",
+    "tensorflow",
+    "jax[cuda",
+    "cupy",
+    "nvidia-",
+    "cudatoolkit",
+    "pytorch-cuda",
+];
+static DEFAULT_ACCELERATORS: &str = "A10G:1";
+static CUDA_SETUP: &str = "conda install cudatoolkit -y";
+/// words of a run script showing it makes use of a GPU
+static GPU_HINTS: &[&str] = &["cuda", "gpu", "nvidia", "--device", "tensor-parallel"];
+
+/// Detected holds what could be inferred from the workdir, None where nothing was found.
+#[derive(Debug, Default, PartialEq)]
+pub struct Detected {
+    pub setup: Option<String>,
+    pub run: Option<String>,
+    pub accelerators: Option<String>,
+}
+
+/// detect inspects workdir for a conda environment file, a pyproject.toml or a
+/// requirements.txt, CUDA dependencies and an entrypoint script.
+pub fn detect(workdir: &Path) -> Result<Detected, ServicingError> {
+    let read = |file: &str| -> Result<Option<String>, ServicingError> {
+        let path = workdir.join(file);
+        match path.is_file() {
+            true => Ok(Some(fs::read_to_string(path)?)),
+            false => Ok(None),
+        }
+    };
+
+    let conda = CONDA_FILES
+        .iter()
+        .find(|file| workdir.join(file).is_file())
+        .copied();
+    let pyproject = read("pyproject.toml")?;
+    let requirements = read("requirements.txt")?;
+    let poetry = pyproject
+        .as_deref()
+        .is_some_and(|p| p.contains("[tool.poetry]"));
+
+    let mut dependencies = String::new();
+    for content in [
+        conda.map(read).transpose()?.flatten(),
+        pyproject.clone(),
+        requirements.clone(),
+    ]
+    .into_iter()
+    .flatten()
+    {
+        dependencies.push_str(&content.to_lowercase());
+    }
+    let cuda = CUDA_HINTS.iter().any(|hint| dependencies.contains(hint));
+
+    let mut setup = Vec::new();
+    if let Some(conda) = conda {
+        setup.push(format!("conda env update -n base -f {conda}"));
+    } else if cuda {
+        setup.push("conda install cudatoolkit -y".to_string());
+    }
+    if poetry {
+        setup.push("pip install poetry".to_string());
+        setup.push("poetry install".to_string());
+    } else if pyproject.is_some() {
+        setup.push("pip install .".to_string());
+    } else if requirements.is_some() {
+        setup.push("pip install -r requirements.txt".to_string());
+    }
+
+    let run = ENTRYPOINTS
+        .iter()
+        .find(|file| workdir.join(file).is_file())
+        .map(|file| match poetry {
+            true => format!("poetry run python {file}\n"),
+            false => format!("python {file}\n"),
+        });
+
+    let detected = Detected {
+        setup: (!setup.is_empty()).then(|| setup.join("\n") + "\n"),
+        run,
+        accelerators: cuda.then(|| DEFAULT_ACCELERATORS.to_string()),
+    };
+    info!("Detected from workdir {:?}: {:?}", workdir, detected);
+    Ok(detected)
+}
+
+/// adapt_cuda_setup adds the CUDA toolkit installation to setup for a service with
+/// accelerators and drops it from one without, sparing CPU-only services its install time.
+pub fn adapt_cuda_setup(setup: &str, accelerators: bool) -> String {
+    let installs_cuda = |line: &str| line.contains("cudatoolkit") || line.contains("cuda-toolkit");
+    match accelerators {
+        true if setup.lines().any(installs_cuda) => setup.to_string(),
+        true => format!("{CUDA_SETUP}\n{setup}"),
+        false => setup
+            .lines()
+            .filter(|line| !installs_cuda(line))
+            .map(|line| format!("{line}\n"))
+            .collect(),
+    }
+}
+
+/// uses_gpu tells whether the run script or the dependencies found in workdir make use of a
+/// GPU, a service requesting accelerators without is likely misconfigured.
+pub fn uses_gpu(workdir: &Path, run: &str) -> Result<bool, ServicingError> {
+    let run = run.to_lowercase();
+    Ok(GPU_HINTS.iter().any(|hint| run.contains(hint)) || detect(workdir)?.accelerators.is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{adapt_cuda_setup, detect};
+
+    #[test]
+    fn test_detect() {
+        let workdir = std::env::temp_dir().join(format!("servicing-detect-{}", std::process::id()));
+        fs::create_dir_all(&workdir).unwrap();
+        fs::write(workdir.join("requirements.txt"), "fastapi\nTorch==2.2\n").unwrap();
+        fs::write(workdir.join("app.py"), "").unwrap();
+
+        let detected = detect(&workdir).unwrap();
+        assert_eq!(
+            detected.setup.as_deref(),
+            Some("conda install cudatoolkit -y\npip install -r requirements.txt\n")
+        );
+        assert_eq!(detected.run.as_deref(), Some("python app.py\n"));
+        assert_eq!(detected.accelerators.as_deref(), Some("A10G:1"));
+        fs::remove_dir_all(workdir).unwrap();
+
+        let setup = "conda install cudatoolkit -y\npip install poetry\n";
+        assert_eq!(adapt_cuda_setup(setup, false), "pip install poetry\n");
+        assert_eq!(adapt_cuda_setup(setup, true), setup);
+        assert_eq!(
+            adapt_cuda_setup("pip install poetry\n", true),
+            "conda install cudatoolkit -y\npip install poetry\n"
+        );
+    }
+}
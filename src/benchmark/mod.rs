@@ -0,0 +1,140 @@
+//! Benchmark module houses the load generation against a running service, used to check the
+//! replica count and autoscaling settings of a service right after it is deployed.
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use futures::future::join_all;
+use log::info;
+use reqwest::Client;
+use serde::Serialize;
+
+use crate::client::OutboundRequest;
+
+/// Report summarises a benchmark run, latencies are in milliseconds.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub requests: usize,
+    pub errors: usize,
+    pub seconds: f64,
+    /// completed requests per second
+    pub throughput: f64,
+    pub mean: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+    /// number of responses per status code, "error" for requests that got no response
+    pub statuses: BTreeMap<String, usize>,
+}
+
+struct Sample {
+    latency: Duration,
+    status: Option<u16>,
+}
+
+/// run sends req from concurrency workers in a loop until duration has elapsed. Every response
+/// body is read in full so the latency covers the whole response.
+pub async fn run(
+    client: &Client,
+    req: &OutboundRequest,
+    concurrency: usize,
+    duration: Duration,
+) -> Report {
+    info!(
+        "Benchmarking {} {} with {} workers for {:?}",
+        req.method, req.url, concurrency, duration
+    );
+    let start = Instant::now();
+    let deadline = start + duration;
+    let workers = (0..concurrency.max(1)).map(|_| async move {
+        let mut samples = Vec::new();
+        while Instant::now() < deadline {
+            let sent = Instant::now();
+            let status = match req.send(client).await {
+                Ok(res) => {
+                    let status = res.status().as_u16();
+                    res.bytes().await.ok().map(|_| status)
+                }
+                Err(_) => None,
+            };
+            samples.push(Sample {
+                latency: sent.elapsed(),
+                status,
+            });
+        }
+        samples
+    });
+    let samples: Vec<Sample> = join_all(workers).await.into_iter().flatten().collect();
+    summarise(samples, start.elapsed())
+}
+
+fn summarise(samples: Vec<Sample>, elapsed: Duration) -> Report {
+    let mut statuses = BTreeMap::new();
+    for sample in &samples {
+        let key = sample
+            .status
+            .map_or("error".to_string(), |status| status.to_string());
+        *statuses.entry(key).or_insert(0) += 1;
+    }
+    let errors = samples
+        .iter()
+        .filter(|s| s.status.is_none_or(|status| status >= 400))
+        .count();
+
+    let mut latencies: Vec<f64> = samples
+        .iter()
+        .map(|s| s.latency.as_secs_f64() * 1000.0)
+        .collect();
+    latencies.sort_by(f64::total_cmp);
+    let percentile = |p: f64| match latencies.len() {
+        0 => 0.0,
+        n => latencies[((n as f64 * p).ceil() as usize).clamp(1, n) - 1],
+    };
+    let seconds = elapsed.as_secs_f64();
+
+    Report {
+        requests: samples.len(),
+        errors,
+        seconds,
+        throughput: match seconds > 0.0 {
+            true => (samples.len() - errors) as f64 / seconds,
+            false => 0.0,
+        },
+        mean: match latencies.is_empty() {
+            true => 0.0,
+            false => latencies.iter().sum::<f64>() / latencies.len() as f64,
+        },
+        p50: percentile(0.5),
+        p90: percentile(0.9),
+        p99: percentile(0.99),
+        max: latencies.last().copied().unwrap_or(0.0),
+        statuses,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{summarise, Sample};
+
+    #[test]
+    fn test_summarise() {
+        let samples = (1..=100)
+            .map(|ms| Sample {
+                latency: Duration::from_millis(ms),
+                status: Some(if ms % 10 == 0 { 500 } else { 200 }),
+            })
+            .collect();
+        let report = summarise(samples, Duration::from_secs(10));
+        assert_eq!(report.requests, 100);
+        assert_eq!(report.errors, 10);
+        assert_eq!(report.throughput, 9.0);
+        assert_eq!(report.p50, 50.0);
+        assert_eq!(report.p99, 99.0);
+        assert_eq!(report.max, 100.0);
+        assert_eq!(report.statuses["500"], 10);
+    }
+}
@@ -0,0 +1,227 @@
+//! Budget module houses the spend guardrails of a service: the hourly cost of its configuration
+//! is estimated before launching it, and its accumulated spend is tracked while it runs.
+use std::{collections::BTreeMap, path::Path};
+
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::ServicingError,
+    models::{Configuration, Timestamp},
+    quote,
+};
+
+/// Budget caps what a service may cost, in dollars.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Budget {
+    pub max_hourly_cost: Option<f64>,
+    pub max_total_cost: Option<f64>,
+    /// take the service down when max_total_cost is crossed, instead of only alerting
+    pub auto_down: bool,
+}
+
+/// Spend tracks the estimated spend of a service across its launches.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Spend {
+    /// estimated hourly cost of the running service
+    pub hourly_cost: Option<f64>,
+    /// when the running service was launched
+    pub since: Option<Timestamp>,
+    /// spend of the previous launches
+    pub spent: f64,
+    /// previous launches, for the reports over a time window
+    #[serde(default)]
+    pub periods: Vec<Period>,
+}
+
+/// Period is a previous launch of a service.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Period {
+    pub start: Timestamp,
+    pub end: Timestamp,
+    pub hourly_cost: f64,
+}
+
+/// CostLine is the estimated spend of a group of services in a cost report.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct CostLine {
+    /// service, owner or label value the services are grouped by
+    pub group: String,
+    pub services: usize,
+    pub hours: f64,
+    pub cost: f64,
+}
+
+impl Budget {
+    /// check refuses a launch at hourly_cost when it is over max_hourly_cost, or when the total
+    /// budget is already used up.
+    pub fn check(&self, name: &str, hourly_cost: f64, spent: f64) -> Result<(), ServicingError> {
+        if let Some(max) = self.max_hourly_cost {
+            if hourly_cost > max {
+                return Err(ServicingError::BudgetExceeded(
+                    name.to_string(),
+                    format!("estimated ${hourly_cost:.2}/h is over the limit of ${max:.2}/h"),
+                ));
+            }
+        }
+        if let Some(max) = self.max_total_cost {
+            if spent >= max {
+                return Err(ServicingError::BudgetExceeded(
+                    name.to_string(),
+                    format!("estimated spend of ${spent:.2} reached the limit of ${max:.2}"),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// over_total tells whether spent crossed max_total_cost.
+    pub fn over_total(&self, spent: f64) -> bool {
+        self.max_total_cost.is_some_and(|max| spent >= max)
+    }
+}
+
+impl Spend {
+    /// total is the spend of the previous launches plus that of the running one until now.
+    pub fn total(&self, now: u64) -> f64 {
+        let running = match (self.hourly_cost, self.since) {
+            (Some(hourly_cost), Some(since)) => {
+                hourly_cost * now.saturating_sub(since.0) as f64 / 3600.0
+            }
+            _ => 0.0,
+        };
+        self.spent + running
+    }
+
+    pub fn start(&mut self, hourly_cost: f64, now: u64) {
+        self.hourly_cost = Some(hourly_cost);
+        self.since = Some(Timestamp(now));
+    }
+
+    /// stop adds the spend of the running launch to the previous ones.
+    pub fn stop(&mut self, now: u64) {
+        self.spent = self.total(now);
+        if let Some(period) = self.running(now) {
+            self.periods.push(period);
+        }
+        self.hourly_cost = None;
+        self.since = None;
+    }
+
+    /// between returns the hours run and the estimated spend of the launches within since and
+    /// until, the running one counting until now. Launches from before the periods were kept
+    /// are not accounted.
+    pub fn between(&self, since: u64, until: u64, now: u64) -> (f64, f64) {
+        let running = self.running(now);
+        self.periods
+            .iter()
+            .chain(running.as_ref())
+            .fold((0.0, 0.0), |(hours, cost), period| {
+                let seconds = period
+                    .end
+                    .0
+                    .min(until)
+                    .saturating_sub(period.start.0.max(since));
+                let period_hours = seconds as f64 / 3600.0;
+                (
+                    hours + period_hours,
+                    cost + period_hours * period.hourly_cost,
+                )
+            })
+    }
+
+    fn running(&self, now: u64) -> Option<Period> {
+        Some(Period {
+            start: self.since?,
+            end: Timestamp(now),
+            hourly_cost: self.hourly_cost?,
+        })
+    }
+}
+
+/// report sums the spend of the services within since and until by group, the groups that
+/// spent nothing in the window being left out.
+pub fn report<'a>(
+    spends: impl IntoIterator<Item = (String, &'a Spend)>,
+    since: u64,
+    until: u64,
+    now: u64,
+) -> Vec<CostLine> {
+    let mut groups: BTreeMap<String, CostLine> = BTreeMap::new();
+    for (group, spend) in spends {
+        let (hours, cost) = spend.between(since, until, now);
+        if hours <= 0.0 {
+            continue;
+        }
+        let line = groups.entry(group.clone()).or_insert(CostLine {
+            group,
+            services: 0,
+            hours: 0.0,
+            cost: 0.0,
+        });
+        line.services += 1;
+        line.hours += hours;
+        line.cost += cost;
+    }
+    groups.into_values().collect()
+}
+
+/// estimate_hourly_cost prices the configuration with the candidate the optimizer would pick,
+/// for every replica. The SkyPilot controller is not included.
+pub fn estimate_hourly_cost(
+    name: &str,
+    config: &Configuration,
+    dir: &Path,
+) -> Result<f64, ServicingError> {
+    let quotes = quote::quote(config, true, dir)?;
+    let price = quotes
+        .iter()
+        .find(|q| q.chosen)
+        .or(quotes.first())
+        .and_then(|q| q.hourly_price)
+        .ok_or(ServicingError::General(format!(
+            "Could not estimate the cost of service {name}"
+        )))?;
+    let hourly_cost = price * f64::from(config.service.replicas);
+    info!("Estimated cost of service {}: ${:.2}/h", name, hourly_cost);
+    Ok(hourly_cost)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{report, Budget, Spend};
+
+    #[test]
+    fn test_budget() {
+        let budget = Budget {
+            max_hourly_cost: Some(2.0),
+            max_total_cost: Some(10.0),
+            auto_down: false,
+        };
+        assert!(budget.check("test", 1.5, 0.0).is_ok());
+        assert!(budget.check("test", 2.5, 0.0).is_err());
+        assert!(budget.check("test", 1.5, 10.0).is_err());
+
+        let mut spend = Spend::default();
+        spend.start(2.0, 0);
+        assert_eq!(spend.total(1800), 1.0);
+        spend.stop(3600);
+        assert_eq!(spend.spent, 2.0);
+        assert_eq!(spend.total(7200), 2.0);
+        assert!(!budget.over_total(spend.total(7200)));
+
+        spend.start(4.0, 10800);
+        assert_eq!(spend.between(1800, 12600, 14400), (1.0, 3.0));
+        let lines = report(
+            [("a".to_string(), &spend), ("a".to_string(), &spend)],
+            0,
+            14400,
+            14400,
+        );
+        assert_eq!(lines.len(), 1);
+        assert_eq!(
+            (lines[0].services, lines[0].hours, lines[0].cost),
+            (2, 4.0, 12.0)
+        );
+    }
+}
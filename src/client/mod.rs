@@ -0,0 +1,319 @@
+//! Client module houses the helpers used to talk to OpenAI-compatible services (vLLM, TGI, ...).
+use std::time::Duration;
+
+use pyo3::{pyclass, pymethods, types::PyAnyMethods, Bound, PyAny, PyRefMut, Python};
+use reqwest::{
+    header::{HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION, CONTENT_TYPE},
+    Client, Method, Response,
+};
+use serde_json::{Map, Value};
+use tokio::runtime::Handle;
+
+use crate::{error::ServicingError, helper};
+
+static CHAT_COMPLETIONS_PATH: &str = "/v1/chat/completions";
+static COMPLETIONS_PATH: &str = "/v1/completions";
+/// how long a service may take to answer with the headers of its response, and then to send
+/// each part of the body
+pub static INFERENCE_TIMEOUT: Duration = Duration::from_secs(300);
+static SSE_DATA_PREFIX: &str = "data:";
+static SSE_DONE: &str = "[DONE]";
+
+/// Endpoint is where requests addressed to a service or alias end up.
+pub struct Endpoint {
+    pub service: String,
+    pub url: String,
+    pub up: bool,
+    /// key the service expects requests to carry
+    pub api_key: Option<String>,
+    /// message to answer with while the service is under maintenance
+    pub maintenance: Option<String>,
+}
+
+/// OpenAIEndpoint is the OpenAI API route a request is sent to.
+pub enum OpenAIEndpoint {
+    Chat,
+    Completions,
+}
+
+impl OpenAIEndpoint {
+    fn path(&self) -> &'static str {
+        match self {
+            OpenAIEndpoint::Chat => CHAT_COMPLETIONS_PATH,
+            OpenAIEndpoint::Completions => COMPLETIONS_PATH,
+        }
+    }
+}
+
+/// to_json converts a Python object into JSON using the python json module, so anything
+/// json.dumps accepts can be sent to a service.
+pub fn to_json(obj: &Bound<'_, PyAny>) -> Result<Value, ServicingError> {
+    let dumped: String = obj
+        .py()
+        .import_bound("json")?
+        .call_method1("dumps", (obj,))?
+        .extract()?;
+    Ok(serde_json::from_str(&dumped)?)
+}
+
+/// build_body creates the request body from the main field (messages or prompt) and any extra
+/// parameters the caller passed (model, temperature, max_tokens, ...).
+pub fn build_body(
+    key: &str,
+    value: Value,
+    params: Option<&Bound<'_, PyAny>>,
+    stream: bool,
+) -> Result<Value, ServicingError> {
+    let mut body = match params {
+        Some(params) => match to_json(params)? {
+            Value::Object(map) => map,
+            _ => {
+                return Err(ServicingError::General(
+                    "Parameters must be keyword arguments".to_string(),
+                ))
+            }
+        },
+        None => Map::new(),
+    };
+    body.insert(key.to_string(), value);
+    body.insert("stream".to_string(), Value::Bool(stream));
+    Ok(Value::Object(body))
+}
+
+/// OutboundRequest is a request on its way to a service, built up front so that middleware can
+/// inspect and change it before it is sent.
+pub struct OutboundRequest {
+    pub method: Method,
+    pub url: String,
+    pub headers: HeaderMap,
+    pub body: Option<Vec<u8>>,
+}
+
+impl OutboundRequest {
+    /// json creates a request to the service with an optional JSON body.
+    pub fn json(method: Method, url: String, body: Option<&Value>) -> Result<Self, ServicingError> {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+        let body = match body {
+            Some(body) => {
+                headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+                Some(serde_json::to_vec(body)?)
+            }
+            None => None,
+        };
+        Ok(OutboundRequest {
+            method,
+            url,
+            headers,
+            body,
+        })
+    }
+
+    /// openai creates a request to the OpenAI-compatible endpoint of the service.
+    pub fn openai(
+        base_url: &str,
+        endpoint: OpenAIEndpoint,
+        body: &Value,
+    ) -> Result<Self, ServicingError> {
+        let url = helper::http_url(base_url, endpoint.path());
+        OutboundRequest::json(Method::POST, url, Some(body))
+    }
+
+    /// authorize attaches the API key of the service as a bearer token, unless the request
+    /// already carries credentials of its own.
+    pub fn authorize(&mut self, api_key: Option<&str>) -> Result<(), ServicingError> {
+        if let Some(api_key) = api_key {
+            if !self.headers.contains_key(AUTHORIZATION) {
+                let mut value = HeaderValue::from_str(&format!("Bearer {api_key}"))
+                    .map_err(|e| ServicingError::General(e.to_string()))?;
+                value.set_sensitive(true);
+                self.headers.insert(AUTHORIZATION, value);
+            }
+        }
+        Ok(())
+    }
+
+    /// send sends the request, returning the response once the headers are received. Only the
+    /// wait for the headers is bounded by INFERENCE_TIMEOUT, a streamed body lasts as long as
+    /// the service keeps sending it.
+    pub async fn send(&self, client: &Client) -> Result<Response, ServicingError> {
+        // lifts the timeout of the client, which would cover the whole body
+        let mut req = client
+            .request(self.method.clone(), &self.url)
+            .headers(self.headers.clone())
+            .timeout(Duration::MAX);
+        if let Some(body) = &self.body {
+            req = req.body(body.clone());
+        }
+        match tokio::time::timeout(INFERENCE_TIMEOUT, req.send()).await {
+            Ok(response) => Ok(response?),
+            Err(_) => Err(ServicingError::General(format!(
+                "{} did not answer within {}s",
+                self.url,
+                INFERENCE_TIMEOUT.as_secs()
+            ))),
+        }
+    }
+}
+
+/// text reads the whole body of a response that is not streamed, within INFERENCE_TIMEOUT.
+pub async fn text(response: Response) -> Result<String, ServicingError> {
+    let url = response.url().to_string();
+    match tokio::time::timeout(INFERENCE_TIMEOUT, response.text()).await {
+        Ok(text) => Ok(text?),
+        Err(_) => Err(ServicingError::General(format!(
+            "{url} did not send its response within {}s",
+            INFERENCE_TIMEOUT.as_secs()
+        ))),
+    }
+}
+
+/// Sse splits the bytes of a server sent events stream into the data of its events.
+#[derive(Default)]
+struct Sse {
+    buffer: Vec<u8>,
+    /// data lines of the event being received
+    data: Vec<String>,
+    /// whether the [DONE] event was received
+    done: bool,
+}
+
+impl Sse {
+    fn feed(&mut self, chunk: &[u8]) {
+        self.buffer.extend_from_slice(chunk);
+    }
+
+    /// finish ends the stream, completing an event cut short of its blank line.
+    fn finish(&mut self) {
+        self.buffer.extend_from_slice(b"\n\n");
+    }
+
+    /// next_event pops the data of the next complete event, its data lines joined by newlines.
+    /// Comments, the other fields and the events without data are skipped, [DONE] ends the
+    /// stream.
+    fn next_event(&mut self) -> Option<String> {
+        while !self.done {
+            let pos = self.buffer.iter().position(|b| *b == b'\n')?;
+            let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line.is_empty() {
+                if self.data.is_empty() {
+                    continue;
+                }
+                let data = self.data.join("\n");
+                self.data.clear();
+                if data.trim() == SSE_DONE {
+                    self.done = true;
+                    return None;
+                }
+                return Some(data);
+            }
+            if let Some(data) = line.strip_prefix(SSE_DATA_PREFIX) {
+                // the space after the colon is not part of the data
+                self.data
+                    .push(data.strip_prefix(' ').unwrap_or(data).to_string());
+            }
+        }
+        None
+    }
+}
+
+/// CompletionStream is a Python iterator over the server sent events of a streaming completion.
+/// Each item is the JSON payload of one event.
+#[pyclass]
+pub struct CompletionStream {
+    response: Option<Response>,
+    handle: Handle,
+    sse: Sse,
+}
+
+impl CompletionStream {
+    pub fn new(response: Response, handle: Handle) -> Self {
+        CompletionStream {
+            response: Some(response),
+            handle,
+            sse: Sse::default(),
+        }
+    }
+}
+
+#[pymethods]
+impl CompletionStream {
+    fn __repr__(&self) -> String {
+        match self.response {
+            Some(_) => "CompletionStream(open)".to_string(),
+            None => "CompletionStream(closed)".to_string(),
+        }
+    }
+
+    fn __iter__(slf: PyRefMut<'_, Self>) -> PyRefMut<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> Result<Option<String>, ServicingError> {
+        loop {
+            if let Some(event) = self.sse.next_event() {
+                return Ok(Some(event));
+            }
+            if self.sse.done {
+                self.response = None;
+            }
+            let Some(response) = self.response.as_mut() else {
+                return Ok(None);
+            };
+            // release the GIL while waiting on the network
+            let handle = &self.handle;
+            let chunk = py.allow_threads(|| {
+                handle.block_on(tokio::time::timeout(INFERENCE_TIMEOUT, response.chunk()))
+            });
+            match chunk {
+                Ok(Ok(Some(chunk))) => self.sse.feed(&chunk),
+                Ok(Ok(None)) => {
+                    self.response = None;
+                    self.sse.finish();
+                }
+                Ok(Err(e)) => return Err(e.into()),
+                Err(_) => {
+                    self.response = None;
+                    return Err(ServicingError::General(format!(
+                        "The stream sent nothing for {}s",
+                        INFERENCE_TIMEOUT.as_secs()
+                    )));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Sse;
+
+    #[test]
+    fn test_sse() {
+        let mut sse = Sse::default();
+        // events cut anywhere across chunks, CRLF line endings included
+        for chunk in [
+            ": keep-alive\n\nda",
+            "ta: {\"id\": 1}\r\n\r",
+            "\nevent: completion\nid: 2\ndata: {\"id\":\ndata:  2}\n\n",
+        ] {
+            sse.feed(chunk.as_bytes());
+        }
+        assert_eq!(sse.next_event().as_deref(), Some("{\"id\": 1}"));
+        assert_eq!(sse.next_event().as_deref(), Some("{\"id\":\n 2}"));
+        assert_eq!(sse.next_event(), None);
+
+        // an event is only complete with its blank line, or at the end of the stream
+        sse.feed(b"data: 3");
+        assert_eq!(sse.next_event(), None);
+        sse.finish();
+        assert_eq!(sse.next_event().as_deref(), Some("3"));
+
+        sse.feed(b"data: [DONE]\n\ndata: 4\n\n");
+        assert_eq!(sse.next_event(), None);
+        assert!(sse.done);
+        assert_eq!(sse.next_event(), None);
+    }
+}
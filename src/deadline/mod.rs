@@ -0,0 +1,160 @@
+//! Deadline module houses the deadline of the call in progress on a thread. Set around a call,
+//! it bounds the waits of everything the call does on that thread: the subprocesses, killed
+//! once it passes, the queue for a subprocess slot and the futures run on the runtime, HTTP
+//! requests included, which are dropped.
+use std::{
+    cell::Cell,
+    future::Future,
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "python")]
+use pyo3::{pyclass, pymethods, Bound, PyAny, PyRefMut};
+
+use crate::error::ServicingError;
+
+thread_local! {
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// current returns the deadline of the call in progress on this thread, if any.
+pub fn current() -> Option<Instant> {
+    DEADLINE.with(Cell::get)
+}
+
+/// enter sets deadline on this thread, unless an earlier one is already set, and returns the
+/// deadline to restore once the call is over.
+pub fn enter(deadline: Instant) -> Option<Instant> {
+    let previous = current();
+    let deadline = previous.map_or(deadline, |previous| previous.min(deadline));
+    DEADLINE.with(|current| current.set(Some(deadline)));
+    previous
+}
+
+/// restore puts back the deadline enter returned.
+pub fn restore(previous: Option<Instant>) {
+    DEADLINE.with(|current| current.set(previous));
+}
+
+/// with_deadline runs f with its waits on this thread bounded by deadline, if any.
+pub fn with_deadline<T>(deadline: Option<Instant>, f: impl FnOnce() -> T) -> T {
+    let Some(deadline) = deadline else {
+        return f();
+    };
+    let previous = enter(deadline);
+    let result = f();
+    restore(previous);
+    result
+}
+
+/// remaining returns the time left before the deadline of this thread, None without one. A
+/// deadline that passed fails with what was about to be waited for.
+pub fn remaining(waiting_for: &str) -> Result<Option<Duration>, ServicingError> {
+    match current() {
+        Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+            Some(left) if !left.is_zero() => Ok(Some(left)),
+            _ => Err(ServicingError::DeadlineExceeded(waiting_for.to_string())),
+        },
+        None => Ok(None),
+    }
+}
+
+/// bound limits fut to the deadline of this thread as it is when bound is called, so that it
+/// holds wherever the future is polled.
+pub fn bound<F: Future>(fut: F) -> impl Future<Output = Result<F::Output, ServicingError>> {
+    let deadline = current();
+    async move {
+        match deadline {
+            Some(deadline) => tokio::time::timeout_at(deadline.into(), fut)
+                .await
+                .map_err(|_| ServicingError::DeadlineExceeded("the runtime".to_string())),
+            None => Ok(fut.await),
+        }
+    }
+}
+
+/// Deadline bounds the calls made within a with block, on the thread entering it, e.g.
+/// `with dispatcher.deadline(30): dispatcher.status("llm")`.
+#[cfg(feature = "python")]
+#[pyclass(unsendable)]
+pub struct Deadline {
+    deadline: Instant,
+    /// deadline of the enclosing block, restored on exit
+    previous: Option<Option<Instant>>,
+}
+
+#[cfg(feature = "python")]
+impl Deadline {
+    pub fn new(timeout: Duration) -> Deadline {
+        Deadline {
+            deadline: Instant::now() + timeout,
+            previous: None,
+        }
+    }
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl Deadline {
+    fn __enter__(mut slf: PyRefMut<'_, Self>) -> PyRefMut<'_, Self> {
+        slf.previous = Some(enter(slf.deadline));
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        _exc_type: Option<&Bound<'_, PyAny>>,
+        _exc_value: Option<&Bound<'_, PyAny>>,
+        _traceback: Option<&Bound<'_, PyAny>>,
+    ) -> bool {
+        if let Some(previous) = self.previous.take() {
+            restore(previous);
+        }
+        false
+    }
+
+    /// remaining returns the seconds left before the deadline, 0 once it passed.
+    fn remaining(&self) -> f64 {
+        self.deadline
+            .saturating_duration_since(Instant::now())
+            .as_secs_f64()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        process::Command,
+        time::{Duration, Instant},
+    };
+
+    use super::{current, remaining, with_deadline};
+    use crate::runner::{self, Mode};
+
+    #[test]
+    fn test_deadline() {
+        assert_eq!(remaining("nothing").unwrap(), None);
+
+        let later = Instant::now() + Duration::from_secs(60);
+        let sooner = Instant::now() + Duration::from_secs(30);
+        with_deadline(Some(sooner), || {
+            // an inner deadline cannot extend the outer one
+            with_deadline(Some(later), || assert_eq!(current(), Some(sooner)));
+            assert!(remaining("a test").unwrap().is_some());
+        });
+        assert_eq!(current(), None);
+
+        with_deadline(Some(Instant::now()), || {
+            let e = remaining("the test").unwrap_err();
+            assert_eq!(e.code(), "DEADLINE_EXCEEDED");
+        });
+
+        let started = Instant::now();
+        let killed = with_deadline(Some(started + Duration::from_millis(200)), || {
+            runner::run(Command::new("sleep").arg("10"), Mode::Capture)
+        });
+        assert_eq!(killed.unwrap_err().kind(), io::ErrorKind::TimedOut);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
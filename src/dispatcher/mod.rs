@@ -1,47 +1,145 @@
 #![allow(dead_code)] // Remove this later
 
 use std::{
-    collections::HashMap,
-    path::PathBuf,
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
     process::Command,
     sync::{Arc, Mutex, OnceLock},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use base64::Engine;
+use dashmap::{mapref::multiple::RefMulti, DashMap};
 use futures::future::join_all;
 use log::{error, info, warn};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 use pyo3::prelude::*;
 use pyo3::{pyclass, pymethods, types::PyDict, Bound, PyAny};
 use regex::Regex;
-use reqwest::Client;
-use serde::{Deserialize, Serialize};
-use tokio::{
-    runtime::{self, Runtime},
-    time::sleep,
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE},
+    Client, Method, Response,
 };
+use serde::{Deserialize, Serialize};
+use tokio::time::sleep;
 
 use crate::{
-    error::ServicingError,
+    alias::{Alias, Candidate, Policy},
+    approval::{self, Action, Protection, Step},
+    autosetup, benchmark,
+    budget::{self, Budget, Spend},
+    client::{self, CompletionStream, Endpoint, OpenAIEndpoint, OutboundRequest},
+    deadline::{self, Deadline},
+    docker::{self, Container},
+    error::{ServicingError, PROBE_FAILED},
+    export,
+    gateway::{Cors, Gateway, RouteOptions, Routes},
     helper,
-    models::{Configuration, UserProvidedConfig},
+    hooks::{self, Hooks},
+    image::{self, ImageBuild, RegistryCredentials, RegistryLogin, Secret},
+    interrupt, introspect,
+    kubernetes::{self, Deployment, Ingress, Mig},
+    layout, leaks, limiter, lint,
+    logic::{self, Observation, State},
+    middleware::{Middleware, Outcome},
+    models::{
+        self, ApiKey, Configuration, HealthCheck, Maintenance, OperationResult, Preemption,
+        ProbeCache, ProbeMethod, ServiceStatus, Timestamp, UpTimings, UserProvidedConfig,
+    },
+    notification::{self, Message, Sink, SinkKind},
+    operation::{self, Operation},
+    orchestrator::{OrchestratorArg, Orchestrators},
+    packaging::Packaging,
+    plan::{self, Plan},
+    policy::{self, Policies},
+    poller::Poller,
+    progress::{Event, Progress, Reason},
+    quota, quote,
+    runner::{self, DryRunRunner, Mode, OutputLog},
+    scaler::{ScaleToZero, Scaler},
+    shared::{self, RuntimeConfig, SharedRuntime},
+    traffic::Traffic,
+    tunnel::{self, OpenTunnel, SshTunnel},
 };
 
-static CACHE_DIR: &str = ".servicing";
 static CACHE_FILE_NAME: &str = "services.bin";
 static CLUSTER_ORCHESTRATOR: &str = "skypilot";
 static SERVICE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
 static REPLICA_UP_CHECK: &str = "no ready replicas";
+static OPENAPI_PATH: &str = "/openapi.json";
+static GATEWAY_PORT: u16 = 8000;
+static CIRCUIT_BREAKER_COOLDOWN: u64 = 30;
+static MAX_TIMINGS: usize = 20;
+static PREEMPTION_CHECK_INTERVAL: u64 = 60;
+static SCALE_TO_ZERO_IDLE_TIMEOUT: u64 = 600;
+static SNAPSHOT_VERSION: u32 = 1;
+/// tag opening the registries saved with a version, followed by the version and the services
+static REGISTRY_MAGIC: &[u8] = b"svcr";
+/// version of the encoding of the services in the registry, bumped whenever Service changes
+static REGISTRY_VERSION: u32 = 1;
+static TEMPLATES_DIR: &str = "templates";
+/// where the named base configurations are kept, in the templates folder
+static BASES_DIR: &str = "bases";
+static API_KEY_ENV: &str = "SERVICING_API_KEY";
+static BENCHMARK_CONCURRENCY: usize = 8;
+static BENCHMARK_DURATION: u64 = 30;
+static BUDGET_CHECK_INTERVAL: u64 = 60;
+static EXPORT_PREFIX: &str = "SVC_";
+/// how often down asks the orchestrator whether the service is gone, right away in the tests
+static DOWN_CHECK_INTERVAL: Duration = Duration::from_secs(if cfg!(test) { 0 } else { 10 });
+static DOWN_WAIT_TIMEOUT: u64 = 600;
+static TTL_CHECK_INTERVAL: u64 = 60;
+/// seconds before the end of its TTL that a service is warned about
+static TTL_WARNING: u64 = 3600;
+static STATUS_POLL_INTERVAL: u64 = 30;
 
 static REGEX_URL: OnceLock<Regex> = OnceLock::new();
 
+/// Entry is a service read from the registry, its shard read-locked while it is held.
+type Entry<'a> = RefMulti<'a, String, Service>;
+
 /// Dispatcher is a struct that is responsible for creating the service configuration and launching
 /// the cluster on a particular cloud provider.
 #[pyclass(subclass)]
 pub struct Dispatcher {
     client: Client,
-    rt: Runtime,
-    service: Arc<Mutex<HashMap<String, Service>>>,
+    rt: Arc<SharedRuntime>,
+    /// registry of the services, locked by shard so that a slow update of one service does not
+    /// hold up the reads of the others
+    service: Arc<DashMap<String, Service>>,
+    aliases: Arc<Mutex<HashMap<String, Alias>>>,
+    gateway: Mutex<Option<Gateway>>,
+    routes: Routes,
+    traffic: Arc<Traffic>,
+    middleware: Arc<Middleware>,
+    scaler: Arc<Scaler>,
+    /// cache of `sky serve status`, while start_status_cache is enabled
+    poller: Arc<Poller>,
+    /// credentials by registry server, never saved
+    registries: Mutex<HashMap<String, RegistryCredentials>>,
+    /// whether up and down only record what they would run, unless told otherwise per call
+    dry_run: bool,
+    last_plan: Mutex<Option<Plan>>,
+    /// output of the last commands run for each service, never saved
+    outputs: Mutex<HashMap<String, Arc<OutputLog>>>,
+    /// validators of the last response of the readiness endpoint of each service
+    probes: Arc<Mutex<HashMap<String, ProbeCache>>>,
+    /// Python readiness checks replacing the probes of the services, never saved
+    ready_checks: Mutex<HashMap<String, Arc<PyObject>>>,
+    /// SSH tunnels by service name
+    tunnels: Mutex<HashMap<String, OpenTunnel>>,
+    /// watcher of the configuration files, while watch_configs is enabled
+    watcher: Mutex<Option<RecommendedWatcher>>,
+    /// hash of each cache file as this dispatcher last loaded or saved it
+    synced: Arc<Mutex<HashMap<PathBuf, String>>>,
+    /// watcher of the directories of those cache files, noticing the saves of other processes
+    cache_watcher: Mutex<Option<RecommendedWatcher>>,
+    hooks: Hooks,
+    /// policies evaluated before every launch, never saved
+    policies: Policies,
+    /// sinks notified of the operations on every service
+    notifications: Mutex<Vec<Sink>>,
 }
 
 #[pyclass]
@@ -52,21 +150,566 @@ struct Service {
     filepath: Option<PathBuf>,
     url: Option<String>,
     up: bool,
+    openapi: Option<String>,
+    latency: Option<Duration>,
+    timings: Vec<UpTimings>,
+    config_hash: Option<String>,
+    /// the fallback placement the last launch succeeded with, None if as configured
+    placement: Option<String>,
+    attempts: u32,
+    preemptions: Vec<Preemption>,
+    /// user and host that created the service
+    owner: Option<Owner>,
+    orchestrator: Orchestrators,
+    health_check: Option<HealthCheck>,
+    probe_method: ProbeMethod,
+    /// bastion the requests to the service go through
+    tunnel: Option<SshTunnel>,
+    /// proxy the requests to the service go through, e.g. socks5h://127.0.0.1:1080
+    proxy: Option<String>,
+    packaging: Option<Packaging>,
+    /// hash of the workdir files staged by the last up
+    workdir_hash: Option<String>,
+    image_build: Option<ImageBuild>,
+    /// reference of the image pushed by the last up
+    image: Option<String>,
+    /// host[:port] that keeps reaching the service across updates, e.g. an elastic IP or an
+    /// existing load balancer in front of the SkyPilot one
+    stable_endpoint: Option<String>,
+    /// whether up issues an API key to the service
+    api_key_enabled: bool,
+    /// key issued by the last up, attached to the requests sent to the service
+    api_key: Option<ApiKey>,
+    budget: Option<Budget>,
+    spend: Spend,
+    /// ingress exposing the service on Kubernetes
+    ingress: Option<Ingress>,
+    /// services of the replica groups, managed along with this one
+    groups: Vec<String>,
+    /// service this one is a replica group of
+    group_of: Option<String>,
+    /// whether the service is only an endpoint, not managed by any orchestrator
+    external: bool,
+    maintenance: Option<Maintenance>,
+    /// when pause suspended the service, None while it runs
+    paused: Option<Timestamp>,
+    /// whether the configuration was changed since the running service was launched
+    needs_update: bool,
+    /// sinks notified of the operations on the service
+    notifications: Vec<Sink>,
+    /// error of the last operation on the service, None if it succeeded
+    last_error: Option<String>,
+    /// id of the last up or down
+    last_operation: Option<String>,
+    /// overrides of the configuration by environment, e.g. fewer replicas in dev
+    overlays: BTreeMap<String, UserProvidedConfig>,
+    /// environment the service was last launched in
+    environment: Option<String>,
+    /// approval its ups require, see protect
+    protection: Option<Protection>,
+    /// up waiting for approval
+    request: Option<approval::Request>,
+    /// requests and decisions on the ups of the service
+    approvals: Vec<Step>,
+}
+
+/// Launch holds what a launch overrides in the rendered configuration.
+#[derive(Default)]
+struct Launch<'a> {
+    /// cloud, region or both
+    placement: Option<&'a str>,
+    /// staged copy of the workdir
+    workdir: Option<&'a Path>,
+    /// container image to run the service from
+    image: Option<&'a str>,
+    /// login to the registry of the image
+    registry: Option<&'a RegistryLogin>,
+    /// key the service should require from its clients
+    api_key: Option<&'a str>,
+}
+
+/// Prepared is what a launch reads from the entry of the service, copied out so that the entry
+/// is not locked while the service is provisioned.
+struct Prepared {
+    orchestrator: Orchestrators,
+    budget: Option<Budget>,
+    /// estimated spend so far
+    spent: f64,
+    /// the fallback placement of the last launch
+    placement: Option<String>,
+    packaging: Option<Packaging>,
+    workdir_hash: Option<String>,
+    image_build: Option<ImageBuild>,
+    api_key_enabled: bool,
+    ingress: Option<Ingress>,
+}
+
+impl Prepared {
+    fn of(service: &Service) -> Prepared {
+        Prepared {
+            orchestrator: service.orchestrator,
+            budget: service.budget.clone(),
+            spent: service.spend.total(models::unix_now()),
+            placement: service.placement.clone(),
+            packaging: service.packaging.clone(),
+            workdir_hash: service.workdir_hash.clone(),
+            image_build: service.image_build.clone(),
+            api_key_enabled: service.api_key_enabled,
+            ingress: service.ingress.clone(),
+        }
+    }
+}
+
+/// Backend is the orchestrator of a service with what it needs to find the service.
+enum Backend {
+    Skypilot,
+    Docker,
+    Kubernetes(Deployment),
+}
+
+impl Backend {
+    fn orchestrator(&self) -> Orchestrators {
+        match self {
+            Backend::Skypilot => Orchestrators::Skypilot,
+            Backend::Docker => Orchestrators::Docker,
+            Backend::Kubernetes(_) => Orchestrators::Kubernetes,
+        }
+    }
+
+    /// down_command returns the command tearing the service down.
+    fn down_command(&self, name: &str, skip_prompt: Option<bool>) -> Command {
+        match self {
+            Backend::Skypilot => sky_serve_down_command(name, skip_prompt),
+            Backend::Docker => docker::down_command(name),
+            Backend::Kubernetes(deployment) => deployment.delete_command(),
+        }
+    }
+
+    /// pause suspends the service without tearing it down, see Dispatcher::pause.
+    fn pause(&self, name: &str) -> Result<(), ServicingError> {
+        match self {
+            Backend::Skypilot => Err(ServicingError::General(format!(
+                "Service {name} runs on SkyPilot and cannot be paused, see set_scale_to_zero"
+            ))),
+            Backend::Docker => docker::pause(name),
+            Backend::Kubernetes(deployment) => deployment.scale(0),
+        }
+    }
+
+    /// resume brings the paused service back, with replicas on Kubernetes.
+    fn resume(&self, name: &str, replicas: u16) -> Result<(), ServicingError> {
+        match self {
+            Backend::Skypilot => Err(ServicingError::General(format!(
+                "Service {name} runs on SkyPilot and cannot be paused"
+            ))),
+            Backend::Docker => docker::resume(name),
+            Backend::Kubernetes(deployment) => deployment.scale(replicas),
+        }
+    }
+
+    /// exists asks the orchestrator whether the service still has live resources.
+    fn exists(&self, name: &str) -> Result<bool, ServicingError> {
+        match self {
+            Backend::Skypilot => helper::sky_service_exists(name),
+            Backend::Docker => docker::exists(name),
+            Backend::Kubernetes(deployment) => deployment.exists(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Owner {
+    user: String,
+    host: String,
+}
+
+/// Untagged is a resource reported by find_untagged.
+#[derive(Serialize)]
+struct Untagged {
+    name: String,
+    /// unmanaged when servicing does not know the service, untagged when it lacks the tags
+    reason: &'static str,
+}
+
+/// EventRow is an event in the history of a service, exported by export.
+#[derive(Serialize)]
+struct EventRow<'a> {
+    service: &'a str,
+    time: Timestamp,
+    /// up, launch, teardown, preemption, maintenance, protected, unprotected, or up requested,
+    /// approved or rejected
+    event: &'static str,
+    detail: Option<String>,
+}
+
+/// ProbeRow is the last probe of a service, exported by export.
+#[derive(Serialize)]
+struct ProbeRow<'a> {
+    service: &'a str,
+    method: ProbeMethod,
+    health_check: Option<&'a str>,
+    ready: Option<bool>,
+    latency_ms: Option<f64>,
+}
+
+/// Heartbeat is the content of the heartbeat file, rewritten at every monitor tick.
+#[derive(Serialize)]
+struct Heartbeat<'a> {
+    timestamp: u64,
+    pid: u32,
+    /// monitor that ticked, preemptions, budget or ttl
+    monitor: &'a str,
+    service: &'a str,
+    services: Vec<ServiceStatus>,
+}
+
+/// V0Service is a service as the first registries, without a version, saved it. It is only
+/// read, to upgrade those registries.
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+struct V0Service {
+    data: Option<V0Config>,
+    template: V0Configuration,
+    filepath: Option<PathBuf>,
+    url: Option<String>,
+    up: bool,
+}
+
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+struct V0Config {
+    port: Option<u16>,
+    replicas: Option<u16>,
+    cloud: Option<String>,
+    workdir: Option<String>,
+    data: Option<String>,
+    disk_size: Option<u16>,
+    cpu: Option<String>,
+    memory: Option<String>,
+    accelerators: Option<String>,
+    setup: Option<String>,
+    run: Option<String>,
+}
+
+/// V0Configuration holds the service and resources of the first Configuration field after field,
+/// as bincode wrote them.
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+struct V0Configuration {
+    readiness_probe: String,
+    replicas: u16,
+    ports: u16,
+    cloud: String,
+    cpus: String,
+    memory: String,
+    disk_size: u16,
+    accelerators: Option<String>,
+    workdir: String,
+    setup: String,
+    run: String,
+}
+
+impl From<V0Service> for Service {
+    fn from(v0: V0Service) -> Self {
+        let t = v0.template;
+        let mut template = Configuration::default();
+        template.service.readiness_probe = t.readiness_probe;
+        template.service.replicas = t.replicas;
+        template.resources.ports = t.ports;
+        template.resources.cloud = t.cloud;
+        template.resources.cpus = t.cpus;
+        template.resources.memory = t.memory;
+        template.resources.disk_size = t.disk_size;
+        template.resources.accelerators = t.accelerators;
+        template.workdir = t.workdir;
+        template.setup = t.setup;
+        template.run = t.run;
+
+        let mut service = Service::new(template);
+        service.data = v0.data.map(|c| UserProvidedConfig {
+            port: c.port,
+            replicas: c.replicas,
+            cloud: c.cloud,
+            workdir: c.workdir,
+            data: c.data,
+            disk_size: c.disk_size,
+            cpu: c.cpu,
+            memory: c.memory,
+            accelerators: c.accelerators,
+            setup: c.setup,
+            run: c.run,
+            ..UserProvidedConfig::default()
+        });
+        service.filepath = v0.filepath;
+        service.url = v0.url;
+        service.up = v0.up;
+        // the creator was not recorded
+        service.owner = None;
+        service
+    }
+}
+
+/// Snapshot is the archive written by Dispatcher::snapshot.
+#[derive(Deserialize, Serialize)]
+struct Snapshot {
+    version: u32,
+    created: u64,
+    /// bincode encoded services, as in the cache
+    registry: Vec<u8>,
+    /// bincode encoded aliases
+    aliases: Vec<u8>,
+    /// configuration files by service name
+    configs: HashMap<String, String>,
+}
+
+impl Service {
+    /// is_ready tells from the body of the readiness endpoint whether the service is ready.
+    fn is_ready(health_check: Option<&HealthCheck>, body: &str) -> bool {
+        !body.to_lowercase().contains(REPLICA_UP_CHECK)
+            && health_check.is_none_or(|check| check.passes(body))
+    }
+
+    /// probe_method is how the readiness endpoint of the service is polled, HEAD only when no
+    /// health check needs the body.
+    fn probe_method(&self) -> ProbeMethod {
+        match (self.probe_method, &self.health_check) {
+            (ProbeMethod::Head, Some(_)) => ProbeMethod::Get,
+            (method, _) => method,
+        }
+    }
+
+    /// check_owner refuses operations on a service created by someone else, unless forced.
+    fn check_owner(&self, name: &str, force: bool) -> Result<(), ServicingError> {
+        match &self.owner {
+            Some(owner) if !force && owner.user != helper::current_user() => {
+                Err(ServicingError::NotOwner(
+                    name.to_string(),
+                    format!("{}@{}", owner.user, owner.host),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// push_timings keeps the timings of the last MAX_TIMINGS up invocations.
+    fn push_timings(&mut self, timings: UpTimings) {
+        self.timings.push(timings);
+        if self.timings.len() > MAX_TIMINGS {
+            self.timings.remove(0);
+        }
+    }
+
+    /// launched returns when the running launch of the service started.
+    fn launched(&self) -> Option<Timestamp> {
+        self.spend
+            .since
+            .or(self.timings.last().map(|timings| timings.started))
+    }
+
+    /// expires_in returns the seconds left before the end of the TTL of the running service,
+    /// None if it has no TTL.
+    fn expires_in(&self, now: u64) -> Option<u64> {
+        let ttl = u64::from(self.data.as_ref()?.ttl_hours?) * 3600;
+        let launched = self.launched()?;
+        Some((launched.0 + ttl).saturating_sub(now))
+    }
+
+    /// was_ready tells whether the service was ready since it was last launched.
+    fn was_ready(&self) -> bool {
+        self.timings
+            .last()
+            .is_some_and(|timings| timings.phases.iter().any(|phase| phase.phase == "ready"))
+    }
+
+    /// state derives the state of the service from its probes and the phase of the up in
+    /// flight, if any.
+    fn state(&self, name: &str) -> State {
+        let launching = operation::recorded(name)
+            .ok()
+            .flatten()
+            .filter(|operation| operation.operation == "up" && operation.in_flight());
+        State::observe(&Observation {
+            up: self.up,
+            has_url: self.url.is_some(),
+            maintenance: self.maintenance.is_some(),
+            paused: self.paused.is_some(),
+            launching: launching.as_ref().map(|operation| operation.phase.as_str()),
+            was_ready: self.was_ready(),
+        })
+    }
+
+    /// backend returns the orchestrator running the service.
+    fn backend(&self, name: &str) -> Backend {
+        match self.orchestrator {
+            Orchestrators::Skypilot => Backend::Skypilot,
+            Orchestrators::Docker => Backend::Docker,
+            Orchestrators::Kubernetes => {
+                Backend::Kubernetes(Deployment::new(name, &self.running_template()))
+            }
+        }
+    }
+
+    /// template_in returns the configuration of the service in environment: its overlay
+    /// applied, if any, and the resources labelled with the environment. Without environment
+    /// it is the configuration as defined.
+    fn template_in(&self, environment: Option<&str>) -> Configuration {
+        let mut template = self.template.clone();
+        if let Some(environment) = environment {
+            if let Some(overlay) = self.overlays.get(environment) {
+                template.update(overlay);
+            }
+            template.resources.labels.insert(
+                logic::ENVIRONMENT_LABEL.to_string(),
+                logic::label_value(environment),
+            );
+        }
+        template
+    }
+
+    /// running_template returns the configuration of the service in the environment it was last
+    /// launched in.
+    fn running_template(&self) -> Configuration {
+        self.template_in(self.environment.as_deref())
+    }
+
+    /// render_in returns the configuration of the service in environment with the file it is
+    /// rendered to, the configuration file of the service without environment.
+    fn render_in(
+        &self,
+        name: &str,
+        environment: Option<&str>,
+    ) -> Result<(Configuration, PathBuf), ServicingError> {
+        let filepath = self
+            .filepath
+            .clone()
+            .ok_or(ServicingError::General("filepath not found".to_string()))?;
+        let Some(environment) = environment else {
+            return Ok((self.template.clone(), filepath));
+        };
+        if !self.overlays.contains_key(environment) {
+            info!(
+                "Service {} has no {} overlay, its configuration is used as is",
+                name, environment
+            );
+        }
+        let template = self.template_in(Some(environment));
+        let path = layout::service_dir(name)?.join(layout::environment_config_file(environment));
+        helper::write_to_file(&path, &template.render()?)?;
+        Ok((template, path))
+    }
+
+    /// policy_input describes to the policies the launch of the service in environment with
+    /// template. It borrows nothing of the service, so that the entry can be let go of while
+    /// the policies run.
+    fn policy_input<'a>(
+        &self,
+        name: &'a str,
+        environment: Option<&'a str>,
+        template: &'a Configuration,
+    ) -> policy::Input<'a> {
+        policy::Input {
+            service: name,
+            orchestrator: self.orchestrator.name(),
+            environment,
+            owner: self
+                .owner
+                .as_ref()
+                .map(|owner| format!("{}@{}", owner.user, owner.host)),
+            config: template,
+        }
+    }
+
+    /// summary returns the state, URL, uptime and spend of the service.
+    fn summary(&self, name: &str) -> ServiceStatus {
+        let now = models::unix_now();
+        let state = self.state(name);
+        let since = self.launched();
+        ServiceStatus {
+            name: name.to_string(),
+            state: state.name().to_string(),
+            url: self.stable_endpoint.clone().or(self.url.clone()),
+            replicas: self.template.service.replicas,
+            ready_replicas: match self.up {
+                true => self.template.service.replicas,
+                false => 0,
+            },
+            uptime: since
+                .filter(|_| self.up)
+                .map(|since| now.saturating_sub(since.0)),
+            cost: self.spend.total(now),
+            hourly_cost: self.spend.hourly_cost,
+            last_error: self.last_error.clone(),
+        }
+    }
+
+    fn new(template: Configuration) -> Self {
+        Service {
+            data: None,
+            template,
+            filepath: None,
+            url: None,
+            up: false,
+            openapi: None,
+            latency: None,
+            timings: Vec::new(),
+            config_hash: None,
+            placement: None,
+            attempts: 0,
+            preemptions: Vec::new(),
+            owner: Some(Owner {
+                user: helper::current_user(),
+                host: helper::current_host(),
+            }),
+            orchestrator: Orchestrators::default(),
+            health_check: None,
+            probe_method: ProbeMethod::default(),
+            tunnel: None,
+            proxy: None,
+            packaging: None,
+            workdir_hash: None,
+            image_build: None,
+            image: None,
+            stable_endpoint: None,
+            api_key_enabled: false,
+            api_key: None,
+            budget: None,
+            spend: Spend::default(),
+            ingress: None,
+            groups: Vec::new(),
+            group_of: None,
+            external: false,
+            maintenance: None,
+            paused: None,
+            needs_update: false,
+            notifications: Vec::new(),
+            last_error: None,
+            last_operation: None,
+            overlays: BTreeMap::new(),
+            environment: None,
+            protection: None,
+            request: None,
+            approvals: Vec::new(),
+        }
+    }
 }
 
 #[pymethods]
 impl Dispatcher {
     #[new]
-    #[pyo3(signature = (*_args, **_kwargs))]
+    #[pyo3(
+        signature = (*_args, **_kwargs),
+        text_signature = "(skip_sky_validation=False, worker_threads=None, dry_run=False, plugins=True)"
+    )]
     pub fn new(
         _args: &Bound<'_, PyAny>,
         _kwargs: Option<&Bound<'_, PyAny>>,
     ) -> Result<Self, ServicingError> {
         // Check if sky_check is True in _kwargs
-        let skip_sky_validation =  _kwargs
+        let skip_sky_validation = _kwargs
             .and_then(|kwargs| kwargs.downcast::<PyDict>().ok())
             .and_then(|dict| dict.get_item("skip_sky_validation").unwrap_or(None))
-            .map(|sky_check| sky_check.is_truthy().unwrap_or(false)).unwrap_or(false);
+            .map(|sky_check| sky_check.is_truthy().unwrap_or(false))
+            .unwrap_or(false);
 
         // Check if the user has installed the required python package
         if !skip_sky_validation && !helper::check_python_package_installed(CLUSTER_ORCHESTRATOR) {
@@ -76,213 +719,3908 @@ impl Dispatcher {
         let re = Regex::new(r"\b(?:\d{1,3}\.){3}\d{1,3}:\d+\b")?;
         let _ = REGEX_URL.get_or_init(|| re);
 
-        let service = Arc::new(Mutex::new(HashMap::new()));
+        let service = Arc::new(DashMap::new());
+
+        // the runtime and client are shared with the other live dispatchers
+        let runtime_option = |key: &str| {
+            _kwargs
+                .and_then(|kwargs| kwargs.downcast::<PyDict>().ok())
+                .and_then(|dict| dict.get_item(key).unwrap_or(None))
+        };
+        let config = RuntimeConfig {
+            worker_threads: runtime_option("worker_threads")
+                .map(|workers| workers.extract::<usize>())
+                .transpose()?,
+            thread_name: runtime_option("thread_name")
+                .map(|name| name.extract::<String>())
+                .transpose()?,
+            max_blocking_threads: runtime_option("max_blocking_threads")
+                .map(|threads| threads.extract::<usize>())
+                .transpose()?,
+            services: fs::read_dir(layout::services_dir()?)?.count(),
+        };
+        let (rt, client) = shared::acquire(&config)?;
+        interrupt::install(&rt);
+
+        // SERVICING_DRY_RUN turns it on for the whole engine, e.g. in training environments
+        let dry_run = _kwargs
+            .and_then(|kwargs| kwargs.downcast::<PyDict>().ok())
+            .and_then(|dict| dict.get_item("dry_run").unwrap_or(None))
+            .map(|dry_run| dry_run.is_truthy())
+            .transpose()?
+            .unwrap_or(false)
+            || plan::enabled_by_env();
+
+        // plugins run around the operations of every dispatcher unless turned off
+        let hooks = Hooks::default();
+        let plugins = _kwargs
+            .and_then(|kwargs| kwargs.downcast::<PyDict>().ok())
+            .and_then(|dict| dict.get_item("plugins").unwrap_or(None))
+            .map(|plugins| plugins.is_truthy())
+            .transpose()?
+            .unwrap_or(true);
+        if plugins {
+            if let Err(e) = hooks.load_entry_points(_args.py()) {
+                warn!("Could not load the servicing plugins: {e}");
+            }
+        }
+
+        // operations journaled by a process that died before finishing them
+        match operation::all() {
+            Ok(operations) => {
+                for interrupted in operations.iter().filter(|o| !o.in_flight()) {
+                    warn!(
+                        "{} {} of service {} was interrupted in phase {}, run recover() once \
+                         the registry is loaded to finish or roll it back",
+                        interrupted.operation,
+                        interrupted.id,
+                        interrupted.service,
+                        interrupted.phase
+                    );
+                }
+            }
+            Err(e) => warn!("Could not read the operation journal: {e}"),
+        }
 
-        // tokio runtime with one dedicated worker
-        let rt = runtime::Builder::new_multi_thread()
-            .worker_threads(1)
-            .thread_name("servicing")
-            .enable_all()
-            .build()?;
+        let notifications = layout::root()
+            .and_then(|dir| notification::load(&dir))
+            .unwrap_or_else(|e| {
+                warn!("Could not load the notification sinks: {e}");
+                Vec::new()
+            });
 
         Ok(Self {
-            client: Client::builder()
-                .pool_max_idle_per_host(0)
-                .timeout(Duration::from_secs(10))
-                .build()?,
+            client,
             rt,
             service,
+            aliases: Arc::new(Mutex::new(HashMap::new())),
+            gateway: Mutex::new(None),
+            routes: Arc::new(Mutex::new(HashMap::new())),
+            traffic: Arc::new(Traffic::default()),
+            middleware: Arc::new(Middleware::default()),
+            scaler: Arc::new(Scaler::default()),
+            poller: Arc::new(Poller::default()),
+            registries: Mutex::new(HashMap::new()),
+            dry_run,
+            last_plan: Mutex::new(None),
+            outputs: Mutex::new(HashMap::new()),
+            probes: Arc::new(Mutex::new(HashMap::new())),
+            ready_checks: Mutex::new(HashMap::new()),
+            tunnels: Mutex::new(HashMap::new()),
+            watcher: Mutex::new(None),
+            synced: Arc::new(Mutex::new(HashMap::new())),
+            cache_watcher: Mutex::new(None),
+            hooks,
+            policies: Policies::default(),
+            notifications: Mutex::new(notifications),
         })
     }
 
-    pub fn add_service(
-        &mut self,
-        name: String,
-        config: Option<UserProvidedConfig>,
-    ) -> Result<(), ServicingError> {
-        // check if service already exists
-        if self.service.lock()?.contains_key(&name) {
-            return Err(ServicingError::ServiceAlreadyExists(name));
+    /// __repr__ summarises the dispatcher, e.g. `Dispatcher(services=2, up=1, aliases=0)`.
+    fn __repr__(&self) -> Result<String, ServicingError> {
+        let up = self.service.iter().filter(|s| s.up).count();
+        let mut repr = format!(
+            "Dispatcher(services={}, up={}, aliases={}",
+            self.service.len(),
+            up,
+            self.aliases.lock()?.len()
+        );
+        if let Some(gateway) = self.gateway.lock()?.as_ref() {
+            repr.push_str(&format!(", gateway={}", gateway.port));
         }
+        repr.push(')');
+        Ok(repr)
+    }
 
-        let mut service = Service {
-            data: None,
-            template: Configuration::default(),
-            filepath: None,
-            url: None,
-            up: false,
-        };
-
-        // Update the configuration with the user provided configuration, if provided
-        if let Some(config) = config {
-            info!("Adding the configuration with the user provided configuration");
-            service.template.update(&config);
-            service.data = Some(config);
+    /// __str__ lists the services with their state, one per line.
+    fn __str__(&self) -> Result<String, ServicingError> {
+        let entries = page(self.service.iter(), None, None);
+        let mut lines = vec![format!("Dispatcher with {} services", entries.len())];
+        for (name, service) in entries.iter().map(|entry| entry.pair()) {
+            let state = service.state(name);
+            match service.maintenance {
+                Some(_) if state != State::Maintenance => {
+                    lines.push(format!("  {name}: {state} (maintenance)"))
+                }
+                _ => lines.push(format!("  {name}: {state}")),
+            }
         }
+        Ok(lines.join("\n"))
+    }
 
-        // create a directory in the user home directory
-        let pwd = helper::create_directory(CACHE_DIR, true)?;
+    /// _repr_html_ renders the services as a table in Jupyter and IPython.
+    fn _repr_html_(&self) -> Result<String, ServicingError> {
+        Ok(models::html_table(&statuses(&self.service)?))
+    }
+
+    /// add_service registers a new service. The orchestrator is an Orchestrators variant or its
+    /// name, SkyPilot by default. With auto_setup, the setup and run commands and the
+    /// accelerators not provided in config are inferred from the files of the workdir.
+    ///
+    /// Unless cuda_setup is False, a setup not provided in config installs the CUDA toolkit
+    /// only if the service has accelerators. A warning is logged when accelerators are requested
+    /// but neither the run command nor the dependencies seem to use a GPU.
+    #[pyo3(signature = (name, config=None, orchestrator=None, auto_setup=None, cuda_setup=None))]
+    pub fn add_service(
+        &mut self,
+        name: String,
+        config: Option<UserProvidedConfig>,
+        orchestrator: Option<OrchestratorArg>,
+        auto_setup: Option<bool>,
+        cuda_setup: Option<bool>,
+    ) -> Result<OperationResult, ServicingError> {
+        layout::check_name("service", &name)?;
+        self.hooked("add", &name, |name| {
+            let started = Instant::now();
+            // check if service already exists
+            if self.service.contains_key(&name) || self.aliases.lock()?.contains_key(&name) {
+                return Err(ServicingError::ServiceAlreadyExists(name));
+            }
 
-        // create a file in the created directory
-        let file = helper::create_file(&pwd, &(name.clone() + "_service.yaml"))?;
+            let orchestrator = match orchestrator {
+                Some(orchestrator) => orchestrator.resolve()?,
+                None => Orchestrators::default(),
+            };
+            let mut service = Service::new(orchestrator.default_template(&templates_dir()?)?);
+            service.orchestrator = orchestrator;
 
-        // write the configuration to the file
-        let content = serde_yaml::to_string(&service.template)?;
-        helper::write_to_file(&file, &content)?;
+            // Update the configuration with the user provided configuration, if provided, over
+            // the bases it extends
+            if let Some(config) = config.map(|c| c.resolve(read_base)).transpose()? {
+                info!("Adding the configuration with the user provided configuration");
+                service.template.update(&config);
+                service.data = Some(config);
+            }
 
-        service.filepath = Some(file);
+            if let Some(true) = auto_setup {
+                let detected = autosetup::detect(&PathBuf::from(&service.template.workdir))?;
+                let provided = service.data.as_ref();
+                let template = &mut service.template;
+                if let (Some(setup), None) =
+                    (detected.setup, provided.and_then(|c| c.setup.as_ref()))
+                {
+                    template.setup = setup;
+                }
+                if let (Some(run), None) = (detected.run, provided.and_then(|c| c.run.as_ref())) {
+                    template.run = run;
+                }
+                if let (Some(accelerators), None) = (
+                    detected.accelerators,
+                    provided.and_then(|c| c.accelerators.as_ref()),
+                ) {
+                    template.resources.accelerators = Some(accelerators);
+                }
+            }
 
-        self.service.lock()?.insert(name, service);
+            let template = &mut service.template;
+            let provided_setup = service.data.as_ref().is_some_and(|c| c.setup.is_some());
+            if cuda_setup.unwrap_or(true) && !provided_setup {
+                let accelerators = template.resources.accelerators.is_some();
+                template.setup = autosetup::adapt_cuda_setup(&template.setup, accelerators);
+            }
+            if let Some(accelerators) = &template.resources.accelerators {
+                if !autosetup::uses_gpu(&PathBuf::from(&template.workdir), &template.run)? {
+                    warn!(
+                        "Service {} requests accelerators {} but does not seem to use a GPU",
+                        name, accelerators
+                    );
+                }
+            }
 
-        Ok(())
+            check_accelerators(&service.template)?;
+            self.register_service(name.clone(), service)?;
+            self.result("add_service", &name, started, Vec::new())
+        })
     }
 
-    pub fn remove_service(&mut self, name: String) -> Result<(), ServicingError> {
-        // check if service is still up
-        let mut service = self.service.lock()?;
-        if let Some(service) = service.get(&name) {
-            if service.up {
-                return Err(ServicingError::ClusterProvisionError(format!(
-                    "Service {} is still up",
-                    name
-                )));
-            }
-            // check if service is not yet up but started
-            if let Some(_) = service.url {
-                return Err(ServicingError::ClusterProvisionError(format!(
-                    "Service {} is starting",
-                    name
-                )));
+    /// add_replica_group adds a group of replicas with resources of their own to the service,
+    /// e.g. CPU replicas for preprocessing next to the GPU ones. The group is launched as the
+    /// service name-group, from the configuration of the service updated with config, and is
+    /// brought up, down and health-checked along with the service.
+    #[pyo3(signature = (name, group, config=None))]
+    pub fn add_replica_group(
+        &mut self,
+        name: String,
+        group: String,
+        config: Option<UserProvidedConfig>,
+    ) -> Result<(), ServicingError> {
+        let member = format!("{name}-{group}");
+        layout::check_name("service", &member)?;
+        if self.service.contains_key(&member) || self.aliases.lock()?.contains_key(&member) {
+            return Err(ServicingError::ServiceAlreadyExists(member));
+        }
+        let mut service = match self.service.get(&name) {
+            Some(parent) if parent.group_of.is_some() => {
+                return Err(ServicingError::General(format!(
+                    "Service {name} is itself a replica group"
+                )))
             }
-            // remove the configuration file
-            if let Some(filepath) = &service.filepath {
-                helper::delete_file(filepath)?;
+            Some(parent) => {
+                let mut service = Service::new(parent.template.clone());
+                service.orchestrator = parent.orchestrator;
+                service.health_check = parent.health_check.clone();
+                service
             }
-        } else {
-            return Err(ServicingError::ServiceNotFound(name));
+            None => return Err(ServicingError::ServiceNotFound(name)),
+        };
+        if let Some(config) = config.map(|c| c.resolve(read_base)).transpose()? {
+            service.template.update(&config);
+            service.data = Some(config);
         }
+        service.group_of = Some(name.clone());
+        self.register_service(member.clone(), service)?;
 
-        // remove from cache
-        service.remove(&name);
+        if let Some(mut parent) = self.service.get_mut(&name) {
+            parent.groups.push(member);
+        }
         Ok(())
     }
 
-    pub fn up(&mut self, name: String, skip_prompt: Option<bool>) -> Result<(), ServicingError> {
-        // get the service configuration
-        if let Some(service) = self.service.lock()?.get_mut(&name) {
-            // check if service is either up or starting
-            if let Some(_) = service.url {
-                return Err(ServicingError::ClusterProvisionError(format!(
-                    "Service {} is starting or already up",
-                    name
-                )));
-            }
+    /// register_external registers a service that is not managed by any orchestrator, e.g. a
+    /// vendor API, reached at url. It is listed, health-checked on readiness_probe (/health by
+    /// default) by status, and can be invoked and aliased like any other service, but not
+    /// brought up or down.
+    #[pyo3(signature = (name, url, readiness_probe=None))]
+    pub fn register_external(
+        &mut self,
+        py: Python<'_>,
+        name: String,
+        url: String,
+        readiness_probe: Option<String>,
+    ) -> Result<(), ServicingError> {
+        layout::check_name("service", &name)?;
+        if self.service.contains_key(&name) || self.aliases.lock()?.contains_key(&name) {
+            return Err(ServicingError::ServiceAlreadyExists(name));
+        }
+        let url = url.trim_end_matches('/').to_string();
+        if url.is_empty() {
+            return Err(ServicingError::General(format!(
+                "No url for external service {name}"
+            )));
+        }
 
-            info!("Launching the service with the configuration: {:?}", name);
-            // launch the cluster
-            let mut cmd = Command::new("sky");
+        let mut service = Service::new(Configuration::default());
+        if let Some(readiness_probe) = readiness_probe {
+            service.template.service.readiness_probe = readiness_probe;
+        }
+        service.url = Some(url);
+        service.external = true;
+        self.service.insert(name.clone(), service);
 
-            cmd.arg("serve").arg("up").arg("-n").arg(&name).arg(
-                service
-                    .filepath
-                    .as_ref()
-                    .ok_or(ServicingError::General("filepath not found".to_string()))?,
-            );
+        // poll once so the service can be picked by aliases right away
+        if let Err(e) = self.status(py, name.clone(), None, None) {
+            warn!("External service {} is not reachable: {e}", name);
+        }
+        Ok(())
+    }
 
-            if let Some(true) = skip_prompt {
-                cmd.arg("-y");
+    pub fn add_service_from_sky_yaml(
+        &mut self,
+        name: String,
+        path: PathBuf,
+    ) -> Result<OperationResult, ServicingError> {
+        layout::check_name("service", &name)?;
+        self.hooked("add", &name, |name| {
+            let started = Instant::now();
+            // check if service already exists
+            if self.service.contains_key(&name) || self.aliases.lock()?.contains_key(&name) {
+                return Err(ServicingError::ServiceAlreadyExists(name));
             }
 
-            let mut child = cmd.spawn()?;
-
-            // ley skypilot handle the CLI interaction
-
-            let output = child.wait()?;
-            if !output.success() {
-                return Err(ServicingError::ClusterProvisionError(format!(
-                    "Cluster provision failed with code {:?}",
-                    output
-                )));
-            }
+            info!("Importing the SkyPilot configuration from {:?}", path);
+            let content = helper::read_from_file(&path)?;
 
-            // get the url of the service
-            let output = Command::new("sky")
-                .arg("serve")
-                .arg("status")
-                .arg(&name)
-                .output()?
-                .stdout;
+            let service = Service::new(Configuration::from_sky_yaml(&content)?);
 
-            // parse the output to get the url
-            let output = String::from_utf8_lossy(&output);
+            self.register_service(name.clone(), service)?;
+            self.result("add_service", &name, started, Vec::new())
+        })
+    }
 
-            let url = REGEX_URL
-                .get()
-                .ok_or(ServicingError::General("Could not get REGEX".to_string()))?
-                .find(&output)
-                .ok_or(ServicingError::General(
-                    "Cannot find service URL".to_string(),
-                ))?
-                .as_str();
+    /// remove_service removes the service from the dispatcher along with its configuration file.
+    /// The orchestrator is asked whether the service still has live resources, in which case the
+    /// removal is refused, as it is when the orchestrator cannot tell. With purge the service is
+    /// torn down first instead, whatever its state.
+    /// Services created by another user can only be removed with force. The replica groups of the
+    /// service are removed with it.
+    #[pyo3(signature = (name, purge=None, force=None))]
+    pub fn remove_service(
+        &self,
+        name: String,
+        purge: Option<bool>,
+        force: Option<bool>,
+    ) -> Result<(), ServicingError> {
+        self.hooked("remove", &name, |name| {
+            let purge = purge.unwrap_or(false);
+            if let Some(service) = self.service.get(&name) {
+                service.check_owner(&name, force.unwrap_or(false))?;
+            }
+            // check if service is still up
+            let (external, backend) = match self.service.get(&name) {
+                Some(service) => (service.external, service.backend(&name)),
+                None => (false, Backend::Skypilot),
+            };
+            match self.service.get(&name) {
+                Some(_) if purge || external => {}
+                Some(service) if service.up => {
+                    return Err(ServicingError::ClusterProvisionError(format!(
+                        "Service {} is still up",
+                        name
+                    )));
+                }
+                // check if service is not yet up but started
+                Some(service) if service.url.is_some() => {
+                    return Err(ServicingError::ClusterProvisionError(format!(
+                        "Service {} is starting",
+                        name
+                    )));
+                }
+                Some(_) => {}
+                None => return Err(ServicingError::ServiceNotFound(name)),
+            }
 
-            service.url = Some(url.to_string());
-            let service_clone = self.service.clone();
-            let client_clone = self.client.clone();
+            // the local flags may be stale, check with the orchestrator
+            if !external && backend.exists(&name)? {
+                if !purge {
+                    return Err(ServicingError::ClusterProvisionError(format!(
+                        "Service {} still has live resources, take it down or remove it with purge",
+                        name
+                    )));
+                }
+                info!("Purging the resources of service {}", name);
+                let mut cmd = backend.down_command(&name, Some(true));
+                let _permit = limiter::acquire(&runner::describe(&cmd))?;
+                let output = runner::run(&mut cmd, Mode::Interactive { echo: true })?.status;
+                if !output.success() {
+                    return Err(ServicingError::ClusterProvisionError(format!(
+                        "Tearing down service {} failed with code {:?}",
+                        name, output
+                    )));
+                }
+            }
 
-            let url = url.to_string() + &service.template.service.readiness_probe;
-
-            // spawn a green thread to check when service comes online, then update the service status
-            let fut = async move {
-                let url = format!("http://{}", url);
-                loop {
-                    match helper::fetch(&client_clone, &url).await {
-                        Ok(resp) => {
-                            if resp.to_lowercase().contains(REPLICA_UP_CHECK) {
-                                sleep(SERVICE_CHECK_INTERVAL).await;
-                                continue;
-                            }
-                            match service_clone.lock() {
-                                Ok(mut service) => {
-                                    if let Some(service) = service.get_mut(&name) {
-                                        service.up = true;
-                                    } else {
-                                        warn!("Service not found");
-                                    }
-                                    info!("Service {} is up", name);
-                                    break;
-                                }
-                                Err(e) => {
-                                    error!("Error fetching the service: {:?}", e);
-                                    break;
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            error!("Error fetching the service endpoint: {:?}", e);
-                            break;
-                        }
+            // remove from cache along with the configuration file
+            let removed = self.service.remove(&name);
+            if let Some((_, service)) = removed {
+                if let Some(filepath) = &service.filepath {
+                    helper::delete_file(filepath)?;
+                }
+                if let Some(parent) = &service.group_of {
+                    if let Some(mut parent) = self.service.get_mut(parent) {
+                        parent.groups.retain(|member| member != &name);
                     }
                 }
-            };
-            self.rt.spawn(fut);
-
-            return Ok(());
-        }
-        Err(ServicingError::ServiceNotFound(name))
+                for member in service.groups {
+                    self.remove_service(member, Some(purge), force)?;
+                }
+            }
+            layout::remove_service_dir(&name)?;
+            self.outputs.lock()?.remove(&name);
+            self.probes.lock()?.remove(&name);
+            self.ready_checks.lock()?.remove(&name);
+            self.tunnels.lock()?.remove(&name);
+            Ok(())
+        })
     }
 
-    pub fn down(
+    /// up launches the service. The configuration on disk is compared with the one servicing
+    /// rendered, a mismatch is logged unless allow_drift is False, in which case it is an error.
+    ///
+    /// When SkyPilot reports a quota or capacity failure, the launch is retried up to retries
+    /// times (by default once per fallback entry), each time with the next entry of fallback. An
+    /// entry is a cloud ("gcp"), a region ("eu-west-1") or both ("gcp:us-central1").
+    ///
+    /// The GPU quota of the target cloud is checked first: a zero quota in the region the
+    /// service is pinned to is an error unless there is a fallback, otherwise it is logged.
+    ///
+    /// With a progress callback, the phases (provision, endpoint, ready) are reported to it
+    /// instead of echoing the SkyPilot output.
+    ///
+    /// With a confirm callback, it is called with the plan of the optimizer (cloud, instance,
+    /// region, accelerators, replicas, hourly_price, hourly_cost) and the service is only
+    /// launched if it returns a truthy value. SkyPilot is then not prompted.
+    ///
+    /// The replica groups of the service are launched after it, the same way.
+    ///
+    /// With dry_run, or in a dispatcher created with dry_run, nothing is built or launched: the
+    /// commands and manifests of the first attempt are recorded instead, see last_plan.
+    ///
+    /// With env, the service is launched in that environment, e.g. "staging": its overlay for
+    /// the environment, see set_overlay, is applied to its configuration, rendered apart, and
+    /// the environment is recorded in the metadata of the service and as a label of its
+    /// resources. The replica groups are launched in the same environment.
+    ///
+    /// The policies, see add_policy, are evaluated against the configuration before anything is
+    /// built or provisioned and their violations refuse the launch, unless override_policies is
+    /// True in which case they are only logged.
+    ///
+    /// A protected service, see protect, is only launched once approved: request_up it instead.
+    /// dry_run is still allowed.
+    #[pyo3(signature = (name, skip_prompt=None, allow_drift=None, retries=None, fallback=None, progress=None, confirm=None, dry_run=None, env=None, override_policies=None))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn up(
         &mut self,
+        py: Python<'_>,
         name: String,
         skip_prompt: Option<bool>,
-        force: Option<bool>,
+        allow_drift: Option<bool>,
+        retries: Option<u32>,
+        fallback: Option<Vec<String>>,
+        progress: Option<PyObject>,
+        confirm: Option<PyObject>,
+        dry_run: Option<bool>,
+        env: Option<String>,
+        override_policies: Option<bool>,
+    ) -> Result<OperationResult, ServicingError> {
+        self.run_up(
+            py,
+            name,
+            skip_prompt,
+            allow_drift,
+            retries,
+            fallback,
+            progress,
+            confirm,
+            dry_run.unwrap_or(self.dry_run),
+            env,
+            override_policies,
+            false,
+        )
+    }
+
+    /// protect requires the ups of the service to be approved, or lifts the requirement with on
+    /// False: up is refused and request_up records a request that approve or reject decides
+    /// on. Any user but the requester may approve, only those of approvers if given, and so may
+    /// anyone holding token. Only the owner of the service can protect it.
+    #[pyo3(signature = (name, on=None, approvers=None, token=None))]
+    pub fn protect(
+        &self,
+        name: String,
+        on: Option<bool>,
+        approvers: Option<Vec<String>>,
+        token: Option<String>,
     ) -> Result<(), ServicingError> {
-        // get the service configuration
-        match self.service.lock()?.get_mut(&name) {
-            Some(service) if service.up || service.url.is_some() => {
-                // Update service status
-                service.url = None;
-                service.up = false;
+        match self.service.get_mut(&name) {
+            Some(mut service) => {
+                service.check_owner(&name, false)?;
+                let on = on.unwrap_or(true);
+                let detail = match (on, &service.request) {
+                    (true, _) => approvers
+                        .as_ref()
+                        .map(|a| format!("approvers {}", a.join(", "))),
+                    (false, Some(request)) => Some(format!(
+                        "dropping the up requested by {}",
+                        request.requester
+                    )),
+                    (false, None) => None,
+                };
+                service.approvals.push(Step {
+                    time: Timestamp::now(),
+                    action: match on {
+                        true => Action::Protected,
+                        false => Action::Unprotected,
+                    },
+                    by: format!("{}@{}", helper::current_user(), helper::current_host()),
+                    detail,
+                });
+                service.protection = match on {
+                    true => Some(Protection::new(
+                        approvers.unwrap_or_default(),
+                        token.as_deref(),
+                    )),
+                    false => {
+                        service.request = None;
+                        None
+                    }
+                };
+                Ok(())
+            }
+            None => Err(ServicingError::ServiceNotFound(name)),
+        }
+    }
+
+    /// request_up asks for the protected service to be launched in env, for reason. The
+    /// request waits for approve or reject, one at a time.
+    #[pyo3(signature = (name, env=None, reason=None))]
+    pub fn request_up(
+        &self,
+        name: String,
+        env: Option<String>,
+        reason: Option<String>,
+    ) -> Result<(), ServicingError> {
+        if let Some(env) = &env {
+            check_environment(env)?;
+        }
+        let mut service = self
+            .service
+            .get_mut(&name)
+            .ok_or(ServicingError::ServiceNotFound(name.clone()))?;
+        if service.protection.is_none() {
+            return Err(ServicingError::General(format!(
+                "Service {name} is not protected, use up"
+            )));
+        }
+        if let Some(request) = &service.request {
+            return Err(ServicingError::General(format!(
+                "An up of service {name} requested by {} is already waiting for approval",
+                request.requester
+            )));
+        }
+        let requester = format!("{}@{}", helper::current_user(), helper::current_host());
+        info!("Up of service {} requested by {}", name, requester);
+        let detail = match (&env, &reason) {
+            (Some(env), Some(reason)) => Some(format!("in {env}: {reason}")),
+            (Some(env), None) => Some(format!("in {env}")),
+            (None, reason) => reason.clone(),
+        };
+        service.approvals.push(Step {
+            time: Timestamp::now(),
+            action: Action::Requested,
+            by: requester.clone(),
+            detail,
+        });
+        service.request = Some(approval::Request {
+            requester,
+            requested: Timestamp::now(),
+            environment: env,
+            reason,
+        });
+        Ok(())
+    }
+
+    /// approve approves the up requested for the protected service and launches it, in the
+    /// environment requested, see up. The approver is another user than the requester, one of
+    /// the approvers if the service has some, or holds its token.
+    #[pyo3(signature = (name, token=None, progress=None))]
+    pub fn approve(
+        &mut self,
+        py: Python<'_>,
+        name: String,
+        token: Option<String>,
+        progress: Option<PyObject>,
+    ) -> Result<OperationResult, ServicingError> {
+        let request = self.decide(&name, token.as_deref(), Action::Approved, None)?;
+        self.run_up(
+            py,
+            name,
+            None,
+            None,
+            None,
+            None,
+            progress,
+            None,
+            self.dry_run,
+            request.environment,
+            None,
+            true,
+        )
+    }
+
+    /// reject turns down the up requested for the protected service, for reason. The same
+    /// users as for approve can reject it.
+    #[pyo3(signature = (name, token=None, reason=None))]
+    pub fn reject(
+        &self,
+        name: String,
+        token: Option<String>,
+        reason: Option<String>,
+    ) -> Result<(), ServicingError> {
+        self.decide(&name, token.as_deref(), Action::Rejected, reason)?;
+        Ok(())
+    }
+
+    /// pending_ups lists the ups waiting for approval as dicts (service, requester, requested,
+    /// environment, reason).
+    pub fn pending_ups(&self) -> Vec<HashMap<String, Option<String>>> {
+        let mut pending: Vec<_> = self
+            .service
+            .iter()
+            .filter_map(|entry| {
+                let request = entry.request.as_ref()?;
+                Some(HashMap::from([
+                    ("service".to_string(), Some(entry.key().clone())),
+                    ("requester".to_string(), Some(request.requester.clone())),
+                    (
+                        "requested".to_string(),
+                        Some(request.requested.rfc3339(None)),
+                    ),
+                    ("environment".to_string(), request.environment.clone()),
+                    ("reason".to_string(), request.reason.clone()),
+                ]))
+            })
+            .collect();
+        pending.sort_by(|a, b| a["requested"].cmp(&b["requested"]));
+        pending
+    }
+
+    /// down tears the service down, force also allows taking down a service created by another
+    /// user or one that is not up. With wait, the orchestrator is polled until it no longer
+    /// reports the service, for at most timeout seconds (600 by default), so that it can be
+    /// brought up again under the same name right away. Purge implies wait: the SkyPilot record
+    /// of the service is purged along with it, and once the service is gone the state cached for
+    /// it (URL, OpenAPI document, latency) and the configurations rendered for its environments
+    /// and for scale to zero are deleted. The replica groups of the service are taken down along
+    /// with it.
+    /// dry_run records the commands instead of running them, as for up.
+    #[pyo3(signature = (name, skip_prompt=None, force=None, purge=None, wait=None, timeout=None, dry_run=None))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn down(
+        &mut self,
+        name: String,
+        skip_prompt: Option<bool>,
+        force: Option<bool>,
+        purge: Option<bool>,
+        wait: Option<bool>,
+        timeout: Option<u64>,
+        dry_run: Option<bool>,
+    ) -> Result<OperationResult, ServicingError> {
+        self.hooked("down", &name, |name| {
+            let started = Instant::now();
+            let groups = self.groups(&name)?;
+            if dry_run.unwrap_or(self.dry_run) {
+                let mut plan = Plan::new("down");
+                self.plan_teardown(&name, skip_prompt, force, &mut plan)?;
+                for member in &groups {
+                    match self.plan_teardown(member, skip_prompt, force, &mut plan) {
+                        Ok(()) | Err(ServicingError::ServiceNotUp(_)) => {}
+                        Err(e) => return Err(e),
+                    }
+                }
+                *self.last_plan.lock()? = Some(plan);
+                return self.result("down", &name, started, groups);
+            }
+            self.logged(&name, "down", || {
+                self.teardown(name.clone(), skip_prompt, force, purge, wait, timeout)
+            })?;
+            // the groups are only up when the whole service is
+            for member in &groups {
+                let teardown = self.logged(member, "down", || {
+                    self.teardown(member.clone(), skip_prompt, force, purge, wait, timeout)
+                });
+                match teardown {
+                    Ok(()) | Err(ServicingError::ServiceNotUp(_)) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            self.result("down", &name, started, groups)
+        })
+    }
+
+    /// status returns the service as JSON with its state, polling a service that is up once to
+    /// see if it still is, or one that was up since its launch to see if it is back, so that a
+    /// crashed service is told from a booting one. The poll does not hold the GIL and works from
+    /// any context, including a thread already running an event loop. Timestamps are RFC 3339,
+    /// in UTC unless tz names another timezone, e.g. "Europe/Zurich".
+    #[pyo3(signature = (name, pretty=None, tz=None))]
+    pub fn status(
+        &mut self,
+        py: Python<'_>,
+        name: String,
+        pretty: Option<bool>,
+        tz: Option<String>,
+    ) -> Result<String, ServicingError> {
+        let timezone = tz.as_deref().map(models::parse_timezone).transpose()?;
+        // if service is up poll once to see if it's still up, or back up if it was ready since
+        // its launch; external ones are always polled
+        let probe = match self.service.get(&name) {
+            // a paused service does not answer until resumed
+            Some(service) if service.paused.is_some() => None,
+            Some(service) => match (
+                service.up || service.external || service.was_ready(),
+                &service.url,
+            ) {
+                (true, Some(url)) => Some((
+                    url.clone(),
+                    service.template.service.readiness_probe.clone(),
+                    service.health_check.clone(),
+                    service.probe_method(),
+                    service.tunnel.clone(),
+                    service.proxy.clone(),
+                )),
+                _ => None,
+            },
+            None => return Err(ServicingError::ServiceNotFound(name)),
+        };
+        let check = self.ready_checks.lock()?.get(&name).cloned();
+        info!("Checking the status of the service: {:?}", name);
+
+        let polled = probe.map(|(url, path, health_check, method, tunnel, proxy)| {
+            let base = self.route(&name, tunnel.as_ref(), &helper::http_url(&url, ""))?;
+            let url = self.route(&name, tunnel.as_ref(), &helper::http_url(&url, &path))?;
+            let client = self.client(proxy.as_deref())?;
+            let probes = self.probes.clone();
+            let service = name.clone();
+            let start = Instant::now();
+            py.allow_threads(|| {
+                helper::block_on(&self.rt, async move {
+                    let ready = match check {
+                        Some(check) => ready_check(check, base).await,
+                        None => {
+                            let mut cache =
+                                probes.lock()?.get(&service).cloned().unwrap_or_default();
+                            let ready = helper::probe(&client, &url, method, &mut cache, |body| {
+                                Service::is_ready(health_check.as_ref(), body)
+                            })
+                            .await;
+                            probes.lock()?.insert(service.clone(), cache);
+                            ready.map_err(|e| ServicingError::General(e.to_string()))
+                        }
+                    };
+                    match ready {
+                        Ok(true) => Ok(start.elapsed()),
+                        Ok(false) => Err(ServicingError::ServiceNotUp(service)),
+                        Err(e) => Err(e),
+                    }
+                })
+            })
+            .and_then(|r| r)
+        });
+
+        let (mut status, groups) = match self.service.get_mut(&name) {
+            Some(mut service) => {
+                match polled {
+                    Some(Ok(latency)) => {
+                        info!("Service {} is up", name);
+                        service.up = true;
+                        service.latency = Some(latency);
+                    }
+                    Some(Err(e)) => {
+                        warn!("{:?}", e);
+                        service.up = false;
+                    }
+                    None => {}
+                }
+                let mut status = models::in_timezone(timezone, || serde_json::to_value(&*service))?;
+                status["state"] = service.state(&name).name().into();
+                (status, service.groups.clone())
+            }
+            None => return Err(ServicingError::ServiceNotFound(name)),
+        };
+        // what SkyPilot last reported, when its status is cached
+        if let Some(output) = self.poller.fresh() {
+            if let Some(row) = logic::serve_statuses(&output)
+                .into_iter()
+                .find(|row| row.name == name)
+            {
+                status["sky"] = serde_json::to_value(row)?;
+            }
+        }
+        // a Kubernetes service is only up while one of its pods is ready
+        let deployment = match self.service.get(&name).map(|s| s.backend(&name)) {
+            Some(Backend::Kubernetes(deployment)) if status["up"] == true => Some(deployment),
+            _ => None,
+        };
+        if let Some(deployment) = deployment {
+            match py.allow_threads(|| deployment.readiness()) {
+                Ok((replicas, ready)) => {
+                    status["kubernetes"] = serde_json::json!({
+                        "replicas": replicas,
+                        "ready": ready,
+                    });
+                    if ready == 0 {
+                        status["up"] = serde_json::Value::Bool(false);
+                    }
+                }
+                Err(e) => warn!("Checking the pods of service {} failed: {e}", name),
+            }
+            if deployment.autoscaled {
+                match py.allow_threads(|| deployment.autoscaler()) {
+                    Ok((current, desired)) => {
+                        status["kubernetes"]["autoscaler"] = serde_json::json!({
+                            "current_replicas": current,
+                            "desired_replicas": desired,
+                        });
+                    }
+                    Err(e) => warn!("Checking the autoscaler of service {} failed: {e}", name),
+                }
+            }
+        }
+
+        // a service with replica groups is only up when all of them are
+        if !groups.is_empty() {
+            let mut statuses = serde_json::Map::new();
+            for member in groups {
+                let member_status: serde_json::Value =
+                    serde_json::from_str(&self.status(py, member.clone(), None, tz.clone())?)?;
+                if member_status["up"] != serde_json::Value::Bool(true) {
+                    status["up"] = serde_json::Value::Bool(false);
+                }
+                statuses.insert(member, member_status);
+            }
+            status["groups"] = serde_json::Value::Object(statuses);
+        }
+
+        Ok(match pretty {
+            Some(true) => serde_json::to_string_pretty(&status)?,
+            _ => serde_json::to_string(&status)?,
+        })
+    }
+
+    /// last_plan returns what the last dry-run up or down would have run as JSON: the operation
+    /// and its steps, each with the service, the command line, the manifest fed to it and the
+    /// content of the files it refers to, such as the rendered configuration.
+    #[pyo3(signature = (pretty=None))]
+    pub fn last_plan(&self, pretty: Option<bool>) -> Result<Option<String>, ServicingError> {
+        match self.last_plan.lock()?.as_ref() {
+            Some(plan) => Ok(Some(match pretty {
+                Some(true) => serde_json::to_string_pretty(plan)?,
+                _ => serde_json::to_string(plan)?,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    /// last_output returns the last 64 KB of output of the commands run for the service by up and
+    /// down, each preceded by its command line, e.g. to show why a launch failed. It is kept in
+    /// memory only, empty for a service nothing ran for yet in this process.
+    pub fn last_output(&self, name: String) -> Result<String, ServicingError> {
+        if !self.service.contains_key(&name) {
+            return Err(ServicingError::ServiceNotFound(name));
+        }
+        Ok(self
+            .outputs
+            .lock()?
+            .get(&name)
+            .map(|log| log.text())
+            .unwrap_or_default())
+    }
+
+    /// status_obj polls the service like status and returns a summary of it, rendered as a table
+    /// in notebooks.
+    pub fn status_obj(
+        &mut self,
+        py: Python<'_>,
+        name: String,
+    ) -> Result<ServiceStatus, ServicingError> {
+        self.status(py, name.clone(), None, None)?;
+        match self.service.get(&name) {
+            Some(service) => Ok(service.summary(&name)),
+            None => Err(ServicingError::ServiceNotFound(name)),
+        }
+    }
+
+    /// watch_configs follows the configuration files of the services on disk. When one is edited
+    /// outside of the dispatcher, the service picks the new configuration up and is flagged as
+    /// needing an update if it is running; a file that does not parse is left for up to report
+    /// as drift. callback, if provided, is called with the service name and whether the edit was
+    /// picked up. enable=False stops watching.
+    #[pyo3(signature = (enable=true, callback=None))]
+    pub fn watch_configs(
+        &self,
+        enable: bool,
+        callback: Option<PyObject>,
+    ) -> Result<(), ServicingError> {
+        let mut watcher = self.watcher.lock()?;
+        if !enable {
+            *watcher = None;
+            return Ok(());
+        }
+
+        let services = self.service.clone();
+        let handler = move |event: notify::Result<notify::Event>| {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    warn!("Watching the configurations failed: {e}");
+                    return;
+                }
+            };
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                return;
+            }
+            for path in &event.paths {
+                let (Some((name, reloaded)), Some(callback)) =
+                    (reload_config(&services, path), &callback)
+                else {
+                    continue;
+                };
+                Python::with_gil(|py| {
+                    if let Err(e) = callback.call1(py, (&name, reloaded)) {
+                        warn!("Configuration watch callback failed: {e}");
+                    }
+                });
+            }
+        };
+        let watch_error = |e: notify::Error| {
+            ServicingError::General(format!("Cannot watch the configurations: {e}"))
+        };
+        let mut new = notify::recommended_watcher(handler).map_err(watch_error)?;
+        let dir = layout::services_dir()?;
+        new.watch(&dir, RecursiveMode::Recursive)
+            .map_err(watch_error)?;
+        info!("Watching the configurations in {:?}", dir);
+        *watcher = Some(new);
+        Ok(())
+    }
+
+    /// monitor_preemptions polls SkyPilot every interval seconds while the service is up and
+    /// records the spot replicas it reports as preempted. callback, if provided, is called with
+    /// the service name and replica id of every new preemption.
+    #[pyo3(signature = (name, interval=None, callback=None))]
+    pub fn monitor_preemptions(
+        &self,
+        name: String,
+        interval: Option<u64>,
+        callback: Option<PyObject>,
+    ) -> Result<(), ServicingError> {
+        match self.service.get(&name) {
+            Some(service) if service.url.is_some() => {}
+            Some(_) => return Err(ServicingError::ServiceNotUp(name)),
+            None => return Err(ServicingError::ServiceNotFound(name)),
+        }
+
+        let interval = Duration::from_secs(interval.unwrap_or(PREEMPTION_CHECK_INTERVAL));
+        let callback = callback.map(Arc::new);
+        let service_clone = self.service.clone();
+        let poller = self.poller.clone();
+        self.rt.spawn(async move {
+            info!("Monitoring service {} for preemptions", name);
+            loop {
+                sleep(interval).await;
+                beat(&service_clone, "preemptions", &name);
+                let output = match poller.fresh() {
+                    Some(output) => output,
+                    None => {
+                        let permit =
+                            match limiter::acquire_async(&format!("sky serve status {name}")).await
+                            {
+                                Ok(permit) => permit,
+                                Err(e) => {
+                                    error!("Error checking service {} for preemptions: {e}", name);
+                                    break;
+                                }
+                            };
+                        let mut cmd = Command::new("sky");
+                        cmd.arg("serve").arg("status").arg(&name);
+                        let output = match runner::run_async(cmd, Mode::Capture).await {
+                            Ok(output) => String::from_utf8_lossy(&output.stdout).to_string(),
+                            Err(e) => {
+                                error!("Error checking service {} for preemptions: {e}", name);
+                                break;
+                            }
+                        };
+                        drop(permit);
+                        output
+                    }
+                };
+
+                let (new, maintenance) = match service_clone.get_mut(&name) {
+                    Some(mut service) if service.url.is_some() => {
+                        let mut new = Vec::new();
+                        for replica in logic::preempted_replicas(&name, &output) {
+                            if service.preemptions.iter().all(|p| p.replica != replica) {
+                                warn!("Replica {} of service {} was preempted", replica, name);
+                                service.preemptions.push(Preemption {
+                                    replica,
+                                    detected: Timestamp::now(),
+                                });
+                                new.push(replica);
+                            }
+                        }
+                        (new, service.maintenance.is_some())
+                    }
+                    // the service went down or was removed
+                    _ => break,
+                };
+
+                if maintenance && !new.is_empty() {
+                    info!("Service {} is under maintenance, not alerting", name);
+                }
+                if let (Some(callback), false, false) = (&callback, new.is_empty(), maintenance) {
+                    // python callbacks need the GIL, keep them off the runtime workers
+                    let callback = callback.clone();
+                    let name = name.clone();
+                    let _ = tokio::task::spawn_blocking(move || {
+                        Python::with_gil(|py| {
+                            for replica in new {
+                                if let Err(e) = callback.call1(py, (&name, replica)) {
+                                    warn!("Preemption callback failed: {e}");
+                                }
+                            }
+                        })
+                    })
+                    .await;
+                }
+            }
+            info!("Stopped monitoring service {} for preemptions", name);
+        });
+        Ok(())
+    }
+
+    /// monitor_budget checks every interval seconds (60 by default) the estimated spend of the
+    /// service against its max_total_cost. When it is crossed, callback is called with the
+    /// service name and the spend, and the service is taken down if the budget says so.
+    #[pyo3(signature = (name, interval=None, callback=None))]
+    pub fn monitor_budget(
+        &self,
+        name: String,
+        interval: Option<u64>,
+        callback: Option<PyObject>,
+    ) -> Result<(), ServicingError> {
+        let backend = match self.service.get(&name).as_deref() {
+            Some(Service { budget: None, .. }) => {
+                return Err(ServicingError::General(format!(
+                    "No budget set for service {name}"
+                )))
+            }
+            Some(service) if service.url.is_some() => service.backend(&name),
+            Some(_) => return Err(ServicingError::ServiceNotUp(name)),
+            None => return Err(ServicingError::ServiceNotFound(name)),
+        };
+
+        let interval = Duration::from_secs(interval.unwrap_or(BUDGET_CHECK_INTERVAL));
+        let callback = callback.map(Arc::new);
+        let service_clone = self.service.clone();
+        let scaler = self.scaler.clone();
+        self.rt.spawn(async move {
+            info!("Monitoring the spend of service {}", name);
+            loop {
+                sleep(interval).await;
+                beat(&service_clone, "budget", &name);
+                let (spent, auto_down, maintenance) = match service_clone.get_mut(&name) {
+                    Some(mut service) if service.url.is_some() => {
+                        let spent = service.spend.total(models::unix_now());
+                        match &service.budget {
+                            Some(budget) if budget.over_total(spent) => {
+                                let auto_down = budget.auto_down;
+                                if auto_down {
+                                    service.url = None;
+                                    service.up = false;
+                                    service.spend.stop(models::unix_now());
+                                }
+                                (spent, auto_down, service.maintenance.is_some())
+                            }
+                            Some(_) => continue,
+                            None => break,
+                        }
+                    }
+                    // the service went down or was removed
+                    _ => break,
+                };
+                warn!(
+                    "Service {} crossed its budget with an estimated spend of ${:.2}",
+                    name, spent
+                );
+
+                if let (Some(callback), false) = (&callback, maintenance) {
+                    // python callbacks need the GIL, keep them off the runtime workers
+                    let callback = callback.clone();
+                    let name = name.clone();
+                    let _ = tokio::task::spawn_blocking(move || {
+                        Python::with_gil(|py| {
+                            if let Err(e) = callback.call1(py, (&name, spent)) {
+                                warn!("Budget callback failed: {e}");
+                            }
+                        })
+                    })
+                    .await;
+                }
+
+                if !auto_down {
+                    break;
+                }
+                info!("Taking service {} down, it is over budget", name);
+                let _ = scaler.disable(&name);
+                let cmd = backend.down_command(&name, Some(true));
+                let result = match limiter::acquire_async(&runner::describe(&cmd)).await {
+                    Ok(_permit) => runner::run_async(cmd, Mode::Interactive { echo: true })
+                        .await
+                        .map_err(ServicingError::from),
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    error!("Error taking service {} down: {e}", name);
+                }
+                break;
+            }
+            info!("Stopped monitoring the spend of service {}", name);
+        });
+        Ok(())
+    }
+
+    /// monitor_ttl checks every interval seconds (60 by default) the age of the service against
+    /// the ttl_hours of its configuration and takes it down once it is older. An hour before,
+    /// a warning is logged. callback is called with the service name, the event, warning or
+    /// expired, and the seconds left.
+    #[pyo3(signature = (name, interval=None, callback=None))]
+    pub fn monitor_ttl(
+        &self,
+        name: String,
+        interval: Option<u64>,
+        callback: Option<PyObject>,
+    ) -> Result<(), ServicingError> {
+        let backend = match self.service.get(&name) {
+            Some(service) if service.data.as_ref().is_none_or(|d| d.ttl_hours.is_none()) => {
+                return Err(ServicingError::General(format!(
+                    "No TTL set for service {name}"
+                )))
+            }
+            Some(service) if service.url.is_some() => service.backend(&name),
+            Some(_) => return Err(ServicingError::ServiceNotUp(name)),
+            None => return Err(ServicingError::ServiceNotFound(name)),
+        };
+
+        let interval = Duration::from_secs(interval.unwrap_or(TTL_CHECK_INTERVAL));
+        let callback = callback.map(Arc::new);
+        let service_clone = self.service.clone();
+        let scaler = self.scaler.clone();
+        self.rt.spawn(async move {
+            info!("Monitoring the TTL of service {}", name);
+            let mut warned = false;
+            loop {
+                sleep(interval).await;
+                beat(&service_clone, "ttl", &name);
+                let left = match service_clone.get_mut(&name) {
+                    Some(mut service) if service.url.is_some() => {
+                        let now = models::unix_now();
+                        match service.expires_in(now) {
+                            Some(0) => {
+                                service.url = None;
+                                service.up = false;
+                                service.spend.stop(now);
+                                0
+                            }
+                            Some(left) if left <= TTL_WARNING && !warned => left,
+                            Some(_) => continue,
+                            None => break,
+                        }
+                    }
+                    // the service went down or was removed
+                    _ => break,
+                };
+                let event = match left {
+                    0 => {
+                        warn!("Service {} reached the end of its TTL", name);
+                        "expired"
+                    }
+                    _ => {
+                        warn!(
+                            "Service {} will be taken down in {} minutes, at the end of its TTL",
+                            name,
+                            left / 60
+                        );
+                        warned = true;
+                        "warning"
+                    }
+                };
+
+                if let Some(callback) = &callback {
+                    // python callbacks need the GIL, keep them off the runtime workers
+                    let callback = callback.clone();
+                    let name = name.clone();
+                    let _ = tokio::task::spawn_blocking(move || {
+                        Python::with_gil(|py| {
+                            if let Err(e) = callback.call1(py, (&name, event, left)) {
+                                warn!("TTL callback failed: {e}");
+                            }
+                        })
+                    })
+                    .await;
+                }
+
+                if left > 0 {
+                    continue;
+                }
+                info!("Taking service {} down, it outlived its TTL", name);
+                let _ = scaler.disable(&name);
+                let cmd = backend.down_command(&name, Some(true));
+                let result = match limiter::acquire_async(&runner::describe(&cmd)).await {
+                    Ok(_permit) => runner::run_async(cmd, Mode::Interactive { echo: true })
+                        .await
+                        .map_err(ServicingError::from),
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    error!("Error taking service {} down: {e}", name);
+                }
+                break;
+            }
+            info!("Stopped monitoring the TTL of service {}", name);
+        });
+        Ok(())
+    }
+
+    /// operations lists the up and down operations recorded for the services, with whether they
+    /// are still in flight or were interrupted with their process.
+    #[pyo3(signature = (pretty=None))]
+    pub fn operations(&self, pretty: Option<bool>) -> Result<String, ServicingError> {
+        let operations: Vec<serde_json::Value> = operation::all()?
+            .into_iter()
+            .map(|o| {
+                let in_flight = o.in_flight();
+                let mut value = serde_json::to_value(o)?;
+                value["in_flight"] = in_flight.into();
+                Ok(value)
+            })
+            .collect::<Result<_, ServicingError>>()?;
+        match pretty {
+            Some(true) => Ok(serde_json::to_string_pretty(&operations)?),
+            _ => Ok(serde_json::to_string(&operations)?),
+        }
+    }
+
+    /// attach picks up the service whose up was interrupted, with its process or by SIGINT, while
+    /// the orchestrator kept provisioning it: the service is looked up with the orchestrator, its
+    /// URL derived again and its readiness monitored as up would, reported to progress, instead
+    /// of taking it down and up again. The interrupted operation is cleared. The URL is returned;
+    /// a service the orchestrator does not run is not up.
+    #[pyo3(signature = (name, progress=None))]
+    pub fn attach(
+        &self,
+        name: String,
+        progress: Option<PyObject>,
+    ) -> Result<String, ServicingError> {
+        match self.service.get(&name) {
+            Some(service) if service.external => return Err(ServicingError::ExternalService(name)),
+            Some(_) => {}
+            None => return Err(ServicingError::ServiceNotFound(name)),
+        }
+        let interrupted = operation::recorded(&name)?;
+        if let Some(previous) = interrupted.as_ref().filter(|o| o.in_flight()) {
+            return Err(ServicingError::OperationInProgress(
+                name,
+                previous.operation.clone(),
+                previous.id.clone(),
+            ));
+        }
+
+        let progress = Arc::new(Progress::new(&name, progress));
+        progress.report("endpoint", Event::Start);
+        let url = match self.serve_state(&name)? {
+            (true, Some(url)) => url,
+            _ => {
+                let e = ServicingError::ServiceNotUp(name.clone());
+                progress.report("endpoint", Event::Failed((&e).into()));
+                return Err(e);
+            }
+        };
+        info!("Attaching to service {} at {}", name, url);
+
+        // the entry is not held while the ingress is applied or progress reported
+        let (ingress, template, untracked) = match self.service.get(&name) {
+            Some(service) => (
+                service.ingress.clone(),
+                service.template.clone(),
+                service.orchestrator == Orchestrators::Skypilot && service.spend.since.is_none(),
+            ),
+            None => return Err(ServicingError::ServiceNotFound(name)),
+        };
+        if let Some(ingress) = &ingress {
+            ingress
+                .apply(&name, &url)
+                .inspect_err(|e| progress.report("endpoint", Event::Failed(e.into())))?;
+        }
+        // the spend was not tracked if up was interrupted before the service was provisioned
+        let hourly_cost = match untracked {
+            true => match layout::root()
+                .and_then(|dir| budget::estimate_hourly_cost(&name, &template, &dir))
+            {
+                Ok(hourly_cost) => Some(hourly_cost),
+                Err(e) => {
+                    info!("Not tracking the spend of service {}: {e}", name);
+                    None
+                }
+            },
+            false => None,
+        };
+        self.record(&name, |service| {
+            if let Some(hourly_cost) = hourly_cost {
+                service.spend.start(hourly_cost, models::unix_now());
+            }
+            service.url = Some(url.clone());
+            service.up = false;
+        })?;
+        progress.report("endpoint", Event::Done);
+        progress.report("ready", Event::Start);
+        self.watch_ready(&name, &url, progress, None)?;
+
+        if let Some(interrupted) = interrupted {
+            interrupted.clear()?;
+        }
+        Ok(url)
+    }
+
+    /// recover finishes or rolls back the up and down operations interrupted with their process
+    /// or by SIGINT, those of the service called name only if provided, according to what
+    /// SkyPilot reports: an interrupted down is run again if the service still runs; an
+    /// interrupted up that got the service running is finished, the status checks taking over,
+    /// unless rollback is set and the service is taken down; one that did not is rolled back.
+    /// The outcome of each operation is returned as JSON.
+    #[pyo3(signature = (name=None, rollback=None, pretty=None))]
+    pub fn recover(
+        &self,
+        name: Option<String>,
+        rollback: Option<bool>,
+        pretty: Option<bool>,
+    ) -> Result<String, ServicingError> {
+        let mut recovered = Vec::new();
+        for interrupted in self.interrupted(name.as_deref())? {
+            let service = &interrupted.service;
+            let (running, url) = self.serve_state(service)?;
+            let take_down =
+                running && (interrupted.operation == "down" || rollback.unwrap_or(false));
+            if take_down {
+                info!(
+                    "Taking service {} down to recover {}",
+                    service, interrupted.id
+                );
+                let backend = match self.service.get(service) {
+                    Some(s) => s.backend(service),
+                    None => Backend::Skypilot,
+                };
+                let mut cmd = backend.down_command(service, Some(true));
+                let _permit = limiter::acquire(&runner::describe(&cmd))?;
+                let status = runner::run(&mut cmd, Mode::Capture)?.status;
+                if !status.success() {
+                    return Err(ServicingError::ClusterProvisionError(format!(
+                        "Tearing down service {service} failed with code {status:?}"
+                    )));
+                }
+            }
+            self.settle(service, running && !take_down, url)?;
+            interrupted.clear()?;
+
+            let outcome = match (interrupted.operation.as_str(), running && !take_down) {
+                ("up", true) | ("down", _) => "finished",
+                _ => "rolled_back",
+            };
+            recovered.push(serde_json::json!({
+                "id": interrupted.id,
+                "service": interrupted.service,
+                "operation": interrupted.operation,
+                "phase": interrupted.phase,
+                "outcome": outcome,
+            }));
+        }
+        match pretty {
+            Some(true) => Ok(serde_json::to_string_pretty(&recovered)?),
+            _ => Ok(serde_json::to_string(&recovered)?),
+        }
+    }
+
+    /// heartbeat_path returns the file rewritten at every tick of monitor_preemptions,
+    /// monitor_budget and monitor_ttl with the time, the process, the monitor that ticked and a
+    /// summary of the services, so that an external watchdog can tell the monitoring loop is
+    /// alive from its modification time or content.
+    pub fn heartbeat_path(&self) -> Result<String, ServicingError> {
+        Ok(layout::heartbeat_path()?.to_string_lossy().to_string())
+    }
+
+    /// set_replicas changes the number of replicas of the service. Like the other set_ methods
+    /// changing the configuration, it re-renders it and a running service is marked as needing
+    /// an update, reported by status.
+    pub fn set_replicas(&self, name: String, replicas: u16) -> Result<(), ServicingError> {
+        self.reconfigure(&name, |template| {
+            template.service.replicas = replicas;
+            Ok(())
+        })
+    }
+
+    /// set_accelerators changes the accelerators of the service, e.g. "A100:1". None removes
+    /// them.
+    #[pyo3(signature = (name, accelerators=None))]
+    pub fn set_accelerators(
+        &self,
+        name: String,
+        accelerators: Option<String>,
+    ) -> Result<(), ServicingError> {
+        self.reconfigure(&name, |template| {
+            template.resources.accelerators = accelerators;
+            check_accelerators(template)
+        })
+    }
+
+    /// set_env sets an environment variable of the service, no value removes it.
+    #[pyo3(signature = (name, key, value=None))]
+    pub fn set_env(
+        &self,
+        name: String,
+        key: String,
+        value: Option<String>,
+    ) -> Result<(), ServicingError> {
+        self.reconfigure(&name, |template| {
+            match value {
+                Some(value) => template.envs.insert(key, value),
+                None => template.envs.remove(&key),
+            };
+            Ok(())
+        })
+    }
+
+    /// start_status_cache runs `sky serve status` for all the services every interval seconds
+    /// (30 by default) in the background and caches its output, which status, replicas,
+    /// find_untagged and monitor_preemptions then answer from instead of forking the CLI. A
+    /// cache that missed a poll is not used. Launches, teardowns and recover always ask
+    /// SkyPilot. Starting again changes the interval.
+    #[pyo3(signature = (interval=None))]
+    pub fn start_status_cache(&self, interval: Option<u64>) -> Result<(), ServicingError> {
+        let interval = Duration::from_secs(interval.unwrap_or(STATUS_POLL_INTERVAL).max(1));
+        self.poller.start(&self.rt, interval)
+    }
+
+    /// stop_status_cache stops polling SkyPilot and drops the cached status, returning whether
+    /// it was running.
+    pub fn stop_status_cache(&self) -> Result<bool, ServicingError> {
+        self.poller.stop()
+    }
+
+    /// replicas returns the replicas SkyPilot reports for the service: their id, status,
+    /// region and endpoint.
+    #[pyo3(signature = (name, pretty=None))]
+    pub fn replicas(&self, name: String, pretty: Option<bool>) -> Result<String, ServicingError> {
+        if !self.service.contains_key(&name) {
+            return Err(ServicingError::ServiceNotFound(name));
+        }
+        let replicas: Vec<_> = logic::replica_statuses(&self.serve_status(Some(&name))?)
+            .into_iter()
+            .filter(|replica| replica.service == name)
+            .collect();
+        Ok(match pretty {
+            Some(true) => serde_json::to_string_pretty(&replicas)?,
+            _ => serde_json::to_string(&replicas)?,
+        })
+    }
+
+    /// preemptions returns the preemptions recorded for the service, oldest first.
+    pub fn preemptions(&self, name: String) -> Result<String, ServicingError> {
+        match self.service.get(&name) {
+            Some(service) => Ok(serde_json::to_string(&service.preemptions)?),
+            None => Err(ServicingError::ServiceNotFound(name)),
+        }
+    }
+
+    /// find_untagged spots the resources that escaped management: the SkyPilot services that are
+    /// not in the registry, and the registered services whose configuration lacks the
+    /// managed-by tag, having been added before servicing tagged the resources it creates.
+    #[pyo3(signature = (pretty=None))]
+    pub fn find_untagged(&self, pretty: Option<bool>) -> Result<String, ServicingError> {
+        let output = self.serve_status(None)?;
+
+        let mut untagged: Vec<Untagged> = logic::serve_services(&output)
+            .into_iter()
+            .filter(|name| !self.service.contains_key(name))
+            .map(|name| Untagged {
+                name,
+                reason: "unmanaged",
+            })
+            .collect();
+        untagged.extend(
+            self.service
+                .iter()
+                .filter(|s| {
+                    !s.external
+                        && s.template
+                            .resources
+                            .labels
+                            .get(logic::MANAGED_BY_LABEL)
+                            .map(String::as_str)
+                            != Some(logic::MANAGED_BY)
+                })
+                .map(|s| Untagged {
+                    name: s.key().clone(),
+                    reason: "untagged",
+                }),
+        );
+        untagged.sort_by(|a, b| a.name.cmp(&b.name));
+        match pretty {
+            Some(true) => Ok(serde_json::to_string_pretty(&untagged)?),
+            _ => Ok(serde_json::to_string(&untagged)?),
+        }
+    }
+
+    /// leaks cross-references the SkyPilot services and clusters, the docker containers and the
+    /// Kubernetes objects labelled by servicing against the registry, and reports those tracked
+    /// by no service along with the sources that could not be listed.
+    #[pyo3(signature = (pretty=None))]
+    pub fn leaks(&self, pretty: Option<bool>) -> Result<String, ServicingError> {
+        let services: Vec<String> = self.service.iter().map(|s| s.key().clone()).collect();
+        let report = leaks::scan(&services);
+        for leak in &report.leaks {
+            warn!("{} {} is tracked by no service", leak.kind, leak.name);
+        }
+        match pretty {
+            Some(true) => Ok(serde_json::to_string_pretty(&report)?),
+            _ => Ok(serde_json::to_string(&report)?),
+        }
+    }
+
+    /// set_health_check validates the JSON body of the readiness endpoint with a JSONPath
+    /// expression, e.g. `$.model_loaded == true`, on top of SkyPilot having a ready replica.
+    /// expected, if provided, is the JSON value the path must hold; without it the value must be
+    /// truthy. No expression removes the check.
+    #[pyo3(signature = (name, expression=None, expected=None))]
+    pub fn set_health_check(
+        &self,
+        name: String,
+        expression: Option<String>,
+        expected: Option<String>,
+    ) -> Result<(), ServicingError> {
+        let check = expression
+            .map(|expression| HealthCheck::parse(&expression, expected))
+            .transpose()?;
+        match self.service.get_mut(&name) {
+            Some(mut service) => {
+                service.health_check = check;
+                Ok(())
+            }
+            None => Err(ServicingError::ServiceNotFound(name)),
+        }
+    }
+
+    /// set_probe_method sets how the readiness endpoint of the service is polled, to spare
+    /// services billing per request: "get" (the default) fetches the body every time, "head"
+    /// only the status code, falling back to GET when HEAD is not supported or a health check
+    /// needs the body, and "conditional" only fetches the body when its ETag or Last-Modified
+    /// changed.
+    #[pyo3(signature = (name, method=None))]
+    pub fn set_probe_method(
+        &self,
+        name: String,
+        method: Option<String>,
+    ) -> Result<(), ServicingError> {
+        let method = method
+            .map(|method| method.parse())
+            .transpose()?
+            .unwrap_or_default();
+        match self.service.get_mut(&name) {
+            Some(mut service) => service.probe_method = method,
+            None => return Err(ServicingError::ServiceNotFound(name)),
+        }
+        self.probes.lock()?.remove(&name);
+        Ok(())
+    }
+
+    /// set_ready_check replaces the readiness probe of the service with check, a Python callable
+    /// called with the URL of the service and returning whether it is ready, for services whose
+    /// health can only be judged by a domain-specific request. It is called on a thread of its
+    /// own rather than the runtime workers; an exception fails the probe, like an unreachable
+    /// endpoint. Checks are not saved with the services. No check restores the HTTP probe.
+    #[pyo3(signature = (name, check=None))]
+    pub fn set_ready_check(
+        &self,
+        py: Python<'_>,
+        name: String,
+        check: Option<PyObject>,
+    ) -> Result<(), ServicingError> {
+        if !self.service.contains_key(&name) {
+            return Err(ServicingError::ServiceNotFound(name));
+        }
+        match check {
+            Some(check) if !check.bind(py).is_callable() => Err(ServicingError::General(format!(
+                "The ready check of service {name} must be callable"
+            ))),
+            Some(check) => {
+                self.ready_checks.lock()?.insert(name, Arc::new(check));
+                Ok(())
+            }
+            None => {
+                self.ready_checks.lock()?.remove(&name);
+                Ok(())
+            }
+        }
+    }
+
+    /// set_tunnel routes the health checks and requests of a service listening on a private
+    /// network through an SSH bastion: user@host, with the private key file key and the SSH
+    /// port port (22 by default). No host removes the tunnel.
+    #[pyo3(signature = (name, host=None, user=None, key=None, port=None))]
+    pub fn set_tunnel(
+        &self,
+        name: String,
+        host: Option<String>,
+        user: Option<String>,
+        key: Option<String>,
+        port: Option<u16>,
+    ) -> Result<(), ServicingError> {
+        let tunnel = host.map(|host| SshTunnel {
+            host,
+            user: user.unwrap_or_else(helper::current_user),
+            key,
+            port,
+        });
+        match self.service.get_mut(&name) {
+            Some(mut service) => service.tunnel = tunnel,
+            None => return Err(ServicingError::ServiceNotFound(name)),
+        }
+        self.tunnels.lock()?.remove(&name);
+        Ok(())
+    }
+
+    /// set_proxy sends the health checks and requests of the service through proxy, e.g.
+    /// socks5h://jump:1080 for a service only reachable through a jump network, leaving the
+    /// other services alone. SOCKS5 and HTTP(S) proxies are supported, None removes the proxy.
+    #[pyo3(signature = (name, proxy=None))]
+    pub fn set_proxy(&self, name: String, proxy: Option<String>) -> Result<(), ServicingError> {
+        if let Some(proxy) = &proxy {
+            shared::proxied(proxy)?;
+        }
+        match self.service.get_mut(&name) {
+            Some(mut service) => {
+                service.proxy = proxy;
+                Ok(())
+            }
+            None => Err(ServicingError::ServiceNotFound(name)),
+        }
+    }
+
+    /// set_scale_to_zero scales the running service down to zero replicas once no request was
+    /// sent to it through invoke, chat, completions or the gateway for idle_timeout seconds. The
+    /// next request scales it back up and waits until the service is ready before going through.
+    #[pyo3(signature = (name, enabled=None, idle_timeout=None))]
+    pub fn set_scale_to_zero(
+        &self,
+        name: String,
+        enabled: Option<bool>,
+        idle_timeout: Option<u64>,
+    ) -> Result<(), ServicingError> {
+        if let Some(false) = enabled {
+            if !self.scaler.disable(&name)? {
+                return Err(ServicingError::General(format!(
+                    "Service {} is not in scale-to-zero mode",
+                    name
+                )));
+            }
+            return Ok(());
+        }
+
+        let (up_config, url) = match self.service.get(&name).as_deref() {
+            Some(Service {
+                filepath: Some(filepath),
+                url: Some(url),
+                template,
+                ..
+            }) => (
+                filepath.clone(),
+                helper::http_url(url, &template.service.readiness_probe),
+            ),
+            Some(_) => return Err(ServicingError::ServiceNotUp(name)),
+            None => return Err(ServicingError::ServiceNotFound(name)),
+        };
+
+        // derive the zero replica configuration from the one the service was launched with
+        let mut zero: serde_yaml::Value =
+            serde_yaml::from_str(&helper::read_from_file(&up_config)?)?;
+        if let Some(service) = zero.get_mut("service").and_then(|s| s.as_mapping_mut()) {
+            service.remove("replica_policy");
+            service.insert("replicas".into(), 0.into());
+        }
+        let zero_config = layout::service_dir(&name)?.join(layout::ZERO_CONFIG_FILE);
+        helper::write_to_file(&zero_config, &serde_yaml::to_string(&zero)?)?;
+
+        self.scaler.enable(
+            &self.rt,
+            &name,
+            ScaleToZero {
+                up_config,
+                zero_config,
+                readiness_url: url,
+                idle_timeout: Duration::from_secs(
+                    idle_timeout.unwrap_or(SCALE_TO_ZERO_IDLE_TIMEOUT),
+                ),
+                up_check: REPLICA_UP_CHECK,
+            },
+        )
+    }
+
+    /// doctor checks the prerequisites of the orchestrator, or of all of them, and returns a JSON
+    /// list of checks (orchestrator, check, ok, detail, hint) with a hint for every failed one.
+    #[pyo3(signature = (orchestrator=None, pretty=None))]
+    pub fn doctor(
+        &self,
+        py: Python<'_>,
+        orchestrator: Option<OrchestratorArg>,
+        pretty: Option<bool>,
+    ) -> Result<String, ServicingError> {
+        let orchestrators = match orchestrator {
+            Some(orchestrator) => vec![orchestrator.resolve()?],
+            None => Orchestrators::ALL.to_vec(),
+        };
+        let checks: Vec<_> = py.allow_threads(|| {
+            orchestrators
+                .iter()
+                .flat_map(Orchestrators::doctor)
+                .collect()
+        });
+        Ok(match pretty {
+            Some(true) => serde_json::to_string_pretty(&checks)?,
+            _ => serde_json::to_string(&checks)?,
+        })
+    }
+
+    /// describe returns the API of the installed version as JSON, for wrappers generating their
+    /// completions and help text: the version, every public method with its documentation and
+    /// parameters, the values of the choice parameters and the capabilities of the orchestrators.
+    #[pyo3(signature = (pretty=None))]
+    pub fn describe(&self, py: Python<'_>, pretty: Option<bool>) -> Result<String, ServicingError> {
+        let api = introspect::describe(&py.get_type_bound::<Dispatcher>())?;
+        Ok(match pretty {
+            Some(true) => serde_json::to_string_pretty(&api)?,
+            _ => serde_json::to_string(&api)?,
+        })
+    }
+
+    /// validate lints the setup and run scripts of the service before anything is provisioned:
+    /// unquoted variables, package managers left to prompt for confirmation and files missing
+    /// from the workdir. It returns the findings as a JSON list, empty when none.
+    #[pyo3(signature = (name, pretty=None))]
+    pub fn validate(&self, name: String, pretty: Option<bool>) -> Result<String, ServicingError> {
+        let lints = match self.service.get(&name) {
+            Some(service) => lint::lint(&service.template),
+            None => return Err(ServicingError::ServiceNotFound(name)),
+        };
+        Ok(match pretty {
+            Some(true) => serde_json::to_string_pretty(&lints)?,
+            _ => serde_json::to_string(&lints)?,
+        })
+    }
+
+    /// set_default_template replaces the configuration new services of the orchestrator start
+    /// from, for every dispatcher of the user. The YAML overrides any part of the built-in
+    /// default, e.g. the setup script, the cloud or the replicas; None restores the built-in one.
+    #[pyo3(signature = (orchestrator, yaml=None))]
+    pub fn set_default_template(
+        &self,
+        orchestrator: OrchestratorArg,
+        yaml: Option<String>,
+    ) -> Result<(), ServicingError> {
+        let orchestrator = orchestrator.resolve()?;
+        let dir = templates_dir()?;
+        let path = orchestrator.template_path(&dir);
+        match yaml {
+            Some(yaml) => {
+                Configuration::from_template(&yaml)?;
+                fs::create_dir_all(&dir)?;
+                helper::write_to_file(&path, &yaml)
+            }
+            None if path.exists() => helper::delete_file(&path),
+            None => Ok(()),
+        }
+    }
+
+    /// define_base saves config as the base configuration called name, for every dispatcher of
+    /// the user, None removing it. A configuration extending it with base=name is resolved when
+    /// its service is added: the default template of the orchestrator comes first, then the base
+    /// of the base and so on, then the base and last the configuration itself, each overriding
+    /// the fields the previous ones set. The labels and node selectors are merged instead, the
+    /// containers and tolerations replaced as a whole. Services already added keep the
+    /// configuration they were rendered with.
+    #[pyo3(signature = (name, config=None))]
+    pub fn define_base(
+        &self,
+        name: String,
+        config: Option<UserProvidedConfig>,
+    ) -> Result<(), ServicingError> {
+        let path = base_path(&name)?;
+        match config {
+            Some(config) => {
+                config.check()?;
+                // refuse unknown bases and cycles before any service runs into them
+                config.resolve(|base| match base == name {
+                    true => Ok(Some(config.clone())),
+                    false => read_base(base),
+                })?;
+                fs::create_dir_all(templates_dir()?.join(BASES_DIR))?;
+                helper::write_to_file(&path, &serde_json::to_string_pretty(&config)?)
+            }
+            None if path.exists() => helper::delete_file(&path),
+            None => Ok(()),
+        }
+    }
+
+    /// bases returns the names of the base configurations, sorted.
+    pub fn bases(&self) -> Result<Vec<String>, ServicingError> {
+        let dir = templates_dir()?.join(BASES_DIR);
+        if !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut names = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                if let Some(name) = path.file_stem() {
+                    names.push(name.to_string_lossy().to_string());
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// resolved_config returns the configuration the service was rendered from, its bases
+    /// applied, an empty one for a service added without configuration. With env, the overlay
+    /// of the service for that environment is applied on top.
+    #[pyo3(signature = (name, env=None))]
+    pub fn resolved_config(
+        &self,
+        name: String,
+        env: Option<String>,
+    ) -> Result<UserProvidedConfig, ServicingError> {
+        match self.service.get(&name) {
+            Some(service) => {
+                let config = service.data.clone().unwrap_or_default();
+                match env.as_ref().and_then(|env| service.overlays.get(env)) {
+                    Some(overlay) => Ok(UserProvidedConfig {
+                        base: config.base.clone(),
+                        ..config.overlay(overlay)
+                    }),
+                    None => Ok(config),
+                }
+            }
+            None => Err(ServicingError::ServiceNotFound(name)),
+        }
+    }
+
+    /// set_overlay sets the overrides of the configuration of the service in the environment
+    /// env, e.g. fewer replicas and CPUs in "dev", applied by up(name, env=env) over the
+    /// configuration of the service; None removes them. The overlay may extend a base
+    /// configuration, resolved now.
+    #[pyo3(signature = (name, env, config=None))]
+    pub fn set_overlay(
+        &self,
+        name: String,
+        env: String,
+        config: Option<UserProvidedConfig>,
+    ) -> Result<(), ServicingError> {
+        check_environment(&env)?;
+        let config = config.map(|c| c.resolve(read_base)).transpose()?;
+        match self.service.get_mut(&name) {
+            Some(mut service) => {
+                match config {
+                    Some(config) => {
+                        config.check()?;
+                        service.overlays.insert(env, config);
+                    }
+                    None => {
+                        service.overlays.remove(&env);
+                    }
+                }
+                Ok(())
+            }
+            None => Err(ServicingError::ServiceNotFound(name)),
+        }
+    }
+
+    /// quote returns a JSON list of the clouds and instance types able to run config, with
+    /// their estimated hourly price, cheapest first. The cloud is left open unless config sets
+    /// one, so the candidates can be compared before committing to a cloud.
+    #[pyo3(signature = (config=None, pretty=None))]
+    pub fn quote(
+        &self,
+        py: Python<'_>,
+        config: Option<UserProvidedConfig>,
+        pretty: Option<bool>,
+    ) -> Result<String, ServicingError> {
+        let config = config.map(|c| c.resolve(read_base)).transpose()?;
+        let mut template = Orchestrators::default().default_template(&templates_dir()?)?;
+        if let Some(config) = &config {
+            template.update(config);
+        }
+        let pin_cloud = config.is_some_and(|c| c.cloud.is_some());
+        let dir = layout::root()?;
+        let quotes = py.allow_threads(|| quote::quote(&template, pin_cloud, &dir))?;
+        Ok(match pretty {
+            Some(true) => serde_json::to_string_pretty(&quotes)?,
+            _ => serde_json::to_string(&quotes)?,
+        })
+    }
+
+    /// set_ingress exposes the service on Kubernetes through an ingress routing host and path
+    /// (/ by default) to it, with TLS from tls_secret if provided. The ingress is created by up
+    /// and removed by down, in namespace or the one of the pods. No host removes the ingress.
+    #[pyo3(signature = (name, host=None, path=None, tls_secret=None, ingress_class=None, namespace=None))]
+    pub fn set_ingress(
+        &self,
+        name: String,
+        host: Option<String>,
+        path: Option<String>,
+        tls_secret: Option<String>,
+        ingress_class: Option<String>,
+        namespace: Option<String>,
+    ) -> Result<(), ServicingError> {
+        let path = path.unwrap_or_else(|| "/".to_string());
+        if !path.starts_with('/') {
+            return Err(ServicingError::General(format!(
+                "Ingress path must start with /, got {path}"
+            )));
+        }
+        match self.service.get_mut(&name) {
+            Some(mut service) => {
+                let namespace = namespace.or_else(|| {
+                    service
+                        .template
+                        .kubernetes
+                        .as_ref()
+                        .and_then(|k| k.namespace.clone())
+                });
+                service.ingress = host.map(|host| Ingress {
+                    host,
+                    path,
+                    tls_secret,
+                    class: ingress_class,
+                    namespace,
+                });
+                Ok(())
+            }
+            None => Err(ServicingError::ServiceNotFound(name)),
+        }
+    }
+
+    /// set_maintenance marks the service as intentionally degraded, or back to normal with on
+    /// False. While under maintenance, monitors do not call their alert callbacks, status shows
+    /// the maintenance and its reason, aliases skip the service and the gateway answers 503 with
+    /// the reason.
+    #[pyo3(signature = (name, on=None, reason=None))]
+    pub fn set_maintenance(
+        &self,
+        name: String,
+        on: Option<bool>,
+        reason: Option<String>,
+    ) -> Result<(), ServicingError> {
+        match self.service.get_mut(&name) {
+            Some(mut service) => {
+                service.maintenance = match on.unwrap_or(true) {
+                    true => {
+                        info!("Service {} is under maintenance", name);
+                        Some(Maintenance {
+                            reason,
+                            since: Timestamp::now(),
+                        })
+                    }
+                    false => None,
+                };
+                Ok(())
+            }
+            None => Err(ServicingError::ServiceNotFound(name)),
+        }
+    }
+
+    /// pause suspends a Docker or Kubernetes service without tearing it down, for it to be
+    /// resumed right away: its container is frozen with docker pause, keeping the state of its
+    /// processes, or its Deployment scaled to zero replicas. A paused service is not polled and
+    /// shows as paused until resume, down still tears it down. SkyPilot services cannot be
+    /// paused, set_scale_to_zero lets them sleep instead.
+    pub fn pause(&self, py: Python<'_>, name: String) -> Result<(), ServicingError> {
+        let backend = match self.service.get(&name) {
+            Some(service) if service.external => return Err(ServicingError::ExternalService(name)),
+            Some(service) if service.url.is_none() => {
+                return Err(ServicingError::ServiceNotUp(name))
+            }
+            Some(service) if service.paused.is_some() => return Ok(()),
+            Some(service) => service.backend(&name),
+            None => return Err(ServicingError::ServiceNotFound(name)),
+        };
+        info!("Pausing service {}", name);
+        py.allow_threads(|| backend.pause(&name))?;
+        if let Some(mut service) = self.service.get_mut(&name) {
+            service.up = false;
+            service.paused = Some(Timestamp::now());
+        }
+        Ok(())
+    }
+
+    /// resume brings back a service suspended by pause: its container is unpaused, or its
+    /// Deployment scaled back to the replicas of its configuration, the minimum of its
+    /// autoscaler if it has one. status tells when it is ready again.
+    pub fn resume(&self, py: Python<'_>, name: String) -> Result<(), ServicingError> {
+        let (backend, replicas) = match self.service.get(&name) {
+            Some(service) if service.paused.is_none() => {
+                return Err(ServicingError::General(format!(
+                    "Service {name} is not paused"
+                )))
+            }
+            Some(service) => (
+                service.backend(&name),
+                kubernetes::replicas(&service.running_template()),
+            ),
+            None => return Err(ServicingError::ServiceNotFound(name)),
+        };
+        info!("Resuming service {}", name);
+        py.allow_threads(|| backend.resume(&name, replicas))?;
+        if let Some(mut service) = self.service.get_mut(&name) {
+            service.paused = None;
+        }
+        Ok(())
+    }
+
+    /// set_budget caps the cost of the service in dollars: up refuses configurations estimated
+    /// over max_hourly_cost, or once the estimated spend reached max_total_cost. With auto_down,
+    /// monitor_budget takes the service down when the spend crosses max_total_cost. No limits
+    /// remove the budget.
+    #[pyo3(signature = (name, max_hourly_cost=None, max_total_cost=None, auto_down=None))]
+    pub fn set_budget(
+        &self,
+        name: String,
+        max_hourly_cost: Option<f64>,
+        max_total_cost: Option<f64>,
+        auto_down: Option<bool>,
+    ) -> Result<(), ServicingError> {
+        match self.service.get_mut(&name) {
+            Some(mut service) => {
+                service.budget = match (max_hourly_cost, max_total_cost) {
+                    (None, None) => None,
+                    _ => Some(Budget {
+                        max_hourly_cost,
+                        max_total_cost,
+                        auto_down: auto_down.unwrap_or(false),
+                    }),
+                };
+                Ok(())
+            }
+            None => Err(ServicingError::ServiceNotFound(name)),
+        }
+    }
+
+    /// spend returns the estimated spend of the service in dollars, across the launches its
+    /// cost could be estimated for.
+    pub fn spend(&self, name: String) -> Result<f64, ServicingError> {
+        match self.service.get(&name) {
+            Some(service) => Ok(service.spend.total(models::unix_now())),
+            None => Err(ServicingError::ServiceNotFound(name)),
+        }
+    }
+
+    /// cost_report sums the estimated spend of every service between since and until, by
+    /// service, owner or the value of the resource label named label, for chargeback. Times are
+    /// RFC 3339 or YYYY-MM-DD dates, midnight in tz (UTC by default); the report covers the
+    /// current calendar month by default. It is returned as JSON, or as CSV lines with format.
+    #[pyo3(signature = (since=None, until=None, group_by=None, label=None, tz=None, format=None, pretty=None))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn cost_report(
+        &self,
+        since: Option<String>,
+        until: Option<String>,
+        group_by: Option<String>,
+        label: Option<String>,
+        tz: Option<String>,
+        format: Option<String>,
+        pretty: Option<bool>,
+    ) -> Result<String, ServicingError> {
+        let timezone = tz.as_deref().map(models::parse_timezone).transpose()?;
+        let since = match since {
+            Some(since) => Timestamp::parse(&since, timezone)?,
+            None => Timestamp::month_start(timezone),
+        };
+        let until = match until {
+            Some(until) => Timestamp::parse(&until, timezone)?,
+            None => Timestamp::now(),
+        };
+        let group_by = group_by.unwrap_or("service".to_string());
+
+        let entries: Vec<Entry> = self.service.iter().collect();
+        let mut spends = Vec::new();
+        for (name, service) in entries.iter().map(|entry| entry.pair()) {
+            let group = match (group_by.as_str(), &label) {
+                ("service", _) => name.clone(),
+                ("owner", _) => match &service.owner {
+                    Some(owner) => owner.user.clone(),
+                    None => "unknown".to_string(),
+                },
+                ("label", Some(label)) => match service.template.resources.labels.get(label) {
+                    Some(value) => value.clone(),
+                    None => "unlabelled".to_string(),
+                },
+                ("label", None) => {
+                    return Err(ServicingError::General(
+                        "Grouping by label needs the label name".to_string(),
+                    ))
+                }
+                (other, _) => {
+                    return Err(ServicingError::General(format!(
+                        "Unknown grouping {other}, expected service, owner or label"
+                    )))
+                }
+            };
+            spends.push((group, &service.spend));
+        }
+        let lines = budget::report(spends, since.0, until.0, models::unix_now());
+
+        match format.as_deref() {
+            Some("csv") => {
+                let mut writer = csv::Writer::from_writer(Vec::new());
+                for line in &lines {
+                    writer.serialize(line)?;
+                }
+                let csv = writer.into_inner().map_err(|e| e.into_error())?;
+                Ok(String::from_utf8_lossy(&csv).to_string())
+            }
+            Some("json") | None => {
+                let report = serde_json::json!({
+                    "since": since,
+                    "until": until,
+                    "group_by": group_by,
+                    "lines": lines,
+                    "total": lines.iter().map(|line| line.cost).sum::<f64>(),
+                });
+                models::in_timezone(timezone, || match pretty {
+                    Some(true) => serde_json::to_string_pretty(&report),
+                    _ => serde_json::to_string(&report),
+                })
+                .map_err(ServicingError::from)
+            }
+            Some(other) => Err(ServicingError::General(format!(
+                "Unknown format {other}, expected json or csv"
+            ))),
+        }
+    }
+
+    /// export dumps tables of the services into directory as CSV (default) or Parquet files,
+    /// named after the table: services, the status of every service; events, the ups, launches,
+    /// teardowns, preemptions, maintenance and approval trails of the services in time order;
+    /// probes, the last readiness probe and latency of every service. Returns the files written,
+    /// empty tables being skipped.
+    #[pyo3(signature = (directory, format=None, tables=None))]
+    pub fn export(
+        &self,
+        directory: String,
+        format: Option<String>,
+        tables: Option<Vec<String>>,
+    ) -> Result<Vec<String>, ServicingError> {
+        let format = match format {
+            Some(format) => format.parse()?,
+            None => export::Format::Csv,
+        };
+        let tables = tables.unwrap_or(vec![
+            "services".to_string(),
+            "events".to_string(),
+            "probes".to_string(),
+        ]);
+        let directory = PathBuf::from(directory);
+        fs::create_dir_all(&directory)?;
+
+        let mut written = Vec::new();
+        for table in tables {
+            let path = directory.join(format!("{table}.{}", format.extension()));
+            let exported = match table.as_str() {
+                "services" => export::write(&statuses(&self.service)?, &path, format)?,
+                "events" => {
+                    let entries: Vec<Entry> = self.service.iter().collect();
+                    export::write(&events(&entries), &path, format)?
+                }
+                "probes" => {
+                    let entries: Vec<Entry> = self.service.iter().collect();
+                    let probes = self.probes.lock()?;
+                    export::write(&probe_rows(&entries, &probes), &path, format)?
+                }
+                other => {
+                    return Err(ServicingError::General(format!(
+                        "Unknown table {other}, expected services, events or probes"
+                    )))
+                }
+            };
+            match exported {
+                true => written.push(path.to_string_lossy().to_string()),
+                false => info!("Not exporting table {}, it is empty", table),
+            }
+        }
+        Ok(written)
+    }
+
+    /// set_workdir_packaging controls what up ships from the workdir of the service: only the
+    /// files matching include (all by default), minus those matching exclude and, unless
+    /// gitignore is False, those ignored by .gitignore. The selection is staged in a copy that is
+    /// reused while the selected files are unchanged, and refused above max_size_mb. No arguments
+    /// ship the workdir as is again.
+    #[pyo3(signature = (name, include=None, exclude=None, gitignore=None, max_size_mb=None))]
+    pub fn set_workdir_packaging(
+        &self,
+        name: String,
+        include: Option<Vec<String>>,
+        exclude: Option<Vec<String>>,
+        gitignore: Option<bool>,
+        max_size_mb: Option<u64>,
+    ) -> Result<(), ServicingError> {
+        let packaging = match (&include, &exclude, gitignore, max_size_mb) {
+            (None, None, None, None) => None,
+            _ => Some(Packaging {
+                include: include.unwrap_or_default(),
+                exclude: exclude.unwrap_or_default(),
+                gitignore: gitignore.unwrap_or(true),
+                max_size: max_size_mb.map(|mb| mb * 1024 * 1024),
+            }),
+        };
+        match self.service.get_mut(&name) {
+            Some(mut service) => {
+                service.packaging = packaging;
+                service.workdir_hash = None;
+                Ok(())
+            }
+            None => Err(ServicingError::ServiceNotFound(name)),
+        }
+    }
+
+    /// set_image_build builds dockerfile in context and pushes the image to registry before every
+    /// up, the service then runs from that image. No registry removes the build step.
+    #[pyo3(signature = (name, registry=None, dockerfile=None, context=None, tag=None))]
+    pub fn set_image_build(
+        &self,
+        name: String,
+        registry: Option<String>,
+        dockerfile: Option<PathBuf>,
+        context: Option<PathBuf>,
+        tag: Option<String>,
+    ) -> Result<(), ServicingError> {
+        let image_build = registry.map(|registry| {
+            let context = context.unwrap_or_else(|| PathBuf::from("."));
+            ImageBuild {
+                dockerfile: dockerfile.unwrap_or_else(|| context.join("Dockerfile")),
+                context,
+                registry,
+                tag,
+            }
+        });
+        match self.service.get_mut(&name) {
+            Some(mut service) => {
+                service.image_build = image_build;
+                Ok(())
+            }
+            None => Err(ServicingError::ServiceNotFound(name)),
+        }
+    }
+
+    /// set_registry_credentials sets the credentials of a container registry, used to push built
+    /// images and by the orchestrator to pull them. The password is either given or printed by
+    /// token_helper, a shell command run at every up (e.g. `aws ecr get-login-password`).
+    /// Credentials are only kept in memory, no username removes them.
+    #[pyo3(signature = (registry, username=None, password=None, token_helper=None))]
+    pub fn set_registry_credentials(
+        &self,
+        registry: String,
+        username: Option<String>,
+        password: Option<String>,
+        token_helper: Option<String>,
+    ) -> Result<(), ServicingError> {
+        let server = image::registry_server(&format!("{registry}/")).to_string();
+        let mut registries = self.registries.lock()?;
+        let Some(username) = username else {
+            registries.remove(&server);
+            return Ok(());
+        };
+        let secret = match (password, token_helper) {
+            (Some(password), None) => Secret::Password(password),
+            (None, Some(helper)) => Secret::Helper(helper),
+            _ => {
+                return Err(ServicingError::General(
+                    "Provide either a password or a token helper".to_string(),
+                ))
+            }
+        };
+        registries.insert(server, RegistryCredentials { username, secret });
+        Ok(())
+    }
+
+    /// set_stable_endpoint makes requests and get_url use endpoint, a host[:port] that survives
+    /// service updates such as an elastic IP or an existing load balancer, instead of the
+    /// endpoint SkyPilot reports. No endpoint goes back to the SkyPilot one.
+    #[pyo3(signature = (name, endpoint=None))]
+    pub fn set_stable_endpoint(
+        &self,
+        name: String,
+        endpoint: Option<String>,
+    ) -> Result<(), ServicingError> {
+        let endpoint = endpoint
+            .map(|endpoint| {
+                let host = endpoint.strip_prefix("http://").unwrap_or(&endpoint);
+                let host = host.trim_end_matches('/');
+                match host.is_empty() || host.contains("://") || host.contains('/') {
+                    true => Err(ServicingError::General(format!(
+                        "Stable endpoint must be host[:port], got {endpoint}"
+                    ))),
+                    false => Ok(host.to_string()),
+                }
+            })
+            .transpose()?;
+        match self.service.get_mut(&name) {
+            Some(mut service) => {
+                service.stable_endpoint = endpoint;
+                Ok(())
+            }
+            None => Err(ServicingError::ServiceNotFound(name)),
+        }
+    }
+
+    /// set_api_key makes every up issue a new API key to the service, handed to it in the
+    /// SERVICING_API_KEY environment variable for it to verify. The key is attached as a bearer
+    /// token to the requests sent through invoke, chat, completions and the gateway.
+    #[pyo3(signature = (name, enabled=None))]
+    pub fn set_api_key(&self, name: String, enabled: Option<bool>) -> Result<(), ServicingError> {
+        match self.service.get_mut(&name) {
+            Some(mut service) => {
+                service.api_key_enabled = enabled.unwrap_or(true);
+                Ok(())
+            }
+            None => Err(ServicingError::ServiceNotFound(name)),
+        }
+    }
+
+    /// api_key returns the API key issued to the service by the last up, for other clients.
+    pub fn api_key(&self, name: String) -> Result<Option<String>, ServicingError> {
+        match self.service.get(&name) {
+            Some(service) => Ok(service.api_key.as_ref().map(|k| k.0.clone())),
+            None => Err(ServicingError::ServiceNotFound(name)),
+        }
+    }
+
+    /// set_subprocess_limit sets how many orchestrator subprocesses (sky, docker, ...) may run at
+    /// once across the process, further ones wait for a slot in order.
+    pub fn set_subprocess_limit(&self, limit: usize) -> Result<(), ServicingError> {
+        limiter::set_limit(limit)
+    }
+
+    /// set_interrupt_policy sets what up and down do with the orchestrator subprocess they wait
+    /// for when the process receives SIGINT, across the process: "kill" terminates it with its
+    /// process group (the default), "detach" leaves it running and "wait" lets the call run to
+    /// completion. Unless waiting, the call fails with INTERRUPTED and its operation is left for
+    /// recover to finish or roll back.
+    pub fn set_interrupt_policy(&self, policy: String) -> Result<(), ServicingError> {
+        interrupt::set_policy(policy.parse()?);
+        Ok(())
+    }
+
+    /// deadline returns a context manager bounding the calls made within its with block, on the
+    /// thread entering it, to timeout seconds: the orchestrator subprocesses still running when
+    /// it passes are killed and the HTTP requests dropped, the call failing with
+    /// DEADLINE_EXCEEDED. Nested blocks cannot extend the deadline of the enclosing one. The
+    /// background work of the dispatcher (gateway, monitors) runs on other threads and is not
+    /// bounded.
+    pub fn deadline(&self, timeout: f64) -> Result<Deadline, ServicingError> {
+        match Duration::try_from_secs_f64(timeout) {
+            Ok(timeout) => Ok(Deadline::new(timeout)),
+            Err(_) => Err(ServicingError::General(format!(
+                "Invalid deadline timeout {timeout}"
+            ))),
+        }
+    }
+
+    /// tasks returns a JSON list of the running and queued orchestrator subprocesses (id, command,
+    /// state, seconds).
+    #[pyo3(signature = (pretty=None))]
+    pub fn tasks(&self, pretty: Option<bool>) -> Result<String, ServicingError> {
+        let tasks = limiter::tasks()?;
+        Ok(match pretty {
+            Some(true) => serde_json::to_string_pretty(&tasks)?,
+            _ => serde_json::to_string(&tasks)?,
+        })
+    }
+
+    /// timings returns how long the phases of the recent ups of the service took, oldest first.
+    /// render, policies, checks (budget and quota), confirm, package, image and endpoint, the
+    /// time spent finding the service URL, are the time spent in each; provision (sky serve up)
+    /// and ready (first healthy readiness probe) are counted from the start of up.
+    #[pyo3(signature = (name, pretty=None))]
+    pub fn timings(&self, name: String, pretty: Option<bool>) -> Result<String, ServicingError> {
+        match self.service.get(&name) {
+            Some(service) => Ok(match pretty {
+                Some(true) => serde_json::to_string_pretty(&service.timings)?,
+                _ => serde_json::to_string(&service.timings)?,
+            }),
+            None => Err(ServicingError::ServiceNotFound(name)),
+        }
+    }
+
+    /// save writes the services to the cache. When another process saved the cache since this
+    /// dispatcher last loaded or saved it, its services are merged in first instead of being
+    /// overwritten.
+    #[pyo3(signature = (location=None))]
+    pub fn save(&self, location: Option<PathBuf>) -> Result<(), ServicingError> {
+        let dir = if let Some(location) = location {
+            helper::create_directory(
+                location
+                    .to_str()
+                    .ok_or(ServicingError::General("Location is None".to_string()))?,
+                false,
+            )?
+        } else {
+            layout::root()?
+        };
+        let path = dir.join(CACHE_FILE_NAME);
+        refresh(&self.service, &self.synced, &path)?;
+        let bin = encode(&self.service.iter().collect::<Vec<_>>())?;
+        // recorded first, so the watcher does not take this save for one of another process
+        self.track_cache(&path, &bin)?;
+        // the previous cache is kept aside, the registry is the one thing that cannot be rebuilt
+        helper::write_atomic(&path, &bin, true)?;
+        info!("Services saved to {:?}", dir.join(CACHE_FILE_NAME));
+
+        Ok(())
+    }
+
+    /// snapshot writes an archive of the whole dispatcher to path: every service with its
+    /// configuration file as currently on disk, and the aliases.
+    pub fn snapshot(&self, path: PathBuf) -> Result<(), ServicingError> {
+        let entries: Vec<Entry> = self.service.iter().collect();
+        let mut configs = HashMap::new();
+        for (name, service) in entries.iter().map(|entry| entry.pair()) {
+            if let Some(filepath) = &service.filepath {
+                configs.insert(name.clone(), helper::read_from_file(filepath)?);
+            }
+        }
+        let snapshot = Snapshot {
+            version: SNAPSHOT_VERSION,
+            created: models::unix_now(),
+            registry: encode(&entries)?,
+            aliases: bincode::serialize(&*self.aliases.lock()?)?,
+            configs,
+        };
+        drop(entries);
+
+        helper::write_to_file_binary(&path, &bincode::serialize(&snapshot)?)?;
+        info!("Snapshot written to {:?}", path);
+        Ok(())
+    }
+
+    /// restore recreates the services and aliases of a snapshot, along with their configuration
+    /// files. Services already known to the dispatcher are refused. With reprovision, every
+    /// restored service is launched again instead of keeping the state recorded in the snapshot,
+    /// which is refused for the protected services, see protect.
+    #[pyo3(signature = (path, reprovision=None))]
+    pub fn restore(
+        &mut self,
+        path: PathBuf,
+        reprovision: Option<bool>,
+    ) -> Result<(), ServicingError> {
+        let snapshot: Snapshot = bincode::deserialize(&helper::read_from_file_binary(&path)?)?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(ServicingError::General(format!(
+                "Unsupported snapshot version {}",
+                snapshot.version
+            )));
+        }
+        let services = decode(&snapshot.registry)?;
+        let aliases: HashMap<String, Alias> = bincode::deserialize(&snapshot.aliases)?;
+        for name in services.keys() {
+            layout::check_name("service", name)?;
+        }
+
+        {
+            let known = self.aliases.lock()?;
+            if let Some(name) = services
+                .keys()
+                .chain(aliases.keys())
+                .find(|name| self.service.contains_key(*name) || known.contains_key(*name))
+            {
+                return Err(ServicingError::ServiceAlreadyExists(name.clone()));
+            }
+        }
+
+        let reprovision = reprovision.unwrap_or(false);
+        let mut names = Vec::new();
+        for (name, mut service) in services {
+            if let Some(content) = snapshot.configs.get(&name) {
+                let file = layout::service_dir(&name)?.join(layout::CONFIG_FILE);
+                helper::write_to_file(&file, content)?;
+                service.filepath = Some(file);
+            }
+            if reprovision {
+                service.url = None;
+                service.up = false;
+            }
+            names.push(name.clone());
+            self.service.insert(name, service);
+        }
+        self.aliases.lock()?.extend(aliases);
+        info!("Restored services {:?} from {:?}", names, path);
+
+        if reprovision {
+            for name in names {
+                // in the environment the service was running in
+                let environment = self
+                    .service
+                    .get(&name)
+                    .and_then(|service| service.environment.clone());
+                self.launch(
+                    name,
+                    Some(true),
+                    Some(true),
+                    None,
+                    None,
+                    None,
+                    None,
+                    environment,
+                    false,
+                    false,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn save_as_b64(&self) -> Result<String, ServicingError> {
+        let bin = encode(&self.service.iter().collect::<Vec<_>>())?;
+        let b64 = base64::prelude::BASE64_STANDARD.encode(bin);
+        Ok(b64)
+    }
+
+    #[pyo3(signature = (location=None, update_status=None))]
+    pub fn load(
+        &mut self,
+        location: Option<PathBuf>,
+        update_status: Option<bool>,
+    ) -> Result<(), ServicingError> {
+        let location = if let Some(location) = location {
+            helper::create_directory(
+                location
+                    .to_str()
+                    .ok_or(ServicingError::General("Location is None".to_string()))?,
+                false,
+            )?
+            .join(CACHE_FILE_NAME)
+        } else {
+            layout::root()?.join(CACHE_FILE_NAME)
+        };
+
+        let bin = helper::read_from_file_binary(&location)?;
+        let mut services = match decode(&bin) {
+            Ok(services) => services,
+            Err(e) => {
+                // fall back to the cache saved before, if any
+                let backup = location.with_extension("bin.bak");
+                if !backup.is_file() {
+                    return Err(e);
+                }
+                warn!(
+                    "Cache {:?} is unreadable ({e}), loading {:?}",
+                    location, backup
+                );
+                decode(&helper::read_from_file_binary(&backup)?)?
+            }
+        };
+
+        self.track_cache(&location, &bin)?;
+
+        for service in services.values_mut() {
+            // registries saved before the layout version 2 point at the flat configurations
+            let migrated = match &service.filepath {
+                Some(filepath) if !filepath.exists() => layout::migrated_config(filepath),
+                _ => None,
+            };
+            if migrated.is_some() {
+                service.filepath = migrated;
+            }
+        }
+        for (name, service) in services {
+            self.service.insert(name, service);
+        }
+
+        // the registry may not reflect the operations interrupted with their process
+        let interrupted = self.interrupted(None)?;
+        for interrupted in &interrupted {
+            if let Err(e) = self.reconcile(interrupted) {
+                warn!(
+                    "Could not reconcile {} {}: {e}",
+                    interrupted.operation, interrupted.id
+                );
+            }
+        }
+        if !interrupted.is_empty() {
+            warn!(
+                "{} interrupted operations, run recover() to finish or roll them back",
+                interrupted.len()
+            );
+        }
+
+        if let Some(true) = update_status {
+            info!("Checking for services that may come up while you were away...");
+
+            // Clones to pass to threads
+            let service_clone = self.service.clone();
+            let client_clone = self.client.clone();
+            let mut service_to_check = Vec::new();
+
+            // iterate through the services and find that are down
+            self.service
+                .iter()
+                .filter(|service| !service.up && service.url.is_some())
+                .for_each(|service| {
+                    service_to_check.push((
+                        service.key().clone(),
+                        service
+                            .url
+                            .clone()
+                            .expect("Gettting url, this should never be None")
+                            + &service.template.service.readiness_probe,
+                    ))
+                });
+
+            if service_to_check.is_empty() {
+                info!("No services to check");
+                return Ok(());
+            }
+
+            info!("Services to check: {:?}", service_to_check);
+
+            self.rt.spawn(async move {
+                let mut handles = Vec::new();
+                for (name, url) in service_to_check {
+                    let client_clone = client_clone.clone();
+                    let url = helper::http_url(&url, "");
+                    let handle = tokio::spawn(async move {
+                        match helper::fetch_and_check(
+                            &client_clone,
+                            &url,
+                            REPLICA_UP_CHECK,
+                            Some(SERVICE_CHECK_INTERVAL),
+                        )
+                        .await
+                        {
+                            Ok(_) => {}
+                            Err(e) => {
+                                return Err(e);
+                            }
+                        }
+                        Ok(name)
+                    });
+                    handles.push(handle);
+                }
+                for res in join_all(handles).await {
+                    match res {
+                        Ok(Ok(r)) => {
+                            if let Some(mut service) = service_clone.get_mut(&r) {
+                                service.up = true;
+                                info!("Service {} is up", r);
+                            }
+                        }
+                        Ok(Err(e)) => {
+                            warn!("{e}");
+                        }
+                        Err(e) => {
+                            error!("{e}");
+                        }
+                    }
+                }
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn load_from_b64(&mut self, b64: String) -> Result<(), ServicingError> {
+        let bin = base64::prelude::BASE64_STANDARD.decode(b64.as_bytes())?;
+        for (name, service) in decode(&bin)? {
+            self.service.insert(name, service);
+        }
+
+        Ok(())
+    }
+
+    /// list returns the names of the services in alphabetical order, only those created by
+    /// owner if provided. offset and limit page through large registries, only the names of
+    /// the page being copied.
+    #[pyo3(signature = (owner=None, offset=None, limit=None))]
+    pub fn list(
+        &self,
+        owner: Option<String>,
+        offset: Option<usize>,
+        limit: Option<usize>,
+    ) -> Result<Vec<String>, ServicingError> {
+        let entries = self
+            .service
+            .iter()
+            .filter(|entry| match (&owner, &entry.owner) {
+                (Some(wanted), Some(owner)) => &owner.user == wanted,
+                (Some(_), None) => false,
+                (None, _) => true,
+            });
+        Ok(page(entries, offset, limit)
+            .into_iter()
+            .map(|entry| entry.key().clone())
+            .collect())
+    }
+
+    /// status_all returns the summaries of the services in alphabetical order, as polled last
+    /// rather than probing them, a page of them with offset and limit. The services are only
+    /// held while their summaries are taken, not while they are serialized.
+    #[pyo3(signature = (offset=None, limit=None, pretty=None))]
+    pub fn status_all(
+        &self,
+        offset: Option<usize>,
+        limit: Option<usize>,
+        pretty: Option<bool>,
+    ) -> Result<String, ServicingError> {
+        let statuses: Vec<ServiceStatus> = page(self.service.iter(), offset, limit)
+            .into_iter()
+            .map(|entry| entry.summary(entry.key()))
+            .collect();
+        Ok(match pretty {
+            Some(true) => serde_json::to_string_pretty(&statuses)?,
+            _ => serde_json::to_string(&statuses)?,
+        })
+    }
+
+    /// export_endpoints returns the URLs of the services that are up, either as env lines
+    /// (`SVC_MY_SERVICE_URL=http://...`, the default) or as a JSON map of service name to URL,
+    /// for docker-compose files and other apps to consume.
+    #[pyo3(signature = (format=None, prefix=None))]
+    pub fn export_endpoints(
+        &self,
+        format: Option<String>,
+        prefix: Option<String>,
+    ) -> Result<String, ServicingError> {
+        let endpoints: BTreeMap<String, String> = self
+            .service
+            .iter()
+            .filter(|service| service.up)
+            .filter_map(|service| {
+                let url = service.stable_endpoint.as_ref().or(service.url.as_ref())?;
+                Some((service.key().clone(), helper::http_url(url, "")))
+            })
+            .collect();
+
+        match format.as_deref().unwrap_or("env") {
+            "env" => {
+                let prefix = prefix.as_deref().unwrap_or(EXPORT_PREFIX);
+                let lines: Vec<String> = endpoints
+                    .iter()
+                    .map(|(name, url)| {
+                        let var: String = name
+                            .chars()
+                            .map(|c| match c.is_ascii_alphanumeric() {
+                                true => c.to_ascii_uppercase(),
+                                false => '_',
+                            })
+                            .collect();
+                        format!("{prefix}{var}_URL={url}")
+                    })
+                    .collect();
+                Ok(lines.join("\n"))
+            }
+            "json" => Ok(serde_json::to_string(&endpoints)?),
+            format => Err(ServicingError::General(format!(
+                "Unknown export format {format}, expected env or json"
+            ))),
+        }
+    }
+
+    /// openapi fetches the OpenAPI document of a running service and caches it. The cached
+    /// document is returned on later calls unless refresh is set.
+    #[pyo3(signature = (name, path=None, refresh=None))]
+    pub fn openapi(
+        &self,
+        name: String,
+        path: Option<String>,
+        refresh: Option<bool>,
+    ) -> Result<String, ServicingError> {
+        let (url, proxy) = match self.service.get(&name) {
+            Some(service) => {
+                if let (Some(spec), None | Some(false)) = (&service.openapi, refresh) {
+                    return Ok(spec.clone());
+                }
+                match (&service.url, service.up) {
+                    (Some(url), true) => (url.clone(), service.proxy.clone()),
+                    _ => return Err(ServicingError::ServiceNotUp(name)),
+                }
+            }
+            None => return Err(ServicingError::ServiceNotFound(name)),
+        };
+
+        let url = helper::http_url(&url, path.as_deref().unwrap_or(OPENAPI_PATH));
+        info!("Fetching the OpenAPI document from {}", url);
+        let client = self.client(proxy.as_deref())?;
+        let spec = helper::block_on(&self.rt, async move { helper::fetch(&client, &url).await })??;
+        // make sure what we cache is actually JSON
+        serde_json::from_str::<serde_json::Value>(&spec)?;
+
+        if let Some(mut service) = self.service.get_mut(&name) {
+            service.openapi = Some(spec.clone());
+        }
+        Ok(spec)
+    }
+
+    /// routes lists the operations of the cached OpenAPI document as "METHOD /path".
+    pub fn routes(&self, name: String) -> Result<Vec<String>, ServicingError> {
+        let spec = self.cached_openapi(&name)?;
+        Ok(helper::openapi_routes(&spec))
+    }
+
+    /// models lists the schema names of the cached OpenAPI document.
+    pub fn models(&self, name: String) -> Result<Vec<String>, ServicingError> {
+        let spec = self.cached_openapi(&name)?;
+        Ok(helper::openapi_models(&spec))
+    }
+
+    /// chat sends a chat completion request to an OpenAI-compatible service. With stream set, an
+    /// iterator over the streamed events is returned instead of the full response.
+    #[pyo3(signature = (name, messages, stream=None, **params))]
+    pub fn chat(
+        &self,
+        py: Python<'_>,
+        name: String,
+        messages: &Bound<'_, PyAny>,
+        stream: Option<bool>,
+        params: Option<&Bound<'_, PyDict>>,
+    ) -> Result<PyObject, ServicingError> {
+        let body = client::build_body(
+            "messages",
+            client::to_json(messages)?,
+            params.map(|p| p.as_any()),
+            stream.unwrap_or(false),
+        )?;
+        self.openai_request(py, &name, OpenAIEndpoint::Chat, body)
+    }
+
+    /// completions sends a completion request to an OpenAI-compatible service. With stream set, an
+    /// iterator over the streamed events is returned instead of the full response.
+    #[pyo3(signature = (name, prompt, stream=None, **params))]
+    pub fn completions(
+        &self,
+        py: Python<'_>,
+        name: String,
+        prompt: &Bound<'_, PyAny>,
+        stream: Option<bool>,
+        params: Option<&Bound<'_, PyDict>>,
+    ) -> Result<PyObject, ServicingError> {
+        let body = client::build_body(
+            "prompt",
+            client::to_json(prompt)?,
+            params.map(|p| p.as_any()),
+            stream.unwrap_or(false),
+        )?;
+        self.openai_request(py, &name, OpenAIEndpoint::Completions, body)
+    }
+
+    /// invoke sends a request to the service, or to one of the members if name is an alias. The
+    /// payload is sent as JSON, method defaults to POST with a payload and GET without.
+    #[pyo3(signature = (name, path, payload=None, method=None))]
+    pub fn invoke(
+        &self,
+        py: Python<'_>,
+        name: String,
+        path: String,
+        payload: Option<&Bound<'_, PyAny>>,
+        method: Option<String>,
+    ) -> Result<String, ServicingError> {
+        let body = payload.map(client::to_json).transpose()?;
+        let method = match method {
+            Some(method) => Method::from_bytes(method.to_uppercase().as_bytes())
+                .map_err(|_| ServicingError::General(format!("Invalid HTTP method {method}")))?,
+            None if body.is_some() => Method::POST,
+            None => Method::GET,
+        };
+
+        let (service, url) = self.resolve(&name)?;
+        let url = helper::http_url(&url, &path);
+
+        let req = OutboundRequest::json(method, url, body.as_ref())?;
+        let response = self.outbound(py, &service, req)?;
+        py.allow_threads(|| self.rt.block_on(deadline::bound(client::text(response))))?
+    }
+
+    /// benchmark sends the request to the service, or to the members of an alias, from
+    /// concurrency workers for duration seconds and returns a JSON report of the throughput, the
+    /// latency percentiles in milliseconds and the status codes. Middleware, rate limits and
+    /// circuit breakers are bypassed so they do not skew the numbers.
+    #[pyo3(signature = (name, path, payload=None, concurrency=None, duration=None, method=None, pretty=None))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn benchmark(
+        &self,
+        py: Python<'_>,
+        name: String,
+        path: String,
+        payload: Option<&Bound<'_, PyAny>>,
+        concurrency: Option<usize>,
+        duration: Option<f64>,
+        method: Option<String>,
+        pretty: Option<bool>,
+    ) -> Result<String, ServicingError> {
+        let body = payload.map(client::to_json).transpose()?;
+        let method = match method {
+            Some(method) => Method::from_bytes(method.to_uppercase().as_bytes())
+                .map_err(|_| ServicingError::General(format!("Invalid HTTP method {method}")))?,
+            None if body.is_some() => Method::POST,
+            None => Method::GET,
+        };
+        let duration = match duration {
+            Some(duration) if duration > 0.0 => Duration::from_secs_f64(duration),
+            Some(_) => {
+                return Err(ServicingError::General(
+                    "Benchmark duration must be positive".to_string(),
+                ))
+            }
+            None => Duration::from_secs(BENCHMARK_DURATION),
+        };
+
+        let (service, url) = self.resolve(&name)?;
+        let mut req = OutboundRequest::json(method, helper::http_url(&url, &path), body.as_ref())?;
+        if let Some(s) = self.service.get(&service) {
+            req.authorize(s.api_key.as_ref().map(|k| k.0.as_str()))?;
+        }
+
+        let concurrency = concurrency.unwrap_or(BENCHMARK_CONCURRENCY);
+        let client = self.client_for(&service)?;
+        let report = py.allow_threads(|| {
+            self.rt.block_on(deadline::bound(async {
+                self.scaler.wake(&client, &service).await?;
+                Ok::<_, ServicingError>(benchmark::run(&client, &req, concurrency, duration).await)
+            }))
+        })??;
+        Ok(match pretty {
+            Some(true) => serde_json::to_string_pretty(&report)?,
+            _ => serde_json::to_string(&report)?,
+        })
+    }
+
+    /// add_middleware registers Python hooks run around every request sent to a service through
+    /// invoke, chat, completions and the gateway. before is called with a request dict (service,
+    /// method, url, headers, body_size) and may change its headers in place or return new ones,
+    /// after is called with the request dict and a response dict (status, elapsed, error).
+    #[pyo3(signature = (before=None, after=None))]
+    pub fn add_middleware(
+        &self,
+        before: Option<PyObject>,
+        after: Option<PyObject>,
+    ) -> Result<(), ServicingError> {
+        self.middleware.add(before, after)
+    }
+
+    pub fn clear_middleware(&self) -> Result<(), ServicingError> {
+        self.middleware.clear()
+    }
+
+    /// add_hook registers Python hooks run around the operations on services: add, up, down and
+    /// remove, or those of operations. before is called with a context dict (service, operation,
+    /// orchestrator, owner, url, config) and aborts the operation by raising, after is called
+    /// with the context once the operation is done and its error, None if it succeeded.
+    /// Installed packages can provide hooks as well, from a servicing.hooks entry point.
+    #[pyo3(signature = (before=None, after=None, operations=None))]
+    pub fn add_hook(
+        &self,
+        before: Option<PyObject>,
+        after: Option<PyObject>,
+        operations: Option<Vec<String>>,
+    ) -> Result<(), ServicingError> {
+        self.hooks.add(before, after, operations)
+    }
+
+    /// clear_hooks removes the hooks registered with add_hook, and those of the plugins too if
+    /// plugins is True.
+    #[pyo3(signature = (plugins=None))]
+    pub fn clear_hooks(&self, plugins: Option<bool>) -> Result<(), ServicingError> {
+        self.hooks.clear(plugins.unwrap_or(false))
+    }
+
+    /// add_policy registers a policy evaluated against the rendered configuration of every
+    /// service before it is launched, either check, a Python callable, or the OPA bundle at
+    /// bundle evaluated with the opa CLI. Both get an input dict (service, orchestrator,
+    /// environment, owner, config): check returns its violations as strings, None if there are
+    /// none, and query (data.servicing.deny by default) evaluates to the violations of the
+    /// bundle, as messages or objects with a msg field. name defaults to the name of check or
+    /// the path of bundle.
+    #[pyo3(signature = (check=None, bundle=None, query=None, name=None))]
+    pub fn add_policy(
+        &self,
+        py: Python<'_>,
+        check: Option<PyObject>,
+        bundle: Option<PathBuf>,
+        query: Option<String>,
+        name: Option<String>,
+    ) -> Result<(), ServicingError> {
+        match (check, bundle) {
+            (Some(check), None) => self.policies.add_callable(py, check, name),
+            (None, Some(bundle)) => self.policies.add_bundle(bundle, query, name),
+            _ => Err(ServicingError::General(
+                "Pass either check or bundle to add_policy".to_string(),
+            )),
+        }
+    }
+
+    /// clear_policies removes the policies registered with add_policy.
+    pub fn clear_policies(&self) -> Result<(), ServicingError> {
+        self.policies.clear()
+    }
+
+    /// policies lists the names of the policies, in the order they are evaluated.
+    pub fn policies(&self) -> Result<Vec<String>, ServicingError> {
+        self.policies.names()
+    }
+
+    /// check_policies evaluates the policies against the configuration the service would be
+    /// launched with in env, without launching it, and returns the violations as dicts (policy,
+    /// message).
+    #[pyo3(signature = (name, env=None))]
+    pub fn check_policies(
+        &self,
+        name: String,
+        env: Option<String>,
+    ) -> Result<Vec<HashMap<String, String>>, ServicingError> {
+        if let Some(env) = &env {
+            check_environment(env)?;
+        }
+        let service = self
+            .service
+            .get(&name)
+            .ok_or(ServicingError::ServiceNotFound(name.clone()))?;
+        let template = service.template_in(env.as_deref());
+        let input = service.policy_input(&name, env.as_deref(), &template);
+        // out of the entry, so that the registry readers are not blocked by the policies
+        drop(service);
+        let violations = self.policies.evaluate(&input)?;
+        Ok(violations
+            .into_iter()
+            .map(|v| {
+                HashMap::from([
+                    ("policy".to_string(), v.policy),
+                    ("message".to_string(), v.message),
+                ])
+            })
+            .collect())
+    }
+
+    /// add_notification posts the outcome of the operations on the service, or on every service
+    /// if name is None, to a Slack or Microsoft Teams incoming webhook: add, up, down and remove,
+    /// or those of operations. kind is slack or teams, guessed from url by default. template is
+    /// the message, with {service}, {operation}, {state}, {url}, {cost} and {error}
+    /// placeholders. The sinks of every service are kept for the other dispatchers of the user.
+    #[pyo3(signature = (url, kind=None, template=None, operations=None, name=None))]
+    pub fn add_notification(
+        &self,
+        url: String,
+        kind: Option<String>,
+        template: Option<String>,
+        operations: Option<Vec<String>>,
+        name: Option<String>,
+    ) -> Result<(), ServicingError> {
+        let kind = match kind {
+            Some(kind) => kind.parse()?,
+            None => SinkKind::detect(&url).ok_or(ServicingError::General(
+                "Cannot tell the kind of the webhook from its url, pass kind".to_string(),
+            ))?,
+        };
+        if let Some(unknown) = operations
+            .iter()
+            .flatten()
+            .find(|o| !hooks::OPERATIONS.contains(&o.as_str()))
+        {
+            return Err(ServicingError::General(format!(
+                "Unknown operation {unknown}, expected one of {}",
+                hooks::OPERATIONS.join(", ")
+            )));
+        }
+        let sink = Sink {
+            kind,
+            url,
+            template,
+            operations,
+        };
+        match name {
+            Some(name) => match self.service.get_mut(&name) {
+                Some(mut service) => service.notifications.push(sink),
+                None => return Err(ServicingError::ServiceNotFound(name)),
+            },
+            None => {
+                let mut notifications = self.notifications.lock()?;
+                notifications.push(sink);
+                notification::save(&layout::root()?, &notifications)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// clear_notifications removes the notification sinks of the service, or those of every
+    /// service if name is None.
+    #[pyo3(signature = (name=None))]
+    pub fn clear_notifications(&self, name: Option<String>) -> Result<(), ServicingError> {
+        match name {
+            Some(name) => match self.service.get_mut(&name) {
+                Some(mut service) => service.notifications.clear(),
+                None => return Err(ServicingError::ServiceNotFound(name)),
+            },
+            None => {
+                let mut notifications = self.notifications.lock()?;
+                notifications.clear();
+                notification::save(&layout::root()?, &notifications)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// plugins lists the servicing.hooks entry points the hooks of the dispatcher were loaded
+    /// from.
+    pub fn plugins(&self) -> Result<Vec<String>, ServicingError> {
+        self.hooks.plugins()
+    }
+
+    /// set_rate_limit limits the requests sent to a service through invoke, chat, completions and
+    /// the gateway to rate per second, at least 0.001, with bursts of up to burst requests, at
+    /// least 1. Requests over the limit wait for their turn. A rate of None removes the limit.
+    #[pyo3(signature = (name, rate=None, burst=None))]
+    pub fn set_rate_limit(
+        &self,
+        name: String,
+        rate: Option<f64>,
+        burst: Option<u32>,
+    ) -> Result<(), ServicingError> {
+        self.check_exists(&name)?;
+        self.traffic
+            .set_rate_limit(&name, rate.map(|rate| (rate, burst.unwrap_or(1))))
+    }
+
+    /// set_circuit_breaker stops sending requests to a service after failures consecutive
+    /// failures, a single probe request is let through every cooldown seconds until one succeeds.
+    /// Failures of None removes the circuit breaker.
+    #[pyo3(signature = (name, failures=None, cooldown=None))]
+    pub fn set_circuit_breaker(
+        &self,
+        name: String,
+        failures: Option<u32>,
+        cooldown: Option<u64>,
+    ) -> Result<(), ServicingError> {
+        self.check_exists(&name)?;
+        self.traffic.set_circuit_breaker(
+            &name,
+            failures.map(|failures| {
+                (
+                    failures,
+                    Duration::from_secs(cooldown.unwrap_or(CIRCUIT_BREAKER_COOLDOWN)),
+                )
+            }),
+        )
+    }
+
+    /// create_alias puts several services behind one name, get_url and invoke on the alias spread
+    /// the traffic across the members that are up according to policy.
+    #[pyo3(signature = (alias, services, policy=None))]
+    pub fn create_alias(
+        &self,
+        alias: String,
+        services: Vec<String>,
+        policy: Option<String>,
+    ) -> Result<(), ServicingError> {
+        let policy = match policy {
+            Some(policy) => policy.parse()?,
+            None => Policy::RoundRobin,
+        };
+        if services.is_empty() {
+            return Err(ServicingError::General(
+                "An alias needs at least one service".to_string(),
+            ));
+        }
+        if self.service.contains_key(&alias) {
+            return Err(ServicingError::ServiceAlreadyExists(alias));
+        }
+        if let Some(missing) = services.iter().find(|s| !self.service.contains_key(*s)) {
+            return Err(ServicingError::ServiceNotFound(missing.clone()));
+        }
+
+        info!("Creating alias {} for {:?}", alias, services);
+        self.aliases
+            .lock()?
+            .insert(alias, Alias::new(services, policy));
+        Ok(())
+    }
+
+    pub fn remove_alias(&self, alias: String) -> Result<(), ServicingError> {
+        match self.aliases.lock()?.remove(&alias) {
+            Some(_) => Ok(()),
+            None => Err(ServicingError::ServiceNotFound(alias)),
+        }
+    }
+
+    pub fn list_aliases(&self) -> Result<HashMap<String, Vec<String>>, ServicingError> {
+        Ok(self
+            .aliases
+            .lock()?
+            .iter()
+            .map(|(name, alias)| (name.clone(), alias.members.clone()))
+            .collect())
+    }
+
+    /// get_url returns the host:port of the service or of an alias member, the stable endpoint
+    /// of the service if it has one. A service exposed through an ingress returns its host and
+    /// path once the ingress controller assigned it an address, a Kubernetes service the address
+    /// of its load balancer, or of its node port while it has none.
+    pub fn get_url(&self, py: Python<'_>, name: String) -> Result<String, ServicingError> {
+        let (service, url) = self.resolve(&name)?;
+        let (ingress, backend) = match self.service.get(&service) {
+            Some(s) => (s.ingress.clone(), s.backend(&service)),
+            None => (None, Backend::Skypilot),
+        };
+        if let (None, Backend::Kubernetes(deployment)) = (&ingress, backend) {
+            match py.allow_threads(|| deployment.address()) {
+                Ok(Some(address)) => {
+                    if let Some(mut s) = self.service.get_mut(&service) {
+                        if s.url.is_some() {
+                            s.url = Some(address.clone());
+                        }
+                    }
+                    return Ok(address);
+                }
+                Ok(None) => info!("Service {} has no address yet", service),
+                Err(e) => warn!("Checking the address of service {} failed: {e}", service),
+            }
+        }
+        if let Some(ingress) = ingress {
+            match py.allow_threads(|| ingress.address(&service)) {
+                Ok(Some(_)) => return Ok(ingress.url()),
+                Ok(None) => info!("Ingress of service {} has no address yet", service),
+                Err(e) => warn!("Checking the ingress of service {} failed: {e}", service),
+            }
+        }
+        Ok(url)
+    }
+
+    /// start_gateway runs a local reverse proxy routing /svc/<name>/... to the service or alias
+    /// called name, answering 503 while it is not up unless it is scaled to zero, in which case
+    /// the request wakes it up, and /services.json to the state of every service for dashboards.
+    /// Port 0 picks a free port, the port the gateway listens on is returned.
+    #[pyo3(signature = (port=None))]
+    pub fn start_gateway(&self, port: Option<u16>) -> Result<u16, ServicingError> {
+        let mut gateway = self.gateway.lock()?;
+        if let Some(gateway) = gateway.as_ref() {
+            return Err(ServicingError::General(format!(
+                "Gateway already running on port {}",
+                gateway.port
+            )));
+        }
+
+        let aliases = self.aliases.clone();
+        let registry = self.service.clone();
+        let scaler = self.scaler.clone();
+        let services = self.service.clone();
+        let started = Gateway::start(
+            &self.rt,
+            port.unwrap_or(GATEWAY_PORT),
+            self.client.clone(),
+            Arc::new(move |name| resolve(&aliases, &registry, &scaler, name)),
+            Arc::new(move || statuses(&services)),
+            self.traffic.clone(),
+            self.middleware.clone(),
+            self.scaler.clone(),
+            self.routes.clone(),
+        )?;
+        let port = started.port;
+        *gateway = Some(started);
+        Ok(port)
+    }
+
+    /// set_gateway_route sets the options of the gateway route of a service or alias: a CORS
+    /// policy answering preflight requests at the gateway, so browser frontends can call the
+    /// service, and headers added to every forwarded request. No options reset the route.
+    #[pyo3(signature = (name, cors_origins=None, cors_methods=None, cors_headers=None, headers=None))]
+    pub fn set_gateway_route(
+        &self,
+        name: String,
+        cors_origins: Option<Vec<String>>,
+        cors_methods: Option<Vec<String>>,
+        cors_headers: Option<Vec<String>>,
+        headers: Option<HashMap<String, String>>,
+    ) -> Result<(), ServicingError> {
+        let cors = cors_origins.map(|origins| Cors {
+            origins,
+            methods: cors_methods.unwrap_or_default(),
+            headers: cors_headers.unwrap_or_default(),
+        });
+        let mut inject = HeaderMap::new();
+        for (key, value) in headers.unwrap_or_default() {
+            let key = HeaderName::from_bytes(key.as_bytes())
+                .map_err(|e| ServicingError::General(format!("Invalid header name {key}: {e}")))?;
+            let value = HeaderValue::from_str(&value).map_err(|e| {
+                ServicingError::General(format!("Invalid header value {value}: {e}"))
+            })?;
+            inject.insert(key, value);
+        }
+
+        let mut routes = self.routes.lock()?;
+        match (cors, inject.is_empty()) {
+            (None, true) => {
+                routes.remove(&name);
+            }
+            (cors, _) => {
+                routes.insert(
+                    name,
+                    RouteOptions {
+                        cors,
+                        headers: inject,
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+
+    pub fn stop_gateway(&self) -> Result<(), ServicingError> {
+        match self.gateway.lock()?.take() {
+            Some(_) => Ok(()),
+            None => Err(ServicingError::General(
+                "Gateway is not running".to_string(),
+            )),
+        }
+    }
+}
+
+impl Dispatcher {
+    /// serve_status returns the output of `sky serve status`, from the cache when it is fresh,
+    /// otherwise by running it for the service, or for all of them.
+    fn serve_status(&self, name: Option<&str>) -> Result<String, ServicingError> {
+        if let Some(output) = self.poller.fresh() {
+            return Ok(output);
+        }
+        let mut cmd = Command::new("sky");
+        cmd.arg("serve").arg("status").args(name);
+        let command = match name {
+            Some(name) => format!("sky serve status {name}"),
+            None => "sky serve status".to_string(),
+        };
+        let output = {
+            let _permit = limiter::acquire(&command)?;
+            runner::run(&mut cmd, Mode::Capture)?
+        };
+        if !output.status.success() {
+            return Err(ServicingError::General(format!(
+                "sky serve status failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    /// serve_state returns whether the orchestrator of the service runs it and its endpoint, if
+    /// reported.
+    fn serve_state(&self, name: &str) -> Result<(bool, Option<String>), ServicingError> {
+        // the registry entry is released before asking the orchestrator
+        let (backend, port) = match self.service.get(name) {
+            Some(service) => (service.backend(name), service.template.resources.ports),
+            None => (Backend::Skypilot, 0),
+        };
+        match backend {
+            Backend::Docker => docker::state(name, port),
+            Backend::Kubernetes(deployment) if !deployment.exists()? => Ok((false, None)),
+            Backend::Kubernetes(deployment) => {
+                let (_, ready) = deployment.readiness()?;
+                Ok((ready > 0, deployment.address()?))
+            }
+            Backend::Skypilot => sky_serve_state(name),
+        }
+    }
+
+    /// watch_ready probes the service listening at url in the background until it is ready,
+    /// marking it up, with the phase reported to progress. The ready phase is timed from started
+    /// if provided.
+    fn watch_ready(
+        &self,
+        name: &str,
+        url: &str,
+        progress: Arc<Progress>,
+        started: Option<Instant>,
+    ) -> Result<(), ServicingError> {
+        let name = name.to_string();
+        let (proxy, health_check, method, tunnel, path) = match self.service.get(&name) {
+            Some(service) => (
+                service.proxy.clone(),
+                service.health_check.clone(),
+                service.probe_method(),
+                service.tunnel.clone(),
+                service.template.service.readiness_probe.clone(),
+            ),
+            None => return Err(ServicingError::ServiceNotFound(name)),
+        };
+        let service_clone = self.service.clone();
+        let client_clone = self.client(proxy.as_deref())?;
+        let check = self.ready_checks.lock()?.get(&name).cloned();
+
+        // opening the tunnel can take a while, the entry of the service is not held meanwhile
+        let base = self.route(&name, tunnel.as_ref(), &format!("http://{url}"))?;
+        let url = format!("http://{}{}", url, path);
+        let url = self.route(&name, tunnel.as_ref(), &url)?;
+
+        // spawn a green thread to check when service comes online, then update the service status
+        let fut = async move {
+            let mut cache = ProbeCache::default();
+            loop {
+                let ready = match &check {
+                    Some(check) => ready_check(check.clone(), base.clone()).await,
+                    None => helper::probe(&client_clone, &url, method, &mut cache, |body| {
+                        Service::is_ready(health_check.as_ref(), body)
+                    })
+                    .await
+                    .map_err(ServicingError::from),
+                };
+                match ready {
+                    Ok(false) => {
+                        report(&progress, "ready", Event::Waiting(SERVICE_CHECK_INTERVAL)).await;
+                        sleep(SERVICE_CHECK_INTERVAL).await;
+                    }
+                    Ok(true) => {
+                        if let Some(mut service) = service_clone.get_mut(&name) {
+                            service.up = true;
+                            if let (Some(timings), Some(started)) =
+                                (service.timings.last_mut(), started)
+                            {
+                                timings.record("ready", started.elapsed());
+                            }
+                        } else {
+                            warn!("Service not found");
+                        }
+                        info!("Service {} is up", name);
+                        report(&progress, "ready", Event::Done).await;
+                        break;
+                    }
+                    Err(e) => {
+                        error!("Error fetching the service endpoint: {:?}", e);
+                        let reason = Reason {
+                            message: e.to_string(),
+                            code: PROBE_FAILED,
+                        };
+                        report(&progress, "ready", Event::Failed(reason)).await;
+                        break;
+                    }
+                }
+            }
+        };
+        self.rt.spawn(fut);
+        Ok(())
+    }
+
+    /// run_up launches the service and its replica groups, see up. A protected service is only
+    /// launched once approved.
+    #[allow(clippy::too_many_arguments)]
+    fn run_up(
+        &mut self,
+        py: Python<'_>,
+        name: String,
+        skip_prompt: Option<bool>,
+        allow_drift: Option<bool>,
+        retries: Option<u32>,
+        fallback: Option<Vec<String>>,
+        progress: Option<PyObject>,
+        confirm: Option<PyObject>,
+        dry_run: bool,
+        env: Option<String>,
+        override_policies: Option<bool>,
+        approved: bool,
+    ) -> Result<OperationResult, ServicingError> {
+        if let Some(env) = &env {
+            check_environment(env)?;
+        }
+        self.hooked("up", &name, |name| {
+            let started = Instant::now();
+            let groups = self.groups(&name)?;
+            if dry_run {
+                let mut plan = Plan::new("up");
+                for member in std::iter::once(&name).chain(&groups) {
+                    self.plan_launch(member, skip_prompt, env.as_deref(), &mut plan)?;
+                }
+                *self.last_plan.lock()? = Some(plan);
+                return self.result("up", &name, started, groups);
+            }
+            for member in std::iter::once(&name).chain(&groups) {
+                self.logged(member, "up", || {
+                    self.launch(
+                        member.clone(),
+                        skip_prompt,
+                        allow_drift,
+                        retries,
+                        fallback.clone(),
+                        progress.as_ref().map(|p| p.clone_ref(py)),
+                        confirm.as_ref().map(|c| c.clone_ref(py)),
+                        env.clone(),
+                        override_policies.unwrap_or(false),
+                        approved,
+                    )
+                })?;
+            }
+            self.result("up", &name, started, groups)
+        })
+    }
+
+    /// decide records the decision of the current user, or of the holder of token, on the up
+    /// requested for the protected service and returns the request.
+    fn decide(
+        &self,
+        name: &str,
+        token: Option<&str>,
+        action: Action,
+        detail: Option<String>,
+    ) -> Result<approval::Request, ServicingError> {
+        let mut service = self
+            .service
+            .get_mut(name)
+            .ok_or(ServicingError::ServiceNotFound(name.to_string()))?;
+        let (Some(protection), Some(request)) = (&service.protection, &service.request) else {
+            return Err(ServicingError::General(format!(
+                "No up of service {name} is waiting for approval"
+            )));
+        };
+        let user = helper::current_user();
+        protection.check(name, request, &user, token)?;
+        let by = match token {
+            Some(_) => "token holder".to_string(),
+            None => format!("{user}@{}", helper::current_host()),
+        };
+        info!("Up of service {} {} by {}", name, action.event(), by);
+        service.approvals.push(Step {
+            time: Timestamp::now(),
+            action,
+            by,
+            detail,
+        });
+        Ok(service.request.take().expect("checked above"))
+    }
+
+    /// launch launches one service, see up. Every launch goes through it, so a protected service
+    /// is refused unless approved. The entry of the service is only locked to read what the
+    /// launch needs and to record its outcome, not while the service is provisioned.
+    #[allow(clippy::too_many_arguments)]
+    fn launch(
+        &self,
+        name: String,
+        skip_prompt: Option<bool>,
+        allow_drift: Option<bool>,
+        retries: Option<u32>,
+        fallback: Option<Vec<String>>,
+        progress: Option<PyObject>,
+        confirm: Option<PyObject>,
+        environment: Option<String>,
+        override_policies: bool,
+        approved: bool,
+    ) -> Result<(), ServicingError> {
+        let mut timings = UpTimings::new();
+        let (started, template, filepath, environment) = {
+            let Some(mut service) = self.service.get_mut(&name) else {
+                return Err(ServicingError::ServiceNotFound(name));
+            };
+            if service.external {
+                return Err(ServicingError::ExternalService(name));
+            }
+            if service.protection.is_some() && !approved {
+                return Err(ServicingError::ApprovalRequired(name));
+            }
+            // check if service is either up or starting
+            if service.url.is_some() {
+                return Err(ServicingError::ClusterProvisionError(format!(
+                    "Service {} is starting or already up",
+                    name
+                )));
+            }
+
+            if let (Some(filepath), Some(expected)) = (&service.filepath, &service.config_hash) {
+                let on_disk = helper::content_hash(&helper::read_from_file(filepath)?);
+                if &on_disk != expected {
+                    if let Some(false) = allow_drift {
+                        return Err(ServicingError::ConfigDrift(name));
+                    }
+                    warn!(
+                        "Configuration {:?} of service {} was modified on disk, launching it anyway",
+                        filepath, name
+                    );
+                }
+            }
+
+            let started = Instant::now();
+            // the configuration in the environment is what gets launched
+            let (template, filepath) = service.render_in(&name, environment.as_deref())?;
+            service.environment = environment.clone();
+            layout::record_environment(&name, environment.as_deref())?;
+            timings.record("render", started.elapsed());
+            (started, template, filepath, environment)
+        };
+        let (input, prepared) = match self.service.get(&name) {
+            Some(service) => (
+                service.policy_input(&name, environment.as_deref(), &template),
+                Prepared::of(&service),
+            ),
+            None => return Err(ServicingError::ServiceNotFound(name)),
+        };
+
+        // refuse what the policies forbid before anything is built or provisioned
+        let phase = Instant::now();
+        let violations = self.policies.evaluate(&input)?;
+        timings.record("policies", phase.elapsed());
+        if !violations.is_empty() {
+            let described = policy::describe(&violations);
+            if !override_policies {
+                return Err(ServicingError::PolicyViolation(name, described));
+            }
+            warn!(
+                "Launching service {} despite its policy violations: {}",
+                name, described
+            );
+        }
+
+        // only SkyPilot provisions cloud resources, with a cost and a quota
+        let sky = prepared.orchestrator == Orchestrators::Skypilot;
+
+        // refuse configurations over budget before anything is built or provisioned
+        let phase = Instant::now();
+        let hourly_cost = match &prepared.budget {
+            _ if !sky => None,
+            Some(budget) => {
+                let dir = layout::root()?;
+                let hourly_cost = budget::estimate_hourly_cost(&name, &template, &dir)?;
+                budget.check(&name, hourly_cost, prepared.spent)?;
+                Some(hourly_cost)
+            }
+            // the spend is still tracked for cost_report, without failing the launch
+            None => match layout::root()
+                .and_then(|dir| budget::estimate_hourly_cost(&name, &template, &dir))
+            {
+                Ok(hourly_cost) => Some(hourly_cost),
+                Err(e) => {
+                    info!("Not tracking the spend of service {}: {e}", name);
+                    None
+                }
+            },
+        };
+
+        // report a zero GPU quota before SkyPilot goes through its failover
+        let strict = fallback.as_ref().is_none_or(Vec::is_empty);
+        if sky {
+            quota::check(&name, &template, prepared.placement.as_deref(), strict)?;
+        }
+        timings.record("checks", phase.elapsed());
+
+        // the caller approves the plan instead of SkyPilot prompting for it
+        let skip_prompt = match &confirm {
+            Some(confirm) => {
+                let phase = Instant::now();
+                confirm_launch(&name, &template, confirm)?;
+                timings.record("confirm", phase.elapsed());
+                Some(true)
+            }
+            None => skip_prompt,
+        };
+
+        info!("Launching the service with the configuration: {:?}", name);
+        let progress = Arc::new(Progress::new(&name, progress));
+        // ship a staged copy of the selected workdir files instead of the whole workdir
+        let workdir = match &prepared.packaging {
+            Some(packaging) if !template.workdir.is_empty() => {
+                let phase = Instant::now();
+                progress.report("package", Event::Start);
+                operation::advance(&name, "package");
+                let staged = layout::service_dir(&name)?.join(layout::WORKDIR_DIR);
+                let package = packaging
+                    .stage(
+                        &name,
+                        &PathBuf::from(&template.workdir),
+                        &staged,
+                        prepared.workdir_hash.as_deref(),
+                    )
+                    .inspect_err(|e| progress.report("package", Event::Failed(e.into())))?;
+                info!(
+                    "Shipping {} bytes of workdir for service {}{}",
+                    package.size,
+                    name,
+                    if package.reused { ", unchanged" } else { "" }
+                );
+                self.record(&name, |service| service.workdir_hash = Some(package.hash))?;
+                timings.record("package", phase.elapsed());
+                progress.report("package", Event::Done);
+                Some(package.dir)
+            }
+            _ => None,
+        };
+
+        // build and push the image the service runs from
+        let login = match &prepared.image_build {
+            Some(image_build) => {
+                let server = image::registry_server(&image_build.registry);
+                self.registries
+                    .lock()?
+                    .get(server)
+                    .map(|credentials| credentials.login(server))
+                    .transpose()?
+            }
+            None => None,
+        };
+        let image = match &prepared.image_build {
+            Some(image_build) => {
+                let phase = Instant::now();
+                progress.report("image", Event::Start);
+                operation::advance(&name, "image");
+                let image = image_build
+                    .build_and_push(&name, login.as_ref(), progress.is_enabled())
+                    .inspect_err(|e| progress.report("image", Event::Failed(e.into())))?;
+                self.record(&name, |service| service.image = Some(image.clone()))?;
+                timings.record("image", phase.elapsed());
+                progress.report("image", Event::Done);
+                Some(image)
+            }
+            None => None,
+        };
+
+        // a new key is issued at every launch
+        let api_key = match prepared.api_key_enabled {
+            true => Some(ApiKey(helper::generate_api_key()?)),
+            false => None,
+        };
+        self.record(&name, |service| service.api_key = api_key.clone())?;
+
+        let fallback = fallback.unwrap_or_default();
+        let retries = retries.unwrap_or(fallback.len() as u32);
+
+        // launch the cluster, capacity failures move on to the next fallback placement
+        let mut attempt = 0;
+        progress.report("provision", Event::Start);
+        operation::advance(&name, "provision");
+        let failed = |mut timings: UpTimings, e: ServicingError| -> Result<(), ServicingError> {
+            timings.record("provision", started.elapsed());
+            self.record(&name, |service| service.push_timings(timings))?;
+            progress.report("provision", Event::Failed((&e).into()));
+            Err(e)
+        };
+        // Docker and Kubernetes know the endpoint as soon as the service is applied
+        let mut endpoint = None;
+        let mut placement = None;
+        if !sky {
+            let launch = Launch {
+                placement: None,
+                workdir: workdir.as_deref(),
+                image: image.as_deref(),
+                registry: login.as_ref(),
+                api_key: api_key.as_ref().map(|k| k.0.as_str()),
+            };
+            match provision(
+                &name,
+                prepared.orchestrator,
+                &template,
+                &launch,
+                progress.is_enabled(),
+            ) {
+                Ok(url) => endpoint = Some(url),
+                Err(e) => return failed(timings, e),
+            }
+        } else {
+            loop {
+                placement = match attempt {
+                    0 => None,
+                    n => fallback
+                        .get(n as usize - 1)
+                        .or(fallback.last())
+                        .map(String::as_str),
+                };
+                let launch = Launch {
+                    placement,
+                    workdir: workdir.as_deref(),
+                    image: image.as_deref(),
+                    registry: login.as_ref(),
+                    api_key: api_key.as_ref().map(|k| k.0.as_str()),
+                };
+                match sky_serve_up(&name, &filepath, skip_prompt, &launch, &progress) {
+                    Ok(_) => break,
+                    Err(e @ (ServicingError::NoCapacity(_) | ServicingError::QuotaExceeded(_)))
+                        if attempt < retries =>
+                    {
+                        attempt += 1;
+                        warn!("{e}, retrying (attempt {})", attempt + 1);
+                        progress.report("provision", Event::Retry((&e).into()));
+                        // a failed launch can leave the service registered with the controller
+                        if helper::sky_service_exists(&name)? {
+                            let _permit = limiter::acquire(&format!("sky serve down {name}"))?;
+                            runner::run(
+                                &mut sky_serve_down_command(&name, Some(true)),
+                                Mode::Interactive { echo: true },
+                            )?;
+                        }
+                    }
+                    Err(e) => return failed(timings, e),
+                }
+            }
+        }
+        self.record(&name, |service| {
+            if sky {
+                service.placement = placement.map(str::to_string);
+            }
+            service.attempts = attempt + 1;
+            if let Some(hourly_cost) = hourly_cost {
+                service.spend.start(hourly_cost, models::unix_now());
+            }
+            service.needs_update = false;
+        })?;
+        // skypilot runs the optimizer, provisioning and setup in the one process
+        timings.record("provision", started.elapsed());
+        progress.report("provision", Event::Done);
+
+        // get the url of the service
+        let phase = Instant::now();
+        progress.report("endpoint", Event::Start);
+        operation::advance(&name, "endpoint");
+        let url = match endpoint {
+            Some(url) => url,
+            None => {
+                let permit = limiter::acquire(&format!("sky serve status {name}"))?;
+                let output = runner::run(
+                    Command::new("sky").arg("serve").arg("status").arg(&name),
+                    Mode::Capture,
+                )?
+                .stdout;
+                drop(permit);
+
+                // parse the output to get the url
+                let output = String::from_utf8_lossy(&output);
+
+                REGEX_URL
+                    .get()
+                    .ok_or(ServicingError::General("Could not get REGEX".to_string()))?
+                    .find(&output)
+                    .ok_or(ServicingError::General(
+                        "Cannot find service URL".to_string(),
+                    ))
+                    .inspect_err(|e| progress.report("endpoint", Event::Failed(e.into())))?
+                    .as_str()
+                    .to_string()
+            }
+        };
+        if let Some(ingress) = &prepared.ingress {
+            ingress
+                .apply(&name, &url)
+                .inspect_err(|e| progress.report("endpoint", Event::Failed(e.into())))?;
+        }
+
+        timings.record("endpoint", phase.elapsed());
+        self.record(&name, |service| {
+            service.push_timings(timings);
+            service.url = Some(url.to_string());
+        })?;
+        progress.report("endpoint", Event::Done);
+        progress.report("ready", Event::Start);
+        operation::advance(&name, "ready");
+
+        self.watch_ready(&name, &url, progress, Some(started))
+    }
+
+    /// record updates the entry of the service with what a launch found out, the entry being
+    /// locked for the update only.
+    fn record(&self, name: &str, update: impl FnOnce(&mut Service)) -> Result<(), ServicingError> {
+        match self.service.get_mut(name) {
+            Some(mut service) => {
+                update(&mut service);
+                Ok(())
+            }
+            None => Err(ServicingError::ServiceNotFound(name.to_string())),
+        }
+    }
+
+    /// teardown tears one service down, see down.
+    fn teardown(
+        &self,
+        name: String,
+        skip_prompt: Option<bool>,
+        force: Option<bool>,
+        purge: Option<bool>,
+        wait: Option<bool>,
+        timeout: Option<u64>,
+    ) -> Result<(), ServicingError> {
+        let purge = purge.unwrap_or(false);
+        // the service is only gone once the orchestrator no longer reports it
+        let wait = wait.unwrap_or(false) || purge;
+        // get the service configuration
+        let backend = match self.service.get(&name) {
+            Some(service) if service.external => return Err(ServicingError::ExternalService(name)),
+            Some(service) => {
+                service.check_owner(&name, force.unwrap_or(false))?;
+                service.backend(&name)
+            }
+            None => return Err(ServicingError::ServiceNotFound(name)),
+        };
+        match self.service.get_mut(&name) {
+            Some(mut service) if service.up || service.url.is_some() => {
+                // Update service status
+                service.url = None;
+                service.up = false;
+                service.paused = None;
+                service.spend.stop(models::unix_now());
             }
             Some(_) => match force {
                 Some(true) => {}
@@ -292,245 +4630,1277 @@ impl Dispatcher {
             },
             None => return Err(ServicingError::ServiceNotFound(name)),
         }
+        self.scaler.disable(&name)?;
         info!("Destroying the service with the configuration: {:?}", name);
-        // launch the cluster
-        let mut cmd = Command::new("sky");
-        cmd.arg("serve").arg("down").arg(&name);
-        if let Some(true) = skip_prompt {
-            cmd.arg("-y");
+        operation::advance(&name, "teardown");
+        let output = match &backend {
+            Backend::Docker => docker::down(&name)?,
+            backend => {
+                let mut cmd = backend.down_command(&name, skip_prompt);
+                if purge && matches!(backend, Backend::Skypilot) {
+                    // also drops the record of a service that failed to go down
+                    cmd.arg("--purge");
+                }
+                let _permit = limiter::acquire(&runner::describe(&cmd))?;
+                runner::run(&mut cmd, Mode::Interactive { echo: true })?.status
+            }
+        };
+
+        operation::advance(&name, "release");
+        let ingress = match self.service.get(&name) {
+            Some(service) => service.ingress.clone(),
+            None => None,
+        };
+        if let Some(ingress) = ingress {
+            if let Err(e) = ingress.delete(&name) {
+                warn!("Removing the ingress of service {} failed: {e}", name);
+            }
+        }
+
+        // replicas may still be terminating once sky serve down returns
+        if wait {
+            if !output.success() {
+                return Err(ServicingError::ClusterProvisionError(format!(
+                    "Tearing down service {} failed with code {:?}",
+                    name, output
+                )));
+            }
+            let timeout = timeout.unwrap_or(DOWN_WAIT_TIMEOUT);
+            let deadline = Instant::now() + Duration::from_secs(timeout);
+            while backend.exists(&name)? {
+                if Instant::now() >= deadline {
+                    return Err(ServicingError::ClusterProvisionError(format!(
+                        "Service {} is still reported by {} {}s after down",
+                        name,
+                        backend.orchestrator(),
+                        timeout
+                    )));
+                }
+                info!(
+                    "Waiting for the resources of service {} to be released",
+                    name
+                );
+                std::thread::sleep(DOWN_CHECK_INTERVAL);
+            }
+        }
+
+        if purge {
+            if let Some(mut service) = self.service.get_mut(&name) {
+                service.openapi = None;
+                service.latency = None;
+            }
+            layout::remove_renders(&name)?;
+        }
+
+        Ok(())
+    }
+
+    /// groups returns the services of the replica groups of name, after checking it exists.
+    fn groups(&self, name: &str) -> Result<Vec<String>, ServicingError> {
+        match self.service.get(name) {
+            Some(service) => Ok(service.groups.clone()),
+            None => Err(ServicingError::ServiceNotFound(name.to_string())),
+        }
+    }
+
+    /// resolve returns the service name and url that a request to name should go to.
+    fn resolve(&self, name: &str) -> Result<(String, String), ServicingError> {
+        let endpoint = resolve(&self.aliases, &self.service, &self.scaler, name)?;
+        let tunnel = match self.service.get(&endpoint.service) {
+            Some(service) => service.tunnel.clone(),
+            None => None,
+        };
+        let url = self.route(&endpoint.service, tunnel.as_ref(), &endpoint.url)?;
+        Ok((endpoint.service, url))
+    }
+
+    /// client returns the HTTP client going through proxy, the shared one without.
+    fn client(&self, proxy: Option<&str>) -> Result<Client, ServicingError> {
+        match proxy {
+            Some(proxy) => shared::proxied(proxy),
+            None => Ok(self.client.clone()),
+        }
+    }
+
+    /// client_for returns the HTTP client the requests to the service go through.
+    fn client_for(&self, service: &str) -> Result<Client, ServicingError> {
+        let proxy = match self.service.get(service) {
+            Some(service) => service.proxy.clone(),
+            None => None,
+        };
+        self.client(proxy.as_deref())
+    }
+
+    /// route returns url pointed at the local end of the SSH tunnel of the service if it has
+    /// one, opening the tunnel if it is not running yet.
+    fn route(
+        &self,
+        name: &str,
+        tunnel: Option<&SshTunnel>,
+        url: &str,
+    ) -> Result<String, ServicingError> {
+        let Some(config) = tunnel else {
+            return Ok(url.to_string());
+        };
+        let target = tunnel::target(url);
+        let mut tunnels = self.tunnels.lock()?;
+        let serving = tunnels
+            .get_mut(name)
+            .is_some_and(|open| open.serves(config, &target));
+        if !serving {
+            tunnels.insert(name.to_string(), OpenTunnel::open(config, &target)?);
+        }
+        match tunnels.get(name) {
+            Some(open) => Ok(open.rewrite(url)),
+            None => Err(ServicingError::General(format!(
+                "No tunnel for service {name}"
+            ))),
+        }
+    }
+
+    /// outbound sends a request to a service on the runtime without holding the GIL. The
+    /// middleware, rate limit and circuit breaker of the service are applied and its latency
+    /// recorded. Error statuses are turned into errors.
+    fn outbound(
+        &self,
+        py: Python<'_>,
+        service: &str,
+        mut req: OutboundRequest,
+    ) -> Result<Response, ServicingError> {
+        if let Some(s) = self.service.get(service) {
+            req.authorize(s.api_key.as_ref().map(|k| k.0.as_str()))?;
+        }
+        self.middleware.before(py, service, &mut req)?;
+
+        let client = self.client_for(service)?;
+        let (res, elapsed) = py.allow_threads(|| {
+            self.rt.block_on(deadline::bound(async {
+                self.scaler.wake(&client, service).await?;
+                self.traffic.acquire(service).await?;
+                let start = Instant::now();
+                let res = req.send(&client).await;
+                self.traffic.record(
+                    service,
+                    res.as_ref().is_ok_and(|r| !r.status().is_server_error()),
+                );
+                Ok::<_, ServicingError>((res, start.elapsed()))
+            }))
+        })??;
+
+        self.middleware.after(
+            py,
+            service,
+            &req,
+            &Outcome {
+                status: res.as_ref().ok().map(|r| r.status().as_u16()),
+                elapsed,
+                error: res.as_ref().err().map(|e| e.to_string()),
+            },
+        );
+
+        let response = res?.error_for_status()?;
+        self.record_latency(service, elapsed)?;
+        Ok(response)
+    }
+
+    fn check_exists(&self, name: &str) -> Result<(), ServicingError> {
+        if self.service.contains_key(name) {
+            return Ok(());
+        }
+        Err(ServicingError::ServiceNotFound(name.to_string()))
+    }
+
+    fn record_latency(&self, name: &str, latency: Duration) -> Result<(), ServicingError> {
+        if let Some(mut service) = self.service.get_mut(name) {
+            service.latency = Some(latency);
+        }
+        Ok(())
+    }
+
+    fn openai_request(
+        &self,
+        py: Python<'_>,
+        name: &str,
+        endpoint: OpenAIEndpoint,
+        body: serde_json::Value,
+    ) -> Result<PyObject, ServicingError> {
+        let (service, url) = self.resolve(name)?;
+        let stream = matches!(body.get("stream"), Some(serde_json::Value::Bool(true)));
+
+        let req = OutboundRequest::openai(&url, endpoint, &body)?;
+        let response = self.outbound(py, &service, req)?;
+        if stream {
+            let stream = CompletionStream::new(response, self.rt.handle().clone());
+            return Ok(Py::new(py, stream)?.into_py(py));
+        }
+        let text =
+            py.allow_threads(|| self.rt.block_on(deadline::bound(client::text(response))))??;
+        Ok(text.into_py(py))
+    }
+
+    fn cached_openapi(&self, name: &str) -> Result<serde_json::Value, ServicingError> {
+        match self.service.get(name).as_deref() {
+            Some(Service {
+                openapi: Some(spec),
+                ..
+            }) => Ok(serde_json::from_str(spec)?),
+            Some(_) => Err(ServicingError::General(format!(
+                "No OpenAPI document cached for service {name}, call openapi first"
+            ))),
+            None => Err(ServicingError::ServiceNotFound(name.to_string())),
+        }
+    }
+
+    /// plan_launch records the commands and manifests launch would run for the service, by
+    /// running its steps with a DryRunRunner.
+    fn plan_launch(
+        &self,
+        name: &str,
+        skip_prompt: Option<bool>,
+        environment: Option<&str>,
+        plan: &mut Plan,
+    ) -> Result<(), ServicingError> {
+        let service = self
+            .service
+            .get(name)
+            .ok_or(ServicingError::ServiceNotFound(name.to_string()))?;
+        if service.external {
+            return Err(ServicingError::ExternalService(name.to_string()));
+        }
+        let (template, filepath) = service.render_in(name, environment)?;
+        let workdir = match &service.packaging {
+            Some(_) if !template.workdir.is_empty() => {
+                Some(layout::service_dir(name)?.join(layout::WORKDIR_DIR))
+            }
+            _ => None,
+        };
+
+        let dry_run = Arc::new(DryRunRunner::default());
+        runner::with_runner(dry_run.clone(), || {
+            let login = match &service.image_build {
+                Some(image_build) => {
+                    let server = image::registry_server(&image_build.registry);
+                    self.registries
+                        .lock()?
+                        .get(server)
+                        .map(|credentials| credentials.login(server))
+                        .transpose()?
+                }
+                None => None,
+            };
+            let image = service
+                .image_build
+                .as_ref()
+                .map(|image_build| image_build.build_and_push(name, login.as_ref(), true))
+                .transpose()?;
+            let launch = Launch {
+                placement: None,
+                workdir: workdir.as_deref(),
+                image: image.as_deref(),
+                registry: login.as_ref(),
+                // only the name of the variable is on the command line
+                api_key: service.api_key_enabled.then_some(""),
+            };
+            match service.orchestrator {
+                Orchestrators::Docker | Orchestrators::Kubernetes => {
+                    provision(name, service.orchestrator, &template, &launch, true)?;
+                }
+                Orchestrators::Skypilot => sky_serve_up(
+                    name,
+                    &filepath,
+                    skip_prompt,
+                    &launch,
+                    &Progress::new(name, None),
+                )?,
+            }
+            // the endpoint is only known once launched
+            if let Some(ingress) = &service.ingress {
+                let port = template.resources.ports;
+                ingress.apply(name, &format!("<endpoint>:{port}"))?;
+            }
+            Ok::<_, ServicingError>(())
+        })?;
+        plan.extend(name, dry_run.take());
+        Ok(())
+    }
+
+    /// plan_teardown records the commands teardown would run for the service.
+    fn plan_teardown(
+        &self,
+        name: &str,
+        skip_prompt: Option<bool>,
+        force: Option<bool>,
+        plan: &mut Plan,
+    ) -> Result<(), ServicingError> {
+        let service = self
+            .service
+            .get(name)
+            .ok_or(ServicingError::ServiceNotFound(name.to_string()))?;
+        if service.external {
+            return Err(ServicingError::ExternalService(name.to_string()));
+        }
+        service.check_owner(name, force.unwrap_or(false))?;
+        if !service.up && service.url.is_none() && force != Some(true) {
+            return Err(ServicingError::ServiceNotUp(name.to_string()));
+        }
+
+        let dry_run = Arc::new(DryRunRunner::default());
+        runner::with_runner(dry_run.clone(), || {
+            runner::run(
+                &mut service.backend(name).down_command(name, skip_prompt),
+                Mode::Interactive { echo: true },
+            )?;
+            match &service.ingress {
+                Some(ingress) => ingress.delete(name),
+                None => Ok(()),
+            }
+        })?;
+        plan.extend(name, dry_run.take());
+        Ok(())
+    }
+
+    /// output_log returns the log keeping the output of the commands run for the service.
+    fn output_log(&self, name: &str) -> Result<Arc<OutputLog>, ServicingError> {
+        Ok(self
+            .outputs
+            .lock()?
+            .entry(name.to_string())
+            .or_default()
+            .clone())
+    }
+
+    /// logged runs operation on the service with the output of its commands kept in memory and
+    /// in the logs folder of the service.
+    fn logged<T>(
+        &self,
+        name: &str,
+        operation: &str,
+        f: impl FnOnce() -> Result<T, ServicingError>,
+    ) -> Result<T, ServicingError> {
+        let log = self.output_log(name)?;
+        let result = runner::with_output_log(log.clone(), f);
+        if let Err(e) = layout::write_log(name, operation, &log.text()) {
+            warn!("Could not keep the {operation} log of service {name}: {e}");
+        }
+        result
+    }
+
+    /// hooked runs f, an operation on the service, between the hooks registered for it. f is
+    /// handed the name of the service.
+    fn hooked<T>(
+        &self,
+        operation: &str,
+        name: &str,
+        f: impl FnOnce(String) -> Result<T, ServicingError>,
+    ) -> Result<T, ServicingError> {
+        let context = self.hook_context(operation, name)?;
+        Python::with_gil(|py| self.hooks.before(py, &context))?;
+        // the sinks of a removed service are notified as well
+        let sinks = match self.service.get(name) {
+            Some(service) => service.notifications.clone(),
+            None => Vec::new(),
+        };
+        let recorded = match operation {
+            "up" | "down" => self.begin(operation, name)?,
+            _ => None,
+        };
+        let (result, interrupted) = interrupt::watch(|| f(name.to_string()));
+        // an interrupted operation is left for recover to finish or roll back
+        let result = match result {
+            Err(e) if interrupted => {
+                warn!("{operation} of service {name} was interrupted: {e}");
+                Err(ServicingError::Interrupted(format!(
+                    "{operation} of service {name}"
+                )))
+            }
+            result => result,
+        };
+        if let Some(recorded) = recorded {
+            let kept = match &result {
+                Err(ServicingError::Interrupted(_)) => operation::interrupt(name),
+                _ => recorded.clear(),
+            };
+            if let Err(e) = kept {
+                warn!(
+                    "Could not update the record of {} {}: {e}",
+                    operation, recorded.id
+                );
+            }
+        }
+        if let Some(mut service) = self.service.get_mut(name) {
+            service.last_error = result.as_ref().err().map(|e| e.to_string());
+        }
+        let context = self.hook_context(operation, name)?;
+        Python::with_gil(|py| self.hooks.after(py, &context, result.as_ref().err()));
+        if let Err(e) = self.notify(operation, name, sinks, result.as_ref().err()) {
+            warn!("Could not notify the {operation} of service {name}: {e}");
+        }
+        result
+    }
+
+    /// begin records operation on the service before it runs, once an operation interrupted
+    /// before it is reconciled. An operation still in flight in another process is an error.
+    fn begin(&self, operation: &str, name: &str) -> Result<Option<Operation>, ServicingError> {
+        if !self.service.contains_key(name) {
+            return Ok(None);
+        }
+        if let Some(previous) = operation::recorded(name)? {
+            if previous.in_flight() {
+                return Err(ServicingError::OperationInProgress(
+                    name.to_string(),
+                    previous.operation,
+                    previous.id,
+                ));
+            }
+            self.reconcile(&previous)?;
+            previous.clear()?;
+        }
+
+        let recorded = Operation::new(name, operation);
+        recorded.record()?;
+        if let Some(mut service) = self.service.get_mut(name) {
+            service.last_operation = Some(recorded.id.clone());
+        }
+        Ok(Some(recorded))
+    }
+
+    /// reconcile brings the registry in line with what the orchestrator reports of the service of
+    /// an interrupted operation. The record is kept for recover to finish or roll the operation
+    /// back.
+    fn reconcile(&self, interrupted: &Operation) -> Result<(), ServicingError> {
+        warn!(
+            "{} {} of service {} was interrupted in phase {}, checking the service",
+            interrupted.operation, interrupted.id, interrupted.service, interrupted.phase
+        );
+        let (running, url) = self.serve_state(&interrupted.service)?;
+        self.settle(&interrupted.service, running, url)
+    }
+
+    /// settle records whether the service runs and where. Readiness is left to the status checks.
+    fn settle(&self, name: &str, running: bool, url: Option<String>) -> Result<(), ServicingError> {
+        if let Some(mut service) = self.service.get_mut(name) {
+            match (running, service.url.is_some()) {
+                (false, true) => {
+                    info!("Service {} is not running, marking it down", name);
+                    service.url = None;
+                    service.up = false;
+                    service.spend.stop(models::unix_now());
+                }
+                (true, false) => {
+                    info!("Service {} is running at {:?}", name, url);
+                    service.url = url;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// interrupted returns the operations of the registered services, of name only if provided,
+    /// that were interrupted with their process.
+    fn interrupted(&self, name: Option<&str>) -> Result<Vec<Operation>, ServicingError> {
+        Ok(operation::all()?
+            .into_iter()
+            .filter(|o| name.is_none_or(|name| o.service == name))
+            .filter(|o| self.service.contains_key(&o.service) && !o.in_flight())
+            .collect())
+    }
+
+    /// notify posts the outcome of operation on the service to sinks and to the sinks of every
+    /// service, in the background.
+    fn notify(
+        &self,
+        operation: &str,
+        name: &str,
+        mut sinks: Vec<Sink>,
+        error: Option<&ServicingError>,
+    ) -> Result<(), ServicingError> {
+        sinks.extend(self.notifications.lock()?.iter().cloned());
+        sinks.retain(|sink| sink.applies_to(operation));
+        if sinks.is_empty() {
+            return Ok(());
+        }
+
+        let payloads: Vec<_> = {
+            let entry = self.service.get(name);
+            let service = entry.as_deref();
+            let state = match (error, service) {
+                (Some(_), _) => "failed",
+                (None, Some(s)) => s.state(name).name(),
+                (None, None) => "removed",
+            };
+            let message = Message {
+                service: name,
+                operation,
+                state,
+                url: service.and_then(|s| s.url.as_deref()),
+                cost: service.map(|s| s.spend.total(models::unix_now())),
+                error: error.map(|e| e.to_string()),
+            };
+            sinks
+                .iter()
+                .map(|sink| (sink.kind, sink.url.clone(), sink.payload(&message)))
+                .collect()
+        };
+        let client = self.client.clone();
+        self.rt.spawn(async move {
+            for (kind, url, payload) in payloads {
+                let sent = client
+                    .post(&url)
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(payload.to_string())
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status());
+                // the url of a webhook is its secret, keep it out of the logs
+                if let Err(e) = sent {
+                    warn!("{:?} notification failed: {}", kind, e.without_url());
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// hook_context describes the service to the hooks of operation.
+    fn hook_context<'a>(
+        &self,
+        operation: &'a str,
+        name: &'a str,
+    ) -> Result<hooks::Context<'a>, ServicingError> {
+        let entry = self.service.get(name);
+        let service = entry.as_deref();
+        Ok(hooks::Context {
+            service: name,
+            operation,
+            orchestrator: service.map(|s| s.orchestrator.name()),
+            owner: service
+                .and_then(|s| s.owner.as_ref())
+                .map(|owner| format!("{}@{}", owner.user, owner.host)),
+            url: service.and_then(|s| s.url.clone()),
+            config: service.map(|s| s.template.render()).transpose()?,
+        })
+    }
+
+    /// result describes the service after operation, started at started.
+    fn result(
+        &self,
+        operation: &str,
+        name: &str,
+        started: Instant,
+        groups: Vec<String>,
+    ) -> Result<OperationResult, ServicingError> {
+        match self.service.get(name) {
+            Some(service) => Ok(OperationResult {
+                operation: operation.to_string(),
+                service: name.to_string(),
+                filepath: service
+                    .filepath
+                    .as_ref()
+                    .map(|path| path.display().to_string()),
+                endpoint: service.stable_endpoint.clone().or(service.url.clone()),
+                duration: started.elapsed().as_secs_f64(),
+                orchestrator: service.orchestrator.name().to_string(),
+                // SkyPilot knows the service under its name while it is launched
+                orchestrator_id: service.url.as_ref().map(|_| name.to_string()),
+                groups,
+                operation_id: match operation {
+                    "up" | "down" => service.last_operation.clone(),
+                    _ => None,
+                },
+            }),
+            None => Err(ServicingError::ServiceNotFound(name.to_string())),
+        }
+    }
+
+    /// reconfigure applies change to the configuration of the service and renders it again. The
+    /// configuration is left as is if change fails.
+    fn reconfigure(
+        &self,
+        name: &str,
+        change: impl FnOnce(&mut Configuration) -> Result<(), ServicingError>,
+    ) -> Result<(), ServicingError> {
+        let mut service = self
+            .service
+            .get_mut(name)
+            .ok_or(ServicingError::ServiceNotFound(name.to_string()))?;
+        if service.external {
+            return Err(ServicingError::ExternalService(name.to_string()));
+        }
+        let mut template = service.template.clone();
+        change(&mut template)?;
+
+        let content = template.render()?;
+        if let Some(filepath) = &service.filepath {
+            helper::write_to_file(filepath, &content)?;
+        }
+        service.config_hash = Some(helper::content_hash(&content));
+        service.template = template;
+        if service.url.is_some() {
+            info!("Service {} needs an update to apply the change", name);
+            service.needs_update = true;
+        }
+        Ok(())
+    }
+
+    /// track_cache records the content of the cache file at path as in sync and watches the file,
+    /// merging the saves of other processes into the registry as they happen.
+    fn track_cache(&self, path: &Path, bin: &[u8]) -> Result<(), ServicingError> {
+        let tracked = self
+            .synced
+            .lock()?
+            .insert(path.to_path_buf(), helper::content_hash(bin))
+            .is_some();
+        if tracked {
+            return Ok(());
+        }
+
+        let watch_error =
+            |e: notify::Error| ServicingError::General(format!("Cannot watch the cache: {e}"));
+        let mut watcher = self.cache_watcher.lock()?;
+        if watcher.is_none() {
+            let services = self.service.clone();
+            let synced = self.synced.clone();
+            let handler = move |event: notify::Result<notify::Event>| {
+                let Ok(event) = event else {
+                    return;
+                };
+                if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                    return;
+                }
+                for path in &event.paths {
+                    if path.file_name().is_some_and(|file| file == CACHE_FILE_NAME) {
+                        if let Err(e) = refresh(&services, &synced, path) {
+                            warn!("Cannot refresh the services from {:?}: {e}", path);
+                        }
+                    }
+                }
+            };
+            *watcher = Some(notify::recommended_watcher(handler).map_err(watch_error)?);
+        }
+        if let (Some(watcher), Some(dir)) = (watcher.as_mut(), path.parent()) {
+            watcher
+                .watch(dir, RecursiveMode::NonRecursive)
+                .map_err(watch_error)?;
+        }
+        Ok(())
+    }
+
+    /// register_service renders the service configuration to the folder of the service and
+    /// adds the service to the dispatcher.
+    fn register_service(&self, name: String, mut service: Service) -> Result<(), ServicingError> {
+        let file = layout::service_dir(&name)?.join(layout::CONFIG_FILE);
+        let created = models::unix_now();
+
+        // tag the cloud resources of the service for traceability
+        let user = service.owner.as_ref().map(|owner| owner.user.as_str());
+        let tags = logic::tags(&name, user.unwrap_or("unknown"), created);
+        service.template.resources.labels.extend(tags);
+
+        // write the configuration to the file
+        let content = service.template.render()?;
+        helper::write_to_file(&file, &content)?;
+        let metadata = layout::Metadata {
+            name: &name,
+            orchestrator: service.orchestrator.name(),
+            owner: service
+                .owner
+                .as_ref()
+                .map(|owner| format!("{}@{}", owner.user, owner.host)),
+            created,
+            layout: layout::LAYOUT_VERSION,
+            environment: None,
+        };
+        layout::write_metadata(&name, &metadata)?;
+
+        service.config_hash = Some(helper::content_hash(&content));
+        service.filepath = Some(file);
+
+        self.service.insert(name, service);
+
+        Ok(())
+    }
+}
+
+/// sky_serve_up_command returns the command launching the service with SkyPilot, applying the
+/// overrides of launch to the configuration.
+fn sky_serve_up_command(
+    name: &str,
+    filepath: &PathBuf,
+    skip_prompt: Option<bool>,
+    launch: &Launch,
+) -> Command {
+    let mut cmd = Command::new("sky");
+    cmd.arg("serve").arg("up").arg("-n").arg(name).arg(filepath);
+    if let Some(workdir) = launch.workdir {
+        cmd.arg("--workdir").arg(workdir);
+    }
+    if let Some(image) = launch.image {
+        cmd.arg("--image-id").arg(format!("docker:{image}"));
+    }
+    if let Some(login) = launch.registry {
+        // --env without a value makes SkyPilot read it from the environment, off the command line
+        for (key, value) in [
+            ("SKYPILOT_DOCKER_SERVER", &login.server),
+            ("SKYPILOT_DOCKER_USERNAME", &login.username),
+            ("SKYPILOT_DOCKER_PASSWORD", &login.password),
+        ] {
+            cmd.env(key, value).arg("--env").arg(key);
         }
-        let mut child = cmd.spawn()?;
+    }
+    if let Some(api_key) = launch.api_key {
+        cmd.env(API_KEY_ENV, api_key).arg("--env").arg(API_KEY_ENV);
+    }
 
-        child.wait()?;
+    if let Some(placement) = launch.placement {
+        info!("Launching service {} in {}", name, placement);
+        cmd.args(logic::placement_args(
+            placement,
+            Orchestrators::Skypilot.clouds(),
+        ));
+    }
 
-        Ok(())
+    if let Some(true) = skip_prompt {
+        cmd.arg("-y");
     }
+    cmd
+}
 
-    pub fn status(&mut self, name: String, pretty: Option<bool>) -> Result<String, ServicingError> {
-        // Check if the service exists
-        if let Some(service) = self.service.lock()?.get_mut(&name) {
-            info!("Checking the status of the service: {:?}", name);
+/// sky_serve_down_command returns the command tearing the service down.
+fn sky_serve_down_command(name: &str, skip_prompt: Option<bool>) -> Command {
+    let mut cmd = Command::new("sky");
+    cmd.arg("serve").arg("down").arg(name);
+    if let Some(true) = skip_prompt {
+        cmd.arg("-y");
+    }
+    cmd
+}
 
-            // if service is up poll once to see if it's still up
-            if let (true, Some(url)) = (service.up, &service.url) {
-                let url = format!(
-                    "http://{}{}",
-                    url, &service.template.service.readiness_probe
+/// provision runs the service with template on its Docker or Kubernetes orchestrator and
+/// returns its endpoint. SkyPilot launches go through sky_serve_up.
+fn provision(
+    name: &str,
+    orchestrator: Orchestrators,
+    template: &Configuration,
+    launch: &Launch,
+    quiet: bool,
+) -> Result<String, ServicingError> {
+    let env: Vec<(&str, &str)> = launch
+        .api_key
+        .map(|k| (API_KEY_ENV, k))
+        .into_iter()
+        .collect();
+    match orchestrator {
+        Orchestrators::Docker => {
+            let container = Container {
+                base: launch.image,
+                workdir: launch.workdir,
+                login: launch.registry,
+                env,
+            };
+            docker::up(name, template, &container, quiet)
+        }
+        Orchestrators::Kubernetes => {
+            if launch.workdir.is_some() || !template.workdir.is_empty() {
+                info!(
+                    "The workdir of service {} is not shipped to Kubernetes, bake it in the image",
+                    name
                 );
-
-                let r = self.rt.block_on(async {
-                    let res = helper::fetch(&self.client, &url).await;
-                    match res {
-                        Ok(resp) => {
-                            if resp.to_lowercase().contains(REPLICA_UP_CHECK) {
-                                Err(ServicingError::ServiceNotUp(name.clone()))
-                            } else {
-                                // it's up
-                                Ok(())
-                            }
-                        }
-                        Err(e) => Err::<(), _>(ServicingError::General(e.to_string())),
-                    }
-                });
-
-                match r {
-                    Ok(_) => {
-                        //No-op
-                        info!("Service {} is up", name);
-                    }
-                    Err(e) => {
-                        warn!("{:?}", e);
-                        service.up = false;
-                    }
-                }
             }
-
-            return Ok(match pretty {
-                Some(true) => serde_json::to_string_pretty(service)?,
-                _ => serde_json::to_string(service)?,
-            });
+            let deployment = Deployment::new(name, template);
+            let image = launch.image.unwrap_or(docker::BASE_IMAGE);
+            deployment.apply(template, image, &env)?;
+            deployment.endpoint()
         }
-        Err(ServicingError::ServiceNotFound(name))
+        Orchestrators::Skypilot => Err(ServicingError::General(format!(
+            "Service {name} is launched with SkyPilot, not provisioned"
+        ))),
     }
+}
 
-    pub fn save(&self, location: Option<PathBuf>) -> Result<(), ServicingError> {
-        let bin = bincode::serialize(&*self.service.lock()?)?;
-
-        helper::write_to_file_binary(
-            &helper::create_file(
-                &{
-                    if let Some(location) = location {
-                        helper::create_directory(
-                            location
-                                .to_str()
-                                .ok_or(ServicingError::General("Location is None".to_string()))?,
-                            false,
-                        )?
-                    } else {
-                        helper::create_directory(CACHE_DIR, true)?
-                    }
-                },
-                CACHE_FILE_NAME,
-            )?,
-            &bin,
-        )?;
-
-        Ok(())
+/// sky_serve_up launches the service with SkyPilot, applying the overrides of launch to the
+/// configuration. SkyPilot handles the CLI interaction, its output is echoed unless progress is
+/// reported, and kept to diagnose failures.
+fn sky_serve_up(
+    name: &str,
+    filepath: &PathBuf,
+    skip_prompt: Option<bool>,
+    launch: &Launch,
+    progress: &Progress,
+) -> Result<(), ServicingError> {
+    let mut cmd = sky_serve_up_command(name, filepath, skip_prompt, launch);
+    let _permit = limiter::acquire(&format!("sky serve up -n {name}"))?;
+    let echo = !progress.is_enabled();
+    let output = runner::run(&mut cmd, Mode::Interactive { echo })?;
+    if !output.status.success() {
+        let captured = [output.stdout, output.stderr].concat();
+        let captured = String::from_utf8_lossy(&captured);
+        return Err(
+            ServicingError::from_provision_output(name, &captured).unwrap_or(
+                ServicingError::ClusterProvisionError(format!(
+                    "Cluster provision failed with code {:?}",
+                    output.status
+                )),
+            ),
+        );
     }
+    Ok(())
+}
 
-    pub fn save_as_b64(&self) -> Result<String, ServicingError> {
-        let bin = bincode::serialize(&*self.service.lock()?)?;
-        let b64 = base64::prelude::BASE64_STANDARD.encode(bin);
-        Ok(b64)
+/// templates_dir is where the default templates of the orchestrators are kept.
+fn templates_dir() -> Result<PathBuf, ServicingError> {
+    Ok(layout::root()?.join(TEMPLATES_DIR))
+}
+
+/// check_environment refuses an environment name that cannot name its configuration file.
+fn check_environment(environment: &str) -> Result<(), ServicingError> {
+    layout::check_name("environment", environment)
+}
+
+/// base_path returns where the base configuration called name is kept.
+fn base_path(name: &str) -> Result<PathBuf, ServicingError> {
+    layout::check_name("base", name)?;
+    Ok(templates_dir()?
+        .join(BASES_DIR)
+        .join(format!("{name}.json")))
+}
+
+/// read_base returns the base configuration called name, None if it is not defined.
+fn read_base(name: &str) -> Result<Option<UserProvidedConfig>, ServicingError> {
+    let path = base_path(name)?;
+    match path.is_file() {
+        true => Ok(Some(serde_json::from_str(&helper::read_from_file(&path)?)?)),
+        false => Ok(None),
     }
+}
 
-    pub fn load(
-        &mut self,
-        location: Option<PathBuf>,
-        update_status: Option<bool>,
-    ) -> Result<(), ServicingError> {
-        let location = if let Some(location) = location {
-            helper::create_directory(
-                location
-                    .to_str()
-                    .ok_or(ServicingError::General("Location is None".to_string()))?,
-                false,
-            )?
-            .join(CACHE_FILE_NAME)
-        } else {
-            helper::create_directory(CACHE_DIR, true)?.join(CACHE_FILE_NAME)
-        };
+/// check_accelerators refuses MIG slices outside of Kubernetes, where they cannot be requested.
+fn check_accelerators(template: &Configuration) -> Result<(), ServicingError> {
+    if let Some(accelerators) = &template.resources.accelerators {
+        if Mig::parse(accelerators)?.is_some() && template.resources.cloud != "kubernetes" {
+            return Err(ServicingError::General(format!(
+                "MIG accelerators {accelerators} need the kubernetes cloud"
+            )));
+        }
+    }
+    Ok(())
+}
 
-        let bin = helper::read_from_file_binary(&location)?;
+/// confirm_launch calls confirm with the candidate the optimizer picks for the configuration,
+/// refusing the launch unless it returns a truthy value.
+fn confirm_launch(
+    name: &str,
+    config: &Configuration,
+    confirm: &PyObject,
+) -> Result<(), ServicingError> {
+    let dir = layout::root()?;
+    let quotes = quote::quote(config, true, &dir)?;
+    let chosen = quotes.iter().find(|q| q.chosen).or(quotes.first());
+    let replicas = config.service.replicas;
+    let hourly_price = chosen.and_then(|q| q.hourly_price);
 
-        self.service
-            .lock()?
-            .extend(bincode::deserialize::<HashMap<String, Service>>(&bin)?);
+    let approved = Python::with_gil(|py| -> PyResult<bool> {
+        let plan = PyDict::new_bound(py);
+        plan.set_item("service", name)?;
+        plan.set_item("cloud", chosen.map(|q| &q.cloud))?;
+        plan.set_item("instance", chosen.map(|q| &q.instance))?;
+        plan.set_item("region", chosen.map(|q| &q.region))?;
+        plan.set_item("accelerators", chosen.map(|q| &q.accelerators))?;
+        plan.set_item("replicas", replicas)?;
+        plan.set_item("hourly_price", hourly_price)?;
+        plan.set_item(
+            "hourly_cost",
+            hourly_price.map(|price| price * f64::from(replicas)),
+        )?;
+        confirm.call1(py, (plan,))?.bind(py).is_truthy()
+    })?;
+    match approved {
+        true => Ok(()),
+        false => Err(ServicingError::LaunchDeclined(name.to_string())),
+    }
+}
 
-        if let Some(true) = update_status {
-            info!("Checking for services that may come up while you were away...");
+/// refresh merges the cache file at path into services when another process saved it since it
+/// was last in sync: the saved services replace those of the registry, the services only known
+/// to this process are kept. It tells whether the cache had changed.
+fn refresh(
+    services: &DashMap<String, Service>,
+    synced: &Mutex<HashMap<PathBuf, String>>,
+    path: &Path,
+) -> Result<bool, ServicingError> {
+    let bin = match fs::read(path) {
+        Ok(bin) => bin,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+    let hash = helper::content_hash(&bin);
+    let mut synced = synced.lock()?;
+    match synced.get(path) {
+        Some(known) if *known != hash => {}
+        _ => return Ok(false),
+    }
 
-            // Clones to pass to threads
-            let service_clone = self.service.clone();
-            let client_clone = self.client.clone();
-            let mut service_to_check = Vec::new();
+    let saved = decode(&bin)?;
+    info!(
+        "Cache {:?} was saved by another process, refreshing {} services",
+        path,
+        saved.len()
+    );
+    for (name, service) in saved {
+        services.insert(name, service);
+    }
+    synced.insert(path.to_path_buf(), hash);
+    Ok(true)
+}
 
-            // iterate through the services and find that are down
-            self.service
-                .lock()?
-                .iter()
-                .filter(|(_, service)| !service.up && service.url.is_some())
-                .for_each(|(name, service)| {
-                    service_to_check.push((
-                        name.clone(),
-                        service
-                            .url
-                            .clone()
-                            .expect("Gettting url, this should never be None")
-                            + &service.template.service.readiness_probe,
-                    ))
-                });
+/// reload_config picks up the configuration file at path when it belongs to a service and was
+/// changed outside of the dispatcher, returning the name of the service and whether it parsed.
+fn reload_config(services: &DashMap<String, Service>, path: &Path) -> Option<(String, bool)> {
+    if path.file_name()? != layout::CONFIG_FILE {
+        return None;
+    }
+    let name = path.parent()?.file_name()?.to_str()?.to_string();
+    let content = fs::read_to_string(path).ok()?;
+    let hash = helper::content_hash(&content);
 
-            if service_to_check.is_empty() {
-                info!("No services to check");
-                return Ok(());
+    let mut service = services.get_mut(&name)?;
+    // the dispatcher records the hash of what it writes
+    if service.external || service.config_hash.as_ref() == Some(&hash) {
+        return None;
+    }
+    match Configuration::from_sky_yaml(&content) {
+        Ok(template) => {
+            info!(
+                "Configuration of service {} was edited on disk, reloading it",
+                name
+            );
+            service.template = template;
+            service.config_hash = Some(hash);
+            if service.url.is_some() {
+                service.needs_update = true;
             }
+            Some((name, true))
+        }
+        Err(e) => {
+            warn!(
+                "Configuration of service {} was edited on disk but is invalid: {e}",
+                name
+            );
+            Some((name, false))
+        }
+    }
+}
 
-            info!("Services to check: {:?}", service_to_check);
+/// report reports progress from the runtime, the callback needs the GIL so it is kept off the
+/// runtime workers.
+async fn report(progress: &Arc<Progress>, phase: &'static str, event: Event) {
+    if !progress.is_enabled() {
+        return;
+    }
+    let progress = progress.clone();
+    let _ = tokio::task::spawn_blocking(move || progress.report(phase, event)).await;
+}
 
-            self.rt.spawn(async move {
-                let mut handles = Vec::new();
-                for (name, url) in service_to_check {
-                    let client_clone = client_clone.clone();
-                    let url = format!("http://{}", url);
-                    let handle = tokio::spawn(async move {
-                        match helper::fetch_and_check(
-                            &client_clone,
-                            &url,
-                            REPLICA_UP_CHECK,
-                            Some(SERVICE_CHECK_INTERVAL),
-                        )
-                        .await
+/// resolve returns the endpoint that a request to name should go to. Aliases are resolved to one
+/// of their members that is up, or to one scaled to zero, to be woken up, when none is.
+fn resolve(
+    aliases: &Mutex<HashMap<String, Alias>>,
+    registry: &DashMap<String, Service>,
+    scaler: &Scaler,
+    name: &str,
+) -> Result<Endpoint, ServicingError> {
+    let mut aliases = aliases.lock()?;
+    let service = match aliases.get_mut(name) {
+        Some(alias) => {
+            let members = alias.members.clone();
+            let mut candidates = Vec::new();
+            for asleep in [false, true] {
+                candidates = members
+                    .iter()
+                    .filter_map(|member| match registry.get(member) {
+                        Some(s)
+                            if (s.up || asleep && scaler.enabled(member))
+                                && s.url.is_some()
+                                && s.maintenance.is_none() =>
                         {
-                            Ok(_) => {}
-                            Err(e) => {
-                                return Err(e);
-                            }
+                            Some(Candidate {
+                                name: member,
+                                latency: s.latency,
+                            })
                         }
-                        Ok(name)
-                    });
-                    handles.push(handle);
+                        _ => None,
+                    })
+                    .collect();
+                if !candidates.is_empty() {
+                    break;
                 }
-                for res in join_all(handles).await {
-                    let mut service = match service_clone.lock() {
-                        Ok(s) => s,
-                        Err(e) => {
-                            error!("Poisoned lock {e}");
-                            return;
-                        }
-                    };
+            }
+            alias
+                .pick(&candidates)
+                .ok_or_else(|| ServicingError::ServiceNotUp(name.to_string()))?
+        }
+        None => name.to_string(),
+    };
+    drop(aliases);
 
-                    match res {
-                        Ok(Ok(r)) => {
-                            if let Some(service) = service.get_mut(&r) {
-                                service.up = true;
-                                info!("Service {} is up", r);
-                            }
-                        }
-                        Ok(Err(e)) => {
-                            warn!("{e}");
-                        }
-                        Err(e) => {
-                            error!("{e}");
-                        }
-                    }
-                }
+    if let Some(s) = registry.get(&service) {
+        if let Some(url) = s.stable_endpoint.as_ref().or(s.url.as_ref()) {
+            return Ok(Endpoint {
+                url: url.clone(),
+                up: s.up,
+                api_key: s.api_key.as_ref().map(|k| k.0.clone()),
+                maintenance: s.maintenance.as_ref().map(|m| m.message(&service)),
+                service,
             });
         }
-
-        Ok(())
+        return Err(ServicingError::General("Service is down".to_string()));
     }
+    Err(ServicingError::ServiceNotFound(service))
+}
 
-    pub fn load_from_b64(&mut self, b64: String) -> Result<(), ServicingError> {
-        let bin = base64::prelude::BASE64_STANDARD.decode(b64.as_bytes())?;
-        self.service
-            .lock()?
-            .extend(bincode::deserialize::<HashMap<String, Service>>(&bin)?);
+/// sky_serve_state returns whether SkyPilot runs the service and its endpoint, if reported.
+fn sky_serve_state(name: &str) -> Result<(bool, Option<String>), ServicingError> {
+    let output = {
+        let _permit = limiter::acquire(&format!("sky serve status {name}"))?;
+        runner::run(
+            Command::new("sky").arg("serve").arg("status").arg(name),
+            Mode::Capture,
+        )?
+    };
+    if !output.status.success() {
+        return Err(ServicingError::General(format!(
+            "Cannot check service {name}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    let output = String::from_utf8_lossy(&output.stdout);
+    let running = logic::serve_services(&output).iter().any(|s| s == name);
+    let url = REGEX_URL
+        .get()
+        .filter(|_| running)
+        .and_then(|re| re.find(&output))
+        .map(|url| url.as_str().to_string());
+    Ok((running, url))
+}
 
+/// beat rewrites the heartbeat file for the tick of monitor on the service.
+fn beat(registry: &DashMap<String, Service>, monitor: &str, name: &str) {
+    let written = statuses(registry).and_then(|services| {
+        let heartbeat = Heartbeat {
+            timestamp: models::unix_now(),
+            pid: std::process::id(),
+            monitor,
+            service: name,
+            services,
+        };
+        let path = layout::heartbeat_path()?;
+        helper::write_atomic(&path, &serde_json::to_vec_pretty(&heartbeat)?, false)?;
         Ok(())
+    });
+    if let Err(e) = written {
+        warn!("Could not write the heartbeat of the {monitor} monitor of {name}: {e}");
     }
+}
+
+/// statuses summarises the services of the registry, sorted by name.
+fn statuses(registry: &DashMap<String, Service>) -> Result<Vec<ServiceStatus>, ServicingError> {
+    Ok(page(registry.iter(), None, None)
+        .into_iter()
+        .map(|entry| entry.summary(entry.key()))
+        .collect())
+}
+
+/// page sorts the entries of the registry by name and returns those from offset on, at most
+/// limit of them. The shards of the entries returned stay read-locked until they are dropped.
+fn page<'a>(
+    entries: impl Iterator<Item = Entry<'a>>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Vec<Entry<'a>> {
+    let mut entries: Vec<Entry> = entries.collect();
+    entries.sort_unstable_by(|a, b| a.key().cmp(b.key()));
+    entries
+        .into_iter()
+        .skip(offset.unwrap_or_default())
+        .take(limit.unwrap_or(usize::MAX))
+        .collect()
+}
+
+/// encode serializes entries as the map the registry is saved as, after REGISTRY_MAGIC and
+/// REGISTRY_VERSION. Holding all of them keeps an insert meanwhile from making the map longer
+/// than the length written first.
+fn encode(entries: &[Entry]) -> Result<Vec<u8>, ServicingError> {
+    let map: HashMap<&String, &Service> = entries.iter().map(|entry| entry.pair()).collect();
+    let mut bin = REGISTRY_MAGIC.to_vec();
+    bin.extend(bincode::serialize(&(REGISTRY_VERSION, map))?);
+    Ok(bin)
+}
 
-    pub fn list(&self) -> Result<Vec<String>, ServicingError> {
-        Ok(self.service.lock()?.keys().cloned().collect())
+/// decode reads the services of a registry saved by encode, or by the versions before the
+/// registry had one: the map of services as they are now, or the first services, upgraded.
+fn decode(bin: &[u8]) -> Result<HashMap<String, Service>, ServicingError> {
+    if let Some(versioned) = bin.strip_prefix(REGISTRY_MAGIC) {
+        let version: u32 = bincode::deserialize(versioned)?;
+        if version != REGISTRY_VERSION {
+            return Err(ServicingError::General(format!(
+                "Unsupported registry version {version}, expected {REGISTRY_VERSION}"
+            )));
+        }
+        let (_, services): (u32, HashMap<String, Service>) = bincode::deserialize(versioned)?;
+        return Ok(services);
+    }
+    match bincode::deserialize::<HashMap<String, Service>>(bin) {
+        Ok(services) => Ok(services),
+        Err(e) => match bincode::deserialize::<HashMap<String, V0Service>>(bin) {
+            Ok(services) => {
+                info!("Upgrading a registry of {} services", services.len());
+                Ok(services
+                    .into_iter()
+                    .map(|(name, service)| (name, service.into()))
+                    .collect())
+            }
+            Err(_) => Err(e.into()),
+        },
     }
+}
+
+/// ready_check calls the Python readiness check of a service with its url on a blocking thread,
+/// keeping the GIL off the runtime workers.
+async fn ready_check(check: Arc<PyObject>, url: String) -> Result<bool, ServicingError> {
+    tokio::task::spawn_blocking(move || {
+        Python::with_gil(|py| Ok(check.call1(py, (url,))?.bind(py).is_truthy()?))
+    })
+    .await
+    .map_err(|e| ServicingError::General(format!("Ready check failed: {e}")))?
+}
 
-    pub fn get_url(&self, name: String) -> Result<String, ServicingError> {
-        if let Some(service) = self.service.lock()?.get(&name) {
-            if let Some(url) = &service.url {
-                return Ok(url.clone());
+/// events returns the history of the services in time order.
+fn events<'a>(entries: &'a [Entry<'a>]) -> Vec<EventRow<'a>> {
+    let now = models::unix_now();
+    let mut events = Vec::new();
+    for (name, service) in entries.iter().map(|entry| entry.pair()) {
+        let event = |time, event, detail| EventRow {
+            service: name,
+            time,
+            event,
+            detail,
+        };
+        for timings in &service.timings {
+            let seconds: f64 = timings.phases.iter().map(|phase| phase.seconds).sum();
+            events.push(event(timings.started, "up", Some(format!("{seconds:.1}s"))));
+        }
+        let running = service.spend.since.map(|since| budget::Period {
+            start: since,
+            end: Timestamp(now),
+            hourly_cost: service.spend.hourly_cost.unwrap_or_default(),
+        });
+        for period in service.spend.periods.iter().chain(running.as_ref()) {
+            let cost = Some(format!("${:.2}/h", period.hourly_cost));
+            events.push(event(period.start, "launch", cost.clone()));
+            if running
+                .as_ref()
+                .is_none_or(|running| running.start != period.start)
+            {
+                events.push(event(period.end, "teardown", cost));
             }
-            return Err(ServicingError::General("Service is down".to_string()));
         }
-        Err(ServicingError::ServiceNotFound(name))
+        for preemption in &service.preemptions {
+            let replica = Some(format!("replica {}", preemption.replica));
+            events.push(event(preemption.detected, "preemption", replica));
+        }
+        if let Some(maintenance) = &service.maintenance {
+            events.push(event(
+                maintenance.since,
+                "maintenance",
+                maintenance.reason.clone(),
+            ));
+        }
+        for step in &service.approvals {
+            let detail = match &step.detail {
+                Some(detail) => format!("by {}: {detail}", step.by),
+                None => format!("by {}", step.by),
+            };
+            events.push(event(step.time, step.action.event(), Some(detail)));
+        }
     }
+    events.sort_by_key(|event| (event.time, event.service));
+    events
+}
+
+/// probe_rows returns the last probe of every service, sorted by name.
+fn probe_rows<'a>(
+    entries: &'a [Entry<'a>],
+    probes: &HashMap<String, ProbeCache>,
+) -> Vec<ProbeRow<'a>> {
+    let mut rows: Vec<ProbeRow> = entries
+        .iter()
+        .map(|entry| entry.pair())
+        .map(|(name, service)| ProbeRow {
+            service: name,
+            method: service.probe_method,
+            health_check: service
+                .health_check
+                .as_ref()
+                .map(|check| check.path.as_str()),
+            ready: probes.get(name).and_then(|probe| probe.ready),
+            latency_ms: service
+                .latency
+                .map(|latency| latency.as_secs_f64() * 1000.0),
+        })
+        .collect();
+    rows.sort_by_key(|row| row.service);
+    rows
 }
 
 #[cfg(test)]
 mod tests {
-    use pyo3::{pyclass, types::PyDict, Bound, PyAny, Python};
+    use pyo3::{
+        pyclass,
+        types::{PyCFunction, PyDict, PyDictMethods},
+        Bound, Py, Python,
+    };
+
+    use std::{
+        collections::HashMap,
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+    };
+
+    use dashmap::DashMap;
+    use reqwest::Method;
 
-    use crate::models::UserProvidedConfig;
+    use super::{decode, encode, Entry, V0Config, V0Configuration, V0Service};
+    use crate::{
+        approval::Action,
+        client::OutboundRequest,
+        error::ServicingError,
+        models::UserProvidedConfig,
+        orchestrator::{OrchestratorArg, Orchestrators},
+        runner::{self, ScriptedRunner},
+    };
 
     #[pyclass]
     struct Empty;
 
+    static SKY_LISTED: &str = "Services
+NAME     VERSION  UPTIME  STATUS  REPLICAS  ENDPOINT
+testing  1        2m      READY   1/1       1.2.3.4:30001
+";
+    static SKY_EMPTY: &str = "Services
+No live services.
+";
+
     #[test]
     fn test_dispatcher() {
         pyo3::prepare_freethreaded_python();
         Python::with_gil(|py| {
             let bound_args = Bound::new(py, Empty).unwrap();
             let bound_kwargs = Some(PyDict::new_bound(py));
-            // let bound_kwargs = Some(PyDict::new_bound(py));
+            if let Some(kwargs) = &bound_kwargs {
+                kwargs.set_item("skip_sky_validation", true).unwrap();
+            }
             let mut dis = super::Dispatcher::new(&bound_args, bound_kwargs.as_deref()).unwrap();
 
             dis.add_service(
@@ -547,10 +5917,32 @@ mod tests {
                     cpu: None,
                     accelerators: None,
                     memory: None,
+                    namespace: None,
+                    labels: None,
+                    node_selector: None,
+                    tolerations: None,
+                    service_account: None,
+                    init_containers: None,
+                    sidecars: None,
+                    min_replicas: None,
+                    max_replicas: None,
+                    target_utilization: None,
+                    ttl_hours: None,
+                    base: None,
                 }),
+                Some(OrchestratorArg::Name("sky".to_string())),
+                None,
+                None,
             )
             .unwrap();
 
+            // names reaching out of the state directory are refused
+            for name in ["", "..", "../.."] {
+                assert!(dis
+                    .add_service(name.to_string(), None, None, None, None)
+                    .is_err());
+            }
+
             // test the runtime... should NOT panic
             dis.rt.block_on(async { "" });
 
@@ -558,22 +5950,357 @@ mod tests {
 
             // check what has been added
             {
-                let services = dis.service.lock().unwrap();
-                let service = services.get("testing").unwrap();
+                let service = dis.service.get("testing").unwrap();
                 assert_eq!(service.template.resources.ports, 1234);
                 assert_eq!(service.template.service.replicas, 5);
                 assert_eq!(service.template.resources.cloud, "aws");
+                assert!(!service.template.setup.contains("cudatoolkit"));
             }
 
-            dis.remove_service("testing".to_string()).unwrap();
-            assert!(dis.service.lock().unwrap().get("testing").is_none());
+            // a protected service is only launched once approved, whatever the path
+            dis.protect("testing".to_string(), None, None, None)
+                .unwrap();
+            let up = dis.up(
+                py,
+                "testing".to_string(),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(false),
+                None,
+                None,
+            );
+            assert_eq!(up.unwrap_err().code(), "APPROVAL_REQUIRED");
+            dis.request_up("testing".to_string(), None, Some("demo".to_string()))
+                .unwrap();
+            let snapshot =
+                std::env::temp_dir().join(format!("servicing-snapshot-{}.bin", std::process::id()));
+            dis.snapshot(snapshot.clone()).unwrap();
+            let mut other = super::Dispatcher::new(&bound_args, bound_kwargs.as_deref()).unwrap();
+            let restored = other.restore(snapshot.clone(), Some(true));
+            assert_eq!(restored.unwrap_err().code(), "APPROVAL_REQUIRED");
+            std::fs::remove_file(snapshot).unwrap();
+            // the requester cannot approve its own request
+            let approved = dis.approve(py, "testing".to_string(), None, None);
+            assert_eq!(approved.unwrap_err().code(), "APPROVAL_REFUSED");
+            dis.protect("testing".to_string(), Some(false), None, None)
+                .unwrap();
+            {
+                let service = dis.service.get("testing").unwrap();
+                let trail: Vec<Action> = service.approvals.iter().map(|s| s.action).collect();
+                assert_eq!(
+                    trail,
+                    vec![Action::Protected, Action::Requested, Action::Unprotected]
+                );
+                assert!(service.request.is_none() && service.protection.is_none());
+            }
+
+            // removal is refused while SkyPilot lists the service, and when it cannot tell
+            let sky = Arc::new(
+                ScriptedRunner::default()
+                    .reply("sky serve status testing", 0, SKY_LISTED)
+                    .reply("sky serve status testing", 1, "")
+                    .reply("sky serve status testing", 0, SKY_EMPTY),
+            );
+            runner::with_runner(sky, || {
+                for _ in 0..2 {
+                    assert!(dis
+                        .remove_service("testing".to_string(), None, None)
+                        .is_err());
+                }
+                dis.remove_service("testing".to_string(), None, None)
+                    .unwrap();
+            });
+            assert!(dis.service.get("testing").is_none());
 
             dis.load(None, None).unwrap();
             {
-                let services = dis.service.lock().unwrap();
-                let service = services.get("testing").unwrap();
+                let service = dis.service.get("testing").unwrap();
                 assert_eq!(service.template.resources.ports, 1234);
             }
+            assert_eq!(dis.list(None, None, Some(1)).unwrap().len(), 1);
+            assert!(dis.list(None, Some(usize::MAX), None).unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_policies() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let args = Bound::new(py, Empty).unwrap();
+            let kwargs = PyDict::new_bound(py);
+            kwargs.set_item("skip_sky_validation", true).unwrap();
+            let dispatcher = super::Dispatcher::new(&args, Some(&kwargs)).unwrap();
+            let dis = Py::new(py, dispatcher).unwrap();
+            dis.borrow_mut(py)
+                .add_service("policed".to_string(), None, None, None, None)
+                .unwrap();
+
+            // the callables run out of the registry entry, so that they can call the methods
+            // borrowing the dispatcher immutably; up borrows it mutably, so a callable evaluated
+            // for an up cannot call back into it
+            let globals = PyDict::new_bound(py);
+            globals.set_item("dispatcher", &dis).unwrap();
+            py.run_bound(
+                "def replicas(input):\n    \
+                     dispatcher.set_maintenance(input['service'], True, 'policed')\n    \
+                     return ['replicas: %d' % input['config']['service']['replicas']]\n\
+                 def tag(request):\n    \
+                     dispatcher.clear_middleware()\n    \
+                     request['headers']['x-tag'] = 'once'\n",
+                Some(&globals),
+                None,
+            )
+            .unwrap();
+            let check = globals.get_item("replicas").unwrap().unwrap().unbind();
+            let dis = dis.borrow(py);
+            dis.add_policy(py, Some(check), None, None, None).unwrap();
+            assert_eq!(dis.policies().unwrap(), vec!["replicas"]);
+
+            let violations = dis.check_policies("policed".to_string(), None).unwrap();
+            assert_eq!(violations.len(), 1);
+            assert_eq!(violations[0]["policy"], "replicas");
+            assert!(violations[0]["message"].starts_with("replicas: "));
+            assert!(dis.service.get("policed").unwrap().maintenance.is_some());
+
+            // so do the middleware hooks
+            let tag = globals.get_item("tag").unwrap().unwrap().unbind();
+            dis.add_middleware(Some(tag), None).unwrap();
+            let mut req =
+                OutboundRequest::json(Method::GET, "http://127.0.0.1:1".to_string(), None).unwrap();
+            dis.middleware.before(py, "policed", &mut req).unwrap();
+            assert_eq!(req.headers["x-tag"], "once");
+            assert!(dis.middleware.is_empty());
+            let sky = ScriptedRunner::default().reply("sky serve status", 0, SKY_EMPTY);
+            runner::with_runner(Arc::new(sky), || {
+                dis.remove_service("policed".to_string(), None, None)
+            })
+            .unwrap();
+        });
+    }
+
+    #[test]
+    fn test_down_purge() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let args = Bound::new(py, Empty).unwrap();
+            let kwargs = PyDict::new_bound(py);
+            kwargs.set_item("skip_sky_validation", true).unwrap();
+            let mut dis = super::Dispatcher::new(&args, Some(&kwargs)).unwrap();
+            dis.add_service("purged".to_string(), None, None, None, None)
+                .unwrap();
+            if let Some(mut service) = dis.service.get_mut("purged") {
+                service.up = true;
+                service.url = Some("1.2.3.4:30001".to_string());
+            }
+            let dir = crate::layout::service_dir("purged").unwrap();
+            std::fs::write(dir.join("service.dev.yaml"), "run: x").unwrap();
+
+            // the replicas are still shutting down when sky serve down returns
+            let sky = Arc::new(
+                ScriptedRunner::default()
+                    .reply("sky serve down purged", 0, "")
+                    .reply(
+                        "sky serve status purged",
+                        0,
+                        &SKY_LISTED.replace("testing", "purged"),
+                    )
+                    .reply("sky serve status purged", 0, SKY_EMPTY),
+            );
+            runner::with_runner(sky.clone(), || {
+                dis.down(
+                    "purged".to_string(),
+                    Some(true),
+                    None,
+                    Some(true),
+                    None,
+                    None,
+                    None,
+                )
+            })
+            .unwrap();
+            assert_eq!(
+                sky.commands(),
+                vec![
+                    "sky serve down purged -y --purge",
+                    "sky serve status purged",
+                    "sky serve status purged"
+                ]
+            );
+            {
+                let service = dis.service.get("purged").unwrap();
+                assert!(!service.up && service.url.is_none());
+            }
+            assert!(!dir.join("service.dev.yaml").exists());
+            assert!(dir.join("service.yaml").is_file());
+
+            runner::with_runner(sky, || dis.remove_service("purged".to_string(), None, None))
+                .unwrap();
+        });
+    }
+
+    #[test]
+    fn test_launch_unlocked() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let args = Bound::new(py, Empty).unwrap();
+            let kwargs = PyDict::new_bound(py);
+            kwargs.set_item("skip_sky_validation", true).unwrap();
+            let mut dis = super::Dispatcher::new(&args, Some(&kwargs)).unwrap();
+            dis.add_service("unlocked".to_string(), None, None, None, None)
+                .unwrap();
+            dis.service.get_mut("unlocked").unwrap().orchestrator = Orchestrators::Docker;
+
+            // the progress callback checks that the entry of the service is free while it runs
+            let registry = dis.service.clone();
+            let locked = Arc::new(AtomicBool::new(false));
+            let seen = locked.clone();
+            let progress = PyCFunction::new_closure_bound(py, None, None, move |_, _| {
+                if registry.try_get("unlocked").is_locked() {
+                    seen.store(true, Ordering::SeqCst);
+                }
+            })
+            .unwrap();
+            let docker = Arc::new(ScriptedRunner::default().reply("docker", 0, ""));
+            runner::with_runner(docker, || {
+                dis.up(
+                    py,
+                    "unlocked".to_string(),
+                    None,
+                    None,
+                    None,
+                    None,
+                    Some(progress.into_any().unbind()),
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+            })
+            .unwrap();
+            assert!(!locked.load(Ordering::SeqCst));
+            assert!(dis.service.get("unlocked").unwrap().url.is_some());
+        });
+    }
+
+    #[test]
+    fn test_pause() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let args = Bound::new(py, Empty).unwrap();
+            let kwargs = PyDict::new_bound(py);
+            kwargs.set_item("skip_sky_validation", true).unwrap();
+            let mut dis = super::Dispatcher::new(&args, Some(&kwargs)).unwrap();
+            dis.add_service("paused".to_string(), None, None, None, None)
+                .unwrap();
+            assert!(dis.pause(py, "paused".to_string()).is_err());
+            if let Some(mut service) = dis.service.get_mut("paused") {
+                service.up = true;
+                service.url = Some("127.0.0.1:8080".to_string());
+            }
+            // SkyPilot services sleep with scale to zero instead
+            assert!(dis.pause(py, "paused".to_string()).is_err());
+
+            let commands = Arc::new(
+                ScriptedRunner::default()
+                    .reply("docker pause servicing-paused", 0, "")
+                    .reply("docker unpause servicing-paused", 0, "")
+                    .reply("kubectl scale deployment/servicing-paused-app", 0, "")
+                    .reply("kubectl get deployment/servicing-paused-app", 0, ""),
+            );
+            runner::with_runner(commands.clone(), || {
+                for orchestrator in [Orchestrators::Docker, Orchestrators::Kubernetes] {
+                    dis.service.get_mut("paused").unwrap().orchestrator = orchestrator;
+                    dis.pause(py, "paused".to_string()).unwrap();
+                    let status: serde_json::Value =
+                        serde_json::from_str(&dis.status(py, "paused".to_string(), None, None)?)
+                            .unwrap();
+                    assert_eq!(status["state"], "paused");
+                    assert_eq!(status["up"], false);
+                    dis.resume(py, "paused".to_string()).unwrap();
+                    assert!(dis.resume(py, "paused".to_string()).is_err());
+                }
+                Ok::<_, ServicingError>(())
+            })
+            .unwrap();
+            assert_eq!(
+                commands.commands(),
+                vec![
+                    "docker pause servicing-paused",
+                    "docker unpause servicing-paused",
+                    "kubectl scale deployment/servicing-paused-app --replicas=0",
+                    "kubectl scale deployment/servicing-paused-app --replicas=2",
+                ]
+            );
+            runner::with_runner(commands, || {
+                dis.remove_service("paused".to_string(), Some(true), None)
+            })
+            .unwrap();
         });
     }
+
+    #[test]
+    fn test_decode_registry() {
+        // a registry as the first version saved it, before it had a version
+        let v0 = HashMap::from([(
+            "llm".to_string(),
+            V0Service {
+                data: Some(V0Config {
+                    port: Some(8000),
+                    replicas: None,
+                    cloud: None,
+                    workdir: None,
+                    data: None,
+                    disk_size: None,
+                    cpu: None,
+                    memory: None,
+                    accelerators: Some("A100:1".to_string()),
+                    setup: None,
+                    run: None,
+                }),
+                template: V0Configuration {
+                    readiness_probe: "/health".to_string(),
+                    replicas: 2,
+                    ports: 8000,
+                    cloud: "aws".to_string(),
+                    cpus: "4+".to_string(),
+                    memory: "10+".to_string(),
+                    disk_size: 100,
+                    accelerators: Some("A100:1".to_string()),
+                    workdir: ".".to_string(),
+                    setup: "pip install .".to_string(),
+                    run: "python service.py".to_string(),
+                },
+                filepath: Some(PathBuf::from("/home/user/.servicing/llm_service.yaml")),
+                url: Some("http://1.2.3.4:30001".to_string()),
+                up: true,
+            },
+        )]);
+        let services = decode(&bincode::serialize(&v0).unwrap()).unwrap();
+        let service = &services["llm"];
+        assert_eq!(service.template.resources.ports, 8000);
+        assert_eq!(service.template.run, "python service.py");
+        assert_eq!(
+            service
+                .data
+                .as_ref()
+                .and_then(|c| c.accelerators.as_deref()),
+            Some("A100:1")
+        );
+        assert_eq!(service.url.as_deref(), Some("http://1.2.3.4:30001"));
+        assert!(service.up && service.owner.is_none());
+
+        // saved again with its version, and read back
+        let registry: DashMap<_, _> = services.into_iter().collect();
+        let bin = encode(&registry.iter().collect::<Vec<Entry>>()).unwrap();
+        assert!(bin.starts_with(b"svcr"));
+        assert_eq!(
+            decode(&bin).unwrap()["llm"].template.run,
+            "python service.py"
+        );
+        assert!(decode(b"svcr\x09\0\0\0").is_err());
+    }
 }
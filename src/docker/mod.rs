@@ -0,0 +1,296 @@
+//! Docker module houses the Docker backend, running a service in a local container to try its
+//! configuration out before paying for a SkyPilot launch. The image is built from a Dockerfile
+//! generated from the configuration: the workdir is copied in, the setup runs at build time and
+//! the run command is the command of the container.
+use std::{
+    fs,
+    path::Path,
+    process::{Command, ExitStatus},
+};
+
+use log::info;
+
+use crate::{
+    error::ServicingError,
+    image::{self, RegistryLogin},
+    layout, limiter,
+    models::Configuration,
+    runner::{self, Mode},
+};
+
+/// image the service image is built from by default, with conda and bash as the default setup
+/// expects
+pub static BASE_IMAGE: &str = "continuumio/miniconda3";
+/// where the workdir is copied in the image
+static WORKDIR: &str = "/workdir";
+/// host address the port of the service is published on, so it is not reachable from outside
+static PUBLISH_HOST: &str = "127.0.0.1";
+
+/// Container is how the container of a service is built and run, besides its configuration.
+#[derive(Default)]
+pub struct Container<'a> {
+    /// image the service image is built from, BASE_IMAGE by default
+    pub base: Option<&'a str>,
+    /// staged copy of the workdir, the configured workdir by default
+    pub workdir: Option<&'a Path>,
+    /// login to the registry of the base image
+    pub login: Option<&'a RegistryLogin>,
+    /// environment variables set besides those of the configuration, kept off the command line
+    pub env: Vec<(&'a str, &'a str)>,
+}
+
+/// container_name returns the name of the container of the service, as object_name does for
+/// Kubernetes.
+pub fn container_name(name: &str) -> String {
+    // image repositories are lowercase and their separators cannot repeat or end them
+    let name = name.to_lowercase().replace(['_', '.'], "-");
+    format!("servicing-{name}")
+        .trim_end_matches('-')
+        .to_string()
+}
+
+/// image_name returns the name of the local image of the service.
+pub fn image_name(name: &str) -> String {
+    format!("{}:latest", container_name(name))
+}
+
+/// dockerfile renders the Dockerfile of config, built from base. The setup and run scripts go
+/// through bash as they would on a SkyPilot replica.
+pub fn dockerfile(config: &Configuration, base: &str, copy_workdir: bool) -> String {
+    let script = |script: &str| {
+        // a JSON string is a valid exec form argument, newlines and quotes included
+        serde_json::to_string(script).unwrap_or_default()
+    };
+    let mut lines = vec![format!("FROM {base}"), format!("WORKDIR {WORKDIR}")];
+    if copy_workdir {
+        lines.push(format!("COPY . {WORKDIR}"));
+    }
+    if !config.setup.trim().is_empty() {
+        lines.push(format!(
+            "RUN [\"/bin/bash\", \"-c\", {}]",
+            script(&config.setup)
+        ));
+    }
+    lines.push(format!("EXPOSE {}", config.resources.ports));
+    lines.push(format!(
+        "CMD [\"/bin/bash\", \"-c\", {}]",
+        script(&config.run)
+    ));
+    lines.join("\n") + "\n"
+}
+
+/// up builds the image of the service and runs it in a container publishing the port of the
+/// service, replacing the container of a previous up. It returns the endpoint of the service.
+/// A single replica is run, whatever the configuration asks for.
+pub fn up(
+    name: &str,
+    config: &Configuration,
+    container: &Container,
+    quiet: bool,
+) -> Result<String, ServicingError> {
+    let dir = layout::service_dir(name)?.join(layout::DOCKER_DIR);
+    fs::create_dir_all(&dir)?;
+    let workdir = container
+        .workdir
+        .or_else(|| (!config.workdir.is_empty()).then(|| Path::new(&config.workdir)));
+    let path = dir.join("Dockerfile");
+    let base = container.base.unwrap_or(BASE_IMAGE);
+    fs::write(&path, dockerfile(config, base, workdir.is_some()))?;
+
+    if let Some(login) = container.login {
+        image::docker_login(name, login)?;
+    }
+
+    let image = image_name(name);
+    info!("Building image {} for service {}", image, name);
+    let mut build = Command::new("docker");
+    build
+        .arg("build")
+        .arg("-f")
+        .arg(&path)
+        .arg("-t")
+        .arg(&image)
+        .arg(workdir.unwrap_or(&dir));
+    let output = {
+        let _permit = limiter::acquire(&format!("docker build {name}"))?;
+        runner::run(&mut build, Mode::Interactive { echo: !quiet })?
+    };
+    if !output.status.success() {
+        return Err(ServicingError::ImageBuildError(
+            name.to_string(),
+            format!(
+                "docker build failed\n{}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ));
+    }
+
+    if config.service.replicas > 1 {
+        info!(
+            "Running a single replica of service {} out of {} in Docker",
+            name, config.service.replicas
+        );
+    }
+    down(name)?;
+    let output = {
+        let _permit = limiter::acquire(&format!("docker run {name}"))?;
+        runner::run(&mut run_command(name, config, container), Mode::Capture)?
+    };
+    if !output.status.success() {
+        return Err(ServicingError::ClusterProvisionError(format!(
+            "Running the container of service {} failed: {}",
+            name,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(endpoint(config.resources.ports))
+}
+
+/// endpoint returns where the container of a service listening on port is reached, on the host.
+fn endpoint(port: u16) -> String {
+    format!("{PUBLISH_HOST}:{port}")
+}
+
+/// run_command returns the command running the container of the service in the background.
+fn run_command(name: &str, config: &Configuration, container: &Container) -> Command {
+    let port = config.resources.ports;
+    let mut cmd = Command::new("docker");
+    cmd.arg("run")
+        .arg("-d")
+        .arg("--name")
+        .arg(container_name(name))
+        .arg("-p")
+        .arg(format!("{PUBLISH_HOST}:{port}:{port}"));
+    if config.resources.accelerators.is_some() {
+        cmd.arg("--gpus").arg("all");
+    }
+    // -e without a value makes docker read it from the environment, off the command line
+    let env = config
+        .envs
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.as_str()));
+    for (key, value) in env.chain(container.env.iter().copied()) {
+        cmd.env(key, value).arg("-e").arg(key);
+    }
+    cmd.arg(image_name(name));
+    cmd
+}
+
+/// down removes the container of the service, stopping it if it runs. A service without a
+/// container is already down.
+pub fn down(name: &str) -> Result<ExitStatus, ServicingError> {
+    if !exists(name)? {
+        return Ok(ExitStatus::default());
+    }
+    let _permit = limiter::acquire(&format!("docker rm {name}"))?;
+    Ok(runner::run(&mut down_command(name), Mode::Capture)?.status)
+}
+
+/// down_command returns the command removing the container of the service.
+pub fn down_command(name: &str) -> Command {
+    let mut cmd = Command::new("docker");
+    cmd.arg("rm").arg("-f").arg(container_name(name));
+    cmd
+}
+
+/// pause freezes the processes of the container of the service, which keeps its state until
+/// resume.
+pub fn pause(name: &str) -> Result<(), ServicingError> {
+    docker(name, "pause")
+}
+
+/// resume thaws the container of the service paused by pause.
+pub fn resume(name: &str) -> Result<(), ServicingError> {
+    docker(name, "unpause")
+}
+
+/// docker runs the docker command taking the container of the service as argument.
+fn docker(name: &str, command: &str) -> Result<(), ServicingError> {
+    let _permit = limiter::acquire(&format!("docker {command} {name}"))?;
+    let output = runner::run(
+        Command::new("docker")
+            .arg(command)
+            .arg(container_name(name)),
+        Mode::Capture,
+    )?;
+    if !output.status.success() {
+        return Err(ServicingError::General(format!(
+            "docker {command} of service {name} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+/// exists tells whether the service has a container, running or not.
+pub fn exists(name: &str) -> Result<bool, ServicingError> {
+    ps(name, &["--all"])
+}
+
+/// state returns whether the container of the service runs and the endpoint of the service,
+/// listening on port, if it does.
+pub fn state(name: &str, port: u16) -> Result<(bool, Option<String>), ServicingError> {
+    let running = ps(name, &["--filter", "status=running"])?;
+    Ok((running, running.then(|| endpoint(port))))
+}
+
+/// ps tells whether docker ps lists the container of the service, filtered by args.
+fn ps(name: &str, args: &[&str]) -> Result<bool, ServicingError> {
+    let _permit = limiter::acquire(&format!("docker ps {name}"))?;
+    let output = runner::run(
+        Command::new("docker")
+            .arg("ps")
+            .arg("-q")
+            .arg("--filter")
+            .arg(format!("name=^{}$", container_name(name)))
+            .args(args),
+        Mode::Capture,
+    )?;
+    if !output.status.success() {
+        return Err(ServicingError::General(format!(
+            "docker ps failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(!output.stdout.trim_ascii().is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{container_name, dockerfile, image_name, run_command, Container};
+    use crate::{models::Configuration, runner::describe};
+
+    #[test]
+    fn test_dockerfile() {
+        let config = Configuration {
+            setup: "pip install -r requirements.txt\necho \"done\"\n".to_string(),
+            run: "python service.py".to_string(),
+            envs: BTreeMap::from([("MODEL".to_string(), "llama".to_string())]),
+            ..Configuration::default()
+        };
+        assert_eq!(
+            dockerfile(&config, "python:3.11", true),
+            "FROM python:3.11\n\
+             WORKDIR /workdir\n\
+             COPY . /workdir\n\
+             RUN [\"/bin/bash\", \"-c\", \"pip install -r requirements.txt\\necho \\\"done\\\"\\n\"]\n\
+             EXPOSE 8080\n\
+             CMD [\"/bin/bash\", \"-c\", \"python service.py\"]\n"
+        );
+
+        let container = Container {
+            env: vec![("API_KEY", "secret")],
+            ..Container::default()
+        };
+        let cmd = run_command("llm", &config, &container);
+        assert_eq!(
+            describe(&cmd),
+            "docker run -d --name servicing-llm -p 127.0.0.1:8080:8080 -e MODEL -e API_KEY \
+             servicing-llm:latest"
+        );
+        assert_eq!(container_name("My_LLM.v2"), "servicing-my-llm-v2");
+        assert_eq!(image_name("llm-"), "servicing-llm:latest");
+    }
+}
@@ -1,6 +1,7 @@
 use std::sync::{mpsc, PoisonError};
 
-use pyo3::{exceptions::PyRuntimeError, PyErr};
+#[cfg(feature = "python")]
+use pyo3::{exceptions::PyRuntimeError, types::PyAnyMethods, PyErr, Python};
 use thiserror::Error;
 
 #[allow(dead_code)] // Remove this later
@@ -12,10 +13,22 @@ pub enum ServicingError {
     IO(#[from] std::io::Error),
     #[error("Package {0} is not installed")]
     PipPackageError(&'static str),
+    #[error("Orchestrator {0} is not supported")]
+    UnknownOrchestrator(String),
     #[error("{0}")]
     ReqwestError(#[from] reqwest::Error),
     #[error("{0}")]
     ClusterProvisionError(String),
+    #[error("Cloud quota exceeded while provisioning service {0}: request a quota increase, or try another region or cloud")]
+    QuotaExceeded(String),
+    #[error("No capacity available for the resources of service {0}: try another region or cloud, or different accelerators")]
+    NoCapacity(String),
+    #[error("Cloud credentials expired or missing while provisioning service {0}: refresh them and check with `sky check`")]
+    CredentialsExpired(String),
+    #[error(
+        "No {1} quota for service {0}: request a quota increase, or try another region or cloud"
+    )]
+    NoQuota(String, String),
     #[error("{0}")]
     SerdeYamlError(#[from] serde_yaml::Error),
     #[error("{0}")]
@@ -26,6 +39,34 @@ pub enum ServicingError {
     ServiceAlreadyExists(String),
     #[error("Service {0} not up")]
     ServiceNotUp(String),
+    #[error("Service {0} is external, it is not managed by servicing")]
+    ExternalService(String),
+    #[error("Service {0} is owned by {1}, use force to override")]
+    NotOwner(String, String),
+    #[error("Building the image of service {0} failed: {1}")]
+    ImageBuildError(String, String),
+    #[error("Workdir of service {0} is {1} bytes, over the limit of {2} bytes")]
+    WorkdirTooLarge(String, u64, u64),
+    #[error("Service {0} is over budget: {1}")]
+    BudgetExceeded(String, String),
+    #[error("Service {0} violates the policies: {1}")]
+    PolicyViolation(String, String),
+    #[error("Service {0} is protected, its up has to be requested with request_up and approved")]
+    ApprovalRequired(String),
+    #[error("Approval of the up of service {0} refused: {1}")]
+    ApprovalRefused(String, String),
+    #[error("Launch of service {0} was declined")]
+    LaunchDeclined(String),
+    #[error("Configuration of service {0} was modified on disk")]
+    ConfigDrift(String),
+    #[error("Circuit breaker open for service {0}")]
+    CircuitOpen(String),
+    #[error("Service {0} is busy with {1} {2}")]
+    OperationInProgress(String, String, String),
+    #[error("Deadline exceeded while waiting for {0}")]
+    DeadlineExceeded(String),
+    #[error("{0} was interrupted")]
+    Interrupted(String),
     #[error("{0}")]
     BinaryEncodeError(#[from] bincode::Error),
     #[error("{0}")]
@@ -36,11 +77,123 @@ pub enum ServicingError {
     LockError(String),
     #[error("{0}")]
     Base64Error(#[from] base64::DecodeError),
+    #[error("{0}")]
+    CsvError(#[from] csv::Error),
+    #[cfg(feature = "parquet")]
+    #[error("{0}")]
+    ParquetError(#[from] parquet::errors::ParquetError),
+    #[cfg(feature = "python")]
+    #[error("{0}")]
+    PythonError(#[from] PyErr),
 }
 
+/// code of a readiness probe that could not reach the service
+pub static PROBE_FAILED: &str = "PROBE_FAILED";
+
+static QUOTA_PATTERNS: &[&str] = &[
+    "quota exceeded",
+    "exceeded quota",
+    "quotaexceeded",
+    "insufficientquota",
+    "vcpulimitexceeded",
+];
+static CAPACITY_PATTERNS: &[&str] = &[
+    "insufficientinstancecapacity",
+    "zone_resource_pool_exhausted",
+    "no capacity",
+    "out of capacity",
+    "failed to acquire resources",
+    "resourcesunavailableerror",
+];
+static CREDENTIALS_PATTERNS: &[&str] = &[
+    "expiredtoken",
+    "credentials have expired",
+    "unable to locate credentials",
+    "invalidclienttokenid",
+    "requestexpired",
+    "reauthentication",
+];
+
+impl ServicingError {
+    /// from_provision_output recognises the common provisioning failures in the output of the
+    /// orchestrator, so the caller gets something more actionable than an exit code.
+    pub fn from_provision_output(name: &str, output: &str) -> Option<ServicingError> {
+        let output = output.to_lowercase();
+        let matches = |patterns: &[&str]| patterns.iter().any(|p| output.contains(p));
+
+        if matches(CREDENTIALS_PATTERNS) {
+            Some(ServicingError::CredentialsExpired(name.to_string()))
+        } else if matches(QUOTA_PATTERNS) {
+            Some(ServicingError::QuotaExceeded(name.to_string()))
+        } else if matches(CAPACITY_PATTERNS) {
+            Some(ServicingError::NoCapacity(name.to_string()))
+        } else {
+            None
+        }
+    }
+
+    /// code returns a stable, machine-readable code for the error, e.g. PROVISION_CAPACITY, for
+    /// UI layers mapping errors to their own messages and runbooks. Released codes never change.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ServicingError::General(_) => "GENERAL",
+            ServicingError::IO(_) => "IO",
+            ServicingError::PipPackageError(_) => "PACKAGE_MISSING",
+            ServicingError::UnknownOrchestrator(_) => "UNKNOWN_ORCHESTRATOR",
+            ServicingError::ReqwestError(_) => "HTTP",
+            ServicingError::ClusterProvisionError(_) => "PROVISION_FAILED",
+            ServicingError::QuotaExceeded(_) => "PROVISION_QUOTA",
+            ServicingError::NoCapacity(_) => "PROVISION_CAPACITY",
+            ServicingError::CredentialsExpired(_) => "CREDENTIALS_EXPIRED",
+            ServicingError::NoQuota(_, _) => "QUOTA_ZERO",
+            ServicingError::SerdeYamlError(_) => "INVALID_YAML",
+            ServicingError::SerdeJsonError(_) => "INVALID_JSON",
+            ServicingError::ServiceNotFound(_) => "SERVICE_NOT_FOUND",
+            ServicingError::ServiceAlreadyExists(_) => "SERVICE_EXISTS",
+            ServicingError::ServiceNotUp(_) => "SERVICE_NOT_UP",
+            ServicingError::ExternalService(_) => "SERVICE_EXTERNAL",
+            ServicingError::NotOwner(_, _) => "NOT_OWNER",
+            ServicingError::ImageBuildError(_, _) => "IMAGE_BUILD",
+            ServicingError::WorkdirTooLarge(_, _, _) => "WORKDIR_TOO_LARGE",
+            ServicingError::BudgetExceeded(_, _) => "BUDGET_EXCEEDED",
+            ServicingError::PolicyViolation(_, _) => "POLICY_VIOLATION",
+            ServicingError::ApprovalRequired(_) => "APPROVAL_REQUIRED",
+            ServicingError::ApprovalRefused(_, _) => "APPROVAL_REFUSED",
+            ServicingError::LaunchDeclined(_) => "LAUNCH_DECLINED",
+            ServicingError::ConfigDrift(_) => "CONFIG_DRIFT",
+            ServicingError::CircuitOpen(_) => "CIRCUIT_OPEN",
+            ServicingError::OperationInProgress(_, _, _) => "OPERATION_IN_PROGRESS",
+            ServicingError::DeadlineExceeded(_) => "DEADLINE_EXCEEDED",
+            ServicingError::Interrupted(_) => "INTERRUPTED",
+            ServicingError::BinaryEncodeError(_) => "STATE_ENCODING",
+            ServicingError::SendError(_) | ServicingError::LockError(_) => "INTERNAL",
+            ServicingError::RegexError(_) => "INVALID_PATTERN",
+            ServicingError::Base64Error(_) => "INVALID_BASE64",
+            ServicingError::CsvError(_) => "CSV_ENCODING",
+            #[cfg(feature = "parquet")]
+            ServicingError::ParquetError(_) => "PARQUET_ENCODING",
+            #[cfg(feature = "python")]
+            ServicingError::PythonError(_) => "PYTHON",
+        }
+    }
+}
+
+#[cfg(feature = "python")]
 impl From<ServicingError> for PyErr {
     fn from(err: ServicingError) -> PyErr {
-        PyErr::new::<PyRuntimeError, _>(err.to_string())
+        match err {
+            // keep the original python exception
+            ServicingError::PythonError(err) => err,
+            err => {
+                let code = err.code();
+                let exc = PyErr::new::<PyRuntimeError, _>(err.to_string());
+                Python::with_gil(|py| {
+                    // best effort, the message alone is still a valid error
+                    let _ = exc.value_bound(py).setattr("code", code);
+                });
+                exc
+            }
+        }
     }
 }
 
@@ -49,3 +202,18 @@ impl<T> From<PoisonError<T>> for ServicingError {
         ServicingError::LockError(err.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ServicingError;
+
+    #[test]
+    fn test_provision_codes() {
+        let quota = ServicingError::from_provision_output("svc", "QuotaExceeded: vCPU");
+        assert_eq!(quota.map(|e| e.code()), Some("PROVISION_QUOTA"));
+        let capacity =
+            ServicingError::from_provision_output("svc", "InsufficientInstanceCapacity in zone");
+        assert_eq!(capacity.map(|e| e.code()), Some("PROVISION_CAPACITY"));
+        assert!(ServicingError::from_provision_output("svc", "all good").is_none());
+    }
+}
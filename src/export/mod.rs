@@ -0,0 +1,240 @@
+//! Export module houses the dumps of the registry, the events and the probe metrics of the
+//! services into CSV or Parquet files, a row per item, for analyses in pandas and the like
+//! without reading the binary cache.
+use std::{path::Path, str::FromStr};
+
+use serde::Serialize;
+
+use crate::error::ServicingError;
+
+/// Format is the file format of an export.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Csv,
+    Parquet,
+}
+
+impl FromStr for Format {
+    type Err = ServicingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(Format::Csv),
+            "parquet" => Ok(Format::Parquet),
+            _ => Err(ServicingError::General(format!(
+                "Unknown export format {s}, expected csv or parquet"
+            ))),
+        }
+    }
+}
+
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Parquet => "parquet",
+        }
+    }
+}
+
+/// write dumps rows to path in format, a column per field of the rows, which must be flat.
+/// Without rows nothing is written and false returned.
+pub fn write<T: Serialize>(
+    rows: &[T],
+    path: &Path,
+    format: Format,
+) -> Result<bool, ServicingError> {
+    if rows.is_empty() {
+        return Ok(false);
+    }
+    match format {
+        Format::Csv => {
+            let mut writer = csv::Writer::from_path(path)?;
+            for row in rows {
+                writer.serialize(row)?;
+            }
+            writer.flush()?;
+        }
+        #[cfg(feature = "parquet")]
+        Format::Parquet => parquet::write(rows, path)?,
+        #[cfg(not(feature = "parquet"))]
+        Format::Parquet => {
+            return Err(ServicingError::General(
+                "servicing was built without Parquet support, export to CSV instead".to_string(),
+            ))
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(feature = "parquet")]
+mod parquet {
+    use std::{fs::File, path::Path, sync::Arc};
+
+    use parquet::{
+        data_type::{BoolType, ByteArray, ByteArrayType, DoubleType},
+        file::{properties::WriterProperties, writer::SerializedFileWriter},
+        schema::parser::parse_message_type,
+    };
+    use serde::Serialize;
+    use serde_json::{Map, Value};
+
+    use crate::error::ServicingError;
+
+    /// Column is the type of a column, told from the values of the rows.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub(super) enum Column {
+        Double,
+        Boolean,
+        Text,
+    }
+
+    /// column types the values as numbers or booleans if they all are, as text otherwise.
+    pub(super) fn column<'a>(values: impl Iterator<Item = &'a Value>) -> Column {
+        let mut column = None;
+        for value in values {
+            let kind = match value {
+                Value::Null => continue,
+                Value::Number(_) => Column::Double,
+                Value::Bool(_) => Column::Boolean,
+                _ => return Column::Text,
+            };
+            if column.is_some_and(|column| column != kind) {
+                return Column::Text;
+            }
+            column = Some(kind);
+        }
+        column.unwrap_or(Column::Text)
+    }
+
+    /// write dumps rows, at least one, in one row group, every column being optional.
+    pub(super) fn write<T: Serialize>(rows: &[T], path: &Path) -> Result<(), ServicingError> {
+        let rows = rows
+            .iter()
+            .map(|row| match serde_json::to_value(row)? {
+                Value::Object(fields) => Ok(fields),
+                _ => Err(ServicingError::General(
+                    "Only structs can be exported".to_string(),
+                )),
+            })
+            .collect::<Result<Vec<Map<String, Value>>, ServicingError>>()?;
+        let Some(first) = rows.first() else {
+            return Ok(());
+        };
+        let columns: Vec<(&String, Column)> = first
+            .keys()
+            .map(|name| (name, column(rows.iter().map(|row| &row[name]))))
+            .collect();
+
+        let fields: String = columns
+            .iter()
+            .map(|(name, column)| match column {
+                Column::Double => format!("optional double {name};"),
+                Column::Boolean => format!("optional boolean {name};"),
+                Column::Text => format!("optional binary {name} (UTF8);"),
+            })
+            .collect();
+        let schema = Arc::new(parse_message_type(&format!("message row {{ {fields} }}"))?);
+        let properties = Arc::new(WriterProperties::builder().build());
+        let mut writer = SerializedFileWriter::new(File::create(path)?, schema, properties)?;
+
+        let mut row_group = writer.next_row_group()?;
+        for (name, column) in &columns {
+            let Some(mut writer) = row_group.next_column()? else {
+                break;
+            };
+            let values = rows.iter().map(|row| &row[name.as_str()]);
+            let levels: Vec<i16> = values.clone().map(|v| i16::from(!v.is_null())).collect();
+            match column {
+                Column::Double => {
+                    let values: Vec<f64> = values.filter_map(Value::as_f64).collect();
+                    writer
+                        .typed::<DoubleType>()
+                        .write_batch(&values, Some(&levels), None)?;
+                }
+                Column::Boolean => {
+                    let values: Vec<bool> = values.filter_map(Value::as_bool).collect();
+                    writer
+                        .typed::<BoolType>()
+                        .write_batch(&values, Some(&levels), None)?;
+                }
+                Column::Text => {
+                    let values: Vec<ByteArray> = values
+                        .filter(|value| !value.is_null())
+                        .map(|value| match value {
+                            Value::String(text) => ByteArray::from(text.as_str()),
+                            value => ByteArray::from(value.to_string().as_str()),
+                        })
+                        .collect();
+                    writer
+                        .typed::<ByteArrayType>()
+                        .write_batch(&values, Some(&levels), None)?;
+                }
+            }
+            writer.close()?;
+        }
+        row_group.close()?;
+        writer.close()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde::Serialize;
+
+    use super::{write, Format};
+
+    #[derive(Serialize)]
+    struct Row {
+        service: &'static str,
+        ready: Option<bool>,
+        latency_ms: Option<f64>,
+    }
+
+    #[test]
+    fn test_write() {
+        let dir = std::env::temp_dir().join(format!("servicing-export-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let rows = [
+            Row {
+                service: "llm",
+                ready: Some(true),
+                latency_ms: Some(12.5),
+            },
+            Row {
+                service: "ocr",
+                ready: None,
+                latency_ms: None,
+            },
+        ];
+
+        let path = dir.join("probes.csv");
+        assert!(write(&rows, &path, Format::Csv).unwrap());
+        assert!(!write(&rows[..0], &dir.join("empty.csv"), Format::Csv).unwrap());
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "service,ready,latency_ms\nllm,true,12.5\nocr,,\n"
+        );
+
+        #[cfg(feature = "parquet")]
+        {
+            use parquet::file::reader::{FileReader, SerializedFileReader};
+
+            use super::parquet::{column, Column};
+
+            let values = [serde_json::json!(1), serde_json::Value::Null];
+            assert_eq!(column(values.iter()), Column::Double);
+            let values = [serde_json::json!(1), serde_json::json!("a")];
+            assert_eq!(column(values.iter()), Column::Text);
+
+            let path = dir.join("probes.parquet");
+            write(&rows, &path, Format::Parquet).unwrap();
+            let reader = SerializedFileReader::new(fs::File::open(&path).unwrap()).unwrap();
+            assert_eq!(reader.metadata().file_metadata().num_rows(), 2);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
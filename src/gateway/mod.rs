@@ -0,0 +1,458 @@
+//! Gateway module houses the local reverse proxy exposing every service under one port.
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use axum::{
+    body::Body,
+    extract::{Path, Request, State},
+    http::{
+        header::{
+            ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+            ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_MAX_AGE, ACCESS_CONTROL_REQUEST_METHOD,
+            HOST, ORIGIN, VARY,
+        },
+        HeaderMap, HeaderValue, Method, StatusCode,
+    },
+    response::{IntoResponse, Response},
+    routing::{any, get},
+    Json, Router,
+};
+use log::{error, info, warn};
+use pyo3::Python;
+use reqwest::Client;
+use serde::Serialize;
+use tokio::{net::TcpListener, runtime::Runtime, sync::oneshot};
+
+use crate::{
+    client::{Endpoint, OutboundRequest},
+    error::ServicingError,
+    helper,
+    middleware::{Middleware, Outcome},
+    models::{self, ServiceStatus},
+    scaler::Scaler,
+    traffic::Traffic,
+};
+
+static GATEWAY_PREFIX: &str = "/svc";
+static STATUS_PATH: &str = "/services.json";
+/// version of the schema of /services.json, bumped on breaking changes only
+static STATUS_SCHEMA: u32 = 1;
+
+static CORS_MAX_AGE: &str = "600";
+static CORS_DEFAULT_METHODS: &str = "GET, POST, PUT, PATCH, DELETE, OPTIONS";
+
+/// Resolver maps a service or alias name to the endpoint requests should be forwarded to.
+pub type Resolver = Arc<dyn Fn(&str) -> Result<Endpoint, ServicingError> + Send + Sync>;
+
+/// Statuses summarises the registered services for /services.json.
+pub type Statuses = Arc<dyn Fn() -> Result<Vec<ServiceStatus>, ServicingError> + Send + Sync>;
+
+/// Routes holds the options of the gateway routes by service or alias name.
+pub type Routes = Arc<Mutex<HashMap<String, RouteOptions>>>;
+
+/// Cors is the CORS policy of a route, answering preflight requests at the gateway.
+#[derive(Clone, Default)]
+pub struct Cors {
+    /// allowed origins, "*" allows any
+    pub origins: Vec<String>,
+    pub methods: Vec<String>,
+    pub headers: Vec<String>,
+}
+
+impl Cors {
+    /// allow_origin returns the Access-Control-Allow-Origin value for origin, if allowed.
+    fn allow_origin(&self, origin: &HeaderValue) -> Option<HeaderValue> {
+        let origin_str = origin.to_str().ok()?;
+        match self.origins.iter().any(|o| o == "*" || o == origin_str) {
+            true => Some(origin.clone()),
+            false => None,
+        }
+    }
+
+    fn apply(&self, origin: Option<&HeaderValue>, headers: &mut HeaderMap) {
+        if let Some(allowed) = origin.and_then(|origin| self.allow_origin(origin)) {
+            headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, allowed);
+            headers.append(VARY, HeaderValue::from_static("Origin"));
+        }
+    }
+
+    fn preflight(&self, origin: Option<&HeaderValue>) -> Response {
+        let mut headers = HeaderMap::new();
+        self.apply(origin, &mut headers);
+        let methods = match self.methods.is_empty() {
+            true => CORS_DEFAULT_METHODS.to_string(),
+            false => self.methods.join(", "),
+        };
+        let values = [
+            (ACCESS_CONTROL_ALLOW_METHODS, methods),
+            (ACCESS_CONTROL_ALLOW_HEADERS, self.headers.join(", ")),
+            (ACCESS_CONTROL_MAX_AGE, CORS_MAX_AGE.to_string()),
+        ];
+        for (name, value) in values {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                if !value.is_empty() {
+                    headers.insert(name, value);
+                }
+            }
+        }
+        (StatusCode::NO_CONTENT, headers).into_response()
+    }
+}
+
+/// RouteOptions are the options of the route of one service or alias.
+#[derive(Clone, Default)]
+pub struct RouteOptions {
+    pub cors: Option<Cors>,
+    /// headers added to every request forwarded to the service
+    pub headers: HeaderMap,
+}
+
+#[derive(Clone)]
+struct GatewayState {
+    client: Client,
+    resolver: Resolver,
+    statuses: Statuses,
+    traffic: Arc<Traffic>,
+    middleware: Arc<Middleware>,
+    scaler: Arc<Scaler>,
+    routes: Routes,
+}
+
+/// StatusReport is the body of /services.json, flat enough for a Grafana JSON datasource to
+/// read the services as table rows.
+#[derive(Serialize)]
+struct StatusReport {
+    schema: u32,
+    /// unix time the report was generated at
+    generated: u64,
+    services: Vec<ServiceStatus>,
+}
+
+/// Gateway is a running reverse proxy, dropping it shuts the proxy down.
+pub struct Gateway {
+    pub port: u16,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl Drop for Gateway {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+impl Gateway {
+    /// start binds the gateway on localhost and serves it on the provided runtime.
+    #[allow(clippy::too_many_arguments)]
+    pub fn start(
+        rt: &Runtime,
+        port: u16,
+        client: Client,
+        resolver: Resolver,
+        statuses: Statuses,
+        traffic: Arc<Traffic>,
+        middleware: Arc<Middleware>,
+        scaler: Arc<Scaler>,
+        routes: Routes,
+    ) -> Result<Gateway, ServicingError> {
+        let addr = SocketAddr::from(([127, 0, 0, 1], port));
+        let listener = rt.block_on(TcpListener::bind(addr))?;
+        let port = listener.local_addr()?.port();
+
+        let app = Router::new()
+            .route(STATUS_PATH, get(services))
+            .route(&format!("{GATEWAY_PREFIX}/:name"), any(forward_root))
+            .route(&format!("{GATEWAY_PREFIX}/:name/*path"), any(forward))
+            .with_state(GatewayState {
+                client,
+                resolver,
+                statuses,
+                traffic,
+                middleware,
+                scaler,
+                routes,
+            });
+
+        let (tx, rx) = oneshot::channel();
+        rt.spawn(async move {
+            info!("Gateway listening on port {}", port);
+            let server = axum::serve(listener, app).with_graceful_shutdown(async {
+                let _ = rx.await;
+            });
+            if let Err(e) = server.await {
+                error!("Gateway stopped: {e}");
+            }
+            info!("Gateway on port {} stopped", port);
+        });
+
+        Ok(Gateway {
+            port,
+            shutdown: Some(tx),
+        })
+    }
+}
+
+/// services answers /services.json with the state of every service.
+async fn services(State(state): State<GatewayState>) -> Response {
+    match (state.statuses)() {
+        Ok(services) => Json(StatusReport {
+            schema: STATUS_SCHEMA,
+            generated: models::unix_now(),
+            services,
+        })
+        .into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+async fn forward_root(
+    State(state): State<GatewayState>,
+    Path(name): Path<String>,
+    req: Request,
+) -> Response {
+    proxy(state, name, String::new(), req).await
+}
+
+async fn forward(
+    State(state): State<GatewayState>,
+    Path((name, path)): Path<(String, String)>,
+    req: Request,
+) -> Response {
+    proxy(state, name, path, req).await
+}
+
+/// proxy applies the options of the route around forwarding the request.
+async fn proxy(state: GatewayState, name: String, path: String, req: Request) -> Response {
+    let options = state
+        .routes
+        .lock()
+        .ok()
+        .and_then(|routes| routes.get(&name).cloned())
+        .unwrap_or_default();
+    let origin = req.headers().get(ORIGIN).cloned();
+
+    if let Some(cors) = &options.cors {
+        let preflight = req.method() == Method::OPTIONS
+            && req.headers().contains_key(ACCESS_CONTROL_REQUEST_METHOD);
+        if preflight {
+            return cors.preflight(origin.as_ref());
+        }
+    }
+
+    let mut res = forward_to_service(state, name, path, req, options.headers).await;
+    if let Some(cors) = &options.cors {
+        cors.apply(origin.as_ref(), res.headers_mut());
+    }
+    res
+}
+
+async fn forward_to_service(
+    state: GatewayState,
+    name: String,
+    path: String,
+    req: Request,
+    inject: HeaderMap,
+) -> Response {
+    let endpoint = match (state.resolver)(&name) {
+        Ok(Endpoint {
+            maintenance: Some(message),
+            ..
+        }) => return (StatusCode::SERVICE_UNAVAILABLE, message).into_response(),
+        // a service scaled to zero is not up until the request wakes it
+        Ok(endpoint) if endpoint.up || state.scaler.enabled(&endpoint.service) => endpoint,
+        Ok(_) => return unavailable(&name),
+        Err(ServicingError::ServiceNotFound(name)) => {
+            return (StatusCode::NOT_FOUND, format!("Service {name} not found")).into_response()
+        }
+        Err(_) => return unavailable(&name),
+    };
+
+    if let Err(e) = state.scaler.wake(&state.client, &endpoint.service).await {
+        return (StatusCode::SERVICE_UNAVAILABLE, e.to_string()).into_response();
+    }
+    if let Err(e) = state.traffic.acquire(&endpoint.service).await {
+        return (StatusCode::SERVICE_UNAVAILABLE, e.to_string()).into_response();
+    }
+
+    let mut url = helper::http_url(&endpoint.url, &format!("/{path}"));
+    if let Some(query) = req.uri().query() {
+        url.push('?');
+        url.push_str(query);
+    }
+
+    let (parts, body) = req.into_parts();
+    let body = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+
+    let mut headers = parts.headers;
+    headers.remove(HOST);
+    for (name, value) in inject.iter() {
+        headers.insert(name, value.clone());
+    }
+
+    let mut req = OutboundRequest {
+        method: parts.method,
+        url,
+        headers,
+        body: Some(body.to_vec()),
+    };
+    if let Err(e) = req.authorize(endpoint.api_key.as_deref()) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    }
+
+    let run_middleware = !state.middleware.is_empty();
+    if run_middleware {
+        // python hooks need the GIL, keep them off the runtime workers
+        let middleware = state.middleware.clone();
+        let service = endpoint.service.clone();
+        let hooked = tokio::task::spawn_blocking(move || {
+            Python::with_gil(|py| middleware.before(py, &service, &mut req)).map(|_| req)
+        })
+        .await;
+        req = match hooked {
+            Ok(Ok(req)) => req,
+            Ok(Err(e)) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        };
+    }
+
+    let start = Instant::now();
+    let res = req.send(&state.client).await;
+    let elapsed = start.elapsed();
+
+    state.traffic.record(
+        &endpoint.service,
+        res.as_ref()
+            .is_ok_and(|res| !res.status().is_server_error()),
+    );
+
+    if run_middleware {
+        let outcome = Outcome {
+            status: res.as_ref().ok().map(|r| r.status().as_u16()),
+            elapsed,
+            error: res.as_ref().err().map(|e| e.to_string()),
+        };
+        let middleware = state.middleware.clone();
+        let service = endpoint.service.clone();
+        tokio::task::spawn_blocking(move || {
+            Python::with_gil(|py| middleware.after(py, &service, &req, &outcome))
+        });
+    }
+
+    match res {
+        Ok(res) => {
+            let mut builder = Response::builder().status(res.status());
+            if let Some(headers) = builder.headers_mut() {
+                headers.extend(res.headers().clone());
+            }
+            builder
+                .body(Body::from_stream(res.bytes_stream()))
+                .unwrap_or_else(|e| {
+                    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+                })
+        }
+        Err(e) => {
+            warn!("Gateway failed to reach service {}: {e}", endpoint.service);
+            (StatusCode::BAD_GATEWAY, e.to_string()).into_response()
+        }
+    }
+}
+
+fn unavailable(name: &str) -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        format!("Service {name} not up"),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::PathBuf,
+        sync::{Arc, Mutex, OnceLock},
+        time::Duration,
+    };
+
+    use reqwest::{Client, StatusCode};
+
+    use super::Gateway;
+    use crate::{
+        client::Endpoint,
+        error::ServicingError,
+        runner::{self, ScriptedRunner},
+        scaler::{ScaleToZero, Scaler},
+    };
+
+    #[test]
+    fn test_gateway_wakes() {
+        // the gateway runs on this thread, along with the commands of the wake up
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let port = Arc::new(OnceLock::new());
+        let resolver_port = port.clone();
+        let scaler = Arc::new(Scaler::default());
+        let gateway = Gateway::start(
+            &rt,
+            0,
+            Client::new(),
+            Arc::new(move |name| match name {
+                // scaled to zero, served by the gateway itself once woken
+                "sleepy" | "down" => Ok(Endpoint {
+                    service: name.to_string(),
+                    url: format!("127.0.0.1:{}", resolver_port.get().unwrap()),
+                    up: false,
+                    api_key: None,
+                    maintenance: None,
+                }),
+                _ => Err(ServicingError::ServiceNotFound(name.to_string())),
+            }),
+            Arc::new(|| Ok(Vec::new())),
+            Default::default(),
+            Default::default(),
+            scaler.clone(),
+            Arc::new(Mutex::new(Default::default())),
+        )
+        .unwrap();
+        port.set(gateway.port).unwrap();
+        scaler
+            .enable(
+                &rt,
+                "sleepy",
+                ScaleToZero {
+                    up_config: PathBuf::from("sleepy.yaml"),
+                    zero_config: PathBuf::from("sleepy_zero.yaml"),
+                    readiness_url: format!("http://127.0.0.1:{}/services.json", gateway.port),
+                    idle_timeout: Duration::from_secs(3600),
+                    up_check: "no ready replicas",
+                },
+            )
+            .unwrap();
+        scaler.doze("sleepy");
+
+        let sky = Arc::new(ScriptedRunner::default().reply("sky serve update sleepy", 0, ""));
+        let client = Client::new();
+        let get = |name: &str| {
+            let url = format!("http://127.0.0.1:{}/svc/{name}/services.json", gateway.port);
+            runner::with_runner(sky.clone(), || {
+                rt.block_on(async { client.get(url).send().await.unwrap().status() })
+            })
+        };
+        assert_eq!(get("sleepy"), StatusCode::OK);
+        assert_eq!(
+            sky.commands(),
+            vec!["sky serve update sleepy sleepy.yaml -y"]
+        );
+        assert_eq!(get("down"), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(get("missing"), StatusCode::NOT_FOUND);
+    }
+}
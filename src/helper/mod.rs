@@ -1,31 +1,99 @@
 //! Helper module houses all the helper functions used by the service module.
 use std::{
     fs,
-    io::{self, Read},
+    future::Future,
+    io::{self, Read, Write},
     path::{Path, PathBuf},
     process::Command,
-    sync::mpsc::Receiver,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver},
+    },
     thread::{spawn, JoinHandle},
     time::Duration,
 };
 
 use log::info;
-use reqwest::{header::ACCEPT, Client};
-use tokio::time::sleep;
+use reqwest::{
+    header::{ACCEPT, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    Client, StatusCode,
+};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tokio::{
+    runtime::{Handle, Runtime, RuntimeFlavor},
+    task::block_in_place,
+    time::sleep,
+};
 
-use crate::error::ServicingError;
+use crate::{
+    deadline,
+    error::ServicingError,
+    interrupt, logic,
+    models::{ProbeCache, ProbeMethod},
+    runner::{self, Mode},
+};
 
 /// check_python_package_installed checks if the user has installed the required python package.
 /// True is returned if the package is installed, otherwise false.
 pub(super) fn check_python_package_installed(package: &str) -> bool {
     info!("Checking for python package: {}", package);
-    let output = Command::new("pip").arg("show").arg(package).output();
+    let output = runner::run(Command::new("pip").arg("show").arg(package), Mode::Capture);
     match output {
         Ok(output) => output.status.success(),
         Err(_) => false,
     }
 }
 
+/// current_user returns the name of the user running servicing.
+pub(super) fn current_user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+/// current_host returns the name of the machine running servicing.
+pub(super) fn current_host() -> String {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .ok()
+        .or_else(|| {
+            fs::read_to_string("/etc/hostname")
+                .ok()
+                .map(|h| h.trim().to_string())
+        })
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// sky_service_exists asks SkyPilot whether it still knows about the service, i.e. whether cloud
+/// resources may still be live. The service is only reported gone when the services table does
+/// not list it; a missing sky CLI or a failing status is an error, as SkyPilot could not tell.
+pub(super) fn sky_service_exists(name: &str) -> Result<bool, ServicingError> {
+    info!("Checking SkyPilot for service: {}", name);
+    let _permit = crate::limiter::acquire(&format!("sky serve status {name}"))?;
+    let output = match runner::run(
+        Command::new("sky").arg("serve").arg("status").arg(name),
+        Mode::Capture,
+    ) {
+        Ok(output) => output,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(ServicingError::General(format!(
+                "sky CLI not found, cannot tell whether service {name} has live resources"
+            )));
+        }
+        Err(e) => Err(e)?,
+    };
+    if !output.status.success() {
+        return Err(ServicingError::General(format!(
+            "sky serve status {name} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(logic::serve_services(&stdout).iter().any(|s| s == name))
+}
+
 pub(super) fn create_directory(dirname: &str, home: bool) -> Result<PathBuf, ServicingError> {
     let dir_name = if home {
         match dirs::home_dir() {
@@ -58,18 +126,6 @@ pub(super) fn create_directory(dirname: &str, home: bool) -> Result<PathBuf, Ser
     }
 }
 
-pub(super) fn create_file(dirname: &PathBuf, filename: &str) -> Result<PathBuf, ServicingError> {
-    // create a file in the provided directory
-    let path = Path::new(dirname).join(filename);
-    match fs::File::create(&path) {
-        Ok(_) => {
-            info!("File '{:?}' created successfully.", path);
-            Ok(path)
-        }
-        Err(e) => Err(e)?,
-    }
-}
-
 pub(super) fn delete_file(filepath: &PathBuf) -> Result<(), ServicingError> {
     // delete a file in the provided directory
     match fs::remove_file(filepath) {
@@ -86,7 +142,7 @@ pub(super) fn delete_file(filepath: &PathBuf) -> Result<(), ServicingError> {
 
 pub(super) fn write_to_file(filepath: &PathBuf, content: &str) -> Result<(), ServicingError> {
     // write content to a file in the provided file
-    match fs::write(filepath, content) {
+    match write_atomic(filepath, content.as_bytes(), false) {
         Ok(_) => {
             info!("Content written to file '{:?}' successfully.", filepath);
             Ok(())
@@ -95,12 +151,48 @@ pub(super) fn write_to_file(filepath: &PathBuf, content: &str) -> Result<(), Ser
     }
 }
 
+pub(super) fn read_from_file(filepath: &PathBuf) -> Result<String, ServicingError> {
+    // read content from a file in the provided file
+    match fs::read_to_string(filepath) {
+        Ok(content) => {
+            info!("Content read from file '{:?}' successfully.", filepath);
+            Ok(content)
+        }
+        Err(e) => Err(e)?,
+    }
+}
+
+/// generate_api_key returns a random 256-bit key, hex encoded.
+pub(super) fn generate_api_key() -> Result<String, ServicingError> {
+    let mut bytes = [0u8; 32];
+    fs::File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+    Ok(bytes.iter().map(|b| format!("{b:02x}")).collect())
+}
+
+/// http_url joins the url of a service and path. The url of a managed service is a host:port
+/// reached over plain HTTP, that of an external one may carry its own scheme.
+pub(super) fn http_url(url: &str, path: &str) -> String {
+    match url.contains("://") {
+        true => format!("{}{}", url.trim_end_matches('/'), path),
+        false => format!("http://{url}{path}"),
+    }
+}
+
+/// content_hash returns the hex encoded sha256 of the content, used to detect changes to the
+/// rendered configurations and the cache.
+pub(super) fn content_hash(content: impl AsRef<[u8]>) -> String {
+    Sha256::digest(content.as_ref())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
 pub(super) fn write_to_file_binary(
     filepath: &PathBuf,
     content: &[u8],
 ) -> Result<(), ServicingError> {
     // write content to a file in the provided file
-    match fs::write(filepath, content) {
+    match write_atomic(filepath, content, false) {
         Ok(_) => {
             info!("Content written to file '{:?}' successfully.", filepath);
             Ok(())
@@ -109,6 +201,46 @@ pub(super) fn write_to_file_binary(
     }
 }
 
+static WRITE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// write_atomic replaces the content of filepath so that readers and crashes only ever see the
+/// previous or the new content: it is written to a temporary file next to it, synced to disk,
+/// then renamed over it. With backup, the previous content is kept as filepath.bak.
+pub(super) fn write_atomic(filepath: &Path, content: &[u8], backup: bool) -> io::Result<()> {
+    let name = filepath
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No file name"))?
+        .to_string_lossy();
+    let dir = match filepath.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    // unique per process and call, concurrent writers never share a temporary file
+    let tmp = dir.join(format!(
+        ".{name}.{}.{}.tmp",
+        std::process::id(),
+        WRITE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let written = fs::File::create(&tmp).and_then(|mut file| {
+        file.write_all(content)?;
+        file.sync_all()
+    });
+    if let Err(e) = written {
+        let _ = fs::remove_file(&tmp);
+        return Err(e);
+    }
+    if backup && filepath.is_file() {
+        fs::copy(filepath, dir.join(format!("{name}.bak")))?;
+    }
+    fs::rename(&tmp, filepath)?;
+    // persist the rename itself, not supported everywhere
+    if let Ok(dir) = fs::File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
 pub(super) fn read_from_file_binary(filepath: &PathBuf) -> Result<Vec<u8>, ServicingError> {
     // read content from a file in the provided file
     match fs::read(filepath) {
@@ -120,7 +252,6 @@ pub(super) fn read_from_file_binary(filepath: &PathBuf) -> Result<Vec<u8>, Servi
     }
 }
 
-#[allow(dead_code)]
 pub(super) fn read_from_child<T>(
     mut child: T,
 ) -> (Receiver<Vec<u8>>, JoinHandle<Result<(), ServicingError>>)
@@ -129,32 +260,24 @@ where
 {
     let (tx, rx) = std::sync::mpsc::channel::<Vec<u8>>();
     let handle = spawn(move || {
+        // forward whatever is available, prompts are not newline terminated
+        let mut buffer = [0; 4096];
         loop {
-            let mut buffer = [0; 2];
-
-            match child.read_exact(&mut buffer) {
-                Ok(_) => {
-                    if tx.send(buffer.to_vec()).is_err() {
+            match child.read(&mut buffer) {
+                Ok(0) => {
+                    info!("End of file reached.");
+                    break;
+                }
+                Ok(n) => {
+                    if tx.send(buffer[..n].to_vec()).is_err() {
                         log::warn!("Failed to send data to the receiver.");
                         return Err(ServicingError::General(
                             "Failed to send data to the receiver.".to_string(),
                         ));
                     }
                 }
-                Err(e) => {
-                    if e.kind() == io::ErrorKind::UnexpectedEof {
-                        info!("End of file reached.");
-                        if tx.send(buffer.to_vec()).is_err() {
-                            log::warn!("Failed to send data to the receiver.");
-                            return Err(ServicingError::General(
-                                "Failed to send data to the receiver.".to_string(),
-                            ));
-                        }
-                        break;
-                    } else {
-                        return Err(ServicingError::General(e.to_string()));
-                    }
-                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(ServicingError::General(e.to_string())),
             }
         }
         Ok(())
@@ -162,6 +285,56 @@ where
     (rx, handle)
 }
 
+/// tee_child_output echoes the output of a child process to writer as it arrives and hands back
+/// everything that was written once the child closes it.
+pub(super) fn tee_child_output<T, W>(child: T, mut writer: W) -> JoinHandle<Vec<u8>>
+where
+    T: Read + Send + 'static,
+    W: Write + Send + 'static,
+{
+    let (rx, reader) = read_from_child(child);
+    spawn(move || {
+        let mut output = Vec::new();
+        for chunk in rx {
+            // echoing is best effort, the output is still collected
+            let _ = writer.write_all(&chunk).and_then(|_| writer.flush());
+            output.extend_from_slice(&chunk);
+        }
+        if let Ok(Err(e)) = reader.join() {
+            log::warn!("Failed to read child output: {e}");
+        }
+        output
+    })
+}
+
+/// block_on runs the future on the runtime and waits for its output. Unlike Runtime::block_on it
+/// does not panic when called from a thread already in a runtime context, such as a Python
+/// callback run by the runtime or an async framework embedding tokio: the future is spawned on
+/// the runtime and its output received over a channel instead. The future is dropped once the
+/// deadline of the calling thread passes, or once the call it serves receives SIGINT.
+pub(super) fn block_on<F>(rt: &Runtime, fut: F) -> Result<F::Output, ServicingError>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let fut = deadline::bound(interrupt::bound(fut));
+    let fut = async move { fut.await.and_then(|output| output) };
+    let Ok(current) = Handle::try_current() else {
+        return rt.block_on(fut);
+    };
+
+    let (tx, rx) = mpsc::channel();
+    rt.spawn(async move {
+        let _ = tx.send(fut.await);
+    });
+    let received = match current.runtime_flavor() {
+        // let the other tasks of a multi-threaded runtime move off this worker meanwhile
+        RuntimeFlavor::MultiThread => block_in_place(|| rx.recv()),
+        _ => rx.recv(),
+    };
+    received.map_err(|_| ServicingError::General("Runtime task was cancelled".to_string()))?
+}
+
 pub async fn fetch(client: &Client, url: &str) -> Result<String, reqwest::Error> {
     let res = client
         .get(url)
@@ -172,6 +345,52 @@ pub async fn fetch(client: &Client, url: &str) -> Result<String, reqwest::Error>
     Ok(body)
 }
 
+/// probe polls the readiness endpoint at url with method and tells whether the service is ready,
+/// judging the body with ready. cache keeps the validators of the last response, so unchanged
+/// bodies are not sent again to conditional requests.
+pub async fn probe(
+    client: &Client,
+    url: &str,
+    method: ProbeMethod,
+    cache: &mut ProbeCache,
+    ready: impl Fn(&str) -> bool,
+) -> Result<bool, reqwest::Error> {
+    if method == ProbeMethod::Head {
+        let res = client.head(url).send().await?;
+        match res.status() {
+            StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED => {
+                info!("HEAD not supported by {url}, probing with GET")
+            }
+            status => return Ok(status.is_success()),
+        }
+    }
+
+    let mut req = client.get(url).header(ACCEPT, "application/json");
+    if method == ProbeMethod::Conditional {
+        if let Some(etag) = &cache.etag {
+            req = req.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &cache.last_modified {
+            req = req.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+    let res = req.send().await?;
+    if let (StatusCode::NOT_MODIFIED, Some(ready)) = (res.status(), cache.ready) {
+        return Ok(ready);
+    }
+    let header = |name| {
+        res.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    cache.etag = header(ETAG);
+    cache.last_modified = header(LAST_MODIFIED);
+    let is_ready = ready(&res.text().await?);
+    cache.ready = Some(is_ready);
+    Ok(is_ready)
+}
+
 pub async fn fetch_and_check(
     client: &Client,
     url: &str,
@@ -197,3 +416,35 @@ pub async fn fetch_and_check(
 
     Ok(())
 }
+
+/// openapi_routes lists every operation in an OpenAPI document as "METHOD /path".
+pub fn openapi_routes(spec: &Value) -> Vec<String> {
+    let mut routes = Vec::new();
+    if let Some(paths) = spec.get("paths").and_then(Value::as_object) {
+        for (path, item) in paths {
+            if let Some(item) = item.as_object() {
+                for method in item.keys() {
+                    // path items may also carry parameters, summary, etc.
+                    if matches!(
+                        method.as_str(),
+                        "get" | "put" | "post" | "delete" | "options" | "head" | "patch" | "trace"
+                    ) {
+                        routes.push(format!("{} {}", method.to_uppercase(), path));
+                    }
+                }
+            }
+        }
+    }
+    routes
+}
+
+/// openapi_models lists the schema names declared in an OpenAPI document.
+pub fn openapi_models(spec: &Value) -> Vec<String> {
+    spec.get("components")
+        .and_then(|c| c.get("schemas"))
+        // OpenAPI 2 keeps the schemas under definitions
+        .or(spec.get("definitions"))
+        .and_then(Value::as_object)
+        .map(|schemas| schemas.keys().cloned().collect())
+        .unwrap_or_default()
+}
@@ -0,0 +1,204 @@
+//! Hooks module houses the plugins run before and after the operations on a service (add, up,
+//! down, remove), for integrations such as CMDB registration or ticketing. Plugins are
+//! registered from Python or installed as packages declaring a `servicing.hooks` entry point.
+use std::sync::Mutex;
+
+use log::{info, warn};
+use pyo3::{
+    types::{PyAnyMethods, PyDict, PyDictMethods, PyModule},
+    Bound, PyObject, Python,
+};
+
+use crate::error::ServicingError;
+
+pub static ENTRY_POINT_GROUP: &str = "servicing.hooks";
+pub static OPERATIONS: &[&str] = &["add", "up", "down", "remove"];
+
+struct Hook {
+    /// entry point the hook was loaded from, None when registered from Python
+    plugin: Option<String>,
+    before: Option<PyObject>,
+    after: Option<PyObject>,
+    /// operations the hook applies to, all of them if None
+    operations: Option<Vec<String>>,
+}
+
+/// Context describes the service an operation is run on, handed to the hooks as a dict.
+pub struct Context<'a> {
+    pub service: &'a str,
+    pub operation: &'a str,
+    pub orchestrator: Option<&'static str>,
+    pub owner: Option<String>,
+    pub url: Option<String>,
+    /// rendered configuration
+    pub config: Option<String>,
+}
+
+/// Hooks keeps the registered hooks. Before hooks are called with the context dict (service,
+/// operation, orchestrator, owner, url, config); after hooks with the context dict updated after
+/// the operation and an error, None if it succeeded.
+#[derive(Default)]
+pub struct Hooks {
+    hooks: Mutex<Vec<Hook>>,
+}
+
+impl Hooks {
+    pub fn add(
+        &self,
+        before: Option<PyObject>,
+        after: Option<PyObject>,
+        operations: Option<Vec<String>>,
+    ) -> Result<(), ServicingError> {
+        if let Some(unknown) = operations
+            .iter()
+            .flatten()
+            .find(|o| !OPERATIONS.contains(&o.as_str()))
+        {
+            return Err(ServicingError::General(format!(
+                "Unknown operation {unknown}, expected one of {}",
+                OPERATIONS.join(", ")
+            )));
+        }
+        self.hooks.lock()?.push(Hook {
+            plugin: None,
+            before,
+            after,
+            operations,
+        });
+        Ok(())
+    }
+
+    /// clear removes the hooks registered from Python, plugins only with all set.
+    pub fn clear(&self, all: bool) -> Result<(), ServicingError> {
+        self.hooks.lock()?.retain(|h| !all && h.plugin.is_some());
+        Ok(())
+    }
+
+    /// plugins lists the entry points the hooks were loaded from.
+    pub fn plugins(&self) -> Result<Vec<String>, ServicingError> {
+        Ok(self
+            .hooks
+            .lock()?
+            .iter()
+            .filter_map(|h| h.plugin.clone())
+            .collect())
+    }
+
+    /// load_entry_points registers the plugins of the installed packages. Each entry point of
+    /// the servicing.hooks group names an object, typically a module, whose before and after
+    /// attributes are the hooks. A plugin failing to load is skipped. The plugins are loaded out
+    /// of the lock, their import may register hooks.
+    pub fn load_entry_points(&self, py: Python<'_>) -> Result<(), ServicingError> {
+        let metadata = PyModule::import_bound(py, "importlib.metadata")?;
+        let kwargs = PyDict::new_bound(py);
+        kwargs.set_item("group", ENTRY_POINT_GROUP)?;
+        let entry_points = metadata.call_method("entry_points", (), Some(&kwargs))?;
+
+        let mut hooks = Vec::new();
+        for entry_point in entry_points.iter()? {
+            let entry_point = entry_point?;
+            let name: String = entry_point.getattr("name")?.extract()?;
+            let plugin = match entry_point.call_method0("load") {
+                Ok(plugin) => plugin,
+                Err(e) => {
+                    warn!("Could not load the servicing plugin {name}: {e}");
+                    continue;
+                }
+            };
+            let attr = |attr| {
+                plugin
+                    .getattr(attr)
+                    .ok()
+                    .filter(|hook| hook.is_callable())
+                    .map(|hook| hook.unbind())
+            };
+            info!("Loaded the servicing plugin {}", name);
+            hooks.push(Hook {
+                plugin: Some(name),
+                before: attr("before"),
+                after: attr("after"),
+                operations: None,
+            });
+        }
+        self.hooks.lock()?.extend(hooks);
+        Ok(())
+    }
+
+    /// before runs the before hooks of the operation in registration order, an exception aborts
+    /// the operation.
+    pub fn before(&self, py: Python<'_>, context: &Context) -> Result<(), ServicingError> {
+        let before = self.select(py, context.operation, |h| h.before.as_ref())?;
+        if before.is_empty() {
+            return Ok(());
+        }
+
+        let context = context_dict(py, context)?;
+        for hook in before {
+            hook.call1(py, (&context,))?;
+        }
+        Ok(())
+    }
+
+    /// after runs the after hooks of the operation, exceptions are logged and do not affect the
+    /// outcome of the operation.
+    pub fn after(&self, py: Python<'_>, context: &Context, error: Option<&ServicingError>) {
+        let after = match self.select(py, context.operation, |h| h.after.as_ref()) {
+            Ok(after) if !after.is_empty() => after,
+            _ => return,
+        };
+
+        let dict = match context_dict(py, context) {
+            Ok(dict) => dict,
+            Err(e) => {
+                warn!("Could not build the hook arguments: {e}");
+                return;
+            }
+        };
+        let error = error.map(|e| e.to_string());
+        for hook in after {
+            if let Err(e) = hook.call1(py, (&dict, error.as_deref())) {
+                warn!("Hook after {} failed: {e}", context.operation);
+            }
+        }
+    }
+
+    /// select returns the hooks of operation picked by hook, out of the lock so that they can use
+    /// the dispatcher, registering hooks included.
+    fn select(
+        &self,
+        py: Python<'_>,
+        operation: &str,
+        hook: impl Fn(&Hook) -> Option<&PyObject>,
+    ) -> Result<Vec<PyObject>, ServicingError> {
+        Ok(self
+            .hooks
+            .lock()?
+            .iter()
+            .filter(|h| h.applies_to(operation))
+            .filter_map(hook)
+            .map(|hook| hook.clone_ref(py))
+            .collect())
+    }
+}
+
+impl Hook {
+    fn applies_to(&self, operation: &str) -> bool {
+        self.operations
+            .as_ref()
+            .is_none_or(|operations| operations.iter().any(|o| o == operation))
+    }
+}
+
+fn context_dict<'py>(
+    py: Python<'py>,
+    context: &Context,
+) -> Result<Bound<'py, PyDict>, ServicingError> {
+    let dict = PyDict::new_bound(py);
+    dict.set_item("service", context.service)?;
+    dict.set_item("operation", context.operation)?;
+    dict.set_item("orchestrator", context.orchestrator)?;
+    dict.set_item("owner", context.owner.as_deref())?;
+    dict.set_item("url", context.url.as_deref())?;
+    dict.set_item("config", context.config.as_deref())?;
+    Ok(dict)
+}
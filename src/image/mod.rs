@@ -0,0 +1,206 @@
+//! Image module houses the container image build run before launching a service, for backends
+//! running the service from an image rather than from a shipped workdir.
+use std::{fmt, path::PathBuf, process::Command};
+
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::ServicingError,
+    limiter, models,
+    runner::{self, Mode},
+};
+
+/// ImageBuild builds dockerfile in context and pushes the image to registry, e.g.
+/// `ghcr.io/acme`, as `{registry}/{service}:{tag}`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ImageBuild {
+    pub dockerfile: PathBuf,
+    pub context: PathBuf,
+    pub registry: String,
+    /// tag of the image, the time of the build by default so every up pulls a fresh image
+    pub tag: Option<String>,
+}
+
+/// Secret is how the password of a registry is obtained.
+#[derive(Clone)]
+pub enum Secret {
+    Password(String),
+    /// shell command printing a short-lived token, e.g. `aws ecr get-login-password`
+    Helper(String),
+}
+
+/// RegistryCredentials are the credentials of a container registry. They are only kept in
+/// memory and handed to docker and SkyPilot through stdin and the environment.
+#[derive(Clone)]
+pub struct RegistryCredentials {
+    pub username: String,
+    pub secret: Secret,
+}
+
+impl fmt::Debug for RegistryCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RegistryCredentials")
+            .field("username", &self.username)
+            .field("secret", &"<redacted>")
+            .finish()
+    }
+}
+
+/// RegistryLogin is a resolved login to a registry.
+pub struct RegistryLogin {
+    pub server: String,
+    pub username: String,
+    pub password: String,
+}
+
+impl RegistryCredentials {
+    /// login resolves the password, running the token helper if there is one.
+    pub fn login(&self, server: &str) -> Result<RegistryLogin, ServicingError> {
+        let password = match &self.secret {
+            Secret::Password(password) => password.clone(),
+            Secret::Helper(helper) => {
+                let output = runner::run(Command::new("sh").arg("-c").arg(helper), Mode::Capture)?;
+                if !output.status.success() {
+                    return Err(ServicingError::General(format!(
+                        "Token helper of registry {server} failed: {}",
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    )));
+                }
+                String::from_utf8_lossy(&output.stdout).trim().to_string()
+            }
+        };
+        Ok(RegistryLogin {
+            server: server.to_string(),
+            username: self.username.clone(),
+            password,
+        })
+    }
+}
+
+/// registry_server returns the registry host of an image or repository reference, e.g.
+/// `ghcr.io` for `ghcr.io/acme/app`. References without one are on Docker Hub.
+pub fn registry_server(reference: &str) -> &str {
+    match reference.split_once('/') {
+        Some((host, _)) if host.contains(['.', ':']) || host == "localhost" => host,
+        _ => "docker.io",
+    }
+}
+
+impl ImageBuild {
+    /// build_and_push builds and pushes the image and returns its reference, logging in to the
+    /// registry first if a login is provided. With quiet, the docker output is only shown when a
+    /// step fails.
+    pub fn build_and_push(
+        &self,
+        name: &str,
+        login: Option<&RegistryLogin>,
+        quiet: bool,
+    ) -> Result<String, ServicingError> {
+        let image = self.reference(name);
+        let (build, push) = self.commands(&image);
+
+        info!("Building image {} for service {}", image, name);
+        docker(name, "build", build, quiet)?;
+
+        if let Some(login) = login {
+            docker_login(name, login)?;
+        }
+
+        info!("Pushing image {}", image);
+        docker(name, "push", push, quiet)?;
+        Ok(image)
+    }
+
+    fn reference(&self, name: &str) -> String {
+        let tag = self
+            .tag
+            .clone()
+            .unwrap_or_else(|| models::unix_now().to_string());
+        format!("{}/{}:{}", self.registry.trim_end_matches('/'), name, tag)
+    }
+
+    /// commands returns the docker build and push commands of image.
+    fn commands(&self, image: &str) -> (Command, Command) {
+        let mut build = Command::new("docker");
+        build
+            .arg("build")
+            .arg("-f")
+            .arg(&self.dockerfile)
+            .arg("-t")
+            .arg(image)
+            .arg(&self.context);
+        let mut push = Command::new("docker");
+        push.arg("push").arg(image);
+        (build, push)
+    }
+}
+
+fn login_command(login: &RegistryLogin) -> Command {
+    let mut cmd = Command::new("docker");
+    cmd.arg("login")
+        .arg(&login.server)
+        .arg("--username")
+        .arg(&login.username)
+        .arg("--password-stdin");
+    cmd
+}
+
+/// docker_login logs docker in to the registry, the password goes through stdin.
+pub fn docker_login(name: &str, login: &RegistryLogin) -> Result<(), ServicingError> {
+    let _permit = limiter::acquire(&format!("docker login {}", login.server))?;
+    let output = runner::run(
+        &mut login_command(login),
+        Mode::Secret(login.password.as_bytes().to_vec()),
+    )?;
+    if !output.status.success() {
+        return Err(ServicingError::ImageBuildError(
+            name.to_string(),
+            format!(
+                "docker login to {} failed\n{}",
+                login.server,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn docker(name: &str, step: &str, mut cmd: Command, quiet: bool) -> Result<(), ServicingError> {
+    let _permit = limiter::acquire(&format!("docker {step} {name}"))?;
+    let (success, output) = if quiet {
+        let output = runner::run(&mut cmd, Mode::Capture)?;
+        (
+            output.status.success(),
+            String::from_utf8_lossy(&output.stderr).to_string(),
+        )
+    } else {
+        let output = runner::run(&mut cmd, Mode::Interactive { echo: true })?;
+        (output.status.success(), String::new())
+    };
+    if !success {
+        return Err(ServicingError::ImageBuildError(
+            name.to_string(),
+            format!("docker {step} failed\n{}", output.trim())
+                .trim()
+                .to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::registry_server;
+
+    #[test]
+    fn test_registry_server() {
+        assert_eq!(registry_server("ghcr.io/acme/app:1"), "ghcr.io");
+        assert_eq!(registry_server("localhost:5000/app"), "localhost:5000");
+        assert_eq!(
+            registry_server("123.dkr.ecr.us-east-1.amazonaws.com/app"),
+            "123.dkr.ecr.us-east-1.amazonaws.com"
+        );
+        assert_eq!(registry_server("acme/app"), "docker.io");
+    }
+}
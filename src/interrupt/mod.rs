@@ -0,0 +1,261 @@
+//! Interrupt module houses the handling of SIGINT during the operations on the services. The
+//! signal is watched on the runtime, alongside the handler of Python which still raises
+//! KeyboardInterrupt, and the calls watching for it on a thread stop waiting: the subprocesses
+//! they run are killed with their process group or left running, depending on the policy.
+use std::{
+    cell::Cell,
+    future::Future,
+    io,
+    process::{Child, Command},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering},
+        OnceLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{info, warn};
+use tokio::{
+    runtime::Runtime,
+    signal::unix::{signal, SignalKind},
+    sync::Notify,
+};
+
+use crate::error::ServicingError;
+
+/// time a stopped subprocess is given to exit on SIGTERM before it is killed
+static KILL_GRACE: Duration = Duration::from_secs(5);
+static KILL_POLL: Duration = Duration::from_millis(50);
+
+/// SIGINTs received since the process started
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+static POLICY: AtomicU8 = AtomicU8::new(Policy::Kill as u8);
+/// whether a runtime task is watching SIGINT
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// generation at which the call in progress on this thread started watching
+    static WATCHING: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+/// Policy is what an interrupted call does with the subprocess it waits for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Policy {
+    /// the subprocess and its process group are terminated, killed if they linger
+    Kill,
+    /// the subprocess is left running, e.g. for recover to pick the operation up
+    Detach,
+    /// the call runs to completion, SIGINT only reaching Python once it returns
+    Wait,
+}
+
+impl FromStr for Policy {
+    type Err = ServicingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "kill" => Ok(Policy::Kill),
+            "detach" => Ok(Policy::Detach),
+            "wait" => Ok(Policy::Wait),
+            _ => Err(ServicingError::General(format!(
+                "Unknown interrupt policy {s}, expected kill, detach or wait"
+            ))),
+        }
+    }
+}
+
+/// policy returns what interrupted calls do with their subprocesses.
+pub fn policy() -> Policy {
+    match POLICY.load(Ordering::Relaxed) {
+        1 => Policy::Detach,
+        2 => Policy::Wait,
+        _ => Policy::Kill,
+    }
+}
+
+/// set_policy sets what interrupted calls do with their subprocesses, across the process.
+pub fn set_policy(policy: Policy) {
+    POLICY.store(policy as u8, Ordering::Relaxed);
+}
+
+fn notifier() -> &'static Notify {
+    static NOTIFIER: OnceLock<Notify> = OnceLock::new();
+    NOTIFIER.get_or_init(Notify::new)
+}
+
+/// Installed marks SIGINT as watched for as long as the task watching it runs.
+struct Installed;
+
+impl Drop for Installed {
+    fn drop(&mut self) {
+        INSTALLED.store(false, Ordering::SeqCst);
+    }
+}
+
+/// install watches SIGINT on rt, unless a runtime already does. The handler installed before,
+/// that of Python, keeps being called.
+pub fn install(rt: &Runtime) {
+    if INSTALLED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    rt.spawn(async {
+        let _installed = Installed;
+        let mut interrupts = match signal(SignalKind::interrupt()) {
+            Ok(interrupts) => interrupts,
+            Err(e) => {
+                warn!("Could not watch SIGINT: {e}");
+                return;
+            }
+        };
+        while interrupts.recv().await.is_some() {
+            notify();
+        }
+    });
+}
+
+/// notify interrupts the calls watching for SIGINT, as receiving it does.
+pub fn notify() {
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+    notifier().notify_waiters();
+}
+
+/// watching returns the generation the call in progress on this thread watches SIGINT from,
+/// None when it does not or when the policy is to wait.
+pub fn watching() -> Option<u64> {
+    WATCHING
+        .with(Cell::get)
+        .filter(|_| policy() != Policy::Wait)
+}
+
+/// with_watching runs f watching SIGINT from generation, if any, on this thread.
+pub fn with_watching<T>(generation: Option<u64>, f: impl FnOnce() -> T) -> T {
+    let Some(generation) = generation else {
+        return f();
+    };
+    let previous = WATCHING.with(|watching| watching.replace(Some(generation)));
+    let result = f();
+    WATCHING.with(|watching| watching.set(previous));
+    result
+}
+
+/// watch runs f watching SIGINT and returns its result with whether SIGINT was received
+/// meanwhile. A call nested in another keeps watching from where the outer one started.
+pub fn watch<T>(f: impl FnOnce() -> T) -> (T, bool) {
+    let generation = WATCHING
+        .with(Cell::get)
+        .unwrap_or_else(|| GENERATION.load(Ordering::SeqCst));
+    let result = with_watching(Some(generation), f);
+    (result, GENERATION.load(Ordering::SeqCst) > generation)
+}
+
+/// interrupted tells whether the call in progress on this thread received SIGINT and has to
+/// stop waiting.
+pub fn interrupted() -> bool {
+    watching().is_some_and(|generation| GENERATION.load(Ordering::SeqCst) > generation)
+}
+
+/// bound stops waiting for fut once the call in progress on this thread, as it is when bound
+/// is called, receives SIGINT.
+pub fn bound<F: Future>(fut: F) -> impl Future<Output = Result<F::Output, ServicingError>> {
+    let generation = watching();
+    async move {
+        let Some(generation) = generation else {
+            return Ok(fut.await);
+        };
+        let interrupt = async {
+            loop {
+                let notified = notifier().notified();
+                tokio::pin!(notified);
+                notified.as_mut().enable();
+                if GENERATION.load(Ordering::SeqCst) > generation {
+                    return;
+                }
+                notified.await;
+            }
+        };
+        tokio::select! {
+            output = fut => Ok(output),
+            _ = interrupt => Err(ServicingError::Interrupted("Waiting on the runtime".to_string())),
+        }
+    }
+}
+
+/// stop deals with child, run by an interrupted call, as the policy says: it is terminated
+/// along with its process group if group is set, and killed if it does not exit within
+/// KILL_GRACE, or left running.
+pub fn stop(child: &mut Child, group: bool) -> io::Result<()> {
+    if policy() == Policy::Detach {
+        info!("Leaving process {} running after SIGINT", child.id());
+        return Ok(());
+    }
+    info!("Terminating process {} after SIGINT", child.id());
+    send(child.id(), "TERM", group);
+    let deadline = Instant::now() + KILL_GRACE;
+    while Instant::now() < deadline {
+        if child.try_wait()?.is_some() {
+            return Ok(());
+        }
+        thread::sleep(KILL_POLL);
+    }
+    if group {
+        send(child.id(), "KILL", group);
+    }
+    child.kill()?;
+    child.wait()?;
+    Ok(())
+}
+
+/// send sends signal to the process pid, or to its process group if group is set.
+fn send(pid: u32, signal: &str, group: bool) {
+    let target = match group {
+        true => format!("-{pid}"),
+        false => pid.to_string(),
+    };
+    let sent = Command::new("kill")
+        .arg(format!("-{signal}"))
+        .arg("--")
+        .arg(target)
+        .output();
+    if let Err(e) = sent {
+        warn!("Could not send SIG{signal} to process {pid}: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        process::Command,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::{interrupted, notify, policy, watch, Policy};
+    use crate::runner::{self, Mode};
+
+    #[test]
+    fn test_interrupt() {
+        assert_eq!(policy(), Policy::Kill);
+        assert_eq!("Detach".parse::<Policy>().unwrap(), Policy::Detach);
+        assert!("ignore".parse::<Policy>().is_err());
+
+        let ((), received) = watch(|| assert!(!interrupted()));
+        assert!(!received);
+
+        let started = Instant::now();
+        let (killed, received) = watch(|| {
+            thread::spawn(|| {
+                thread::sleep(Duration::from_millis(200));
+                notify();
+            });
+            runner::run(Command::new("sleep").arg("10"), Mode::Capture)
+        });
+        assert!(received);
+        assert_eq!(killed.unwrap_err().kind(), io::ErrorKind::Interrupted);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        // only the calls watching when SIGINT arrived are interrupted
+        assert!(!interrupted());
+    }
+}
@@ -0,0 +1,153 @@
+//! Introspect module houses the description of the Python API of the installed version, for the
+//! shells and CLIs wrapping servicing to generate their completions and help text from.
+use std::collections::BTreeMap;
+
+use pyo3::{
+    types::{PyAnyMethods, PyListMethods, PyType},
+    Bound, PyResult,
+};
+use serde::Serialize;
+
+use crate::{alias::Policy, models::ProbeMethod, orchestrator::Orchestrators};
+
+/// Api is the surface of the Dispatcher class.
+#[derive(Serialize)]
+pub struct Api {
+    pub version: &'static str,
+    pub methods: Vec<Method>,
+    /// values accepted by the string parameters naming a choice
+    pub enums: BTreeMap<&'static str, Vec<&'static str>>,
+    pub orchestrators: Vec<Capabilities>,
+}
+
+/// Method is a public method of the Dispatcher class.
+#[derive(Serialize)]
+pub struct Method {
+    pub name: String,
+    pub doc: Option<String>,
+    pub parameters: Vec<Parameter>,
+}
+
+/// Parameter is an argument of a method, optional when it has a default.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Parameter {
+    pub name: String,
+    pub default: Option<String>,
+    pub required: bool,
+}
+
+/// Capabilities describes what an orchestrator supports.
+#[derive(Serialize)]
+pub struct Capabilities {
+    pub name: &'static str,
+    /// Python package of the orchestrator, None for those only needing a binary
+    pub package: Option<&'static str>,
+    pub installed: bool,
+    /// clouds accepted by the placement of a service
+    pub clouds: &'static [&'static str],
+}
+
+/// describe returns the API of class, its methods being listed in alphabetical order.
+pub fn describe(class: &Bound<'_, PyType>) -> PyResult<Api> {
+    let mut methods = Vec::new();
+    for name in class.dir().iter() {
+        let name: String = name.extract()?;
+        let attr = class.getattr(name.as_str())?;
+        if name.starts_with('_') || !attr.is_callable() {
+            continue;
+        }
+        let signature: Option<String> = attr.getattr("__text_signature__")?.extract()?;
+        methods.push(Method {
+            name,
+            doc: attr.getattr("__doc__")?.extract()?,
+            parameters: signature.as_deref().map(parameters).unwrap_or_default(),
+        });
+    }
+
+    let enums = BTreeMap::from([
+        (
+            "orchestrator",
+            Orchestrators::ALL.iter().map(Orchestrators::name).collect(),
+        ),
+        ("policy", Policy::NAMES.to_vec()),
+        ("probe_method", ProbeMethod::NAMES.to_vec()),
+    ]);
+    let orchestrators = Orchestrators::ALL
+        .iter()
+        .map(|o| Capabilities {
+            name: o.name(),
+            package: o.package(),
+            installed: o.installed(),
+            clouds: o.clouds(),
+        })
+        .collect();
+    Ok(Api {
+        version: env!("CARGO_PKG_VERSION"),
+        methods,
+        enums,
+        orchestrators,
+    })
+}
+
+/// parameters parses a text signature such as `($self, name, pretty=None)`, leaving out the
+/// receiver and the positional-only and keyword-only markers.
+fn parameters(signature: &str) -> Vec<Parameter> {
+    let inner = signature
+        .trim()
+        .trim_start_matches('(')
+        .trim_end_matches(')');
+    let mut parts = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (index, c) in inner.char_indices() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&inner[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&inner[start..]);
+
+    parts
+        .into_iter()
+        .map(str::trim)
+        .filter(|p| !p.is_empty() && !p.starts_with('$') && *p != "/" && *p != "*")
+        .map(|p| {
+            let (name, default) = match p.split_once('=') {
+                Some((name, default)) => (name.trim(), Some(default.trim().to_string())),
+                None => (p, None),
+            };
+            Parameter {
+                name: name.trim_start_matches('*').to_string(),
+                required: default.is_none() && !name.starts_with('*'),
+                default,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parameters, Parameter};
+
+    #[test]
+    fn test_parameters() {
+        let parsed = parameters("($self, name, /, *, sizes=(1, 2), pretty=None, **kwargs)");
+        let names: Vec<_> = parsed.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["name", "sizes", "pretty", "kwargs"]);
+        assert_eq!(
+            parsed[2],
+            Parameter {
+                name: "pretty".to_string(),
+                default: Some("None".to_string()),
+                required: false,
+            }
+        );
+        assert!(parsed[0].required);
+        assert!(!parsed[3].required);
+        assert!(parameters("($self)").is_empty());
+    }
+}
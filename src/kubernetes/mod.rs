@@ -0,0 +1,856 @@
+//! Kubernetes module houses the options of services launched on the Kubernetes cloud of
+//! SkyPilot, rendered as the pod_config SkyPilot merges into the pods it creates, and their
+//! exposure through an ingress. It also houses the Kubernetes orchestrator, running a service as
+//! a Deployment behind a LoadBalancer Service applied with kubectl.
+use std::{
+    collections::BTreeMap,
+    net::IpAddr,
+    process::Command,
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use serde_yaml::{Mapping, Value};
+
+use crate::{
+    error::ServicingError,
+    limiter,
+    models::Configuration,
+    runner::{self, Mode},
+};
+
+/// node label of the accelerator, as set by the GPU labeler of SkyPilot
+static ACCELERATOR_LABEL: &str = "skypilot.co/accelerator";
+/// how long the launch waits for a load balancer before falling back to the node port
+static LOAD_BALANCER_WAIT: Duration = Duration::from_secs(60);
+static LOAD_BALANCER_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// average CPU utilization the autoscaler keeps the pods at unless told otherwise, in percent
+pub static DEFAULT_TARGET_UTILIZATION: u16 = 80;
+
+/// Kubernetes holds where the pods of a service land in a shared cluster.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct Kubernetes {
+    pub namespace: Option<String>,
+    pub labels: BTreeMap<String, String>,
+    /// e.g. {"gpu": "a100"}
+    pub node_selector: BTreeMap<String, String>,
+    /// tolerations with their key, operator, value and effect
+    pub tolerations: Vec<BTreeMap<String, String>>,
+    pub service_account: Option<String>,
+    /// containers run to completion before the service, e.g. to download the model
+    pub init_containers: Vec<BTreeMap<String, String>>,
+    /// containers running alongside the service, e.g. a metrics exporter or an auth proxy
+    pub sidecars: Vec<BTreeMap<String, String>>,
+    /// scaling of the Deployment by the Kubernetes orchestrator
+    pub autoscaling: Option<Autoscaling>,
+}
+
+/// Autoscaling is the HorizontalPodAutoscaler of a service run by the Kubernetes orchestrator.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Autoscaling {
+    pub min_replicas: u16,
+    pub max_replicas: u16,
+    /// average CPU utilization of the pods, in percent of their request
+    pub target_utilization: u16,
+}
+
+/// MIG profiles by GPU, as in the NVIDIA MIG user guide
+static MIG_PROFILES: &[(&str, &[&str])] = &[
+    (
+        "a100",
+        &[
+            "1g.5gb", "1g.10gb", "1g.20gb", "2g.10gb", "2g.20gb", "3g.20gb", "3g.40gb", "4g.20gb",
+            "4g.40gb", "7g.40gb", "7g.80gb",
+        ],
+    ),
+    (
+        "h100",
+        &[
+            "1g.10gb", "1g.20gb", "2g.20gb", "3g.40gb", "4g.40gb", "7g.80gb",
+        ],
+    ),
+    (
+        "h200",
+        &[
+            "1g.18gb", "1g.35gb", "2g.35gb", "3g.71gb", "4g.71gb", "7g.141gb",
+        ],
+    ),
+    ("a30", &["1g.6gb", "2g.12gb", "4g.24gb"]),
+];
+
+/// Mig is a request for slices of a MIG partitioned GPU, e.g. `a100-1g.10gb:2`.
+#[derive(Debug, PartialEq)]
+pub struct Mig {
+    pub profile: String,
+    pub count: u32,
+}
+
+impl Mig {
+    /// parse returns the MIG request of accelerators, None if it is not one, and an error if it
+    /// is not a valid one.
+    pub fn parse(accelerators: &str) -> Result<Option<Mig>, ServicingError> {
+        let (name, count) = match accelerators.split_once(':') {
+            Some((name, count)) => (name, Some(count)),
+            None => (accelerators, None),
+        };
+        let name = name.to_lowercase();
+        let (gpu, profile) = match name.split_once('-') {
+            Some((gpu, profile)) if profile.contains("g.") && profile.ends_with("gb") => {
+                (gpu, profile)
+            }
+            _ => return Ok(None),
+        };
+        let invalid = |reason: String| {
+            ServicingError::General(format!("Invalid MIG request {accelerators}: {reason}"))
+        };
+
+        let profiles = MIG_PROFILES
+            .iter()
+            .find(|(name, _)| *name == gpu)
+            .map(|(_, profiles)| *profiles)
+            .ok_or_else(|| invalid(format!("{gpu} does not support MIG")))?;
+        if !profiles.contains(&profile) {
+            return Err(invalid(format!(
+                "{gpu} profiles are {}",
+                profiles.join(", ")
+            )));
+        }
+        let count = match count {
+            Some(count) => count
+                .parse()
+                .ok()
+                .filter(|count| *count > 0)
+                .ok_or_else(|| invalid(format!("{count} is not a number of slices")))?,
+            None => 1,
+        };
+        Ok(Some(Mig {
+            profile: profile.to_string(),
+            count,
+        }))
+    }
+
+    /// resource is the extended resource the NVIDIA device plugin advertises for the profile.
+    pub fn resource(&self) -> String {
+        format!("nvidia.com/mig-{}", self.profile)
+    }
+}
+
+/// check_containers makes sure every container declares at least a name and an image.
+pub fn check_containers(containers: &[BTreeMap<String, String>]) -> Result<(), ServicingError> {
+    for container in containers {
+        for key in ["name", "image"] {
+            if !container.contains_key(key) {
+                return Err(ServicingError::General(format!(
+                    "Container {container:?} has no {key}"
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// container renders a container declared as a map of strings, the command is run by a shell.
+/// Sidecars are native sidecars: init containers that keep running, from Kubernetes 1.29.
+fn container(declared: &BTreeMap<String, String>, sidecar: bool) -> Value {
+    let mut container = Mapping::new();
+    for (key, value) in declared {
+        let value = match key.as_str() {
+            "command" => {
+                Value::Sequence(vec!["/bin/sh".into(), "-c".into(), value.as_str().into()])
+            }
+            _ => value.as_str().into(),
+        };
+        container.insert(key.as_str().into(), value);
+    }
+    if sidecar {
+        container.insert("restartPolicy".into(), "Always".into());
+    }
+    Value::Mapping(container)
+}
+
+impl Kubernetes {
+    /// merge overrides the options set in other.
+    pub fn merge(&mut self, other: Kubernetes) {
+        if other.namespace.is_some() {
+            self.namespace = other.namespace;
+        }
+        self.labels.extend(other.labels);
+        self.node_selector.extend(other.node_selector);
+        if !other.tolerations.is_empty() {
+            self.tolerations = other.tolerations;
+        }
+        if other.service_account.is_some() {
+            self.service_account = other.service_account;
+        }
+        if !other.init_containers.is_empty() {
+            self.init_containers = other.init_containers;
+        }
+        if !other.sidecars.is_empty() {
+            self.sidecars = other.sidecars;
+        }
+        if other.autoscaling.is_some() {
+            self.autoscaling = other.autoscaling;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Kubernetes::default()
+    }
+
+    /// config renders the task level SkyPilot config, `kubernetes.pod_config`. A MIG request
+    /// is rendered as a limit of the service container, since SkyPilot only requests whole GPUs.
+    pub fn config(&self, mig: Option<&Mig>) -> Value {
+        let mut metadata = Mapping::new();
+        if let Some(namespace) = &self.namespace {
+            metadata.insert("namespace".into(), namespace.as_str().into());
+        }
+        if !self.labels.is_empty() {
+            metadata.insert("labels".into(), map(&self.labels));
+        }
+
+        let mut spec = self.pod_spec();
+        if let Some(mig) = mig {
+            // SkyPilot merges the first container into the one running the service
+            let mut limits = Mapping::new();
+            limits.insert(mig.resource().into(), mig.count.into());
+            let mut resources = Mapping::new();
+            resources.insert("limits".into(), Value::Mapping(limits));
+            let mut container = Mapping::new();
+            container.insert("resources".into(), Value::Mapping(resources));
+            spec.insert(
+                "containers".into(),
+                Value::Sequence(vec![Value::Mapping(container)]),
+            );
+        }
+
+        let mut pod_config = Mapping::new();
+        if !metadata.is_empty() {
+            pod_config.insert("metadata".into(), Value::Mapping(metadata));
+        }
+        if !spec.is_empty() {
+            pod_config.insert("spec".into(), Value::Mapping(spec));
+        }
+        let mut kubernetes = Mapping::new();
+        kubernetes.insert("pod_config".into(), Value::Mapping(pod_config));
+        let mut config = Mapping::new();
+        config.insert("kubernetes".into(), Value::Mapping(kubernetes));
+        Value::Mapping(config)
+    }
+
+    /// pod_spec renders the options of the pod spec, without its containers.
+    fn pod_spec(&self) -> Mapping {
+        let mut spec = Mapping::new();
+        if !self.node_selector.is_empty() {
+            spec.insert("nodeSelector".into(), map(&self.node_selector));
+        }
+        if !self.tolerations.is_empty() {
+            let tolerations = self.tolerations.iter().map(map).collect();
+            spec.insert("tolerations".into(), Value::Sequence(tolerations));
+        }
+        if let Some(service_account) = &self.service_account {
+            spec.insert("serviceAccountName".into(), service_account.as_str().into());
+        }
+        let init_containers: Vec<Value> = self
+            .init_containers
+            .iter()
+            .map(|c| container(c, false))
+            .chain(self.sidecars.iter().map(|c| container(c, true)))
+            .collect();
+        if !init_containers.is_empty() {
+            spec.insert("initContainers".into(), Value::Sequence(init_containers));
+        }
+        spec
+    }
+}
+
+fn map(entries: &BTreeMap<String, String>) -> Value {
+    Value::Mapping(
+        entries
+            .iter()
+            .map(|(k, v)| (k.as_str().into(), v.as_str().into()))
+            .collect(),
+    )
+}
+
+/// Ingress exposes a service under a host and path of a Kubernetes ingress controller, routing
+/// to the endpoint SkyPilot reports through a selector-less Service.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Ingress {
+    pub host: String,
+    pub path: String,
+    /// secret holding the TLS certificate of host
+    pub tls_secret: Option<String>,
+    pub class: Option<String>,
+    pub namespace: Option<String>,
+}
+
+impl Ingress {
+    /// url is where the service is reached through the ingress.
+    pub fn url(&self) -> String {
+        format!("{}{}", self.host, self.path.trim_end_matches('/'))
+    }
+
+    /// apply creates or updates the ingress of the service routing to upstream, a host:port.
+    pub fn apply(&self, name: &str, upstream: &str) -> Result<(), ServicingError> {
+        let manifests = self.manifests(name, upstream)?.to_string();
+        kubectl(
+            name,
+            &["apply", "-f", "-"],
+            Mode::Input(manifests.into_bytes()),
+        )
+        .map(|_| ())
+    }
+
+    /// delete removes the objects created by apply.
+    pub fn delete(&self, name: &str) -> Result<(), ServicingError> {
+        for kind in ["ingress", "service", "endpoints"] {
+            let object = format!("{kind}/{}", object_name(name));
+            let mut args = vec!["delete", "--ignore-not-found", object.as_str()];
+            args.extend(self.namespace_args());
+            kubectl(name, &args, Mode::Capture)?;
+        }
+        Ok(())
+    }
+
+    /// address returns the address assigned to the ingress by its controller, if any yet.
+    pub fn address(&self, name: &str) -> Result<Option<String>, ServicingError> {
+        let object = format!("ingress/{}", object_name(name));
+        let mut args = vec![
+            "get",
+            object.as_str(),
+            "-o",
+            "jsonpath={.status.loadBalancer.ingress[0].ip}{.status.loadBalancer.ingress[0].hostname}",
+        ];
+        args.extend(self.namespace_args());
+        let address = kubectl(name, &args, Mode::Capture)?;
+        Ok((!address.is_empty()).then_some(address))
+    }
+
+    fn namespace_args(&self) -> Vec<&str> {
+        match &self.namespace {
+            Some(namespace) => vec!["--namespace", namespace.as_str()],
+            None => vec![],
+        }
+    }
+
+    fn manifests(&self, name: &str, upstream: &str) -> Result<serde_json::Value, ServicingError> {
+        let (host, port) = upstream
+            .rsplit_once(':')
+            .and_then(|(host, port)| Some((host, port.parse::<u16>().ok()?)))
+            .ok_or(ServicingError::General(format!(
+                "Invalid endpoint {upstream} for the ingress of service {name}"
+            )))?;
+        let object = object_name(name);
+        let mut metadata =
+            json!({ "name": object, "labels": { "app.kubernetes.io/managed-by": "servicing" } });
+        if let Some(namespace) = &self.namespace {
+            metadata["namespace"] = json!(namespace);
+        }
+
+        // an IP needs explicit endpoints, a hostname can be referred to directly
+        let mut items = match host.parse::<IpAddr>() {
+            Ok(ip) => vec![
+                json!({
+                    "apiVersion": "v1",
+                    "kind": "Service",
+                    "metadata": metadata,
+                    "spec": { "ports": [{ "name": "http", "port": port, "targetPort": port }] },
+                }),
+                json!({
+                    "apiVersion": "v1",
+                    "kind": "Endpoints",
+                    "metadata": metadata,
+                    "subsets": [{
+                        "addresses": [{ "ip": ip.to_string() }],
+                        "ports": [{ "name": "http", "port": port }],
+                    }],
+                }),
+            ],
+            Err(_) => vec![json!({
+                "apiVersion": "v1",
+                "kind": "Service",
+                "metadata": metadata,
+                "spec": {
+                    "type": "ExternalName",
+                    "externalName": host,
+                    "ports": [{ "name": "http", "port": port }],
+                },
+            })],
+        };
+
+        let mut spec = json!({
+            "rules": [{
+                "host": self.host,
+                "http": { "paths": [{
+                    "path": self.path,
+                    "pathType": "Prefix",
+                    "backend": { "service": { "name": object, "port": { "number": port } } },
+                }] },
+            }],
+        });
+        if let Some(class) = &self.class {
+            spec["ingressClassName"] = json!(class);
+        }
+        if let Some(secret) = &self.tls_secret {
+            spec["tls"] = json!([{ "hosts": [self.host], "secretName": secret }]);
+        }
+        items.push(json!({
+            "apiVersion": "networking.k8s.io/v1",
+            "kind": "Ingress",
+            "metadata": metadata,
+            "spec": spec,
+        }));
+        Ok(json!({ "apiVersion": "v1", "kind": "List", "items": items }))
+    }
+}
+
+/// Deployment runs a service with the Kubernetes orchestrator: a Deployment of its replicas
+/// behind a LoadBalancer Service, named apart from the objects of an ingress.
+#[derive(Clone, Debug)]
+pub struct Deployment {
+    /// name of the service
+    pub name: String,
+    pub namespace: Option<String>,
+    /// whether a HorizontalPodAutoscaler scales the Deployment
+    pub autoscaled: bool,
+}
+
+impl Deployment {
+    pub fn new(name: &str, config: &Configuration) -> Deployment {
+        let options = config.kubernetes.as_ref();
+        Deployment {
+            name: name.to_string(),
+            namespace: options.and_then(|options| options.namespace.clone()),
+            autoscaled: options.is_some_and(|options| options.autoscaling.is_some()),
+        }
+    }
+
+    /// object is the name of the Deployment and of the Service.
+    fn object(&self) -> String {
+        format!("{}-app", object_name(&self.name))
+    }
+
+    fn namespace_args(&self) -> Vec<&str> {
+        match &self.namespace {
+            Some(namespace) => vec!["--namespace", namespace.as_str()],
+            None => vec![],
+        }
+    }
+
+    fn get(&self, kind: &str, jsonpath: &str) -> Result<String, ServicingError> {
+        let object = format!("{kind}/{}", self.object());
+        let jsonpath = format!("jsonpath={jsonpath}");
+        let mut args = vec!["get", object.as_str(), "-o", jsonpath.as_str()];
+        args.extend(self.namespace_args());
+        kubectl(&self.name, &args, Mode::Capture)
+    }
+
+    /// apply creates or updates the objects running config from image with env set besides
+    /// the variables of the configuration. The manifests are not recorded when env is set, it
+    /// carries secrets.
+    pub fn apply(
+        &self,
+        config: &Configuration,
+        image: &str,
+        env: &[(&str, &str)],
+    ) -> Result<(), ServicingError> {
+        let manifests = self.manifests(config, image, env)?.to_string().into_bytes();
+        let mode = match env.is_empty() {
+            true => Mode::Input(manifests),
+            false => Mode::Secret(manifests),
+        };
+        kubectl(&self.name, &["apply", "-f", "-"], mode).map(|_| ())
+    }
+
+    /// delete_command returns the command removing the objects created by apply.
+    pub fn delete_command(&self) -> Command {
+        let object = self.object();
+        let mut cmd = Command::new("kubectl");
+        cmd.arg("delete")
+            .arg("--ignore-not-found")
+            .arg(format!("deployment/{object}"))
+            .arg(format!("service/{object}"))
+            .arg(format!("horizontalpodautoscaler/{object}"))
+            .args(self.namespace_args());
+        cmd
+    }
+
+    /// scale sets the replicas of the Deployment, zero pausing the service while keeping its
+    /// objects.
+    pub fn scale(&self, replicas: u16) -> Result<(), ServicingError> {
+        let object = format!("deployment/{}", self.object());
+        let replicas = format!("--replicas={replicas}");
+        let mut args = vec!["scale", object.as_str(), replicas.as_str()];
+        args.extend(self.namespace_args());
+        kubectl(&self.name, &args, Mode::Capture).map(|_| ())
+    }
+
+    /// exists tells whether the Deployment of the service exists.
+    pub fn exists(&self) -> Result<bool, ServicingError> {
+        let object = format!("deployment/{}", self.object());
+        let mut args = vec!["get", object.as_str(), "--ignore-not-found", "-o", "name"];
+        args.extend(self.namespace_args());
+        Ok(!kubectl(&self.name, &args, Mode::Capture)?.is_empty())
+    }
+
+    /// readiness returns the replicas the Deployment asks for and how many of its pods are
+    /// ready.
+    pub fn readiness(&self) -> Result<(u32, u32), ServicingError> {
+        let output = self.get("deployment", "{.spec.replicas} {.status.readyReplicas}")?;
+        let mut counts = output
+            .split_whitespace()
+            .map(|count| count.parse().unwrap_or_default());
+        // readyReplicas is left out while no pod is ready
+        Ok((
+            counts.next().unwrap_or_default(),
+            counts.next().unwrap_or_default(),
+        ))
+    }
+
+    /// autoscaler returns the current and desired replicas the HorizontalPodAutoscaler of the
+    /// Deployment reports.
+    pub fn autoscaler(&self) -> Result<(u32, u32), ServicingError> {
+        let output = self.get(
+            "horizontalpodautoscaler",
+            "{.status.currentReplicas} {.status.desiredReplicas}",
+        )?;
+        let mut counts = output
+            .split_whitespace()
+            .map(|count| count.parse().unwrap_or_default());
+        Ok((
+            counts.next().unwrap_or_default(),
+            counts.next().unwrap_or_default(),
+        ))
+    }
+
+    /// load_balancer returns the address of the load balancer of the Service, None until its
+    /// controller assigns one.
+    pub fn load_balancer(&self) -> Result<Option<String>, ServicingError> {
+        let output = self.get(
+            "service",
+            "{.status.loadBalancer.ingress[0].ip}{.status.loadBalancer.ingress[0].hostname} {.spec.ports[0].port}",
+        )?;
+        Ok(match output.split_once(' ') {
+            Some((host, port)) if !host.is_empty() => Some(format!("{host}:{port}")),
+            _ => None,
+        })
+    }
+
+    /// node_port returns the address of a node and the node port of the Service, the external
+    /// address of the node if it has one.
+    pub fn node_port(&self) -> Result<Option<String>, ServicingError> {
+        let port = self.get("service", "{.spec.ports[0].nodePort}")?;
+        if port.is_empty() {
+            return Ok(None);
+        }
+        let mut host = String::new();
+        for kind in ["ExternalIP", "InternalIP"] {
+            let jsonpath =
+                format!("jsonpath={{.items[0].status.addresses[?(@.type==\"{kind}\")].address}}");
+            host = kubectl(
+                &self.name,
+                &["get", "nodes", "-o", jsonpath.as_str()],
+                Mode::Capture,
+            )?;
+            if !host.is_empty() {
+                break;
+            }
+        }
+        Ok((!host.is_empty()).then(|| format!("{host}:{port}")))
+    }
+
+    /// address returns where the service is reached: the load balancer, or the node port while
+    /// the Service has no load balancer.
+    pub fn address(&self) -> Result<Option<String>, ServicingError> {
+        match self.load_balancer()? {
+            Some(address) => Ok(Some(address)),
+            None => self.node_port(),
+        }
+    }
+
+    /// endpoint waits up to LOAD_BALANCER_WAIT for the load balancer of the Service, falling
+    /// back to the node port, e.g. on clusters without a load balancer controller.
+    pub fn endpoint(&self) -> Result<String, ServicingError> {
+        let deadline = Instant::now() + LOAD_BALANCER_WAIT;
+        loop {
+            if let Some(address) = self.load_balancer()? {
+                return Ok(address);
+            }
+            if Instant::now() >= deadline {
+                break;
+            }
+            thread::sleep(LOAD_BALANCER_CHECK_INTERVAL);
+        }
+        self.node_port()?.ok_or(ServicingError::General(format!(
+            "Service {} has neither a load balancer nor a node port",
+            self.name
+        )))
+    }
+
+    /// manifests renders the Deployment and the Service as a List, with the
+    /// HorizontalPodAutoscaler of the Deployment if the service autoscales. The setup runs in
+    /// the container before the run command, as on a SkyPilot replica.
+    fn manifests(
+        &self,
+        config: &Configuration,
+        image: &str,
+        env: &[(&str, &str)],
+    ) -> Result<serde_json::Value, ServicingError> {
+        let object = self.object();
+        let port = config.resources.ports;
+        let options = config.kubernetes.clone().unwrap_or_default();
+        let selector = json!({ "app.kubernetes.io/name": object });
+        let mut metadata = json!({
+            "name": object,
+            "labels": { "app.kubernetes.io/managed-by": "servicing" },
+        });
+        if let Some(namespace) = &self.namespace {
+            metadata["namespace"] = json!(namespace);
+        }
+        let mut labels = options.labels.clone();
+        labels.insert("app.kubernetes.io/name".to_string(), object.clone());
+
+        let env: Vec<serde_json::Value> = config
+            .envs
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .chain(env.iter().copied())
+            .map(|(name, value)| json!({ "name": name, "value": value }))
+            .collect();
+        let mut resources = json!({});
+        if let Some(cpus) = quantity(&config.resources.cpus) {
+            resources["requests"]["cpu"] = json!(cpus.to_string());
+        }
+        if let Some(memory) = quantity(&config.resources.memory) {
+            resources["requests"]["memory"] = json!(format!("{memory}Gi"));
+        }
+        let mut spec: serde_json::Value = serde_json::to_value(options.pod_spec())?;
+        if let Some(accelerators) = &config.resources.accelerators {
+            match Mig::parse(accelerators)? {
+                Some(mig) => resources["limits"][mig.resource()] = json!(mig.count),
+                None => {
+                    let (accelerator, count) = match accelerators.split_once(':') {
+                        Some((accelerator, count)) => (accelerator, count.trim()),
+                        None => (accelerators.as_str(), "1"),
+                    };
+                    resources["limits"]["nvidia.com/gpu"] = json!(count);
+                    // the node selector of the options wins
+                    if spec["nodeSelector"][ACCELERATOR_LABEL].is_null() {
+                        spec["nodeSelector"][ACCELERATOR_LABEL] =
+                            json!(accelerator.trim().to_lowercase());
+                    }
+                }
+            }
+        }
+        spec["containers"] = json!([{
+            "name": "service",
+            "image": image,
+            "command": ["/bin/bash", "-c", format!("{}\n{}", config.setup.trim_end(), config.run)],
+            "ports": [{ "name": "http", "containerPort": port }],
+            "env": env,
+            "resources": resources,
+            "readinessProbe": {
+                "httpGet": { "path": config.service.readiness_probe, "port": port },
+            },
+        }]);
+
+        let mut deployment = json!({
+            "apiVersion": "apps/v1",
+            "kind": "Deployment",
+            "metadata": metadata,
+            "spec": {
+                "selector": { "matchLabels": selector },
+                "template": { "metadata": { "labels": labels }, "spec": spec },
+            },
+        });
+        let mut items = vec![json!({
+            "apiVersion": "v1",
+            "kind": "Service",
+            "metadata": metadata,
+            "spec": {
+                "type": "LoadBalancer",
+                "selector": selector,
+                "ports": [{ "name": "http", "port": port, "targetPort": port }],
+            },
+        })];
+        match &options.autoscaling {
+            // the replicas are left to the autoscaler, an apply would reset them
+            Some(autoscaling) => items.push(json!({
+                "apiVersion": "autoscaling/v2",
+                "kind": "HorizontalPodAutoscaler",
+                "metadata": metadata,
+                "spec": {
+                    "scaleTargetRef": {
+                        "apiVersion": "apps/v1",
+                        "kind": "Deployment",
+                        "name": object,
+                    },
+                    "minReplicas": autoscaling.min_replicas,
+                    "maxReplicas": autoscaling.max_replicas,
+                    "metrics": [{
+                        "type": "Resource",
+                        "resource": {
+                            "name": "cpu",
+                            "target": {
+                                "type": "Utilization",
+                                "averageUtilization": autoscaling.target_utilization,
+                            },
+                        },
+                    }],
+                },
+            })),
+            None => deployment["spec"]["replicas"] = json!(config.service.replicas),
+        }
+        items.insert(0, deployment);
+        Ok(json!({ "apiVersion": "v1", "kind": "List", "items": items }))
+    }
+}
+
+/// replicas returns the replicas the Deployment of config runs when it is not scaled, the
+/// minimum of its autoscaler if it has one.
+pub fn replicas(config: &Configuration) -> u16 {
+    match config
+        .kubernetes
+        .as_ref()
+        .and_then(|k| k.autoscaling.as_ref())
+    {
+        Some(autoscaling) => autoscaling.min_replicas,
+        None => config.service.replicas,
+    }
+}
+
+/// quantity reads a SkyPilot resource such as `4+`, the minimum being requested.
+fn quantity(resource: &str) -> Option<f64> {
+    resource.trim().trim_end_matches('+').parse().ok()
+}
+
+/// object_name is the name of the Kubernetes objects servicing creates for the service.
+pub fn object_name(name: &str) -> String {
+    format!("servicing-{}", name.to_lowercase().replace('_', "-"))
+}
+
+/// kubectl runs kubectl with args in mode and returns its output.
+fn kubectl(name: &str, args: &[&str], mode: Mode) -> Result<String, ServicingError> {
+    let _permit = limiter::acquire(&format!("kubectl {} {name}", args[0]))?;
+    let output = runner::run(Command::new("kubectl").args(args), mode)?;
+    if !output.status.success() {
+        return Err(ServicingError::General(format!(
+            "kubectl {} for service {name} failed: {}",
+            args[0],
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{replicas, Deployment, Ingress, Kubernetes, Mig};
+    use crate::models::{Configuration, Resources, UserProvidedConfig};
+
+    #[test]
+    fn test_mig() {
+        let mig = Mig::parse("A100-1g.10gb:2").unwrap().unwrap();
+        assert_eq!(mig.resource(), "nvidia.com/mig-1g.10gb");
+        assert_eq!(mig.count, 2);
+        assert_eq!(Mig::parse("A100:1").unwrap(), None);
+        assert!(Mig::parse("a100-5g.10gb").is_err());
+        assert!(Mig::parse("t4-1g.5gb").is_err());
+        assert!(Mig::parse("a30-1g.6gb:0").is_err());
+    }
+
+    #[test]
+    fn test_ingress_manifests() {
+        let ingress = Ingress {
+            host: "llm.example.com".to_string(),
+            path: "/v1/".to_string(),
+            tls_secret: Some("llm-tls".to_string()),
+            class: None,
+            namespace: Some("ml".to_string()),
+        };
+        assert_eq!(ingress.url(), "llm.example.com/v1");
+
+        let manifests = ingress.manifests("My_LLM", "10.0.0.7:30001").unwrap();
+        let items = manifests["items"].as_array().unwrap();
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[1]["subsets"][0]["addresses"][0]["ip"], "10.0.0.7");
+        let rule = &items[2]["spec"]["rules"][0];
+        assert_eq!(rule["host"], "llm.example.com");
+        assert_eq!(
+            rule["http"]["paths"][0]["backend"]["service"]["name"],
+            "servicing-my-llm"
+        );
+        assert_eq!(items[2]["spec"]["tls"][0]["secretName"], "llm-tls");
+        assert_eq!(items[2]["metadata"]["namespace"], "ml");
+
+        let manifests = ingress.manifests("llm", "lb.example.com:80").unwrap();
+        assert_eq!(manifests["items"][0]["spec"]["type"], "ExternalName");
+        assert!(ingress.manifests("llm", "10.0.0.7").is_err());
+    }
+
+    #[test]
+    fn test_deployment_manifests() {
+        let config = Configuration {
+            resources: Resources {
+                accelerators: Some("A100:2".to_string()),
+                ..Configuration::default().resources
+            },
+            kubernetes: Some(Kubernetes {
+                namespace: Some("ml".to_string()),
+                ..Kubernetes::default()
+            }),
+            ..Configuration::default()
+        };
+        let deployment = Deployment::new("My_LLM", &config);
+        let manifests = deployment
+            .manifests(&config, "acme/llm:1", &[("API_KEY", "secret")])
+            .unwrap();
+        let (deployment, service) = (&manifests["items"][0], &manifests["items"][1]);
+        assert_eq!(deployment["metadata"]["name"], "servicing-my-llm-app");
+        assert_eq!(deployment["metadata"]["namespace"], "ml");
+        assert_eq!(deployment["spec"]["replicas"], 2);
+        let pod = &deployment["spec"]["template"]["spec"];
+        assert_eq!(pod["nodeSelector"]["skypilot.co/accelerator"], "a100");
+        let container = &pod["containers"][0];
+        assert_eq!(container["resources"]["limits"]["nvidia.com/gpu"], "2");
+        assert_eq!(container["resources"]["requests"]["cpu"], "4");
+        assert_eq!(container["resources"]["requests"]["memory"], "10Gi");
+        assert_eq!(container["env"][0]["name"], "API_KEY");
+        assert_eq!(service["spec"]["type"], "LoadBalancer");
+        assert_eq!(
+            service["spec"]["selector"],
+            deployment["spec"]["template"]["metadata"]["labels"]
+        );
+        assert_eq!(manifests["items"].as_array().unwrap().len(), 2);
+
+        let autoscaled = UserProvidedConfig {
+            replicas: Some(3),
+            max_replicas: Some(8),
+            ..UserProvidedConfig::default()
+        };
+        assert!(autoscaled.check().is_ok());
+        let mut config = config;
+        config.update(&autoscaled);
+        assert_eq!(replicas(&config), 3);
+        let manifests = Deployment::new("My_LLM", &config)
+            .manifests(&config, "acme/llm:1", &[])
+            .unwrap();
+        let (deployment, hpa) = (&manifests["items"][0], &manifests["items"][2]);
+        assert!(deployment["spec"]["replicas"].is_null());
+        assert_eq!(
+            hpa["spec"]["scaleTargetRef"]["name"],
+            "servicing-my-llm-app"
+        );
+        assert_eq!(hpa["spec"]["minReplicas"], 3);
+        assert_eq!(hpa["spec"]["maxReplicas"], 8);
+        let target = &hpa["spec"]["metrics"][0]["resource"]["target"];
+        assert_eq!(target["averageUtilization"], 80);
+        let unbounded = UserProvidedConfig {
+            min_replicas: Some(2),
+            ..UserProvidedConfig::default()
+        };
+        assert!(unbounded.check().is_err());
+    }
+}
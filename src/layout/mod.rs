@@ -0,0 +1,259 @@
+//! Layout module houses the organisation of the state directory (~/.servicing). Since version 2
+//! everything belonging to a service lives in its own folder:
+//!
+//! ```text
+//! ~/.servicing/
+//!   layout                  version of the layout
+//!   services.bin            registry of the services
+//!   heartbeat.json          last tick of the monitors
+//!   services/<name>/
+//!     service.yaml          rendered configuration
+//!     service_zero.yaml     configuration scaled to zero replicas
+//!     service.<env>.yaml    configuration rendered for an environment, e.g. service.dev.yaml
+//!     workdir/              staged copy of the workdir
+//!     logs/                 output of the last operations
+//!     metadata.json
+//!     operation.json        journal of the up or down in flight and its phase
+//! ```
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use log::info;
+use serde::Serialize;
+
+use crate::{error::ServicingError, helper};
+
+pub static STATE_DIR: &str = ".servicing";
+static LAYOUT_FILE: &str = "layout";
+static HEARTBEAT_FILE: &str = "heartbeat.json";
+static SERVICES_DIR: &str = "services";
+pub static LAYOUT_VERSION: u32 = 2;
+
+pub static CONFIG_FILE: &str = "service.yaml";
+pub static ZERO_CONFIG_FILE: &str = "service_zero.yaml";
+pub static WORKDIR_DIR: &str = "workdir";
+/// context of the image of the services run with Docker, when they have no workdir
+pub static DOCKER_DIR: &str = "docker";
+pub static LOGS_DIR: &str = "logs";
+static METADATA_FILE: &str = "metadata.json";
+
+/// version 1 kept the configurations flat in the state directory
+static V1_CONFIG_SUFFIX: &str = "_service.yaml";
+static V1_ZERO_CONFIG_SUFFIX: &str = "_service_zero.yaml";
+static V1_WORKDIRS_DIR: &str = "workdirs";
+
+/// Metadata describes a service for tools browsing the state directory.
+#[derive(Serialize)]
+pub struct Metadata<'a> {
+    pub name: &'a str,
+    pub orchestrator: &'a str,
+    pub owner: Option<String>,
+    pub created: u64,
+    pub layout: u32,
+    /// environment the service was last launched in
+    pub environment: Option<&'a str>,
+}
+
+/// check_name refuses a name of kind, e.g. service, that cannot name a file or folder of the
+/// state directory on its own: empty, hidden, or with separators or other characters than
+/// letters, digits, -, _ and .
+pub fn check_name(kind: &str, name: &str) -> Result<(), ServicingError> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+        && !name.starts_with('.');
+    match valid {
+        true => Ok(()),
+        false => Err(ServicingError::General(format!(
+            "Invalid {kind} name {name:?}, expected letters, digits, -, _ and ."
+        ))),
+    }
+}
+
+/// root returns the state directory, migrated to the current layout.
+pub fn root() -> Result<PathBuf, ServicingError> {
+    let root = helper::create_directory(STATE_DIR, true)?;
+    migrate(&root)?;
+    Ok(root)
+}
+
+/// services_dir returns the folder holding the folders of the services.
+pub fn services_dir() -> Result<PathBuf, ServicingError> {
+    let dir = root()?.join(SERVICES_DIR);
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// heartbeat_path returns the file the monitors touch at every tick.
+pub fn heartbeat_path() -> Result<PathBuf, ServicingError> {
+    Ok(root()?.join(HEARTBEAT_FILE))
+}
+
+/// service_dir returns the folder of the service, created if needed.
+pub fn service_dir(name: &str) -> Result<PathBuf, ServicingError> {
+    check_name("service", name)?;
+    let dir = root()?.join(SERVICES_DIR).join(name);
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// write_metadata describes the service in its folder.
+pub fn write_metadata(name: &str, metadata: &Metadata) -> Result<(), ServicingError> {
+    let path = service_dir(name)?.join(METADATA_FILE);
+    helper::write_to_file(&path, &serde_json::to_string_pretty(metadata)?)
+}
+
+/// record_environment records in the metadata of the service the environment it is launched in.
+pub fn record_environment(name: &str, environment: Option<&str>) -> Result<(), ServicingError> {
+    let path = service_dir(name)?.join(METADATA_FILE);
+    let mut metadata: serde_json::Value = match path.is_file() {
+        true => serde_json::from_str(&helper::read_from_file(&path)?)?,
+        false => serde_json::json!({ "name": name }),
+    };
+    metadata["environment"] = environment.into();
+    helper::write_to_file(&path, &serde_json::to_string_pretty(&metadata)?)
+}
+
+/// environment_config_file returns the name of the configuration rendered for environment.
+pub fn environment_config_file(environment: &str) -> String {
+    format!("service.{environment}.yaml")
+}
+
+/// write_log keeps the output of the last operation on the service in its logs folder.
+pub fn write_log(name: &str, operation: &str, output: &str) -> Result<(), ServicingError> {
+    let dir = service_dir(name)?.join(LOGS_DIR);
+    fs::create_dir_all(&dir)?;
+    helper::write_to_file(&dir.join(format!("{operation}.log")), output)
+}
+
+/// remove_service_dir deletes the folder of the service.
+pub fn remove_service_dir(name: &str) -> Result<(), ServicingError> {
+    check_name("service", name)?;
+    let dir = root()?.join(SERVICES_DIR).join(name);
+    if dir.exists() {
+        fs::remove_dir_all(dir)?;
+    }
+    Ok(())
+}
+
+/// remove_renders deletes the configurations rendered out of the one of the service, for scale to
+/// zero and for its environments. They are rendered again at the next launch.
+pub fn remove_renders(name: &str) -> Result<(), ServicingError> {
+    check_name("service", name)?;
+    let dir = root()?.join(SERVICES_DIR).join(name);
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(file) = path.file_name().and_then(|file| file.to_str()) else {
+            continue;
+        };
+        let environment = file.starts_with("service.") && file.ends_with(".yaml");
+        if file == ZERO_CONFIG_FILE || (environment && file != CONFIG_FILE) {
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// migrated_config returns where the configuration file at path lives since version 2, for
+/// registries saved with version 1 paths.
+pub fn migrated_config(path: &Path) -> Option<PathBuf> {
+    let file = path.file_name()?.to_str()?;
+    let name = file.strip_suffix(V1_CONFIG_SUFFIX)?;
+    let migrated = path
+        .parent()?
+        .join(SERVICES_DIR)
+        .join(name)
+        .join(CONFIG_FILE);
+    migrated.is_file().then_some(migrated)
+}
+
+/// migrate moves the files of version 1 into the service folders and records the version.
+fn migrate(root: &Path) -> Result<(), ServicingError> {
+    let marker = root.join(LAYOUT_FILE);
+    let version = fs::read_to_string(&marker)
+        .ok()
+        .and_then(|v| v.trim().parse::<u32>().ok())
+        .unwrap_or(1);
+    if version >= LAYOUT_VERSION {
+        return Ok(());
+    }
+
+    info!("Migrating {:?} to layout version {}", root, LAYOUT_VERSION);
+    let services = root.join(SERVICES_DIR);
+    for entry in fs::read_dir(root)? {
+        let path = entry?.path();
+        let Some(file) = path.file_name().and_then(|f| f.to_str()) else {
+            continue;
+        };
+        let moved = match file.strip_suffix(V1_ZERO_CONFIG_SUFFIX) {
+            Some(name) => Some((name, ZERO_CONFIG_FILE)),
+            None => file
+                .strip_suffix(V1_CONFIG_SUFFIX)
+                .map(|name| (name, CONFIG_FILE)),
+        };
+        if let Some((name, target)) = moved {
+            fs::create_dir_all(services.join(name))?;
+            fs::rename(&path, services.join(name).join(target))?;
+        }
+    }
+    let workdirs = root.join(V1_WORKDIRS_DIR);
+    if workdirs.is_dir() {
+        for entry in fs::read_dir(&workdirs)? {
+            let path = entry?.path();
+            if let Some(name) = path.file_name() {
+                fs::create_dir_all(services.join(name))?;
+                fs::rename(&path, services.join(name).join(WORKDIR_DIR))?;
+            }
+        }
+        fs::remove_dir(&workdirs)?;
+    }
+    helper::write_to_file(&marker, &LAYOUT_VERSION.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{check_name, migrate, migrated_config, remove_service_dir, service_dir};
+
+    #[test]
+    fn test_migrate() {
+        let root = std::env::temp_dir().join(format!("servicing-layout-{}", std::process::id()));
+        fs::create_dir_all(root.join("workdirs/llm")).unwrap();
+        fs::write(root.join("llm_service.yaml"), "run: x").unwrap();
+        fs::write(root.join("llm_service_zero.yaml"), "run: x").unwrap();
+        fs::write(root.join("services.bin"), "").unwrap();
+
+        migrate(&root).unwrap();
+        let dir = root.join("services/llm");
+        assert!(dir.join("service.yaml").is_file());
+        assert!(dir.join("service_zero.yaml").is_file());
+        assert!(dir.join("workdir").is_dir());
+        assert!(root.join("services.bin").is_file());
+        assert!(!root.join("workdirs").exists());
+        assert_eq!(fs::read_to_string(root.join("layout")).unwrap(), "2");
+        assert_eq!(
+            migrated_config(&root.join("llm_service.yaml")),
+            Some(dir.join("service.yaml"))
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_check_name() {
+        assert!(check_name("service", "llm-7b_v1.2").is_ok());
+        for name in ["", ".", "..", "../..", "a/b", "/tmp", ".hidden", "a b"] {
+            assert!(check_name("service", name).is_err(), "{name:?} accepted");
+        }
+        // refused before anything is created or deleted
+        assert!(service_dir("../..").is_err());
+        assert!(remove_service_dir("..").is_err());
+        assert!(remove_service_dir("").is_err());
+    }
+}
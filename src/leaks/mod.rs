@@ -0,0 +1,230 @@
+//! Leaks module houses the scan for the resources that keep running, and billing, while no
+//! service of the registry tracks them: SkyPilot services and clusters, and the containers and
+//! Kubernetes objects labelled by servicing.
+use std::process::Command;
+
+use log::info;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    error::ServicingError,
+    kubernetes, limiter, logic,
+    runner::{self, Mode},
+};
+
+static SERVE_CONTROLLER_PREFIX: &str = "sky-serve-controller-";
+/// kinds SkyPilot creates for the pods of a service, labelled with the tags of the service
+static LABELLED_KINDS: &str = "deployments,pods";
+/// kinds of the ingress of a service, named after it
+static INGRESS_KINDS: &str = "ingresses,services,endpoints";
+static INGRESS_SELECTOR: &str = "app.kubernetes.io/managed-by=servicing";
+
+/// Leak is a resource tracked by no service.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Leak {
+    /// skypilot-service, skypilot-cluster, container or the kind of the Kubernetes object
+    pub kind: String,
+    /// name of the resource, prefixed with the namespace of Kubernetes objects
+    pub name: String,
+    /// service the resource says it was created for, if any
+    pub service: Option<String>,
+}
+
+/// Report is the outcome of a scan.
+#[derive(Serialize, Default)]
+pub struct Report {
+    pub leaks: Vec<Leak>,
+    /// sources that could not be listed, e.g. docker not being installed
+    pub unchecked: Vec<&'static str>,
+}
+
+type Source = fn(&[String]) -> Result<Vec<Leak>, ServicingError>;
+
+/// scan lists the resources of every source that belong to none of services. A source that
+/// cannot be listed is reported as unchecked rather than failing the scan.
+pub fn scan(services: &[String]) -> Report {
+    let sources: [(&'static str, Source); 4] = [
+        ("skypilot services", sky_services),
+        ("skypilot clusters", sky_clusters),
+        ("docker", containers),
+        ("kubernetes", kubernetes_objects),
+    ];
+    let mut report = Report::default();
+    for (source, list) in sources {
+        match list(services) {
+            Ok(leaks) => report.leaks.extend(leaks),
+            Err(e) => {
+                info!("Could not check {source} for leaks: {e}");
+                report.unchecked.push(source);
+            }
+        }
+    }
+    report
+}
+
+fn sky_services(services: &[String]) -> Result<Vec<Leak>, ServicingError> {
+    let output = capture("sky", &["serve", "status"])?;
+    Ok(logic::serve_services(&output)
+        .into_iter()
+        .filter(|name| !services.contains(name))
+        .map(|name| Leak {
+            kind: "skypilot-service".to_string(),
+            name,
+            service: None,
+        })
+        .collect())
+}
+
+/// sky_clusters reports the clusters that are neither a serve controller nor a replica of a
+/// service, replicas being named <service>-<id>.
+fn sky_clusters(services: &[String]) -> Result<Vec<Leak>, ServicingError> {
+    let output = capture("sky", &["status"])?;
+    let is_replica = |cluster: &str, service: &str| {
+        cluster
+            .strip_prefix(service)
+            .and_then(|rest| rest.strip_prefix('-'))
+            .is_some_and(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()))
+    };
+    Ok(logic::clusters(&output)
+        .into_iter()
+        .filter(|cluster| {
+            !cluster.starts_with(SERVE_CONTROLLER_PREFIX)
+                && !services.iter().any(|service| is_replica(cluster, service))
+        })
+        .map(|name| Leak {
+            kind: "skypilot-cluster".to_string(),
+            name,
+            service: None,
+        })
+        .collect())
+}
+
+fn containers(services: &[String]) -> Result<Vec<Leak>, ServicingError> {
+    let filter = format!("label={}={}", logic::MANAGED_BY_LABEL, logic::MANAGED_BY);
+    let format = format!(
+        "{{{{.Names}}}}\t{{{{.Label \"{}\"}}}}",
+        logic::SERVICE_LABEL
+    );
+    let output = capture(
+        "docker",
+        &["ps", "--all", "--filter", &filter, "--format", &format],
+    )?;
+    Ok(output
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .filter(|(_, service)| !tracked(service, services))
+        .map(|(name, service)| Leak {
+            kind: "container".to_string(),
+            name: name.to_string(),
+            service: (!service.is_empty()).then(|| service.to_string()),
+        })
+        .collect())
+}
+
+fn kubernetes_objects(services: &[String]) -> Result<Vec<Leak>, ServicingError> {
+    let selector = format!("{}={}", logic::MANAGED_BY_LABEL, logic::MANAGED_BY);
+    let mut leaks = Vec::new();
+    for (kinds, selector) in [
+        (LABELLED_KINDS, selector.as_str()),
+        (INGRESS_KINDS, INGRESS_SELECTOR),
+    ] {
+        let output = capture(
+            "kubectl",
+            &[
+                "get",
+                kinds,
+                "--all-namespaces",
+                "-l",
+                selector,
+                "-o",
+                "json",
+            ],
+        )?;
+        leaks.extend(untracked_objects(&serde_json::from_str(&output)?, services));
+    }
+    Ok(leaks)
+}
+
+/// untracked_objects returns the objects of a kubectl list that belong to no service, told by
+/// their service label or, for the ingress objects, by their name.
+fn untracked_objects(list: &Value, services: &[String]) -> Vec<Leak> {
+    list["items"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|item| {
+            let metadata = &item["metadata"];
+            let name = metadata["name"].as_str()?;
+            let service = metadata["labels"][logic::SERVICE_LABEL].as_str();
+            let is_tracked = match service {
+                Some(service) => tracked(service, services),
+                None => services.iter().any(|s| kubernetes::object_name(s) == name),
+            };
+            (!is_tracked).then(|| Leak {
+                kind: item["kind"].as_str().unwrap_or("object").to_lowercase(),
+                name: match metadata["namespace"].as_str() {
+                    Some(namespace) => format!("{namespace}/{name}"),
+                    None => name.to_string(),
+                },
+                service: service.map(str::to_string),
+            })
+        })
+        .collect()
+}
+
+/// tracked tells whether the service label value belongs to one of services.
+fn tracked(label: &str, services: &[String]) -> bool {
+    services
+        .iter()
+        .any(|service| logic::label_value(service) == label)
+}
+
+/// capture runs program with args and returns its output, failing if it does.
+fn capture(program: &str, args: &[&str]) -> Result<String, ServicingError> {
+    let _permit = limiter::acquire(&format!("{program} {}", args.join(" ")))?;
+    let output = runner::run(Command::new(program).args(args), Mode::Capture)?;
+    if !output.status.success() {
+        return Err(ServicingError::General(format!(
+            "{program} {} failed: {}",
+            args[0],
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{untracked_objects, Leak};
+
+    #[test]
+    fn test_untracked_objects() {
+        let list = json!({ "items": [
+            { "kind": "Pod", "metadata": { "name": "llm-1-head", "namespace": "ml",
+                "labels": { "servicing-service": "llm" } } },
+            { "kind": "Pod", "metadata": { "name": "old-1-head", "namespace": "ml",
+                "labels": { "servicing-service": "old" } } },
+            { "kind": "Ingress", "metadata": { "name": "servicing-my-llm", "namespace": "ml" } },
+            { "kind": "Ingress", "metadata": { "name": "servicing-gone", "namespace": "ml" } },
+        ]});
+        let services = vec!["llm".to_string(), "my_llm".to_string()];
+        assert_eq!(
+            untracked_objects(&list, &services),
+            vec![
+                Leak {
+                    kind: "pod".to_string(),
+                    name: "ml/old-1-head".to_string(),
+                    service: Some("old".to_string()),
+                },
+                Leak {
+                    kind: "ingress".to_string(),
+                    name: "ml/servicing-gone".to_string(),
+                    service: None,
+                },
+            ]
+        );
+    }
+}
@@ -1,14 +1,110 @@
+//! Servicing configures services and deploys them to clusters. It is built as the `servicing`
+//! Python module and is also usable as a Rust library: without the default `python` feature it
+//! does not depend on pyo3.
+//!
+//! The Rust API covers the orchestrator machinery the Python Dispatcher is built on:
+//!
+//! - [`orchestrator::Orchestrators`]: the backends, their default templates and prerequisites
+//! - [`docker`]: the Docker backend, running a service in a local container
+//! - [`models::Configuration`] and [`models::UserProvidedConfig`]: the configuration of a service
+//! - [`plan`], [`quote`], [`quota`] and [`lint`]: what a launch would run, cost, be limited by
+//!   and trip over
+//! - [`layout`]: the state directory shared with the Python module
+//! - [`logic`]: the state of a service and the parsing of the orchestrator output
+//! - [`runner`]: the commands run on behalf of a service, recorded in dry-run mode
+//!
+//! ```no_run
+//! use servicing::{models::UserProvidedConfig, orchestrator::Orchestrators};
+//!
+//! let orchestrator = Orchestrators::Skypilot;
+//! let mut template = orchestrator.default_template(&servicing::layout::root()?)?;
+//! template.update(&UserProvidedConfig {
+//!     port: Some(8080),
+//!     ..UserProvidedConfig::default()
+//! });
+//! # Ok::<(), servicing::error::ServicingError>(())
+//! ```
+// the helpers shared with the Python module are partly unused without it
+#![cfg_attr(not(feature = "python"), allow(dead_code))]
+
+#[cfg(feature = "python")]
 use env_logger::Builder;
+#[cfg(feature = "python")]
 use pyo3::{pymodule, types::PyModule, Bound, PyResult};
 
-use crate::{dispatcher::Dispatcher, models::UserProvidedConfig};
+#[cfg(feature = "python")]
+use crate::{
+    client::CompletionStream,
+    deadline::Deadline,
+    dispatcher::Dispatcher,
+    models::{OperationResult, ServiceStatus, UserProvidedConfig},
+    orchestrator::Orchestrators,
+};
 
+#[cfg(feature = "python")]
+mod alias;
+#[cfg(feature = "python")]
+mod approval;
+pub mod autosetup;
+#[cfg(feature = "python")]
+mod benchmark;
+#[cfg(feature = "python")]
+mod budget;
+#[cfg(feature = "python")]
+mod client;
+mod deadline;
+#[cfg(feature = "python")]
 mod dispatcher;
-mod error;
+pub mod docker;
+pub mod error;
+#[cfg(feature = "python")]
+mod export;
+#[cfg(feature = "python")]
+mod gateway;
 mod helper;
-mod models;
+#[cfg(feature = "python")]
+mod hooks;
+pub mod image;
+mod interrupt;
+#[cfg(feature = "python")]
+mod introspect;
+pub mod kubernetes;
+pub mod layout;
+#[cfg(feature = "python")]
+mod leaks;
+mod limiter;
+pub mod lint;
+pub mod logic;
+#[cfg(feature = "python")]
+mod middleware;
+pub mod models;
+#[cfg(feature = "python")]
+mod notification;
+#[cfg(feature = "python")]
+mod operation;
+pub mod orchestrator;
+#[cfg(feature = "python")]
+mod packaging;
+pub mod plan;
+#[cfg(feature = "python")]
+mod policy;
+#[cfg(feature = "python")]
+mod poller;
+#[cfg(feature = "python")]
+mod progress;
+pub mod quota;
+pub mod quote;
+pub mod runner;
+#[cfg(feature = "python")]
+mod scaler;
+#[cfg(feature = "python")]
+mod shared;
+#[cfg(feature = "python")]
+mod traffic;
+pub mod tunnel;
 
 /// A Python module implemented in Rust.
+#[cfg(feature = "python")]
 #[pymodule]
 fn servicing(m: &Bound<'_, PyModule>) -> PyResult<()> {
     // if release mode, set log level to warn
@@ -20,5 +116,10 @@ fn servicing(m: &Bound<'_, PyModule>) -> PyResult<()> {
 
     m.add_class::<Dispatcher>()?;
     m.add_class::<UserProvidedConfig>()?;
+    m.add_class::<CompletionStream>()?;
+    m.add_class::<Orchestrators>()?;
+    m.add_class::<ServiceStatus>()?;
+    m.add_class::<OperationResult>()?;
+    m.add_class::<Deadline>()?;
     Ok(())
 }
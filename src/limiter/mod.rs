@@ -0,0 +1,132 @@
+//! Limiter module houses the process-wide limit on concurrent orchestrator subprocesses (sky,
+//! docker, ...), so bulk operations over many services queue instead of forking them all at once.
+use std::{
+    collections::VecDeque,
+    sync::{Condvar, Mutex},
+    time::Instant,
+};
+
+use serde::Serialize;
+
+use crate::{deadline, error::ServicingError};
+
+static DEFAULT_LIMIT: usize = 8;
+
+static SUBPROCESSES: Limiter = Limiter {
+    state: Mutex::new(State {
+        limit: DEFAULT_LIMIT,
+        next_id: 0,
+        running: Vec::new(),
+        queued: VecDeque::new(),
+    }),
+    freed: Condvar::new(),
+};
+
+struct Task {
+    id: u64,
+    command: String,
+    since: Instant,
+}
+
+struct State {
+    limit: usize,
+    next_id: u64,
+    running: Vec<Task>,
+    queued: VecDeque<Task>,
+}
+
+/// TaskInfo describes a running or queued subprocess, as returned by Dispatcher::tasks.
+#[derive(Serialize)]
+pub struct TaskInfo {
+    pub id: u64,
+    pub command: String,
+    pub state: &'static str,
+    /// seconds since the task started running, or was queued
+    pub seconds: f64,
+}
+
+/// Limiter is a counting semaphore that keeps track of who holds and who waits for a permit.
+struct Limiter {
+    state: Mutex<State>,
+    freed: Condvar,
+}
+
+/// Permit allows one subprocess to run, it is released when dropped.
+pub struct Permit {
+    id: u64,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Ok(mut state) = SUBPROCESSES.state.lock() {
+            state.running.retain(|task| task.id != self.id);
+        }
+        SUBPROCESSES.freed.notify_all();
+    }
+}
+
+/// acquire waits for a permit to run command, tasks are let through in the order they queued.
+/// Under a deadline, a task still queued when it passes leaves the queue.
+pub fn acquire(command: &str) -> Result<Permit, ServicingError> {
+    let mut state = SUBPROCESSES.state.lock()?;
+    let id = state.next_id;
+    state.next_id += 1;
+    state.queued.push_back(Task {
+        id,
+        command: command.to_string(),
+        since: Instant::now(),
+    });
+
+    while state.running.len() >= state.limit || state.queued.front().map(|t| t.id) != Some(id) {
+        state = match deadline::remaining(&format!("a subprocess slot to run {command}")) {
+            Ok(None) => SUBPROCESSES.freed.wait(state)?,
+            Ok(Some(left)) => SUBPROCESSES.freed.wait_timeout(state, left)?.0,
+            Err(e) => {
+                state.queued.retain(|task| task.id != id);
+                // the task may have held back the next queued one
+                SUBPROCESSES.freed.notify_all();
+                return Err(e);
+            }
+        };
+    }
+    if let Some(mut task) = state.queued.pop_front() {
+        task.since = Instant::now();
+        state.running.push(task);
+    }
+    // the next queued task may fit as well
+    SUBPROCESSES.freed.notify_all();
+    Ok(Permit { id })
+}
+
+/// acquire_async waits for a permit without blocking the runtime workers.
+pub async fn acquire_async(command: &str) -> Result<Permit, ServicingError> {
+    let command = command.to_string();
+    let deadline = deadline::current();
+    tokio::task::spawn_blocking(move || deadline::with_deadline(deadline, || acquire(&command)))
+        .await
+        .map_err(|e| ServicingError::General(e.to_string()))?
+}
+
+/// set_limit changes the number of subprocesses allowed to run at once.
+pub fn set_limit(limit: usize) -> Result<(), ServicingError> {
+    SUBPROCESSES.state.lock()?.limit = limit.max(1);
+    SUBPROCESSES.freed.notify_all();
+    Ok(())
+}
+
+/// tasks lists the running subprocesses followed by the queued ones.
+pub fn tasks() -> Result<Vec<TaskInfo>, ServicingError> {
+    let state = SUBPROCESSES.state.lock()?;
+    let info = |task: &Task, running| TaskInfo {
+        id: task.id,
+        command: task.command.clone(),
+        state: if running { "running" } else { "queued" },
+        seconds: task.since.elapsed().as_secs_f64(),
+    };
+    Ok(state
+        .running
+        .iter()
+        .map(|task| info(task, true))
+        .chain(state.queued.iter().map(|task| info(task, false)))
+        .collect())
+}
@@ -0,0 +1,204 @@
+//! Lint module houses the checks of the setup and run scripts of a service, catching the typos
+//! that would otherwise only surface once the cluster is provisioned.
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::{autosetup, models::Configuration};
+
+/// package managers prompting for confirmation unless told not to
+static PROMPTING: &[(&str, &[&str])] = &[
+    ("apt", &["install", "remove", "upgrade", "purge"]),
+    ("apt-get", &["install", "remove", "upgrade", "purge"]),
+    ("yum", &["install", "remove", "update"]),
+    ("dnf", &["install", "remove", "upgrade"]),
+    ("conda", &["install", "create", "remove", "update"]),
+    ("mamba", &["install", "create", "remove", "update"]),
+];
+static YES_FLAGS: &[&str] = &["-y", "--yes", "--assume-yes", "-qy", "-yq"];
+/// extensions of the arguments taken for files of the workdir
+static FILE_EXTENSIONS: &[&str] = &[
+    "py", "sh", "txt", "toml", "yaml", "yml", "json", "cfg", "lock", "ipynb",
+];
+/// files tools read from the current directory without being told
+static IMPLICIT_FILES: &[(&str, &str, &str)] = &[
+    ("poetry", "install", "pyproject.toml"),
+    ("uv", "sync", "pyproject.toml"),
+    ("pipenv", "install", "Pipfile"),
+];
+
+/// Lint is one finding in a script of the service.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Lint {
+    /// setup or run
+    pub script: &'static str,
+    /// 1-based line of the script, None for a finding about the whole script
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+/// lint checks the setup and run scripts of template, files being looked up in its workdir.
+pub fn lint(template: &Configuration) -> Vec<Lint> {
+    let workdir = Path::new(&template.workdir);
+    let mut lints = Vec::new();
+    for (script, content) in [("setup", &template.setup), ("run", &template.run)] {
+        for (index, line) in content.lines().enumerate() {
+            let mut report = |message| {
+                lints.push(Lint {
+                    script,
+                    line: Some(index + 1),
+                    message,
+                })
+            };
+            let line = line.trim();
+            if line.starts_with('#') {
+                continue;
+            }
+            for variable in unquoted_variables(line) {
+                report(format!(
+                    "Variable ${variable} is unquoted, it is split on spaces: use \"${variable}\""
+                ));
+            }
+            for command in commands(line) {
+                if let Some(message) = check_prompt(&command) {
+                    report(message);
+                }
+                for file in referenced_files(&command) {
+                    if !workdir.join(&file).exists() {
+                        report(format!(
+                            "File {file} is not in the workdir {}",
+                            workdir.display()
+                        ));
+                    }
+                }
+            }
+        }
+    }
+    if let Some(accelerators) = &template.resources.accelerators {
+        if !autosetup::uses_gpu(workdir, &template.run).unwrap_or(true) {
+            lints.push(Lint {
+                script: "run",
+                line: None,
+                message: format!(
+                    "Accelerators {accelerators} are requested but neither the run script nor \
+                     the dependencies seem to use a GPU"
+                ),
+            });
+        }
+    }
+    lints
+}
+
+/// unquoted_variables returns the variables expanded outside of double quotes in line,
+/// assignments excepted since they are not split.
+fn unquoted_variables(line: &str) -> Vec<String> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut variables = Vec::new();
+    let (mut single, mut double) = (false, false);
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '\'' if !double => single = !single,
+            '"' if !single => double = !double,
+            '$' if !single && !double && (i == 0 || chars[i - 1] != '=') => {
+                let braced = chars.get(i + 1) == Some(&'{');
+                let start = if braced { i + 2 } else { i + 1 };
+                let name: String = chars[start.min(chars.len())..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_alphanumeric() || **c == '_')
+                    .collect();
+                if name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+                    i = start + name.len();
+                    variables.push(name);
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    variables
+}
+
+/// commands splits line into its simple commands, each as its words without sudo.
+fn commands(line: &str) -> Vec<Vec<String>> {
+    line.split(['&', ';', '|'])
+        .map(|command| {
+            command
+                .split_whitespace()
+                .map(|word| word.trim_matches(['"', '\'']).to_string())
+                .skip_while(|word| word == "sudo" || word.contains('='))
+                .collect::<Vec<_>>()
+        })
+        .filter(|words| !words.is_empty())
+        .collect()
+}
+
+fn check_prompt(command: &[String]) -> Option<String> {
+    let (program, actions) = PROMPTING
+        .iter()
+        .find(|(program, _)| command[0] == *program)?;
+    let action = command.get(1).filter(|a| actions.contains(&a.as_str()))?;
+    match command
+        .iter()
+        .any(|word| YES_FLAGS.contains(&word.as_str()))
+    {
+        true => None,
+        false => Some(format!(
+            "{program} {action} prompts for confirmation and hangs the setup, add -y"
+        )),
+    }
+}
+
+/// referenced_files returns the relative paths command refers to, explicitly or implicitly.
+fn referenced_files(command: &[String]) -> Vec<String> {
+    let implicit = IMPLICIT_FILES
+        .iter()
+        .filter(|(program, action, _)| {
+            command[0] == *program && command.get(1).is_some_and(|a| a == action)
+        })
+        .map(|(_, _, file)| file.to_string());
+    let explicit = command[1..]
+        .iter()
+        .filter(|word| {
+            !word.starts_with(['-', '/', '$', '~', '<', '>'])
+                && !word.contains("://")
+                && !word.contains(['*', '?', '='])
+                && word
+                    .rsplit_once('.')
+                    .is_some_and(|(stem, ext)| !stem.is_empty() && FILE_EXTENSIONS.contains(&ext))
+        })
+        .cloned();
+    implicit.chain(explicit).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::lint;
+    use crate::models::Configuration;
+
+    #[test]
+    fn test_lint() {
+        let dir = std::env::temp_dir().join("servicing-lint-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("service.py"), "").unwrap();
+        let template = Configuration {
+            workdir: dir.display().to_string(),
+            setup: "sudo apt-get install -y curl\nconda install cudatoolkit\n# cd $HOME\n\
+                    pip install -r requirements.txt && cd $MODEL_DIR"
+                .to_string(),
+            run: "MODEL=$1 python service.py --dir \"$MODEL_DIR\"".to_string(),
+            ..Configuration::default()
+        };
+
+        let lints: Vec<_> = lint(&template)
+            .into_iter()
+            .map(|l| (l.script, l.line))
+            .collect();
+        assert_eq!(
+            lints,
+            vec![("setup", Some(2)), ("setup", Some(4)), ("setup", Some(4))]
+        );
+    }
+}
@@ -0,0 +1,386 @@
+//! Logic module houses the decisions of servicing that depend on nothing but their inputs: the
+//! state of a service, the arguments of a launch and the parsing of the orchestrator output. It
+//! runs no command and touches no file, so the front ends can reuse and test it in isolation.
+//! The configuration rendering ([`crate::models::Configuration`]) and the plans
+//! ([`crate::plan`]) are pure as well.
+use std::{collections::BTreeMap, fmt};
+
+use serde::Serialize;
+
+/// label marking the cloud resources created through servicing
+pub static MANAGED_BY_LABEL: &str = "managed-by";
+pub static MANAGED_BY: &str = "servicing";
+/// label naming the service a cloud resource was created for
+pub static SERVICE_LABEL: &str = "servicing-service";
+/// label naming the environment the service was launched in, e.g. staging
+pub static ENVIRONMENT_LABEL: &str = "servicing-environment";
+/// longest label value accepted by every cloud
+static MAX_LABEL_LENGTH: usize = 63;
+
+/// State is where a service is in its lifecycle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    Down,
+    /// an up is provisioning the service, there is no endpoint yet
+    Provisioning,
+    /// launched, the endpoint is known but the service is not ready yet, e.g. setup is running
+    Starting,
+    Up,
+    /// up, but requests are refused while it is under maintenance
+    Maintenance,
+    /// was ready since it was launched, but the probes now fail
+    Unhealthy,
+    /// suspended by pause, its container or pods kept until resume
+    Paused,
+}
+
+/// Observation is what the state of a service is derived from: the probes and the phase of
+/// the up in flight.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Observation<'a> {
+    /// whether the last probe found the service ready
+    pub up: bool,
+    pub has_url: bool,
+    pub maintenance: bool,
+    /// whether the service is paused
+    pub paused: bool,
+    /// phase of the up in flight, None when no up runs
+    pub launching: Option<&'a str>,
+    /// whether the service was ready since it was launched
+    pub was_ready: bool,
+}
+
+impl State {
+    /// of derives the state from whether the service is ready, has an endpoint and is under
+    /// maintenance, not telling a service that is booting from one that crashed.
+    pub fn of(up: bool, has_url: bool, maintenance: bool) -> State {
+        State::observe(&Observation {
+            up,
+            has_url,
+            maintenance,
+            ..Observation::default()
+        })
+    }
+
+    /// observe derives the state from the probes and the launch of the service: a service that
+    /// is not ready is provisioning while an up runs without an endpoint, starting until it is
+    /// ready for the first time and unhealthy afterwards. A paused service is paused whatever
+    /// the probes.
+    pub fn observe(observation: &Observation) -> State {
+        match observation {
+            Observation { paused: true, .. } => State::Paused,
+            Observation {
+                up: true,
+                maintenance: true,
+                ..
+            } => State::Maintenance,
+            Observation { up: true, .. } => State::Up,
+            Observation {
+                has_url: false,
+                launching: Some(_),
+                ..
+            } => State::Provisioning,
+            Observation {
+                has_url: true,
+                launching: None,
+                was_ready: true,
+                ..
+            } => State::Unhealthy,
+            Observation { has_url: true, .. } => State::Starting,
+            Observation { has_url: false, .. } => State::Down,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            State::Down => "down",
+            State::Provisioning => "provisioning",
+            State::Starting => "starting",
+            State::Up => "up",
+            State::Maintenance => "maintenance",
+            State::Unhealthy => "unhealthy",
+            State::Paused => "paused",
+        }
+    }
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// placement_args returns the arguments pinning a launch to placement: a cloud among clouds, a
+/// region, or a cloud and a region written `cloud:region`.
+pub fn placement_args(placement: &str, clouds: &[&str]) -> Vec<String> {
+    let args: Vec<&str> = match placement.split_once(':') {
+        Some((cloud, region)) => vec!["--cloud", cloud, "--region", region],
+        None if clouds.contains(&placement) => vec!["--cloud", placement],
+        None => vec!["--region", placement],
+    };
+    args.into_iter().map(str::to_string).collect()
+}
+
+/// tags returns the labels tracing the cloud resources of a service back to it: the service,
+/// its owner, when it was created and the managed-by marker.
+pub fn tags(name: &str, owner: &str, created: u64) -> BTreeMap<String, String> {
+    BTreeMap::from([
+        (MANAGED_BY_LABEL.to_string(), MANAGED_BY.to_string()),
+        (SERVICE_LABEL.to_string(), label_value(name)),
+        ("servicing-owner".to_string(), label_value(owner)),
+        ("servicing-created".to_string(), created.to_string()),
+    ])
+}
+
+/// label_value lowercases value and replaces the characters that GCP refuses in labels, the
+/// strictest of the clouds, with dashes.
+pub fn label_value(value: &str) -> String {
+    value
+        .to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | '0'..='9' | '_' | '-' => c,
+            _ => '-',
+        })
+        .take(MAX_LABEL_LENGTH)
+        .collect()
+}
+
+/// serve_services lists the names in the services table of the output of `sky serve status`.
+pub fn serve_services(status_output: &str) -> Vec<String> {
+    // NAME VERSION UPTIME STATUS REPLICAS ENDPOINT
+    table_names(status_output, "Services")
+}
+
+/// clusters lists the names in the clusters table of the output of `sky status`.
+pub fn clusters(status_output: &str) -> Vec<String> {
+    // NAME LAUNCHED RESOURCES STATUS AUTOSTOP COMMAND
+    table_names(status_output, "Clusters")
+}
+
+/// ServeStatus is a row of the services table of `sky serve status`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ServeStatus {
+    pub name: String,
+    pub status: String,
+    /// ready replicas over the replicas, e.g. 1/2
+    pub replicas: String,
+    pub endpoint: Option<String>,
+}
+
+/// ReplicaStatus is a row of the replicas table of `sky serve status`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ReplicaStatus {
+    pub service: String,
+    pub id: u32,
+    pub status: String,
+    pub region: Option<String>,
+    pub endpoint: Option<String>,
+}
+
+/// serve_statuses parses the services table of the output of `sky serve status`.
+pub fn serve_statuses(status_output: &str) -> Vec<ServeStatus> {
+    table_rows(status_output, "Services")
+        .into_iter()
+        .filter_map(|row| {
+            Some(ServeStatus {
+                name: cell(&row, "NAME")?,
+                status: cell(&row, "STATUS").unwrap_or_default(),
+                replicas: cell(&row, "REPLICAS").unwrap_or_default(),
+                endpoint: cell(&row, "ENDPOINT"),
+            })
+        })
+        .collect()
+}
+
+/// replica_statuses parses the replicas table of the output of `sky serve status`.
+pub fn replica_statuses(status_output: &str) -> Vec<ReplicaStatus> {
+    table_rows(status_output, "Service Replicas")
+        .into_iter()
+        .filter_map(|row| {
+            Some(ReplicaStatus {
+                service: cell(&row, "SERVICE_NAME")?,
+                id: cell(&row, "ID")?.parse().ok()?,
+                status: cell(&row, "STATUS").unwrap_or_default(),
+                region: cell(&row, "REGION"),
+                endpoint: cell(&row, "ENDPOINT"),
+            })
+        })
+        .collect()
+}
+
+/// cell returns the value of the column of row, None when missing or shown as -.
+fn cell(row: &[(String, String)], column: &str) -> Option<String> {
+    row.iter()
+        .find(|(header, _)| header == column)
+        .map(|(_, value)| value.clone())
+        .filter(|value| value != "-")
+}
+
+/// table_names returns the first column of the table under the title line, empty when the
+/// section has no table, e.g. "No existing clusters.".
+fn table_names(output: &str, title: &str) -> Vec<String> {
+    table_rows(output, title)
+        .into_iter()
+        .filter_map(|row| row.into_iter().next().map(|(_, name)| name))
+        .collect()
+}
+
+/// table_rows returns the rows of the table under the title line as (header, value) pairs.
+/// Columns are told apart by runs of at least two spaces, as values like "2 mins ago" hold
+/// single ones.
+fn table_rows(output: &str, title: &str) -> Vec<Vec<(String, String)>> {
+    let mut lines = output
+        .lines()
+        .map(str::trim)
+        .skip_while(|line| *line != title)
+        .skip(1)
+        .skip_while(|line| line.is_empty());
+    let header: Vec<&str> = match lines.next() {
+        Some(header)
+            if header
+                .chars()
+                .all(|c| c.is_ascii_uppercase() || c == '_' || c.is_whitespace()) =>
+        {
+            header.split_whitespace().collect()
+        }
+        _ => return Vec::new(),
+    };
+    lines
+        .take_while(|line| !line.is_empty())
+        .map(|line| {
+            let values = line.split("  ").map(str::trim).filter(|v| !v.is_empty());
+            header
+                .iter()
+                .zip(values)
+                .map(|(header, value)| (header.to_string(), value.to_string()))
+                .collect()
+        })
+        .collect()
+}
+
+/// preempted_replicas lists the ids of the replicas of the service that SkyPilot reports as
+/// preempted in the output of `sky serve status`.
+pub fn preempted_replicas(name: &str, status_output: &str) -> Vec<u32> {
+    // replica rows read: SERVICE_NAME ID VERSION ENDPOINT LAUNCHED RESOURCES STATUS REGION
+    status_output
+        .lines()
+        .filter(|line| line.contains("PREEMPTED"))
+        .filter_map(|line| {
+            let mut columns = line.split_whitespace();
+            if columns.next() != Some(name) {
+                return None;
+            }
+            columns.next()?.parse().ok()
+        })
+        .collect()
+}
+
+/// enabled_clouds parses the clouds listed under "Enabled clouds" in the output of sky check.
+pub fn enabled_clouds(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .skip_while(|line| !line.to_lowercase().contains("enabled clouds"))
+        .skip(1)
+        .map(str::trim)
+        .take_while(|line| !line.is_empty())
+        .map(|line| {
+            line.trim_start_matches(|c: char| !c.is_alphanumeric())
+                .to_string()
+        })
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        clusters, enabled_clouds, placement_args, preempted_replicas, replica_statuses,
+        serve_services, serve_statuses, tags, Observation, State,
+    };
+
+    #[test]
+    fn test_logic() {
+        assert_eq!(State::of(true, true, true), State::Maintenance);
+        assert_eq!(State::of(false, true, true), State::Starting);
+        assert_eq!(State::of(false, false, false).name(), "down");
+
+        let booting = Observation {
+            launching: Some("provision"),
+            ..Observation::default()
+        };
+        assert_eq!(State::observe(&booting), State::Provisioning);
+        let setup = Observation {
+            has_url: true,
+            launching: Some("ready"),
+            ..booting
+        };
+        assert_eq!(State::observe(&setup), State::Starting);
+        let crashed = Observation {
+            launching: None,
+            was_ready: true,
+            ..setup
+        };
+        assert_eq!(State::observe(&crashed), State::Unhealthy);
+        let paused = Observation {
+            paused: true,
+            ..crashed
+        };
+        assert_eq!(State::observe(&paused).name(), "paused");
+
+        let clouds = ["aws", "gcp"];
+        assert_eq!(placement_args("gcp", &clouds), vec!["--cloud", "gcp"]);
+        assert_eq!(
+            placement_args("eu-west-1", &clouds),
+            vec!["--region", "eu-west-1"]
+        );
+        assert_eq!(
+            placement_args("aws:us-east-1", &clouds),
+            vec!["--cloud", "aws", "--region", "us-east-1"]
+        );
+
+        let status = "SERVICE_NAME  ID  VERSION  ENDPOINT  LAUNCHED  RESOURCES  STATUS  REGION\n\
+                      llm  1  1  -  1 min ago  1x AWS  READY  us-east-1\n\
+                      llm  2  1  -  1 min ago  1x AWS  PREEMPTED  us-east-1\n\
+                      other  3  1  -  1 min ago  1x AWS  PREEMPTED  us-east-1";
+        assert_eq!(preempted_replicas("llm", status), vec![2]);
+
+        let status = "Services\n\
+                      NAME  VERSION  UPTIME  STATUS  REPLICAS  ENDPOINT\n\
+                      llm  1  1m  READY  1/2  1.2.3.4:30001\n\
+                      rogue  1  2h  READY  1/1  1.2.3.5:30001\n\n\
+                      Service Replicas\n\
+                      SERVICE_NAME  ID  VERSION  ENDPOINT  LAUNCHED  RESOURCES  STATUS  REGION";
+        assert_eq!(serve_services(status), vec!["llm", "rogue"]);
+        let status = "Services\n\
+                      NAME  VERSION  UPTIME  STATUS  REPLICAS  ENDPOINT\n\
+                      llm  1  -  NO_REPLICA  0/1  -\n\n\
+                      Service Replicas\n\
+                      SERVICE_NAME  ID  VERSION  ENDPOINT  LAUNCHED  RESOURCES  STATUS  REGION\n\
+                      llm  1  1  -  2 mins ago  1x AWS(A10G:1)  PROVISIONING  us-east-1";
+        let services = serve_statuses(status);
+        assert_eq!(services[0].status, "NO_REPLICA");
+        assert_eq!(services[0].endpoint, None);
+        let replicas = replica_statuses(status);
+        assert_eq!(replicas.len(), 1);
+        assert_eq!(
+            (replicas[0].id, replicas[0].status.as_str()),
+            (1, "PROVISIONING")
+        );
+        assert_eq!(replicas[0].region.as_deref(), Some("us-east-1"));
+        let status = "Clusters\nNo existing clusters.\n\nServices\n\
+                      NAME  VERSION  UPTIME  STATUS  REPLICAS  ENDPOINT\n\
+                      llm  1  1m  READY  1/2  1.2.3.4:30001";
+        assert!(clusters(status).is_empty());
+
+        let tags = tags("llm", "Jane.Doe", 1700000000);
+        assert_eq!(tags["managed-by"], "servicing");
+        assert_eq!(tags["servicing-owner"], "jane-doe");
+
+        let output = "Checking credentials to enable clouds for SkyPilot.\n  AWS: enabled\n\n\
+                      \u{1f389} Enabled clouds \u{1f389}\n  \u{2714} AWS\n  \u{2714} GCP\n\n\
+                      To enable a cloud, follow the hints above";
+        assert_eq!(enabled_clouds(output), vec!["AWS", "GCP"]);
+    }
+}
@@ -0,0 +1,158 @@
+//! Middleware module houses the Python hooks run around every outbound request to a service.
+use std::{sync::Mutex, time::Duration};
+
+use log::warn;
+use pyo3::{
+    types::{PyAnyMethods, PyDict, PyDictMethods},
+    Bound, PyObject, Python,
+};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+use crate::{client::OutboundRequest, error::ServicingError};
+
+struct Hook {
+    before: Option<PyObject>,
+    after: Option<PyObject>,
+}
+
+/// Outcome is what became of an outbound request, handed to the after hooks.
+pub struct Outcome {
+    pub status: Option<u16>,
+    pub elapsed: Duration,
+    pub error: Option<String>,
+}
+
+/// Middleware keeps the registered hooks. Before hooks are called with a request dict (service,
+/// method, url, headers, body_size) and may change its headers in place; after hooks are called
+/// with the same dict and a response dict (status, elapsed, error).
+#[derive(Default)]
+pub struct Middleware {
+    hooks: Mutex<Vec<Hook>>,
+}
+
+impl Middleware {
+    pub fn add(
+        &self,
+        before: Option<PyObject>,
+        after: Option<PyObject>,
+    ) -> Result<(), ServicingError> {
+        self.hooks.lock()?.push(Hook { before, after });
+        Ok(())
+    }
+
+    pub fn clear(&self) -> Result<(), ServicingError> {
+        self.hooks.lock()?.clear();
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.lock().map(|h| h.is_empty()).unwrap_or(true)
+    }
+
+    /// before runs the before hooks in registration order, an exception aborts the request.
+    pub fn before(
+        &self,
+        py: Python<'_>,
+        service: &str,
+        req: &mut OutboundRequest,
+    ) -> Result<(), ServicingError> {
+        let before = self.select(py, |h| h.before.as_ref())?;
+        if before.is_empty() {
+            return Ok(());
+        }
+
+        let request = request_dict(py, service, req)?;
+        for hook in before {
+            let returned = hook.call1(py, (&request,))?;
+            // a hook may also hand back a new headers dict instead of editing it in place
+            if let Ok(headers) = returned.downcast_bound::<PyDict>(py) {
+                request.set_item("headers", headers)?;
+            }
+        }
+
+        let headers = request.get_item("headers")?.ok_or(ServicingError::General(
+            "Middleware removed the request headers".to_string(),
+        ))?;
+        req.headers = to_header_map(headers.downcast::<PyDict>().map_err(|e| {
+            ServicingError::General(format!("Middleware headers must be a dict: {e}"))
+        })?)?;
+        Ok(())
+    }
+
+    /// after runs the after hooks, exceptions are logged and do not affect the response.
+    pub fn after(&self, py: Python<'_>, service: &str, req: &OutboundRequest, outcome: &Outcome) {
+        let after = match self.select(py, |h| h.after.as_ref()) {
+            Ok(after) if !after.is_empty() => after,
+            _ => return,
+        };
+
+        let dicts = request_dict(py, service, req).and_then(|request| {
+            let response = PyDict::new_bound(py);
+            response.set_item("status", outcome.status)?;
+            response.set_item("elapsed", outcome.elapsed.as_secs_f64())?;
+            response.set_item("error", outcome.error.as_deref())?;
+            Ok((request, response))
+        });
+        let (request, response) = match dicts {
+            Ok(dicts) => dicts,
+            Err(e) => {
+                warn!("Could not build middleware arguments: {e}");
+                return;
+            }
+        };
+        for hook in after {
+            if let Err(e) = hook.call1(py, (&request, &response)) {
+                warn!("Middleware after hook failed: {e}");
+            }
+        }
+    }
+
+    /// select returns the hooks picked by hook, out of the lock so that they can use the
+    /// dispatcher, adding middleware included.
+    fn select(
+        &self,
+        py: Python<'_>,
+        hook: impl Fn(&Hook) -> Option<&PyObject>,
+    ) -> Result<Vec<PyObject>, ServicingError> {
+        Ok(self
+            .hooks
+            .lock()?
+            .iter()
+            .filter_map(hook)
+            .map(|hook| hook.clone_ref(py))
+            .collect())
+    }
+}
+
+fn request_dict<'py>(
+    py: Python<'py>,
+    service: &str,
+    req: &OutboundRequest,
+) -> Result<Bound<'py, PyDict>, ServicingError> {
+    let headers = PyDict::new_bound(py);
+    for (name, value) in req.headers.iter() {
+        headers.set_item(name.as_str(), String::from_utf8_lossy(value.as_bytes()))?;
+    }
+
+    let request = PyDict::new_bound(py);
+    request.set_item("service", service)?;
+    request.set_item("method", req.method.as_str())?;
+    request.set_item("url", &req.url)?;
+    request.set_item("headers", headers)?;
+    request.set_item("body_size", req.body.as_ref().map_or(0, Vec::len))?;
+    Ok(request)
+}
+
+fn to_header_map(headers: &Bound<'_, PyDict>) -> Result<HeaderMap, ServicingError> {
+    let mut map = HeaderMap::new();
+    for (name, value) in headers.iter() {
+        let name: String = name.extract()?;
+        let value: String = value.str()?.extract()?;
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| ServicingError::General(format!("Invalid header name {name}: {e}")))?;
+        let value = HeaderValue::from_str(&value)
+            .map_err(|e| ServicingError::General(format!("Invalid header value {value}: {e}")))?;
+        map.insert(name, value);
+    }
+    Ok(map)
+}
@@ -1,8 +1,28 @@
+use std::{
+    cell::Cell,
+    collections::BTreeMap,
+    hash::Hash,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+#[cfg(feature = "python")]
+use std::{collections::hash_map::DefaultHasher, hash::Hasher};
+
+use chrono::{DateTime, Datelike, NaiveDate, SecondsFormat, TimeZone, Utc};
+use chrono_tz::Tz;
+
+#[cfg(feature = "python")]
 use pyo3::{pyclass, pymethods};
 use serde::{ser::SerializeStruct, Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
 
-#[pyclass(subclass)]
-#[derive(Clone, Serialize, Deserialize, Debug)]
+use crate::{
+    error::ServicingError,
+    kubernetes::{self, Autoscaling, Kubernetes, Mig},
+};
+
+#[cfg_attr(feature = "python", pyclass(subclass))]
+#[derive(Clone, Default, Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
 pub struct UserProvidedConfig {
     pub port: Option<u16>,
     pub replicas: Option<u16>,
@@ -15,11 +35,141 @@ pub struct UserProvidedConfig {
     pub accelerators: Option<String>,
     pub setup: Option<String>,
     pub run: Option<String>,
+    /// Kubernetes namespace of the pods
+    pub namespace: Option<String>,
+    pub labels: Option<BTreeMap<String, String>>,
+    pub node_selector: Option<BTreeMap<String, String>>,
+    pub tolerations: Option<Vec<BTreeMap<String, String>>>,
+    pub service_account: Option<String>,
+    /// Kubernetes containers run before the service, with a name, an image and a command
+    pub init_containers: Option<Vec<BTreeMap<String, String>>>,
+    /// Kubernetes containers run alongside the service, declared like init_containers
+    pub sidecars: Option<Vec<BTreeMap<String, String>>>,
+    /// bounds of the replicas the Kubernetes orchestrator autoscales the service within, the
+    /// minimum being replicas by default
+    pub min_replicas: Option<u16>,
+    pub max_replicas: Option<u16>,
+    /// average CPU utilization of the pods the autoscaler aims at, in percent of their request
+    pub target_utilization: Option<u16>,
+    /// hours after its launch that monitor_ttl takes the service down
+    pub ttl_hours: Option<u16>,
+    /// name of the base configuration this one extends and overrides
+    pub base: Option<String>,
+}
+
+impl UserProvidedConfig {
+    /// check validates the containers and accelerators, which the constructor does for Python.
+    pub fn check(&self) -> Result<(), ServicingError> {
+        for containers in [&self.init_containers, &self.sidecars]
+            .into_iter()
+            .flatten()
+        {
+            kubernetes::check_containers(containers)?;
+        }
+        if let Some(accelerators) = &self.accelerators {
+            Mig::parse(accelerators)?;
+        }
+        let refused = |reason: &str| Err(ServicingError::General(reason.to_string()));
+        match (self.min_replicas, self.max_replicas) {
+            (_, Some(0)) => return refused("max_replicas must be at least 1"),
+            (Some(min), Some(max)) if min == 0 || min > max => {
+                return refused("min_replicas must be between 1 and max_replicas")
+            }
+            (_, None) if self.min_replicas.is_some() || self.target_utilization.is_some() => {
+                return refused("min_replicas and target_utilization need max_replicas")
+            }
+            _ => {}
+        }
+        if self.target_utilization == Some(0) {
+            return refused("target_utilization must be at least 1");
+        }
+        Ok(())
+    }
+
+    /// overlay returns self overridden by the fields over sets. The labels and node selectors
+    /// are merged, those of over winning; the lists are replaced as a whole.
+    pub fn overlay(&self, over: &UserProvidedConfig) -> UserProvidedConfig {
+        fn merged(
+            under: &Option<BTreeMap<String, String>>,
+            over: &Option<BTreeMap<String, String>>,
+        ) -> Option<BTreeMap<String, String>> {
+            match (under, over) {
+                (Some(under), Some(over)) => {
+                    Some(under.clone().into_iter().chain(over.clone()).collect())
+                }
+                (under, over) => over.clone().or(under.clone()),
+            }
+        }
+        UserProvidedConfig {
+            port: over.port.or(self.port),
+            replicas: over.replicas.or(self.replicas),
+            cloud: over.cloud.clone().or(self.cloud.clone()),
+            workdir: over.workdir.clone().or(self.workdir.clone()),
+            data: over.data.clone().or(self.data.clone()),
+            disk_size: over.disk_size.or(self.disk_size),
+            cpu: over.cpu.clone().or(self.cpu.clone()),
+            memory: over.memory.clone().or(self.memory.clone()),
+            accelerators: over.accelerators.clone().or(self.accelerators.clone()),
+            setup: over.setup.clone().or(self.setup.clone()),
+            run: over.run.clone().or(self.run.clone()),
+            namespace: over.namespace.clone().or(self.namespace.clone()),
+            labels: merged(&self.labels, &over.labels),
+            node_selector: merged(&self.node_selector, &over.node_selector),
+            tolerations: over.tolerations.clone().or(self.tolerations.clone()),
+            service_account: over
+                .service_account
+                .clone()
+                .or(self.service_account.clone()),
+            init_containers: over
+                .init_containers
+                .clone()
+                .or(self.init_containers.clone()),
+            sidecars: over.sidecars.clone().or(self.sidecars.clone()),
+            min_replicas: over.min_replicas.or(self.min_replicas),
+            max_replicas: over.max_replicas.or(self.max_replicas),
+            target_utilization: over.target_utilization.or(self.target_utilization),
+            ttl_hours: over.ttl_hours.or(self.ttl_hours),
+            base: over.base.clone(),
+        }
+    }
+
+    /// resolve returns the configuration with its base applied under it, the base of the base
+    /// under that and so on, bases being looked up by name. The nearer a configuration is to
+    /// self, the higher its precedence, self overriding them all. The base of the result is that
+    /// of self.
+    pub fn resolve(
+        &self,
+        base: impl Fn(&str) -> Result<Option<UserProvidedConfig>, ServicingError>,
+    ) -> Result<UserProvidedConfig, ServicingError> {
+        let mut chain = vec![self.clone()];
+        let mut names: Vec<String> = Vec::new();
+        while let Some(name) = chain.last().and_then(|config| config.base.clone()) {
+            if names.contains(&name) {
+                return Err(ServicingError::General(format!(
+                    "Base {name} extends itself through {}",
+                    names.join(", ")
+                )));
+            }
+            let config = base(&name)?.ok_or_else(|| {
+                ServicingError::General(format!("Base configuration {name} is not defined"))
+            })?;
+            chain.push(config);
+            names.push(name);
+        }
+        Ok(chain
+            .iter()
+            .rev()
+            .fold(UserProvidedConfig::default(), |under, over| {
+                under.overlay(over)
+            }))
+    }
 }
 
+#[cfg(feature = "python")]
 #[pymethods]
 impl UserProvidedConfig {
     #[new]
+    #[pyo3(signature = (port=None, replicas=None, cloud=None, workdir=None, data=None, disk_size=None, cpu=None, memory=None, accelerators=None, setup=None, run=None, namespace=None, labels=None, node_selector=None, tolerations=None, service_account=None, init_containers=None, sidecars=None, min_replicas=None, max_replicas=None, target_utilization=None, ttl_hours=None, base=None))]
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         port: Option<u16>,
@@ -33,8 +183,20 @@ impl UserProvidedConfig {
         accelerators: Option<String>,
         setup: Option<String>,
         run: Option<String>,
-    ) -> Self {
-        UserProvidedConfig {
+        namespace: Option<String>,
+        labels: Option<BTreeMap<String, String>>,
+        node_selector: Option<BTreeMap<String, String>>,
+        tolerations: Option<Vec<BTreeMap<String, String>>>,
+        service_account: Option<String>,
+        init_containers: Option<Vec<BTreeMap<String, String>>>,
+        sidecars: Option<Vec<BTreeMap<String, String>>>,
+        min_replicas: Option<u16>,
+        max_replicas: Option<u16>,
+        target_utilization: Option<u16>,
+        ttl_hours: Option<u16>,
+        base: Option<String>,
+    ) -> Result<Self, ServicingError> {
+        let config = UserProvidedConfig {
             port,
             replicas,
             cloud,
@@ -46,17 +208,155 @@ impl UserProvidedConfig {
             accelerators,
             setup,
             run,
-        }
+            namespace,
+            labels,
+            node_selector,
+            tolerations,
+            service_account,
+            init_containers,
+            sidecars,
+            min_replicas,
+            max_replicas,
+            target_utilization,
+            ttl_hours,
+            base,
+        };
+        config.check()?;
+        Ok(config)
+    }
+
+    /// __repr__ lists the fields that are set, e.g. `UserProvidedConfig(port=8080, cloud='aws')`.
+    fn __repr__(&self) -> String {
+        let strings = [
+            ("base", &self.base),
+            ("cloud", &self.cloud),
+            ("workdir", &self.workdir),
+            ("data", &self.data),
+            ("cpu", &self.cpu),
+            ("memory", &self.memory),
+            ("accelerators", &self.accelerators),
+            ("setup", &self.setup),
+            ("run", &self.run),
+            ("namespace", &self.namespace),
+            ("service_account", &self.service_account),
+        ];
+        let maps = [
+            ("labels", &self.labels),
+            ("node_selector", &self.node_selector),
+        ];
+        let lists = [
+            ("tolerations", &self.tolerations),
+            ("init_containers", &self.init_containers),
+            ("sidecars", &self.sidecars),
+        ];
+        let numbers = [
+            ("port", self.port),
+            ("replicas", self.replicas),
+            ("disk_size", self.disk_size),
+            ("min_replicas", self.min_replicas),
+            ("max_replicas", self.max_replicas),
+            ("target_utilization", self.target_utilization),
+            ("ttl_hours", self.ttl_hours),
+        ];
+        let fields: Vec<String> = numbers
+            .iter()
+            .filter_map(|(k, v)| v.map(|v| format!("{k}={v}")))
+            .chain(
+                strings
+                    .iter()
+                    .filter_map(|(k, v)| v.as_ref().map(|v| format!("{k}={}", py_str(v)))),
+            )
+            .chain(
+                maps.iter()
+                    .filter_map(|(k, v)| v.as_ref().map(|v| format!("{k}={}", py_dict(v)))),
+            )
+            .chain(lists.iter().filter_map(|(k, v)| {
+                let v: Vec<String> = v.as_ref()?.iter().map(py_dict).collect();
+                Some(format!("{k}=[{}]", v.join(", ")))
+            }))
+            .collect();
+        format!("UserProvidedConfig({})", fields.join(", "))
+    }
+
+    fn __str__(&self) -> String {
+        self.__repr__()
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
     }
+
+    fn __hash__(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// py_str quotes a string the way Python's repr does.
+pub fn py_str(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// py_dict formats a map of strings the way Python's repr does.
+fn py_dict(map: &BTreeMap<String, String>) -> String {
+    let entries: Vec<String> = map
+        .iter()
+        .map(|(k, v)| format!("{}: {}", py_str(k), py_str(v)))
+        .collect();
+    format!("{{{}}}", entries.join(", "))
+}
+
+#[derive(Clone, Deserialize, Debug)]
 pub struct Configuration {
     pub service: Service,
     pub resources: Resources,
     pub workdir: String,
     pub setup: String,
     pub run: String,
+    /// The original YAML document this configuration was imported from, if any. Fields that
+    /// servicing does not model are carried here and merged back in when rendering.
+    #[serde(default)]
+    pub passthrough: Option<String>,
+    /// options of the pods when launched on Kubernetes
+    #[serde(default)]
+    pub kubernetes: Option<Kubernetes>,
+    /// environment variables of the service
+    #[serde(default)]
+    pub envs: BTreeMap<String, String>,
+}
+
+impl Serialize for Configuration {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::ser::Serializer,
+    {
+        // passthrough is only kept in the binary cache, it is merged into the YAML by render
+        let should_serialize = !serializer.is_human_readable();
+
+        let mut stats = serializer.serialize_struct("Configuration", 8)?;
+        stats.serialize_field("service", &self.service)?;
+        stats.serialize_field("resources", &self.resources)?;
+        stats.serialize_field("workdir", &self.workdir)?;
+        stats.serialize_field("setup", &self.setup)?;
+        stats.serialize_field("run", &self.run)?;
+        if should_serialize {
+            stats.serialize_field("passthrough", &self.passthrough)?;
+            stats.serialize_field("kubernetes", &self.kubernetes)?;
+            stats.serialize_field("envs", &self.envs)?;
+        } else {
+            if !self.envs.is_empty() {
+                stats.serialize_field("envs", &self.envs)?;
+            }
+            let mig = self.resources.mig();
+            if self.kubernetes.is_some() || mig.is_some() {
+                // rendered as the task config SkyPilot merges into the pods
+                let kubernetes = self.kubernetes.clone().unwrap_or_default();
+                stats.serialize_field("config", &kubernetes.config(mig.as_ref()))?;
+            }
+        }
+        stats.end()
+    }
 }
 
 impl Configuration {
@@ -91,21 +391,239 @@ impl Configuration {
         if let Some(run) = &config.run {
             self.run = run.clone();
         }
+
+        let kubernetes = Kubernetes {
+            namespace: config.namespace.clone(),
+            labels: config.labels.clone().unwrap_or_default(),
+            node_selector: config.node_selector.clone().unwrap_or_default(),
+            tolerations: config.tolerations.clone().unwrap_or_default(),
+            service_account: config.service_account.clone(),
+            init_containers: config.init_containers.clone().unwrap_or_default(),
+            sidecars: config.sidecars.clone().unwrap_or_default(),
+            autoscaling: config.max_replicas.map(|max_replicas| Autoscaling {
+                min_replicas: config
+                    .min_replicas
+                    .unwrap_or(self.service.replicas)
+                    .clamp(1, max_replicas),
+                max_replicas,
+                target_utilization: config
+                    .target_utilization
+                    .unwrap_or(kubernetes::DEFAULT_TARGET_UTILIZATION),
+            }),
+        };
+        if !kubernetes.is_empty() {
+            self.kubernetes
+                .get_or_insert_with(Kubernetes::default)
+                .merge(kubernetes);
+        }
     }
 
     #[allow(dead_code)]
     pub fn test_config() -> Configuration {
         test_config()
     }
+
+    /// from_template parses a default template: a YAML document overriding any part of the
+    /// built-in default configuration, e.g. only the setup script.
+    pub fn from_template(content: &str) -> Result<Configuration, ServicingError> {
+        let overlay: Value = serde_yaml::from_str(content)?;
+        if !overlay.is_mapping() {
+            return Err(ServicingError::General(
+                "Default template must be a mapping".to_string(),
+            ));
+        }
+        let mut base = serde_yaml::to_value(Configuration::default())?;
+        merge_yaml(&mut base, overlay);
+        Ok(serde_yaml::from_value(base)?)
+    }
+
+    /// from_sky_yaml parses a SkyPilot serve YAML into a Configuration. Fields servicing knows
+    /// about are lifted into the struct, everything else is preserved as passthrough.
+    pub fn from_sky_yaml(content: &str) -> Result<Configuration, ServicingError> {
+        let doc: Value = serde_yaml::from_str(content)?;
+        if !doc.is_mapping() {
+            return Err(ServicingError::General(
+                "SkyPilot YAML must be a mapping".to_string(),
+            ));
+        }
+
+        let mut config = Configuration::imported();
+
+        if let Some(probe) = doc.get("service").and_then(|s| s.get("readiness_probe")) {
+            // readiness_probe is either a path or a mapping with a path key
+            if let Some(path) = yaml_string(probe).or(probe.get("path").and_then(yaml_string)) {
+                config.service.readiness_probe = path;
+            }
+        }
+        if let Some(service) = doc.get("service") {
+            if let Some(replicas) = service.get("replicas").and_then(Value::as_u64) {
+                config.service.replicas = replicas as u16;
+            } else if let Some(replicas) = service
+                .get("replica_policy")
+                .and_then(|p| p.get("min_replicas"))
+                .and_then(Value::as_u64)
+            {
+                config.service.replicas = replicas as u16;
+            }
+        }
+
+        if let Some(resources) = doc.get("resources") {
+            if let Some(ports) = resources.get("ports") {
+                // ports can be a single port or a list of them, servicing only tracks the first
+                let port = match ports {
+                    Value::Sequence(ports) => ports.first().and_then(yaml_string),
+                    _ => yaml_string(ports),
+                };
+                if let Some(port) = port {
+                    config.resources.ports = port.parse().map_err(|_| {
+                        ServicingError::General(format!("Invalid port in SkyPilot YAML: {port}"))
+                    })?;
+                }
+            }
+            if let Some(cloud) = resources.get("cloud").and_then(yaml_string) {
+                config.resources.cloud = cloud;
+            }
+            if let Some(cpus) = resources.get("cpus").and_then(yaml_string) {
+                config.resources.cpus = cpus;
+            }
+            if let Some(memory) = resources.get("memory").and_then(yaml_string) {
+                config.resources.memory = memory;
+            }
+            if let Some(disk_size) = resources.get("disk_size").and_then(Value::as_u64) {
+                config.resources.disk_size = disk_size as u16;
+            }
+            if let Some(accelerators) = resources.get("accelerators") {
+                config.resources.accelerators = match accelerators {
+                    // {A100: 1} is equivalent to A100:1
+                    Value::Mapping(map) => map.iter().next().and_then(|(k, v)| {
+                        Some(format!("{}:{}", yaml_string(k)?, yaml_string(v)?))
+                    }),
+                    _ => yaml_string(accelerators),
+                };
+            }
+        }
+
+        config.workdir = doc
+            .get("workdir")
+            .and_then(yaml_string)
+            .unwrap_or_else(|| ".".to_string());
+        config.setup = doc.get("setup").and_then(yaml_string).unwrap_or_default();
+        config.run = doc.get("run").and_then(yaml_string).unwrap_or_default();
+        config.passthrough = Some(content.to_string());
+
+        Ok(config)
+    }
+
+    /// render produces the YAML handed to the cluster orchestrator, merging any passthrough
+    /// fields underneath the ones servicing manages. The fields an imported YAML leaves out are
+    /// only rendered once they no longer hold the value the import gave them.
+    pub fn render(&self) -> Result<String, ServicingError> {
+        let mut managed = serde_yaml::to_value(self)?;
+        let rendered = match &self.passthrough {
+            Some(passthrough) => {
+                let mut base: Value = serde_yaml::from_str(passthrough)?;
+                let imported = serde_yaml::to_value(Configuration::imported())?;
+                drop_unset(&mut managed, &base, &imported);
+                // a readiness_probe mapping keeps its other keys, servicing only manages the path
+                let probe = base
+                    .get("service")
+                    .and_then(|s| s.get("readiness_probe"))
+                    .is_some_and(Value::is_mapping);
+                if let Some(path) = managed
+                    .get_mut("service")
+                    .and_then(|s| s.get_mut("readiness_probe"))
+                    .filter(|_| probe)
+                {
+                    let mut mapping = Mapping::new();
+                    mapping.insert("path".into(), path.clone());
+                    *path = Value::Mapping(mapping);
+                }
+                merge_yaml(&mut base, managed);
+                // SkyPilot refuses replicas along with a replica_policy, whose minimum it is
+                if let Some(service) = base.get_mut("service").and_then(Value::as_mapping_mut) {
+                    if service.contains_key("replica_policy") {
+                        if let Some(replicas) = service.remove("replicas") {
+                            if let Some(policy) = service
+                                .get_mut("replica_policy")
+                                .and_then(Value::as_mapping_mut)
+                            {
+                                policy.insert("min_replicas".into(), replicas);
+                            }
+                        }
+                    }
+                }
+                base
+            }
+            None => managed,
+        };
+        Ok(serde_yaml::to_string(&rendered)?)
+    }
+
+    /// imported is the configuration from_sky_yaml starts from, before the fields of the YAML.
+    fn imported() -> Configuration {
+        Configuration {
+            setup: String::new(),
+            run: String::new(),
+            ..Configuration::default()
+        }
+    }
+}
+
+/// yaml_string returns scalar YAML values as strings, so `cpus: 4` and `cpus: "4+"` both work.
+fn yaml_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// drop_unset removes from managed the fields source leaves out that still hold their value in
+/// defaults, so that rendering an imported YAML does not add fields it did not set.
+fn drop_unset(managed: &mut Value, source: &Value, defaults: &Value) {
+    let Value::Mapping(managed) = managed else {
+        return;
+    };
+    managed.retain(|key, value| {
+        let set = source.get(key);
+        let default = defaults.get(key);
+        if value.is_mapping() {
+            drop_unset(
+                value,
+                set.unwrap_or(&Value::Null),
+                default.unwrap_or(&Value::Null),
+            );
+            return set.is_some() || value.as_mapping().is_some_and(|m| !m.is_empty());
+        }
+        set.is_some() || default != Some(value)
+    });
+}
+
+/// merge_yaml recursively merges overlay into base, overlay wins on conflicts.
+fn merge_yaml(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Mapping(base), Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_yaml(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Service {
     pub readiness_probe: String,
     pub replicas: u16,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Clone, Deserialize, Debug)]
 pub struct Resources {
     pub ports: u16,
     pub cloud: String,
@@ -113,6 +631,9 @@ pub struct Resources {
     pub memory: String,
     pub disk_size: u16,
     pub accelerators: Option<String>,
+    /// labels SkyPilot tags the instances or pods of the service with
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
 }
 
 impl Serialize for Resources {
@@ -120,9 +641,11 @@ impl Serialize for Resources {
     where
         S: serde::ser::Serializer,
     {
-        let should_serialize = self.accelerators.is_some() || !serializer.is_human_readable();
+        let binary = !serializer.is_human_readable();
+        // MIG slices are requested through the pod config instead
+        let should_serialize = (self.accelerators.is_some() && self.mig().is_none()) || binary;
 
-        let mut stats = serializer.serialize_struct("Resources", 6)?;
+        let mut stats = serializer.serialize_struct("Resources", 7)?;
         stats.serialize_field("ports", &self.ports)?;
         stats.serialize_field("cloud", &self.cloud)?;
         stats.serialize_field("cpus", &self.cpus)?;
@@ -131,10 +654,22 @@ impl Serialize for Resources {
         if should_serialize {
             stats.serialize_field("accelerators", &self.accelerators)?;
         }
+        if binary || !self.labels.is_empty() {
+            stats.serialize_field("labels", &self.labels)?;
+        }
         stats.end()
     }
 }
 
+impl Resources {
+    /// mig returns the MIG slices requested by accelerators, if it is a valid MIG request.
+    pub fn mig(&self) -> Option<Mig> {
+        self.accelerators
+            .as_deref()
+            .and_then(|accelerators| Mig::parse(accelerators).ok().flatten())
+    }
+}
+
 impl Default for Configuration {
     fn default() -> Self {
         Configuration {
@@ -149,14 +684,477 @@ impl Default for Configuration {
                 accelerators: None,
                 cloud: "aws".to_string(),
                 disk_size: 100,
+                labels: BTreeMap::new(),
             },
             workdir: ".".to_string(),
             setup: "conda install cudatoolkit -y\n".to_string()
                 + "pip install poetry\n"
                 + "poetry install\n",
             run: "poetry run python service.py\n".to_string(),
+            passthrough: None,
+            kubernetes: None,
+            envs: BTreeMap::new(),
+        }
+    }
+}
+
+/// UpTimings records how long each phase of one up invocation took.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UpTimings {
+    /// when up was called
+    pub started: Timestamp,
+    pub phases: Vec<PhaseTiming>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PhaseTiming {
+    pub phase: String,
+    pub seconds: f64,
+}
+
+impl Default for UpTimings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UpTimings {
+    pub fn new() -> Self {
+        UpTimings {
+            started: Timestamp::now(),
+            phases: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, phase: &str, took: Duration) {
+        self.phases.push(PhaseTiming {
+            phase: phase.to_string(),
+            seconds: took.as_secs_f64(),
+        });
+    }
+}
+
+/// unix_now returns the seconds since the unix epoch.
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+thread_local! {
+    static DISPLAY_TZ: Cell<Option<Tz>> = const { Cell::new(None) };
+}
+
+/// in_timezone runs f with the timestamps it serializes rendered in tz instead of UTC.
+pub fn in_timezone<T>(tz: Option<Tz>, f: impl FnOnce() -> T) -> T {
+    let previous = DISPLAY_TZ.with(|cell| cell.replace(tz));
+    let result = f();
+    DISPLAY_TZ.with(|cell| cell.set(previous));
+    result
+}
+
+/// parse_timezone parses an IANA timezone name, e.g. "Europe/Zurich".
+pub fn parse_timezone(name: &str) -> Result<Tz, ServicingError> {
+    name.parse()
+        .map_err(|_| ServicingError::General(format!("Unknown timezone {name}")))
+}
+
+/// Timestamp is a point in time in seconds since the unix epoch, so it does not depend on the
+/// clock or timezone of whoever reads it. Human readable output renders it as RFC 3339, in UTC
+/// unless another timezone is set with in_timezone.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(transparent)]
+pub struct Timestamp(pub u64);
+
+impl Timestamp {
+    pub fn now() -> Self {
+        Timestamp(unix_now())
+    }
+
+    /// parse reads an RFC 3339 time, or a YYYY-MM-DD date meaning its midnight in tz, UTC if None.
+    pub fn parse(time: &str, tz: Option<Tz>) -> Result<Self, ServicingError> {
+        let seconds = match DateTime::parse_from_rfc3339(time) {
+            Ok(time) => Some(time.timestamp()),
+            Err(_) => NaiveDate::parse_from_str(time, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| midnight(date, tz)),
+        };
+        seconds
+            .and_then(|seconds| u64::try_from(seconds).ok())
+            .map(Timestamp)
+            .ok_or(ServicingError::General(format!(
+                "Invalid time {time}, expected RFC 3339 or YYYY-MM-DD"
+            )))
+    }
+
+    /// month_start returns the first midnight of the current month in tz, UTC if None.
+    pub fn month_start(tz: Option<Tz>) -> Self {
+        let now = Utc::now();
+        let today = match tz {
+            Some(tz) => now.with_timezone(&tz).date_naive(),
+            None => now.date_naive(),
+        };
+        let seconds = today
+            .with_day(1)
+            .and_then(|date| midnight(date, tz))
+            .unwrap_or_default();
+        Timestamp(u64::try_from(seconds).unwrap_or_default())
+    }
+
+    /// rfc3339 renders the timestamp in tz, UTC if None.
+    pub fn rfc3339(&self, tz: Option<Tz>) -> String {
+        let utc = DateTime::<Utc>::from_timestamp(self.0 as i64, 0).unwrap_or_default();
+        match tz {
+            Some(tz) => utc
+                .with_timezone(&tz)
+                .to_rfc3339_opts(SecondsFormat::Secs, true),
+            None => utc.to_rfc3339_opts(SecondsFormat::Secs, true),
+        }
+    }
+}
+
+/// midnight returns the start of date in tz, UTC if None, in seconds since the unix epoch.
+fn midnight(date: NaiveDate, tz: Option<Tz>) -> Option<i64> {
+    let naive = date.and_hms_opt(0, 0, 0)?;
+    match tz {
+        Some(tz) => Some(tz.from_local_datetime(&naive).earliest()?.timestamp()),
+        None => Some(naive.and_utc().timestamp()),
+    }
+}
+
+impl Serialize for Timestamp {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::ser::Serializer,
+    {
+        match serializer.is_human_readable() {
+            true => serializer.serialize_str(&self.rfc3339(DISPLAY_TZ.with(Cell::get))),
+            false => serializer.serialize_u64(self.0),
+        }
+    }
+}
+
+/// ApiKey is a key issued to a service. It is only written out in the binary cache, human
+/// readable output such as status shows it redacted.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(transparent)]
+pub struct ApiKey(pub String);
+
+impl Serialize for ApiKey {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::ser::Serializer,
+    {
+        match serializer.is_human_readable() {
+            true => serializer.serialize_str("<redacted>"),
+            false => serializer.serialize_str(&self.0),
+        }
+    }
+}
+
+/// Preemption records a spot replica of a service being reclaimed by the cloud.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Preemption {
+    pub replica: u32,
+    /// when the preemption was detected
+    pub detected: Timestamp,
+}
+
+/// Maintenance marks a service as intentionally degraded.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Maintenance {
+    pub reason: Option<String>,
+    /// when maintenance started
+    pub since: Timestamp,
+}
+
+impl Maintenance {
+    /// message is what clients of the service are told while it is under maintenance.
+    pub fn message(&self, name: &str) -> String {
+        match &self.reason {
+            Some(reason) => format!("Service {name} is under maintenance: {reason}"),
+            None => format!("Service {name} is under maintenance"),
+        }
+    }
+}
+
+/// ServiceStatus is a summary of the state of a service, rendered as a table in notebooks and
+/// served by the gateway at /services.json.
+#[cfg_attr(feature = "python", pyclass(get_all))]
+#[derive(Clone, Debug, Serialize)]
+pub struct ServiceStatus {
+    pub name: String,
+    /// down, provisioning, starting, up, maintenance, unhealthy or paused
+    pub state: String,
+    pub url: Option<String>,
+    pub replicas: u16,
+    /// replicas serving requests, 0 unless the service is up
+    pub ready_replicas: u16,
+    /// seconds since the service was launched, None when it is down
+    pub uptime: Option<u64>,
+    /// estimated spend in dollars
+    pub cost: f64,
+    pub hourly_cost: Option<f64>,
+    /// error of the last operation on the service, None if it succeeded
+    pub last_error: Option<String>,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl ServiceStatus {
+    fn __repr__(&self) -> String {
+        let url = self
+            .url
+            .as_deref()
+            .map(py_str)
+            .unwrap_or("None".to_string());
+        format!(
+            "ServiceStatus(name={}, state={}, url={}, replicas={}, uptime={}, cost={:.2})",
+            py_str(&self.name),
+            py_str(&self.state),
+            url,
+            self.replicas,
+            self.uptime.map(format_uptime).unwrap_or("-".to_string()),
+            self.cost
+        )
+    }
+
+    /// _repr_html_ renders the status as a table in Jupyter and IPython.
+    fn _repr_html_(&self) -> String {
+        html_table(std::slice::from_ref(self))
+    }
+}
+
+/// OperationResult describes what add_service, up or down did. It is always truthy, so callers
+/// checking the former None return value keep working.
+#[cfg_attr(feature = "python", pyclass(get_all))]
+#[derive(Clone, Debug)]
+pub struct OperationResult {
+    /// add_service, up or down
+    pub operation: String,
+    pub service: String,
+    /// configuration rendered for the orchestrator
+    pub filepath: Option<String>,
+    pub endpoint: Option<String>,
+    /// seconds the operation took
+    pub duration: f64,
+    pub orchestrator: String,
+    /// name of the service in the orchestrator, once it knows about it
+    pub orchestrator_id: Option<String>,
+    /// replica groups handled along with the service
+    pub groups: Vec<String>,
+    /// id of the up or down, None for the other operations
+    pub operation_id: Option<String>,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl OperationResult {
+    fn __repr__(&self) -> String {
+        let optional = |v: &Option<String>| v.as_deref().map(py_str).unwrap_or("None".to_string());
+        let mut repr = format!(
+            "OperationResult(operation={}, service={}, filepath={}, endpoint={}, duration={:.1}, \
+             orchestrator={}, orchestrator_id={}",
+            py_str(&self.operation),
+            py_str(&self.service),
+            optional(&self.filepath),
+            optional(&self.endpoint),
+            self.duration,
+            py_str(&self.orchestrator),
+            optional(&self.orchestrator_id),
+        );
+        if !self.groups.is_empty() {
+            let groups: Vec<String> = self.groups.iter().map(|g| py_str(g)).collect();
+            repr.push_str(&format!(", groups=[{}]", groups.join(", ")));
+        }
+        if let Some(id) = &self.operation_id {
+            repr.push_str(&format!(", operation_id={}", py_str(id)));
+        }
+        repr.push(')');
+        repr
+    }
+
+    fn __bool__(&self) -> bool {
+        true
+    }
+}
+
+/// html_table renders the statuses as an HTML table, one service per row.
+pub fn html_table(statuses: &[ServiceStatus]) -> String {
+    let mut html = String::from(
+        "<table><thead><tr><th>Service</th><th>State</th><th>URL</th><th>Replicas</th>\
+         <th>Uptime</th><th>Cost</th></tr></thead><tbody>",
+    );
+    for status in statuses {
+        let cost = match status.hourly_cost {
+            Some(hourly_cost) => format!("${:.2} (${hourly_cost:.2}/h)", status.cost),
+            None => format!("${:.2}", status.cost),
+        };
+        html.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            html_escape(&status.name),
+            html_escape(&status.state),
+            html_escape(status.url.as_deref().unwrap_or("-")),
+            status.replicas,
+            status.uptime.map(format_uptime).unwrap_or("-".to_string()),
+            html_escape(&cost),
+        ));
+    }
+    html.push_str("</tbody></table>");
+    html
+}
+
+/// format_uptime renders seconds as e.g. `2d 3h 4m`, or `42s` under a minute.
+fn format_uptime(seconds: u64) -> String {
+    let (days, hours, minutes) = (seconds / 86400, seconds % 86400 / 3600, seconds % 3600 / 60);
+    match (days, hours, minutes) {
+        (0, 0, 0) => format!("{seconds}s"),
+        (0, 0, _) => format!("{minutes}m"),
+        (0, _, _) => format!("{hours}h {minutes}m"),
+        _ => format!("{days}d {hours}h {minutes}m"),
+    }
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// ProbeMethod is how the readiness endpoint is polled, for services billing per request.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProbeMethod {
+    /// the body is fetched every time
+    #[default]
+    Get,
+    /// only the status code is fetched, GET is used if the endpoint does not support HEAD
+    Head,
+    /// the body is only fetched when it changed according to ETag or Last-Modified
+    Conditional,
+}
+
+impl ProbeMethod {
+    pub const NAMES: &'static [&'static str] = &["get", "head", "conditional"];
+}
+
+impl FromStr for ProbeMethod {
+    type Err = ServicingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "get" => Ok(ProbeMethod::Get),
+            "head" => Ok(ProbeMethod::Head),
+            "conditional" => Ok(ProbeMethod::Conditional),
+            _ => Err(ServicingError::General(format!(
+                "Unknown probe method {s}, expected get, head or conditional"
+            ))),
+        }
+    }
+}
+
+/// ProbeCache holds the validators of the last response of a readiness endpoint and the verdict
+/// on it, reused when a conditional request is answered 304 Not Modified.
+#[derive(Debug, Clone, Default)]
+pub struct ProbeCache {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub ready: Option<bool>,
+}
+
+/// HealthCheck validates the JSON body of the readiness endpoint with a JSONPath expression,
+/// e.g. `$.model_loaded == true`. Without an expected value the path must hold a truthy value.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HealthCheck {
+    pub path: String,
+    /// expected value as JSON
+    pub expected: Option<String>,
+}
+
+impl HealthCheck {
+    /// parse reads `path` or `path == value`, expected overriding the value of the expression.
+    pub fn parse(expression: &str, expected: Option<String>) -> Result<Self, ServicingError> {
+        let (path, value) = match expression.split_once("==") {
+            Some((path, value)) => (path.trim(), Some(value.trim().to_string())),
+            None => (expression.trim(), None),
+        };
+        parse_json_path(path)?;
+        let expected = expected.or(value).map(|value| {
+            // bare words are taken as strings
+            match serde_json::from_str::<serde_json::Value>(&value) {
+                Ok(_) => value,
+                Err(_) => serde_json::Value::String(value).to_string(),
+            }
+        });
+        Ok(HealthCheck {
+            path: path.to_string(),
+            expected,
+        })
+    }
+
+    /// passes evaluates the check against a response body.
+    pub fn passes(&self, body: &str) -> bool {
+        let (Ok(doc), Ok(path)) = (
+            serde_json::from_str::<serde_json::Value>(body),
+            parse_json_path(&self.path),
+        ) else {
+            return false;
+        };
+        let mut found = Some(&doc);
+        for segment in &path {
+            found = found.and_then(|value| match segment {
+                PathSegment::Key(key) => value.get(key),
+                PathSegment::Index(index) => value.get(index),
+            });
+        }
+        match (found, &self.expected) {
+            (Some(value), Some(expected)) => {
+                serde_json::from_str::<serde_json::Value>(expected).is_ok_and(|e| &e == value)
+            }
+            (Some(value), None) => !matches!(
+                value,
+                serde_json::Value::Null | serde_json::Value::Bool(false)
+            ),
+            (None, _) => false,
+        }
+    }
+}
+
+enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+/// parse_json_path parses the JSONPath subset used by health checks: `$`, `.key`, `['key']` and
+/// `[index]`.
+fn parse_json_path(path: &str) -> Result<Vec<PathSegment>, ServicingError> {
+    let invalid = || ServicingError::General(format!("Invalid JSONPath expression {path}"));
+    let mut rest = path.strip_prefix('$').ok_or_else(invalid)?;
+    let mut segments = Vec::new();
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            if end == 0 {
+                return Err(invalid());
+            }
+            segments.push(PathSegment::Key(after[..end].to_string()));
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']').ok_or_else(invalid)?;
+            let inner = after[..end].trim();
+            segments.push(match inner.parse() {
+                Ok(index) => PathSegment::Index(index),
+                Err(_) => {
+                    PathSegment::Key(inner.trim_matches(|c| c == '\'' || c == '"').to_string())
+                }
+            });
+            rest = &after[end + 1..];
+        } else {
+            return Err(invalid());
         }
     }
+    Ok(segments)
 }
 
 #[inline]
@@ -173,9 +1171,296 @@ pub fn test_config() -> Configuration {
             accelerators: None,
             cloud: "aws".to_string(),
             disk_size: 50,
+            labels: BTreeMap::new(),
         },
         setup: "".to_string(),
         workdir: ".".to_string(),
         run: "python -m http.server 8080\n".to_string(),
+        passthrough: None,
+        kubernetes: None,
+        envs: BTreeMap::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        html_table, in_timezone, parse_timezone, Configuration, HealthCheck, ServiceStatus,
+        Timestamp, UserProvidedConfig,
+    };
+
+    #[test]
+    fn test_html_table() {
+        let status = ServiceStatus {
+            name: "<llm>".to_string(),
+            state: "up".to_string(),
+            url: Some("1.2.3.4:8080".to_string()),
+            replicas: 2,
+            ready_replicas: 2,
+            uptime: Some(3 * 3600 + 120),
+            cost: 7.5,
+            hourly_cost: Some(2.5),
+            last_error: None,
+        };
+        let html = html_table(&[status]);
+        assert!(html.contains("<td>&lt;llm&gt;</td><td>up</td><td>1.2.3.4:8080</td><td>2</td>"));
+        assert!(html.contains("<td>3h 2m</td><td>$7.50 ($2.50/h)</td>"));
+    }
+
+    #[test]
+    fn test_health_check() {
+        let body = r#"{"model_loaded": true, "status": "ok", "workers": [{"ready": 2}]}"#;
+        assert!(HealthCheck::parse("$.model_loaded == true", None)
+            .unwrap()
+            .passes(body));
+        assert!(HealthCheck::parse("$.status", Some("ok".to_string()))
+            .unwrap()
+            .passes(body));
+        assert!(HealthCheck::parse("$.workers[0]['ready'] == 2", None)
+            .unwrap()
+            .passes(body));
+        assert!(!HealthCheck::parse("$.model_loaded == false", None)
+            .unwrap()
+            .passes(body));
+        assert!(!HealthCheck::parse("$.missing", None).unwrap().passes(body));
+        assert!(!HealthCheck::parse("$.status", None)
+            .unwrap()
+            .passes("not json"));
+        assert!(HealthCheck::parse("model_loaded", None).is_err());
+    }
+
+    #[cfg(feature = "python")]
+    #[test]
+    fn test_user_provided_config_repr() {
+        let config = UserProvidedConfig::new(
+            Some(8080),
+            None,
+            Some("aws".to_string()),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some("echo 'hi'".to_string()),
+            None,
+            None,
+            Some([("gpu".to_string(), "a100".to_string())].into()),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(24),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            config.__repr__(),
+            "UserProvidedConfig(port=8080, ttl_hours=24, cloud='aws', run='echo \\'hi\\'', node_selector={'gpu': 'a100'})"
+        );
+        assert!(config.__eq__(&config.clone()));
+        assert_eq!(config.__hash__(), config.clone().__hash__());
+    }
+
+    #[test]
+    fn test_resolve_base() {
+        let gpu = UserProvidedConfig {
+            accelerators: Some("A100:1".to_string()),
+            replicas: Some(2),
+            labels: Some([("team".to_string(), "ml".to_string())].into()),
+            base: Some("default".to_string()),
+            ..UserProvidedConfig::default()
+        };
+        let default = UserProvidedConfig {
+            port: Some(8000),
+            replicas: Some(1),
+            ..UserProvidedConfig::default()
+        };
+        let bases = |name: &str| {
+            Ok(match name {
+                "gpu" => Some(gpu.clone()),
+                "default" => Some(default.clone()),
+                "loop" => Some(UserProvidedConfig {
+                    base: Some("loop".to_string()),
+                    ..UserProvidedConfig::default()
+                }),
+                _ => None,
+            })
+        };
+
+        let config = UserProvidedConfig {
+            replicas: Some(4),
+            labels: Some([("app".to_string(), "llm".to_string())].into()),
+            base: Some("gpu".to_string()),
+            ..UserProvidedConfig::default()
+        };
+        let resolved = config.resolve(bases).unwrap();
+        assert_eq!(resolved.port, Some(8000));
+        assert_eq!(resolved.accelerators.as_deref(), Some("A100:1"));
+        assert_eq!(resolved.replicas, Some(4));
+        assert_eq!(resolved.labels.unwrap().len(), 2);
+        assert_eq!(resolved.base.as_deref(), Some("gpu"));
+
+        let looping = UserProvidedConfig {
+            base: Some("loop".to_string()),
+            ..UserProvidedConfig::default()
+        };
+        assert!(looping.resolve(bases).is_err());
+        let unknown = UserProvidedConfig {
+            base: Some("cpu".to_string()),
+            ..UserProvidedConfig::default()
+        };
+        assert!(unknown.resolve(bases).is_err());
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let timestamp = Timestamp(1_700_000_000);
+        assert_eq!(
+            serde_json::to_string(&timestamp).unwrap(),
+            "\"2023-11-14T22:13:20Z\""
+        );
+        let zurich = parse_timezone("Europe/Zurich").unwrap();
+        assert_eq!(
+            in_timezone(Some(zurich), || serde_json::to_string(&timestamp)).unwrap(),
+            "\"2023-11-14T23:13:20+01:00\""
+        );
+        assert_eq!(
+            bincode::deserialize::<Timestamp>(&bincode::serialize(&timestamp).unwrap()).unwrap(),
+            timestamp
+        );
+        assert!(parse_timezone("Mars/Olympus").is_err());
+
+        assert_eq!(
+            Timestamp::parse("2023-11-14T22:13:20Z", None).unwrap(),
+            timestamp
+        );
+        assert_eq!(
+            Timestamp::parse("2023-11-15", Some(zurich)).unwrap(),
+            Timestamp(1_700_002_800)
+        );
+        assert!(Timestamp::parse("yesterday", None).is_err());
+    }
+
+    #[test]
+    fn test_kubernetes_config() {
+        let mut config = Configuration::default();
+        config.update(&UserProvidedConfig {
+            namespace: Some("ml".to_string()),
+            node_selector: Some([("gpu".to_string(), "a100".to_string())].into()),
+            service_account: Some("serving".to_string()),
+            sidecars: Some(vec![[
+                ("name".to_string(), "exporter".to_string()),
+                ("image".to_string(), "prom/exporter".to_string()),
+            ]
+            .into()]),
+            ..Default::default()
+        });
+
+        let rendered: serde_yaml::Value = serde_yaml::from_str(&config.render().unwrap()).unwrap();
+        let pod_config = &rendered["config"]["kubernetes"]["pod_config"];
+        assert_eq!(pod_config["metadata"]["namespace"].as_str(), Some("ml"));
+        assert_eq!(
+            pod_config["spec"]["nodeSelector"]["gpu"].as_str(),
+            Some("a100")
+        );
+        assert_eq!(
+            pod_config["spec"]["serviceAccountName"].as_str(),
+            Some("serving")
+        );
+        let sidecar = &pod_config["spec"]["initContainers"][0];
+        assert_eq!(sidecar["name"].as_str(), Some("exporter"));
+        assert_eq!(sidecar["restartPolicy"].as_str(), Some("Always"));
+    }
+
+    #[test]
+    fn test_default_template() {
+        let template = Configuration::from_template(
+            "setup: uv sync\nresources:\n  cloud: gcp\nservice:\n  replicas: 1\n",
+        )
+        .unwrap();
+        assert_eq!(template.setup, "uv sync");
+        assert_eq!(template.resources.cloud, "gcp");
+        assert_eq!(template.service.replicas, 1);
+        assert_eq!(template.service.readiness_probe, "/health");
+        assert_eq!(template.resources.ports, 8080);
+        assert!(Configuration::from_template("- gcp").is_err());
+    }
+
+    #[test]
+    fn test_sky_yaml_passthrough() {
+        let yaml = "service:\n  readiness_probe:\n    path: /ready\n  replicas: 3\n\
+                    resources:\n  ports: [9000]\n  cpus: 8\n  accelerators: {A100: 1}\n  use_spot: true\n\
+                    envs:\n  MODEL: llama\n\
+                    run: python app.py\n";
+        let mut config = Configuration::from_sky_yaml(yaml).unwrap();
+        config.envs.insert("PORT".to_string(), "9000".to_string());
+        assert_eq!(config.service.readiness_probe, "/ready");
+        assert_eq!(config.service.replicas, 3);
+        assert_eq!(config.resources.ports, 9000);
+        assert_eq!(config.resources.cpus, "8");
+        assert_eq!(config.resources.accelerators.as_deref(), Some("A100:1"));
+
+        let rendered: serde_yaml::Value = serde_yaml::from_str(&config.render().unwrap()).unwrap();
+        assert_eq!(rendered["envs"]["MODEL"].as_str(), Some("llama"));
+        assert_eq!(rendered["envs"]["PORT"].as_str(), Some("9000"));
+        assert_eq!(rendered["resources"]["use_spot"].as_bool(), Some(true));
+        assert_eq!(rendered["resources"]["ports"].as_u64(), Some(9000));
+        assert_eq!(
+            rendered["service"]["readiness_probe"]["path"].as_str(),
+            Some("/ready")
+        );
+    }
+
+    #[test]
+    fn test_sky_yaml_round_trip() {
+        let render = |config: &Configuration| -> serde_yaml::Value {
+            serde_yaml::from_str(&config.render().unwrap()).unwrap()
+        };
+
+        // only the fields of the source come out, however the defaults differ
+        let yaml =
+            "service:\n  readiness_probe: /\nresources:\n  ports: 8000\nrun: python app.py\n";
+        let rendered = render(&Configuration::from_sky_yaml(yaml).unwrap());
+        assert_eq!(
+            rendered,
+            serde_yaml::from_str::<serde_yaml::Value>(yaml).unwrap()
+        );
+
+        // the keys of a readiness_probe mapping are kept along with the managed path
+        let yaml =
+            "service:\n  readiness_probe:\n    path: /ready\n    initial_delay_seconds: 1200\n\
+                    \x20   post_data: {model: llama}\n  replicas: 2\nresources:\n  ports: 8000\n";
+        let mut config = Configuration::from_sky_yaml(yaml).unwrap();
+        let rendered = render(&config);
+        assert_eq!(
+            rendered,
+            serde_yaml::from_str::<serde_yaml::Value>(yaml).unwrap()
+        );
+        config.service.readiness_probe = "/v1/models".to_string();
+        let probe = &render(&config)["service"]["readiness_probe"];
+        assert_eq!(probe["path"].as_str(), Some("/v1/models"));
+        assert_eq!(probe["initial_delay_seconds"].as_u64(), Some(1200));
+        assert_eq!(probe["post_data"]["model"].as_str(), Some("llama"));
+
+        // replicas are the minimum of a replica_policy, never rendered next to it
+        let yaml = "service:\n  readiness_probe: /\n  replica_policy:\n    min_replicas: 1\n\
+                    \x20   max_replicas: 4\n    target_qps_per_replica: 2.5\nresources:\n  ports: 8000\n";
+        let mut config = Configuration::from_sky_yaml(yaml).unwrap();
+        assert_eq!(config.service.replicas, 1);
+        let rendered = render(&config);
+        assert_eq!(
+            rendered,
+            serde_yaml::from_str::<serde_yaml::Value>(yaml).unwrap()
+        );
+        config.service.replicas = 3;
+        let service = &render(&config)["service"];
+        assert!(service.get("replicas").is_none());
+        assert_eq!(service["replica_policy"]["min_replicas"].as_u64(), Some(3));
+        assert_eq!(service["replica_policy"]["max_replicas"].as_u64(), Some(4));
     }
 }
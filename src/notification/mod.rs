@@ -0,0 +1,173 @@
+//! Notification module houses the built-in sinks posting the outcome of the operations on a
+//! service to Slack or Microsoft Teams incoming webhooks.
+use std::{path::Path, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{error::ServicingError, helper};
+
+static NOTIFICATIONS_FILE: &str = "notifications.json";
+static DEFAULT_TEMPLATE: &str = "Service {service} is {state} after {operation}{url}{cost}{error}";
+
+/// SinkKind is the flavour of incoming webhook a sink posts to.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SinkKind {
+    Slack,
+    Teams,
+}
+
+impl FromStr for SinkKind {
+    type Err = ServicingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "slack" => Ok(SinkKind::Slack),
+            "teams" => Ok(SinkKind::Teams),
+            _ => Err(ServicingError::General(format!(
+                "Unknown notification sink {s}, expected slack or teams"
+            ))),
+        }
+    }
+}
+
+impl SinkKind {
+    /// detect recognises the kind of webhook from its url.
+    pub fn detect(url: &str) -> Option<SinkKind> {
+        if url.contains("hooks.slack.com") {
+            Some(SinkKind::Slack)
+        } else if url.contains(".office.com") || url.contains(".logic.azure.com") {
+            Some(SinkKind::Teams)
+        } else {
+            None
+        }
+    }
+}
+
+/// Sink is a webhook notified of the operations on services.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Sink {
+    pub kind: SinkKind,
+    pub url: String,
+    /// message with {service}, {operation}, {state}, {url}, {cost} and {error} placeholders
+    pub template: Option<String>,
+    /// operations notified, all of them if None
+    pub operations: Option<Vec<String>>,
+}
+
+/// Message is what happened to a service, rendered into the template of a sink.
+pub struct Message<'a> {
+    pub service: &'a str,
+    pub operation: &'a str,
+    pub state: &'a str,
+    pub url: Option<&'a str>,
+    /// estimated spend in dollars
+    pub cost: Option<f64>,
+    pub error: Option<String>,
+}
+
+impl Sink {
+    pub fn applies_to(&self, operation: &str) -> bool {
+        self.operations
+            .as_ref()
+            .is_none_or(|operations| operations.iter().any(|o| o == operation))
+    }
+
+    /// payload returns the JSON body posted to the webhook for message.
+    pub fn payload(&self, message: &Message) -> Value {
+        let text = render(self.template.as_deref(), message);
+        match self.kind {
+            SinkKind::Slack => json!({ "text": text }),
+            SinkKind::Teams => json!({
+                "@type": "MessageCard",
+                "@context": "http://schema.org/extensions",
+                "summary": format!("{} {}", message.service, message.state),
+                "themeColor": if message.error.is_some() { "D70000" } else { "0076D7" },
+                "text": text,
+            }),
+        }
+    }
+}
+
+/// render fills the placeholders of template, the default one if None. In the default template
+/// the optional parts come with their label, in custom ones they are the bare values.
+fn render(template: Option<&str>, message: &Message) -> String {
+    let (template, url, cost, error) = match template {
+        Some(template) => (
+            template,
+            message.url.unwrap_or_default().to_string(),
+            message.cost.map(|c| format!("{c:.2}")).unwrap_or_default(),
+            message.error.clone().unwrap_or_default(),
+        ),
+        None => (
+            DEFAULT_TEMPLATE,
+            message.url.map(|u| format!(", at {u}")).unwrap_or_default(),
+            message
+                .cost
+                .filter(|cost| *cost > 0.0)
+                .map(|c| format!(", ${c:.2} spent"))
+                .unwrap_or_default(),
+            message
+                .error
+                .as_ref()
+                .map(|e| format!(": {e}"))
+                .unwrap_or_default(),
+        ),
+    };
+    template
+        .replace("{service}", message.service)
+        .replace("{operation}", message.operation)
+        .replace("{state}", message.state)
+        .replace("{url}", &url)
+        .replace("{cost}", &cost)
+        .replace("{error}", &error)
+}
+
+/// load reads the sinks notified for every service, kept in dir.
+pub fn load(dir: &Path) -> Result<Vec<Sink>, ServicingError> {
+    let path = dir.join(NOTIFICATIONS_FILE);
+    match path.exists() {
+        true => Ok(serde_json::from_str(&helper::read_from_file(&path)?)?),
+        false => Ok(Vec::new()),
+    }
+}
+
+/// save keeps the sinks notified for every service in dir.
+pub fn save(dir: &Path, sinks: &[Sink]) -> Result<(), ServicingError> {
+    let path = dir.join(NOTIFICATIONS_FILE);
+    helper::write_to_file(&path, &serde_json::to_string_pretty(sinks)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Message, Sink, SinkKind};
+
+    #[test]
+    fn test_payload() {
+        let message = Message {
+            service: "llm",
+            operation: "up",
+            state: "up",
+            url: Some("http://10.0.0.1:8080"),
+            cost: Some(1.5),
+            error: None,
+        };
+        let mut sink = Sink {
+            kind: SinkKind::detect("https://hooks.slack.com/services/T/B/X").unwrap(),
+            url: "https://hooks.slack.com/services/T/B/X".to_string(),
+            template: None,
+            operations: Some(vec!["up".to_string()]),
+        };
+        assert_eq!(
+            sink.payload(&message)["text"],
+            "Service llm is up after up, at http://10.0.0.1:8080, $1.50 spent"
+        );
+        assert!(!sink.applies_to("down"));
+
+        sink.kind = SinkKind::Teams;
+        sink.template = Some("{service}: {state} ({cost}$)".to_string());
+        let payload = sink.payload(&message);
+        assert_eq!(payload["@type"], "MessageCard");
+        assert_eq!(payload["text"], "llm: up (1.50$)");
+    }
+}
@@ -0,0 +1,183 @@
+//! Operation module houses the records of the operations running on the services. Every up and
+//! down gets an id and is recorded in the folder of the service before it runs, the record being
+//! cleared once the operation is over. A record left behind by a dead process tells of an
+//! interrupted operation whose outcome the registry may not reflect. The record is a write-ahead
+//! journal: the phase reached is written before each phase runs, telling how far it got. An
+//! operation stopped by SIGINT keeps its record, marked interrupted, for recover to pick up.
+use std::{
+    fs,
+    process::Command,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{error::ServicingError, helper, layout, models};
+
+static OPERATION_FILE: &str = "operation.json";
+/// age after which the operation of a process that cannot be checked is considered interrupted
+static STALE_AFTER: u64 = 6 * 3600;
+
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Operation is an up or down running on a service.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Operation {
+    pub id: String,
+    pub service: String,
+    /// up or down
+    pub operation: String,
+    pub started: u64,
+    pub pid: u32,
+    pub host: String,
+    /// phase entered last, pending until the first one starts
+    #[serde(default = "pending")]
+    pub phase: String,
+    /// whether the operation was stopped by SIGINT, its process living on
+    #[serde(default)]
+    pub interrupted: bool,
+}
+
+impl Operation {
+    pub fn new(service: &str, operation: &str) -> Operation {
+        let started = models::unix_now();
+        let pid = std::process::id();
+        Operation {
+            id: format!(
+                "{started:x}-{pid:x}-{:x}",
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            ),
+            service: service.to_string(),
+            operation: operation.to_string(),
+            started,
+            pid,
+            host: helper::current_host(),
+            phase: pending(),
+            interrupted: false,
+        }
+    }
+
+    /// in_flight tells whether the process running the operation is alive and still running it.
+    /// The processes of other hosts cannot be checked, their operations are in flight until they
+    /// are stale.
+    pub fn in_flight(&self) -> bool {
+        if self.interrupted {
+            return false;
+        }
+        if self.pid == std::process::id() && self.host == helper::current_host() {
+            return true;
+        }
+        if cfg!(unix) && self.host == helper::current_host() {
+            return Command::new("kill")
+                .arg("-0")
+                .arg(self.pid.to_string())
+                .output()
+                .is_ok_and(|output| output.status.success());
+        }
+        models::unix_now().saturating_sub(self.started) < STALE_AFTER
+    }
+
+    /// record writes the operation to the folder of its service before it runs.
+    pub fn record(&self) -> Result<(), ServicingError> {
+        let path = layout::service_dir(&self.service)?.join(OPERATION_FILE);
+        helper::write_atomic(&path, &serde_json::to_vec_pretty(self)?, false)?;
+        Ok(())
+    }
+
+    /// clear removes the record of the operation once it is over.
+    pub fn clear(&self) -> Result<(), ServicingError> {
+        let path = layout::service_dir(&self.service)?.join(OPERATION_FILE);
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// advance journals that the operation recorded for the service enters phase. Failing to do so
+/// is logged rather than failing the operation.
+pub fn advance(service: &str, phase: &str) {
+    let advanced = recorded(service).and_then(|operation| match operation {
+        Some(operation) => Operation {
+            phase: phase.to_string(),
+            ..operation
+        }
+        .record(),
+        None => Ok(()),
+    });
+    if let Err(e) = advanced {
+        warn!("Could not journal phase {phase} of service {service}: {e}");
+    }
+}
+
+/// interrupt journals that the operation recorded for the service was stopped by SIGINT, keeping
+/// its record.
+pub fn interrupt(service: &str) -> Result<(), ServicingError> {
+    match recorded(service)? {
+        Some(operation) => Operation {
+            interrupted: true,
+            ..operation
+        }
+        .record(),
+        None => Ok(()),
+    }
+}
+
+fn pending() -> String {
+    "pending".to_string()
+}
+
+/// recorded returns the operation recorded for the service, if any.
+pub fn recorded(service: &str) -> Result<Option<Operation>, ServicingError> {
+    let path = layout::service_dir(service)?.join(OPERATION_FILE);
+    match path.is_file() {
+        true => Ok(Some(serde_json::from_str(&helper::read_from_file(&path)?)?)),
+        false => Ok(None),
+    }
+}
+
+/// all returns the operations recorded for every service, in flight or interrupted.
+pub fn all() -> Result<Vec<Operation>, ServicingError> {
+    let mut operations = Vec::new();
+    for entry in fs::read_dir(layout::services_dir()?)? {
+        let path = entry?.path().join(OPERATION_FILE);
+        if path.is_file() {
+            operations.push(serde_json::from_str(&helper::read_from_file(&path)?)?);
+        }
+    }
+    operations.sort_by_key(|operation: &Operation| operation.started);
+    Ok(operations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Operation;
+
+    #[test]
+    fn test_operation() {
+        let first = Operation::new("llm", "up");
+        let second = Operation::new("llm", "up");
+        assert_ne!(first.id, second.id);
+        assert!(first.in_flight());
+        let stopped = Operation {
+            interrupted: true,
+            ..first.clone()
+        };
+        assert!(!stopped.in_flight());
+
+        let interrupted = Operation {
+            pid: u32::MAX,
+            host: "elsewhere".to_string(),
+            started: 0,
+            ..first
+        };
+        assert!(!interrupted.in_flight());
+
+        // records written before the phases were journaled
+        let mut record = serde_json::to_value(&interrupted).unwrap();
+        record.as_object_mut().unwrap().remove("phase");
+        let read: Operation = serde_json::from_value(record).unwrap();
+        assert_eq!(read.phase, "pending");
+    }
+}
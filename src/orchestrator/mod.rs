@@ -0,0 +1,354 @@
+//! Orchestrator module houses the backends able to run a service.
+#[cfg(feature = "python")]
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+};
+
+#[cfg(feature = "python")]
+use pyo3::{pyclass, pymethods, FromPyObject};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::ServicingError,
+    helper, limiter, logic,
+    models::Configuration,
+    runner::{self, Mode},
+};
+
+static SKY_CLOUDS: &[&str] = &[
+    "aws",
+    "gcp",
+    "azure",
+    "ibm",
+    "lambda",
+    "oci",
+    "kubernetes",
+    "runpod",
+    "paperspace",
+    "fluidstack",
+    "cudo",
+    "scp",
+    "vsphere",
+];
+static DOCKER_CLOUDS: &[&str] = &["local"];
+static KUBERNETES_CLOUDS: &[&str] = &["kubernetes"];
+
+/// Orchestrators are the backends a service can be launched with.
+#[cfg_attr(feature = "python", pyclass)]
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub enum Orchestrators {
+    #[default]
+    Skypilot,
+    /// a local container, to try a configuration out
+    Docker,
+    /// a Deployment in the cluster of the current kubectl context
+    Kubernetes,
+}
+
+/// Check is the outcome of one prerequisite check of an orchestrator.
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub orchestrator: &'static str,
+    pub check: &'static str,
+    pub ok: bool,
+    pub detail: String,
+    /// how to fix a failed check
+    pub hint: Option<&'static str>,
+}
+
+impl Orchestrators {
+    pub const ALL: &'static [Orchestrators] = &[
+        Orchestrators::Skypilot,
+        Orchestrators::Docker,
+        Orchestrators::Kubernetes,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Orchestrators::Skypilot => "skypilot",
+            Orchestrators::Docker => "docker",
+            Orchestrators::Kubernetes => "kubernetes",
+        }
+    }
+
+    /// package is the Python package the orchestrator needs installed, if any.
+    pub fn package(&self) -> Option<&'static str> {
+        match self {
+            Orchestrators::Skypilot => Some("skypilot"),
+            Orchestrators::Docker | Orchestrators::Kubernetes => None,
+        }
+    }
+
+    /// binary is the command the orchestrator runs and the arguments printing its version.
+    fn binary(&self) -> (&'static str, &'static [&'static str]) {
+        match self {
+            Orchestrators::Skypilot => ("sky", &["--version"]),
+            Orchestrators::Docker => ("docker", &["--version"]),
+            Orchestrators::Kubernetes => ("kubectl", &["version", "--client"]),
+        }
+    }
+
+    /// installed tells whether the Python package or the binary of the orchestrator is installed.
+    pub fn installed(&self) -> bool {
+        match self.package() {
+            Some(package) => helper::check_python_package_installed(package),
+            None => {
+                let (program, args) = self.binary();
+                run(program, args).is_ok()
+            }
+        }
+    }
+
+    /// available lists the orchestrators whose prerequisites are installed.
+    pub fn available() -> Vec<Orchestrators> {
+        Orchestrators::ALL
+            .iter()
+            .filter(|o| o.installed())
+            .copied()
+            .collect()
+    }
+
+    /// clouds lists the clouds a service of the orchestrator can be placed in.
+    pub fn clouds(&self) -> &'static [&'static str] {
+        match self {
+            Orchestrators::Skypilot => SKY_CLOUDS,
+            Orchestrators::Docker => DOCKER_CLOUDS,
+            Orchestrators::Kubernetes => KUBERNETES_CLOUDS,
+        }
+    }
+
+    /// template_path is where the default template of the orchestrator is kept in dir.
+    pub fn template_path(&self, dir: &Path) -> PathBuf {
+        dir.join(format!("{}.yaml", self.name()))
+    }
+
+    /// default_template returns the configuration new services of the orchestrator start from:
+    /// the built-in one, overridden by the template kept in dir if there is one.
+    pub fn default_template(&self, dir: &Path) -> Result<Configuration, ServicingError> {
+        let path = self.template_path(dir);
+        match path.exists() {
+            true => Configuration::from_template(&helper::read_from_file(&path)?),
+            false => Ok(Configuration::default()),
+        }
+    }
+
+    /// doctor checks the prerequisites of the orchestrator: its binaries, Python packages,
+    /// credentials and connectivity. Checks depending on a failed one are skipped.
+    pub fn doctor(&self) -> Vec<Check> {
+        let check = |check, ok, detail: String, hint| Check {
+            orchestrator: self.name(),
+            check,
+            ok,
+            detail,
+            hint: if ok { None } else { Some(hint) },
+        };
+        match self {
+            Orchestrators::Kubernetes => {
+                let (program, args) = self.binary();
+                let version = run(program, args);
+                let installed = version.is_ok();
+                let mut checks = vec![check(
+                    "binary",
+                    installed,
+                    version.unwrap_or_else(|e| e),
+                    "install kubectl and make sure it is on the PATH",
+                )];
+                if !installed {
+                    return checks;
+                }
+                let context = run("kubectl", &["config", "current-context"]);
+                let reachable =
+                    context.is_ok() && run("kubectl", &["get", "--raw", "/readyz"]).is_ok();
+                checks.push(check(
+                    "cluster",
+                    reachable,
+                    match context {
+                        Ok(context) => format!("Context {context}"),
+                        Err(e) => e,
+                    },
+                    "select a context with `kubectl config use-context` and check the cluster is reachable",
+                ));
+                checks
+            }
+            Orchestrators::Docker => {
+                let (program, args) = self.binary();
+                let version = run(program, args);
+                let installed = version.is_ok();
+                let mut checks = vec![check(
+                    "binary",
+                    installed,
+                    version.unwrap_or_else(|e| e),
+                    "install Docker and make sure the docker command is on the PATH",
+                )];
+                if !installed {
+                    return checks;
+                }
+                let daemon = run("docker", &["info", "--format", "{{.ServerVersion}}"]);
+                checks.push(check(
+                    "daemon",
+                    daemon.is_ok(),
+                    match daemon {
+                        Ok(version) => format!("Docker daemon {version}"),
+                        Err(e) => e,
+                    },
+                    "start the Docker daemon and make sure the user may reach its socket",
+                ));
+                checks
+            }
+            Orchestrators::Skypilot => {
+                let mut checks = vec![check(
+                    "package",
+                    helper::check_python_package_installed("skypilot"),
+                    "Python package skypilot".to_string(),
+                    "pip install skypilot",
+                )];
+                let (program, args) = self.binary();
+                let version = run(program, args);
+                let installed = version.is_ok();
+                checks.push(check(
+                    "binary",
+                    installed,
+                    version.unwrap_or_else(|e| e),
+                    "make sure the sky command from the skypilot package is on the PATH",
+                ));
+                if !installed {
+                    return checks;
+                }
+
+                // sky check verifies the credentials of every cloud, which needs connectivity
+                let enabled = run("sky", &["check"]).map(|output| logic::enabled_clouds(&output));
+                checks.push(match enabled {
+                    Ok(clouds) if !clouds.is_empty() => check(
+                        "credentials",
+                        true,
+                        format!("Enabled clouds: {}", clouds.join(", ")),
+                        "",
+                    ),
+                    Ok(_) => check(
+                        "credentials",
+                        false,
+                        "No cloud is enabled".to_string(),
+                        "set up the credentials of a cloud, see `sky check` for details",
+                    ),
+                    Err(e) => check(
+                        "credentials",
+                        false,
+                        e,
+                        "check the network connection and run `sky check` for details",
+                    ),
+                });
+                checks
+            }
+        }
+    }
+}
+
+/// run runs the command and returns its output, or a description of why it failed.
+fn run(program: &str, args: &[&str]) -> Result<String, String> {
+    let command = format!("{program} {}", args.join(" "));
+    let _permit = limiter::acquire(&command).map_err(|e| e.to_string())?;
+    match runner::run(Command::new(program).args(args), Mode::Capture) {
+        Ok(output) if output.status.success() => {
+            Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        }
+        Ok(output) => Err(format!(
+            "{command} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+        Err(e) => Err(format!("{program} not found: {e}")),
+    }
+}
+
+impl fmt::Display for Orchestrators {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Orchestrators {
+    type Err = ServicingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "skypilot" | "sky" => Ok(Orchestrators::Skypilot),
+            "docker" => Ok(Orchestrators::Docker),
+            "kubernetes" | "k8s" => Ok(Orchestrators::Kubernetes),
+            _ => Err(ServicingError::UnknownOrchestrator(s.to_string())),
+        }
+    }
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl Orchestrators {
+    /// from_str parses an orchestrator name, e.g. "skypilot", "docker" or "kubernetes".
+    #[staticmethod]
+    #[pyo3(name = "from_str")]
+    fn parse(name: &str) -> Result<Self, ServicingError> {
+        name.parse()
+    }
+
+    /// available lists the orchestrators whose prerequisites are installed.
+    #[staticmethod]
+    #[pyo3(name = "available")]
+    fn list_available() -> Vec<Orchestrators> {
+        Orchestrators::available()
+    }
+
+    fn __str__(&self) -> &'static str {
+        self.name()
+    }
+
+    fn __hash__(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// OrchestratorArg accepts either an Orchestrators variant or its name from Python.
+#[cfg(feature = "python")]
+#[derive(FromPyObject)]
+pub enum OrchestratorArg {
+    Variant(Orchestrators),
+    Name(String),
+}
+
+#[cfg(feature = "python")]
+impl OrchestratorArg {
+    pub fn resolve(self) -> Result<Orchestrators, ServicingError> {
+        match self {
+            OrchestratorArg::Variant(orchestrator) => Ok(orchestrator),
+            OrchestratorArg::Name(name) => name.parse(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Orchestrators;
+
+    #[test]
+    fn test_orchestrators() {
+        assert_eq!(
+            "SkyPilot".parse::<Orchestrators>().unwrap(),
+            Orchestrators::Skypilot
+        );
+        assert_eq!(
+            "docker".parse::<Orchestrators>().unwrap(),
+            Orchestrators::Docker
+        );
+        assert_eq!(
+            "k8s".parse::<Orchestrators>().unwrap(),
+            Orchestrators::Kubernetes
+        );
+        assert!("nomad".parse::<Orchestrators>().is_err());
+    }
+}
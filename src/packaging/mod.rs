@@ -0,0 +1,205 @@
+//! Packaging module houses the selection of the workdir files shipped with a service, so that
+//! SkyPilot uploads a staged copy of what the service needs instead of the whole directory.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use ignore::{overrides::OverrideBuilder, WalkBuilder};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{error::ServicingError, helper};
+
+/// ignore file SkyPilot users may already keep in their workdir
+static SKYIGNORE: &str = ".skyignore";
+
+/// Packaging selects the files of the workdir: only those matching include (everything if
+/// empty), minus those matching exclude, minus those ignored by .gitignore if gitignore is set.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Packaging {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub gitignore: bool,
+    /// refuse to ship more than this many bytes
+    pub max_size: Option<u64>,
+}
+
+/// Package is a staged copy of the selected workdir files.
+pub struct Package {
+    pub dir: PathBuf,
+    /// hash of the selected paths, sizes and modification times
+    pub hash: String,
+    pub size: u64,
+    /// whether the staged copy of a previous up was reused
+    pub reused: bool,
+}
+
+struct Selected {
+    path: PathBuf,
+    size: u64,
+    modified: u128,
+}
+
+impl Packaging {
+    /// stage copies the selected files of workdir into dir. When previous is the hash of the same
+    /// selection and dir still exists, the files are left in place.
+    pub fn stage(
+        &self,
+        name: &str,
+        workdir: &Path,
+        dir: &Path,
+        previous: Option<&str>,
+    ) -> Result<Package, ServicingError> {
+        let files = self.select(workdir)?;
+        let size = files.iter().map(|f| f.size).sum();
+        if let Some(max_size) = self.max_size {
+            if size > max_size {
+                return Err(ServicingError::WorkdirTooLarge(
+                    name.to_string(),
+                    size,
+                    max_size,
+                ));
+            }
+        }
+
+        let manifest: String = files
+            .iter()
+            .map(|f| format!("{}\0{}\0{}\n", f.path.display(), f.size, f.modified))
+            .collect();
+        let hash = helper::content_hash(&manifest);
+        if previous == Some(hash.as_str()) && dir.is_dir() {
+            info!("Workdir of service {} unchanged, reusing {:?}", name, dir);
+            return Ok(Package {
+                dir: dir.to_path_buf(),
+                hash,
+                size,
+                reused: true,
+            });
+        }
+
+        info!(
+            "Staging {} files ({} bytes) of the workdir of service {} in {:?}",
+            files.len(),
+            size,
+            name,
+            dir
+        );
+        if dir.exists() {
+            fs::remove_dir_all(dir)?;
+        }
+        fs::create_dir_all(dir)?;
+        for file in &files {
+            let target = dir.join(&file.path);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(workdir.join(&file.path), target)?;
+        }
+        Ok(Package {
+            dir: dir.to_path_buf(),
+            hash,
+            size,
+            reused: false,
+        })
+    }
+
+    /// select lists the selected files relative to workdir, sorted by path.
+    fn select(&self, workdir: &Path) -> Result<Vec<Selected>, ServicingError> {
+        let invalid = |e: ignore::Error| ServicingError::General(format!("Invalid glob: {e}"));
+        let mut overrides = OverrideBuilder::new(workdir);
+        for glob in &self.include {
+            overrides.add(glob).map_err(invalid)?;
+        }
+        for glob in &self.exclude {
+            overrides.add(&format!("!{glob}")).map_err(invalid)?;
+        }
+
+        let walk = WalkBuilder::new(workdir)
+            .hidden(false)
+            .ignore(false)
+            .parents(false)
+            .git_global(false)
+            .git_ignore(self.gitignore)
+            .git_exclude(self.gitignore)
+            .require_git(false)
+            .add_custom_ignore_filename(SKYIGNORE)
+            .overrides(overrides.build().map_err(invalid)?)
+            .filter_entry(|entry| entry.file_name() != ".git")
+            .build();
+
+        let mut files = Vec::new();
+        for entry in walk {
+            let entry = entry.map_err(|e| ServicingError::General(e.to_string()))?;
+            if !entry.file_type().is_some_and(|t| t.is_file()) {
+                continue;
+            }
+            let metadata = entry
+                .metadata()
+                .map_err(|e| ServicingError::General(e.to_string()))?;
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_nanos());
+            files.push(Selected {
+                path: entry
+                    .path()
+                    .strip_prefix(workdir)
+                    .unwrap_or(entry.path())
+                    .to_path_buf(),
+                size: metadata.len(),
+                modified,
+            });
+        }
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::Packaging;
+
+    #[test]
+    fn test_stage_workdir() {
+        let root = std::env::temp_dir().join(format!("servicing-packaging-{}", std::process::id()));
+        let workdir = root.join("workdir");
+        fs::create_dir_all(workdir.join("src")).unwrap();
+        fs::create_dir_all(workdir.join("data")).unwrap();
+        fs::write(workdir.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(workdir.join("app.py"), "print('hi')").unwrap();
+        fs::write(workdir.join("src/model.py"), "pass").unwrap();
+        fs::write(workdir.join("data/train.csv"), "a,b").unwrap();
+        fs::write(workdir.join("debug.log"), "noise").unwrap();
+
+        let packaging = Packaging {
+            include: vec![],
+            exclude: vec!["data/".to_string()],
+            gitignore: true,
+            max_size: None,
+        };
+        let staged = root.join("staged");
+        let package = packaging.stage("test", &workdir, &staged, None).unwrap();
+        assert!(!package.reused);
+        assert!(staged.join("app.py").exists());
+        assert!(staged.join("src/model.py").exists());
+        assert!(!staged.join("data/train.csv").exists());
+        assert!(!staged.join("debug.log").exists());
+
+        let again = packaging
+            .stage("test", &workdir, &staged, Some(&package.hash))
+            .unwrap();
+        assert!(again.reused);
+
+        let limited = Packaging {
+            max_size: Some(1),
+            ..packaging
+        };
+        assert!(limited.stage("test", &workdir, &staged, None).is_err());
+        fs::remove_dir_all(root).unwrap();
+    }
+}
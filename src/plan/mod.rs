@@ -0,0 +1,52 @@
+//! Plan module houses the dry-run mode, in which operations record the commands and manifests
+//! they would run instead of running them, for audits and training environments.
+use std::{collections::BTreeMap, env};
+
+use serde::Serialize;
+
+use crate::runner::Recorded;
+
+static DRY_RUN_ENV: &str = "SERVICING_DRY_RUN";
+
+/// Plan is what one dry-run operation would have done, in order.
+#[derive(Clone, Debug, Serialize)]
+pub struct Plan {
+    pub operation: String,
+    pub steps: Vec<Step>,
+}
+
+/// Step is one command of a plan.
+#[derive(Clone, Debug, Serialize)]
+pub struct Step {
+    pub service: String,
+    pub command: String,
+    /// document fed to the command on stdin
+    pub manifest: Option<String>,
+    /// content of the files the command line refers to, e.g. the rendered configuration
+    pub files: BTreeMap<String, String>,
+}
+
+impl Plan {
+    pub fn new(operation: &str) -> Self {
+        Plan {
+            operation: operation.to_string(),
+            steps: Vec::new(),
+        }
+    }
+
+    /// extend adds the commands recorded for service to the plan.
+    pub fn extend(&mut self, service: &str, recorded: Vec<Recorded>) {
+        self.steps.extend(recorded.into_iter().map(|recorded| Step {
+            service: service.to_string(),
+            command: recorded.command,
+            manifest: recorded.input,
+            files: recorded.files,
+        }));
+    }
+}
+
+/// enabled_by_env tells whether SERVICING_DRY_RUN turns the dry-run mode on.
+pub fn enabled_by_env() -> bool {
+    env::var(DRY_RUN_ENV)
+        .is_ok_and(|value| matches!(value.to_lowercase().as_str(), "1" | "true" | "yes"))
+}
@@ -0,0 +1,318 @@
+//! Policy module houses the checks run against the rendered configuration of a service before
+//! it is provisioned, e.g. "no public clouds for datasets labelled confidential" or "at most 4
+//! GPUs per service". A policy is a Python callable or an OPA bundle evaluated with the opa CLI,
+//! and the violations they report block the launch unless overridden.
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Mutex,
+};
+
+use log::info;
+use pyo3::{
+    types::{PyAnyMethods, PyString},
+    PyObject, Python,
+};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    error::ServicingError,
+    helper, layout, limiter,
+    models::Configuration,
+    runner::{self, Mode},
+};
+
+/// query evaluated in the OPA bundles unless another is given, a set of messages as in
+/// `deny contains msg if { ... }`
+pub static DEFAULT_QUERY: &str = "data.servicing.deny";
+static INPUT_FILE: &str = "policy_input.json";
+
+enum Check {
+    /// called with the input dict, returns the violations as strings
+    Callable(PyObject),
+    Opa {
+        bundle: PathBuf,
+        query: String,
+    },
+}
+
+struct Policy {
+    name: String,
+    check: Check,
+}
+
+/// Violation is a rule of a policy the configuration of a service breaks.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Violation {
+    /// name of the policy reporting it
+    pub policy: String,
+    pub message: String,
+}
+
+/// Input describes the launch evaluated by the policies, handed to them as a dict (service,
+/// orchestrator, environment, owner, config), config being the rendered configuration.
+pub struct Input<'a> {
+    pub service: &'a str,
+    pub orchestrator: &'static str,
+    pub environment: Option<&'a str>,
+    pub owner: Option<String>,
+    pub config: &'a Configuration,
+}
+
+impl Input<'_> {
+    fn to_json(&self) -> Result<Value, ServicingError> {
+        let config: Value = serde_yaml::from_str(&self.config.render()?)?;
+        Ok(serde_json::json!({
+            "service": self.service,
+            "orchestrator": self.orchestrator,
+            "environment": self.environment,
+            "owner": self.owner,
+            "config": config,
+        }))
+    }
+}
+
+/// Policies keeps the policies evaluated before every launch, in registration order.
+#[derive(Default)]
+pub struct Policies {
+    policies: Mutex<Vec<Policy>>,
+}
+
+impl Policies {
+    /// add_callable registers check, named after its __name__ unless name is given.
+    pub fn add_callable(
+        &self,
+        py: Python<'_>,
+        check: PyObject,
+        name: Option<String>,
+    ) -> Result<(), ServicingError> {
+        let name = match name {
+            Some(name) => name,
+            None => check
+                .bind(py)
+                .getattr("__name__")
+                .and_then(|name| name.extract())
+                .unwrap_or_else(|_| "policy".to_string()),
+        };
+        self.policies.lock()?.push(Policy {
+            name,
+            check: Check::Callable(check),
+        });
+        Ok(())
+    }
+
+    /// add_bundle registers the OPA bundle at bundle, a directory or a .tar.gz, whose query
+    /// returns the violations. It is named after the bundle unless name is given.
+    pub fn add_bundle(
+        &self,
+        bundle: PathBuf,
+        query: Option<String>,
+        name: Option<String>,
+    ) -> Result<(), ServicingError> {
+        if !bundle.exists() {
+            return Err(ServicingError::General(format!(
+                "Policy bundle {:?} not found",
+                bundle
+            )));
+        }
+        let name = name.unwrap_or_else(|| bundle.to_string_lossy().into_owned());
+        let query = query.unwrap_or_else(|| DEFAULT_QUERY.to_string());
+        self.policies.lock()?.push(Policy {
+            name,
+            check: Check::Opa { bundle, query },
+        });
+        Ok(())
+    }
+
+    pub fn clear(&self) -> Result<(), ServicingError> {
+        self.policies.lock()?.clear();
+        Ok(())
+    }
+
+    /// names lists the policies in registration order.
+    pub fn names(&self) -> Result<Vec<String>, ServicingError> {
+        Ok(self
+            .policies
+            .lock()?
+            .iter()
+            .map(|p| p.name.clone())
+            .collect())
+    }
+
+    /// evaluate returns the violations input raises with every policy. A policy failing to
+    /// evaluate is an error, the launch is not let through unchecked.
+    pub fn evaluate(&self, input: &Input) -> Result<Vec<Violation>, ServicingError> {
+        // out of the lock, so that a callable registering a policy does not deadlock
+        let policies = Python::with_gil(|py| -> Result<_, ServicingError> {
+            Ok(self
+                .policies
+                .lock()?
+                .iter()
+                .map(|policy| {
+                    let check = match &policy.check {
+                        Check::Callable(check) => Check::Callable(check.clone_ref(py)),
+                        Check::Opa { bundle, query } => Check::Opa {
+                            bundle: bundle.clone(),
+                            query: query.clone(),
+                        },
+                    };
+                    Policy {
+                        name: policy.name.clone(),
+                        check,
+                    }
+                })
+                .collect::<Vec<_>>())
+        })?;
+        if policies.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let json = input.to_json()?;
+        let mut violations = Vec::new();
+        for policy in policies {
+            let messages = match &policy.check {
+                Check::Callable(check) => call(check, &json)?,
+                Check::Opa { bundle, query } => opa_eval(input.service, bundle, query, &json)?,
+            };
+            violations.extend(messages.into_iter().map(|message| Violation {
+                policy: policy.name.clone(),
+                message,
+            }));
+        }
+        Ok(violations)
+    }
+}
+
+/// describe lists violations on one line, for errors and logs.
+pub fn describe(violations: &[Violation]) -> String {
+    violations
+        .iter()
+        .map(|v| format!("{} ({})", v.message, v.policy))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// call calls check with the input as a dict. It returns None or no violation, a string for
+/// one violation or an iterable of strings.
+fn call(check: &PyObject, input: &Value) -> Result<Vec<String>, ServicingError> {
+    Python::with_gil(|py| {
+        let input = py
+            .import_bound("json")?
+            .call_method1("loads", (input.to_string(),))?;
+        let returned = check.call1(py, (input,))?;
+        let returned = returned.bind(py);
+        if returned.is_none() {
+            return Ok(Vec::new());
+        }
+        if returned.is_instance_of::<PyString>() {
+            return Ok(vec![returned.extract()?]);
+        }
+        let mut messages = Vec::new();
+        for message in returned.iter()? {
+            messages.push(message?.str()?.extract()?);
+        }
+        Ok(messages)
+    })
+}
+
+/// opa_eval evaluates query in bundle with the opa CLI, the input being written to the folder
+/// of the service.
+fn opa_eval(
+    name: &str,
+    bundle: &Path,
+    query: &str,
+    input: &Value,
+) -> Result<Vec<String>, ServicingError> {
+    let file = layout::service_dir(name)?.join(INPUT_FILE);
+    helper::write_to_file(&file, &serde_json::to_string_pretty(input)?)?;
+
+    info!("Evaluating the policy {:?} for service {}", bundle, name);
+    let output = {
+        let _permit = limiter::acquire("opa eval")?;
+        runner::run(
+            Command::new("opa")
+                .arg("eval")
+                .arg("--format")
+                .arg("json")
+                .arg("--bundle")
+                .arg(bundle)
+                .arg("--input")
+                .arg(&file)
+                .arg(query),
+            Mode::Capture,
+        )?
+    };
+    if !output.status.success() {
+        return Err(ServicingError::General(format!(
+            "opa eval of {:?} failed: {}",
+            bundle,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    parse_opa(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// parse_opa reads the violations out of the output of opa eval: the value of the query, a set
+/// of messages or of objects with a msg or message field. An undefined query has none.
+fn parse_opa(output: &str) -> Result<Vec<String>, ServicingError> {
+    let output: Value = serde_json::from_str(output)?;
+    let values = output["result"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(|result| result["expressions"].as_array().into_iter().flatten())
+        .map(|expression| &expression["value"]);
+
+    let mut messages = Vec::new();
+    for value in values {
+        let items = match value {
+            Value::Array(items) => items.iter().collect(),
+            Value::Null | Value::Bool(false) => Vec::new(),
+            value => vec![value],
+        };
+        messages.extend(items.into_iter().map(|item| {
+            match item {
+                Value::String(message) => message.clone(),
+                item => item
+                    .get("msg")
+                    .or(item.get("message"))
+                    .and_then(Value::as_str)
+                    .map(str::to_string)
+                    .unwrap_or_else(|| item.to_string()),
+            }
+        }));
+    }
+    Ok(messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{describe, parse_opa, Violation};
+
+    #[test]
+    fn test_parse_opa() {
+        let output = r#"{"result": [{"expressions": [{"value": [
+            "service llm asks for 8 GPUs, at most 4 are allowed",
+            {"msg": "confidential data on a public cloud"}
+        ], "text": "data.servicing.deny"}]}]}"#;
+        let messages = parse_opa(output).unwrap();
+        assert_eq!(
+            messages,
+            vec![
+                "service llm asks for 8 GPUs, at most 4 are allowed",
+                "confidential data on a public cloud"
+            ]
+        );
+        assert!(parse_opa("{}").unwrap().is_empty());
+
+        let violations = vec![Violation {
+            policy: "gpus".to_string(),
+            message: messages[0].clone(),
+        }];
+        assert_eq!(
+            describe(&violations),
+            "service llm asks for 8 GPUs, at most 4 are allowed (gpus)"
+        );
+    }
+}
@@ -0,0 +1,135 @@
+//! Poller module houses the background task running `sky serve status` once per interval for
+//! all the SkyPilot services and caching its output, so that the status reads answer from the
+//! cache rather than forking the CLI on every call.
+use std::{
+    process::Command,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use log::{info, warn};
+use tokio::{task::JoinHandle, time::sleep};
+
+use crate::{
+    error::ServicingError,
+    limiter,
+    runner::{self, Mode},
+};
+
+/// Snapshot is the output of a poll.
+struct Snapshot {
+    taken: Instant,
+    output: String,
+}
+
+impl Snapshot {
+    /// fresh tells whether the snapshot is recent enough to answer from, a poll having been
+    /// missed at most.
+    fn fresh(&self, interval: Duration) -> bool {
+        self.taken.elapsed() < interval * 2
+    }
+}
+
+/// Poller caches the output of `sky serve status` while started.
+#[derive(Default)]
+pub struct Poller {
+    snapshot: Mutex<Option<Snapshot>>,
+    /// polling task and its interval
+    task: Mutex<Option<(JoinHandle<()>, Duration)>>,
+}
+
+impl Poller {
+    /// start polls every interval, replacing the task of a previous start.
+    pub fn start(
+        self: &Arc<Self>,
+        rt: &tokio::runtime::Runtime,
+        interval: Duration,
+    ) -> Result<(), ServicingError> {
+        let mut task = self.task.lock()?;
+        if let Some((previous, _)) = task.take() {
+            previous.abort();
+        }
+        let poller = self.clone();
+        let handle = rt.spawn(async move {
+            info!("Polling sky serve status every {:?}", interval);
+            loop {
+                match poll().await {
+                    Ok(output) => match poller.snapshot.lock() {
+                        Ok(mut snapshot) => {
+                            *snapshot = Some(Snapshot {
+                                taken: Instant::now(),
+                                output,
+                            })
+                        }
+                        Err(e) => {
+                            warn!("Poisoned lock {e}");
+                            break;
+                        }
+                    },
+                    Err(e) => warn!("Could not poll sky serve status: {e}"),
+                }
+                sleep(interval).await;
+            }
+        });
+        *task = Some((handle, interval));
+        Ok(())
+    }
+
+    /// stop ends the polling and drops the cached output, returning whether it was polling.
+    pub fn stop(&self) -> Result<bool, ServicingError> {
+        let stopped = match self.task.lock()?.take() {
+            Some((task, _)) => {
+                task.abort();
+                true
+            }
+            None => false,
+        };
+        *self.snapshot.lock()? = None;
+        Ok(stopped)
+    }
+
+    /// fresh returns the cached output, None when not polling or when the last poll is stale.
+    pub fn fresh(&self) -> Option<String> {
+        let interval = self.task.lock().ok()?.as_ref()?.1;
+        let snapshot = self.snapshot.lock().ok()?;
+        snapshot
+            .as_ref()
+            .filter(|snapshot| snapshot.fresh(interval))
+            .map(|snapshot| snapshot.output.clone())
+    }
+}
+
+async fn poll() -> Result<String, ServicingError> {
+    let _permit = limiter::acquire_async("sky serve status").await?;
+    let mut cmd = Command::new("sky");
+    cmd.arg("serve").arg("status");
+    let output = runner::run_async(cmd, Mode::Capture).await?;
+    if !output.status.success() {
+        return Err(ServicingError::General(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{Poller, Snapshot};
+
+    #[test]
+    fn test_fresh() {
+        assert_eq!(Poller::default().fresh(), None);
+
+        let interval = Duration::from_secs(30);
+        let snapshot = Snapshot {
+            taken: Instant::now(),
+            output: String::new(),
+        };
+        assert!(snapshot.fresh(interval));
+        if let Some(taken) = Instant::now().checked_sub(interval * 3) {
+            assert!(!Snapshot { taken, ..snapshot }.fresh(interval));
+        }
+    }
+}
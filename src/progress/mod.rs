@@ -0,0 +1,100 @@
+//! Progress module houses the reporting of provisioning phases to a Python callback, e.g. one
+//! driving a `rich` progress display, for interactive users.
+use std::time::{Duration, Instant};
+
+use log::warn;
+use pyo3::{
+    types::{PyDict, PyDictMethods},
+    PyObject, Python,
+};
+
+use crate::error::ServicingError;
+
+/// Event is what happened to a phase of the provisioning.
+pub enum Event {
+    Start,
+    Done,
+    /// the phase is retried, with the reason
+    Retry(Reason),
+    /// the phase is waiting, the next check happens after the duration
+    Waiting(Duration),
+    Failed(Reason),
+}
+
+/// Reason is why a phase is retried or failed: a message for people and a stable code for UI
+/// layers, see ServicingError::code.
+pub struct Reason {
+    pub message: String,
+    pub code: &'static str,
+}
+
+impl From<&ServicingError> for Reason {
+    fn from(err: &ServicingError) -> Self {
+        Reason {
+            message: err.to_string(),
+            code: err.code(),
+        }
+    }
+}
+
+impl Event {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Event::Start => "start",
+            Event::Done => "done",
+            Event::Retry(_) => "retry",
+            Event::Waiting(_) => "waiting",
+            Event::Failed(_) => "failed",
+        }
+    }
+}
+
+/// Progress reports the phases of one service. Without a callback nothing is reported and the
+/// SkyPilot output is echoed as is; with one, the callback is called with a dict (service,
+/// phase, event, elapsed, message, code, next_check) and the SkyPilot output is kept quiet.
+pub struct Progress {
+    service: String,
+    callback: Option<PyObject>,
+    started: Instant,
+}
+
+impl Progress {
+    pub fn new(service: &str, callback: Option<PyObject>) -> Self {
+        Progress {
+            service: service.to_string(),
+            callback,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.callback.is_some()
+    }
+
+    /// report calls the callback, exceptions are logged and do not affect the provisioning. It
+    /// must not be called with an entry of the registry held, a slow callback would block its
+    /// readers.
+    pub fn report(&self, phase: &str, event: Event) {
+        let Some(callback) = &self.callback else {
+            return;
+        };
+        Python::with_gil(|py| {
+            let report = PyDict::new_bound(py);
+            let built = report
+                .set_item("service", &self.service)
+                .and_then(|_| report.set_item("phase", phase))
+                .and_then(|_| report.set_item("event", event.as_str()))
+                .and_then(|_| report.set_item("elapsed", self.started.elapsed().as_secs_f64()))
+                .and_then(|_| match &event {
+                    Event::Retry(reason) | Event::Failed(reason) => report
+                        .set_item("message", &reason.message)
+                        .and_then(|_| report.set_item("code", reason.code)),
+                    Event::Waiting(next) => report.set_item("next_check", next.as_secs_f64()),
+                    Event::Start | Event::Done => Ok(()),
+                });
+            if let Err(e) = built.and_then(|_| callback.call1(py, (report,)).map(|_| ())) {
+                warn!("Progress callback failed: {e}");
+            }
+        });
+    }
+}
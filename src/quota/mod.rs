@@ -0,0 +1,238 @@
+//! Quota module houses the pre-check of the GPU quota of the target cloud run before launching
+//! a service, so that a zero quota is reported up front rather than after SkyPilot has gone
+//! through its failover.
+use std::process::Command;
+
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    error::ServicingError,
+    limiter,
+    models::Configuration,
+    orchestrator::Orchestrators,
+    runner::{self, Mode},
+};
+
+/// EC2 quotas of running on-demand instances, in vCPUs, by instance family
+static AWS_G_INSTANCES: &str = "L-DB2E81BA";
+static AWS_P_INSTANCES: &str = "L-417A185B";
+static AWS_G_ACCELERATORS: &[&str] = &["T4", "T4G", "A10G", "L4", "L40S", "M60"];
+static AWS_P_ACCELERATORS: &[&str] = &["K80", "V100", "A100", "A100-80GB", "H100", "H200"];
+
+/// Quota is the limit of the target cloud for the accelerators of a service.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Quota {
+    pub cloud: String,
+    /// None for the default region of the cloud CLI
+    pub region: Option<String>,
+    /// EC2 quota code or GCP quota metric
+    pub metric: String,
+    /// GPUs on GCP, vCPUs of the instance family on AWS
+    pub limit: f64,
+    pub usage: Option<f64>,
+}
+
+/// metric returns the quota limiting the accelerators, e.g. A100:8, on the cloud and how many
+/// accelerators are requested, None if the cloud or the accelerators are not covered.
+pub fn metric(cloud: &str, accelerators: &str) -> Option<(String, u32)> {
+    let (accelerator, count) = match accelerators.split_once(':') {
+        Some((accelerator, count)) => (accelerator, count.trim().parse().ok()?),
+        None => (accelerators, 1),
+    };
+    let accelerator = accelerator.trim().to_uppercase();
+    let metric = match cloud.to_lowercase().as_str() {
+        "aws" if AWS_G_ACCELERATORS.contains(&accelerator.as_str()) => AWS_G_INSTANCES.to_string(),
+        "aws" if AWS_P_ACCELERATORS.contains(&accelerator.as_str()) => AWS_P_INSTANCES.to_string(),
+        "gcp" if !accelerator.starts_with("TPU") => {
+            format!("NVIDIA_{}_GPUS", accelerator.replace('-', "_"))
+        }
+        _ => return None,
+    };
+    Some((metric, count))
+}
+
+/// check queries the quota of the accelerators of config in the cloud and region of placement,
+/// which overrides the configured ones as for a launch. A zero quota fails the check when the
+/// region is pinned and strict is set, there being no fallback to turn to; otherwise SkyPilot
+/// may still launch elsewhere, so it only warns, as does a quota short of the requested
+/// accelerators. A quota that cannot be queried is skipped.
+pub fn check(
+    name: &str,
+    config: &Configuration,
+    placement: Option<&str>,
+    strict: bool,
+) -> Result<(), ServicingError> {
+    let Some(accelerators) = &config.resources.accelerators else {
+        return Ok(());
+    };
+    let (cloud, region) = target(&config.resources.cloud, placement);
+    let Some((metric, count)) = metric(&cloud, accelerators) else {
+        return Ok(());
+    };
+    let quota = match query(&cloud, &metric, region.as_deref()) {
+        Ok(Some(quota)) => quota,
+        Ok(None) => return Ok(()),
+        Err(e) => {
+            info!("Could not check the {metric} quota of service {name}: {e}");
+            return Ok(());
+        }
+    };
+
+    let location = format!(
+        "{} {}",
+        quota.cloud,
+        quota.region.as_deref().unwrap_or("default region")
+    );
+    if quota.limit <= 0.0 {
+        let detail = format!("{} in {}", quota.metric, location);
+        if strict && quota.region.is_some() && placement.is_some() {
+            return Err(ServicingError::NoQuota(name.to_string(), detail));
+        }
+        warn!("Service {} has no {} quota", name, detail);
+    } else if let Some(usage) = quota.usage {
+        let left = quota.limit - usage;
+        if left < f64::from(count) {
+            warn!(
+                "Service {} requests {} accelerators but only {} of the {} quota are left in {}",
+                name, count, left, quota.metric, location
+            );
+        }
+    }
+    Ok(())
+}
+
+/// target returns the cloud and region a launch with placement, a cloud, a region or both
+/// written `cloud:region`, goes to.
+fn target(cloud: &str, placement: Option<&str>) -> (String, Option<String>) {
+    match placement {
+        Some(placement) => match placement.split_once(':') {
+            Some((cloud, region)) => (cloud.to_string(), Some(region.to_string())),
+            None if Orchestrators::Skypilot.clouds().contains(&placement) => {
+                (placement.to_string(), None)
+            }
+            None => (cloud.to_string(), Some(placement.to_string())),
+        },
+        None => (cloud.to_string(), None),
+    }
+}
+
+/// query asks the cloud CLI for the quota, None when the region is neither provided nor
+/// configured in the CLI.
+fn query(cloud: &str, metric: &str, region: Option<&str>) -> Result<Option<Quota>, ServicingError> {
+    match cloud.to_lowercase().as_str() {
+        "aws" => {
+            let mut args = vec![
+                "service-quotas",
+                "get-service-quota",
+                "--service-code",
+                "ec2",
+                "--quota-code",
+                metric,
+                "--output",
+                "json",
+            ];
+            if let Some(region) = region {
+                args.extend(["--region", region]);
+            }
+            let output: Value = serde_json::from_str(&capture("aws", &args)?)?;
+            Ok(output["Quota"]["Value"].as_f64().map(|limit| Quota {
+                cloud: cloud.to_string(),
+                region: region.map(str::to_string),
+                metric: metric.to_string(),
+                limit,
+                usage: None,
+            }))
+        }
+        "gcp" => {
+            let region = match region {
+                Some(region) => region.to_string(),
+                None => capture("gcloud", &["config", "get-value", "compute/region"])?
+                    .trim()
+                    .to_string(),
+            };
+            if region.is_empty() {
+                return Ok(None);
+            }
+            let output = capture(
+                "gcloud",
+                &[
+                    "compute", "regions", "describe", &region, "--format", "json",
+                ],
+            )?;
+            let quota = gcp_quota(&serde_json::from_str(&output)?, metric);
+            Ok(quota.map(|(limit, usage)| Quota {
+                cloud: cloud.to_string(),
+                region: Some(region),
+                metric: metric.to_string(),
+                limit,
+                usage: Some(usage),
+            }))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// gcp_quota reads the limit and usage of metric in the output of `gcloud compute regions
+/// describe`, None when the region does not list it.
+fn gcp_quota(region: &Value, metric: &str) -> Option<(f64, f64)> {
+    region["quotas"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|quota| quota["metric"] == metric)
+        .map(|quota| {
+            (
+                quota["limit"].as_f64().unwrap_or_default(),
+                quota["usage"].as_f64().unwrap_or_default(),
+            )
+        })
+}
+
+fn capture(program: &str, args: &[&str]) -> Result<String, ServicingError> {
+    let _permit = limiter::acquire(&format!("{program} {}", args[..2].join(" ")))?;
+    let output = runner::run(Command::new(program).args(args), Mode::Capture)?;
+    if !output.status.success() {
+        return Err(ServicingError::General(format!(
+            "{program} {} failed: {}",
+            args[..2].join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{gcp_quota, metric, target};
+
+    #[test]
+    fn test_quota() {
+        assert_eq!(metric("aws", "a10g:4"), Some(("L-DB2E81BA".to_string(), 4)));
+        assert_eq!(
+            metric("gcp", "A100-80GB:8"),
+            Some(("NVIDIA_A100_80GB_GPUS".to_string(), 8))
+        );
+        assert_eq!(metric("gcp", "tpu-v4-8"), None);
+        assert_eq!(metric("azure", "A100"), None);
+
+        assert_eq!(
+            target("aws", Some("gcp:us-central1")),
+            ("gcp".to_string(), Some("us-central1".to_string()))
+        );
+        assert_eq!(
+            target("aws", Some("eu-west-1")),
+            ("aws".to_string(), Some("eu-west-1".to_string()))
+        );
+
+        let region = json!({ "quotas": [
+            { "metric": "NVIDIA_T4_GPUS", "limit": 4.0, "usage": 1.0 },
+            { "metric": "CPUS", "limit": 24.0, "usage": 0.0 },
+        ]});
+        assert_eq!(gcp_quota(&region, "NVIDIA_T4_GPUS"), Some((4.0, 1.0)));
+        assert_eq!(gcp_quota(&region, "NVIDIA_L4_GPUS"), None);
+    }
+}
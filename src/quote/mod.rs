@@ -0,0 +1,165 @@
+//! Quote module houses the price comparison run before launching a service, based on the
+//! candidates the SkyPilot optimizer considers for its resources.
+use std::{path::Path, process::Command};
+
+use log::info;
+use serde::Serialize;
+use serde_yaml::{Mapping, Value};
+
+use crate::{
+    error::ServicingError,
+    helper, limiter,
+    models::Configuration,
+    runner::{self, Mode},
+};
+
+static QUOTE_FILE_NAME: &str = "quote.yaml";
+static CHOSEN_MARK: char = '\u{2714}';
+
+/// Quote is one candidate cloud and instance type for the resources of a service.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Quote {
+    pub cloud: String,
+    pub instance: String,
+    pub vcpus: String,
+    pub memory: String,
+    pub accelerators: String,
+    pub region: String,
+    /// estimated price per hour and node in dollars
+    pub hourly_price: Option<f64>,
+    /// whether the optimizer would pick this candidate
+    pub chosen: bool,
+}
+
+/// quote asks the optimizer for the candidates able to run the resources of config, cheapest
+/// first. Unless pin_cloud is set, the cloud of config is left to the optimizer.
+pub fn quote(
+    config: &Configuration,
+    pin_cloud: bool,
+    dir: &Path,
+) -> Result<Vec<Quote>, ServicingError> {
+    let mut resources = match serde_yaml::to_value(&config.resources)? {
+        Value::Mapping(resources) => resources,
+        _ => Mapping::new(),
+    };
+    if !pin_cloud {
+        resources.remove("cloud");
+    }
+    let mut task = Mapping::new();
+    task.insert("resources".into(), Value::Mapping(resources));
+
+    let file = dir.join(QUOTE_FILE_NAME);
+    helper::write_to_file(&file, &serde_yaml::to_string(&task)?)?;
+
+    info!("Asking the SkyPilot optimizer for quotes");
+    let output = {
+        let _permit = limiter::acquire("sky launch --dryrun")?;
+        runner::run(
+            Command::new("sky")
+                .arg("launch")
+                .arg("--dryrun")
+                .arg("-y")
+                .arg(&file),
+            Mode::Capture,
+        )?
+    };
+    if !output.status.success() {
+        return Err(ServicingError::General(format!(
+            "sky launch --dryrun failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    let mut quotes = parse(&String::from_utf8_lossy(&output.stdout));
+    quotes.sort_by(|a, b| match (a.hourly_price, b.hourly_price) {
+        (Some(a), Some(b)) => a.total_cmp(&b),
+        (a, b) => b.is_some().cmp(&a.is_some()),
+    });
+    Ok(quotes)
+}
+
+/// parse reads the table of considered resources printed by the optimizer. Cells are separated
+/// by box drawing characters or runs of spaces depending on the SkyPilot version.
+fn parse(output: &str) -> Vec<Quote> {
+    let cells = |line: &str| -> Vec<String> {
+        line.split(['│', '┃', '|'])
+            .flat_map(|cell| cell.split("  "))
+            .map(str::trim)
+            .filter(|cell| !cell.is_empty())
+            .map(str::to_string)
+            .collect()
+    };
+
+    let mut lines = output.lines();
+    let header = match lines.find(|line| line.contains("CLOUD") && line.contains("COST")) {
+        Some(header) => cells(header),
+        None => return Vec::new(),
+    };
+    let column = |name: &str| header.iter().position(|h| h.starts_with(name));
+    let columns = [
+        column("CLOUD"),
+        column("INSTANCE"),
+        column("vCPUs"),
+        column("Mem"),
+        column("ACCELERATORS"),
+        column("REGION"),
+        column("COST"),
+    ];
+
+    let mut quotes = Vec::new();
+    for line in lines {
+        if line.trim().is_empty() {
+            break;
+        }
+        let chosen = line.contains(CHOSEN_MARK);
+        let row = cells(&line.replace(CHOSEN_MARK, ""));
+        // table borders
+        if row.is_empty() || row.iter().all(|c| c.chars().all(|c| !c.is_alphanumeric())) {
+            continue;
+        }
+        let get = |i: Option<usize>| {
+            i.and_then(|i| row.get(i))
+                .cloned()
+                .unwrap_or_else(|| "-".to_string())
+        };
+        let [cloud, instance, vcpus, memory, accelerators, region, cost] = columns.map(get);
+        quotes.push(Quote {
+            cloud,
+            instance,
+            vcpus,
+            memory,
+            accelerators,
+            region,
+            hourly_price: cost.trim_start_matches('$').trim().parse().ok(),
+            chosen,
+        });
+    }
+    quotes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+
+    #[test]
+    fn test_parse_optimizer_table() {
+        let output = "Considered resources (1 node):\n\
+            ----------------------------------------------------------------------------------------\n \
+            CLOUD   INSTANCE         vCPUs   Mem(GB)   ACCELERATORS   REGION/ZONE   COST ($)   CHOSEN   \n\
+            ----------------------------------------------------------------------------------------\n \
+            AWS     g5.xlarge        4       16        A10G:1         us-east-1     1.01          \u{2714}     \n \
+            Azure   Standard_NV6ads_A10_v5   6   55   A10:1   eastus   0.45\n\
+            ----------------------------------------------------------------------------------------\n\
+            \n\
+            Dryrun finished.\n";
+        let quotes = parse(output);
+        assert_eq!(quotes.len(), 2);
+        assert_eq!(quotes[0].cloud, "AWS");
+        assert_eq!(quotes[0].instance, "g5.xlarge");
+        assert_eq!(quotes[0].region, "us-east-1");
+        assert_eq!(quotes[0].hourly_price, Some(1.01));
+        assert!(quotes[0].chosen);
+        assert_eq!(quotes[1].accelerators, "A10:1");
+        assert!(!quotes[1].chosen);
+    }
+}
@@ -0,0 +1,525 @@
+//! Runner module houses the execution of the orchestrator subprocesses (sky, kubectl, docker,
+//! ...). Every command goes through a CommandRunner, so they can be logged, recorded for audits
+//! or only planned without touching the backends issuing them. The commands run by a call
+//! watching for SIGINT get their own process group, stopped along with them on interruption.
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, VecDeque},
+    fs,
+    io::{self, Write},
+    os::unix::process::{CommandExt, ExitStatusExt},
+    path::Path,
+    process::{Child, Command, ExitStatus, Output, Stdio},
+    sync::{Arc, Mutex, RwLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::info;
+use serde::Serialize;
+
+use crate::{deadline, helper, interrupt};
+
+/// files referred to on the command line are recorded up to this size
+static MAX_RECORDED_FILE: u64 = 1 << 20;
+static REDACTED: &str = "<redacted>";
+/// bytes of output kept by an OutputLog
+pub static OUTPUT_LOG_SIZE: usize = 64 << 10;
+/// how often a command running under a deadline is checked for exit
+static WAIT_POLL: Duration = Duration::from_millis(50);
+
+/// Mode is how a command is connected to servicing and the terminal.
+#[derive(Clone)]
+pub enum Mode {
+    /// no input, the output is captured
+    Capture,
+    /// input is fed to stdin, the output is captured
+    Input(Vec<u8>),
+    /// like Input, but the input is a secret that is never recorded
+    Secret(Vec<u8>),
+    /// stdin is inherited so the command can prompt, the output is captured and echoed to the
+    /// terminal as it arrives if echo is set
+    Interactive { echo: bool },
+}
+
+/// CommandRunner runs commands to completion.
+pub trait CommandRunner: Send + Sync {
+    fn run(&self, cmd: &mut Command, mode: Mode) -> io::Result<Output>;
+}
+
+/// Recorded is a command as seen by a recording runner.
+#[derive(Clone, Debug, Serialize)]
+pub struct Recorded {
+    pub command: String,
+    /// what was fed to stdin
+    pub input: Option<String>,
+    /// content of the files the command line refers to, e.g. the rendered configuration
+    pub files: BTreeMap<String, String>,
+    /// exit code, None when not run
+    pub code: Option<i32>,
+}
+
+impl Recorded {
+    fn new(cmd: &Command, mode: &Mode) -> Self {
+        let input = match mode {
+            Mode::Input(input) => Some(String::from_utf8_lossy(input).to_string()),
+            Mode::Secret(_) => Some(REDACTED.to_string()),
+            Mode::Capture | Mode::Interactive { .. } => None,
+        };
+        let files = cmd
+            .get_args()
+            .map(Path::new)
+            .filter(|path| {
+                fs::metadata(path).is_ok_and(|m| m.is_file() && m.len() <= MAX_RECORDED_FILE)
+            })
+            .filter_map(|path| {
+                let content = fs::read(path).ok()?;
+                Some((
+                    path.display().to_string(),
+                    String::from_utf8_lossy(&content).to_string(),
+                ))
+            })
+            .collect();
+        Recorded {
+            command: describe(cmd),
+            input,
+            files,
+            code: None,
+        }
+    }
+}
+
+/// SystemRunner runs the commands. Under a deadline, a command still running when it passes is
+/// killed, and so is one interrupted by SIGINT unless the interrupt policy says otherwise.
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn run(&self, cmd: &mut Command, mode: Mode) -> io::Result<Output> {
+        let deadline = deadline::current();
+        if deadline.is_some_and(|deadline| deadline <= Instant::now()) {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "deadline exceeded before the command ran",
+            ));
+        }
+        let watched = interrupt::watching().is_some();
+        let blocking = deadline.is_none() && !watched;
+        match mode {
+            Mode::Capture if blocking => cmd.stdin(Stdio::null()).output(),
+            Mode::Input(input) | Mode::Secret(input) if blocking => {
+                let mut child = cmd
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped())
+                    .spawn()?;
+                if let Some(mut stdin) = child.stdin.take() {
+                    stdin.write_all(&input)?;
+                }
+                child.wait_with_output()
+            }
+            mode => {
+                // a command prompting stays in the foreground group to read the terminal
+                let group = watched && !matches!(mode, Mode::Interactive { .. });
+                if group {
+                    cmd.process_group(0);
+                }
+                let (stdin, input, echo) = match mode {
+                    Mode::Capture => (Stdio::null(), None, false),
+                    Mode::Input(input) | Mode::Secret(input) => {
+                        (Stdio::piped(), Some(input), false)
+                    }
+                    Mode::Interactive { echo } => (Stdio::inherit(), None, echo),
+                };
+                let (out, err): (Box<dyn Write + Send>, Box<dyn Write + Send>) = match echo {
+                    true => (Box::new(io::stdout()), Box::new(io::stderr())),
+                    false => (Box::new(io::sink()), Box::new(io::sink())),
+                };
+                let mut child = cmd
+                    .stdin(stdin)
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped())
+                    .spawn()?;
+                let stdout = child
+                    .stdout
+                    .take()
+                    .map(|stdout| helper::tee_child_output(stdout, out));
+                let stderr = child
+                    .stderr
+                    .take()
+                    .map(|stderr| helper::tee_child_output(stderr, err));
+                if let (Some(mut stdin), Some(input)) = (child.stdin.take(), input) {
+                    stdin.write_all(&input)?;
+                }
+                let status = wait(&mut child, deadline, group)?;
+                Ok(Output {
+                    status,
+                    stdout: stdout.and_then(|t| t.join().ok()).unwrap_or_default(),
+                    stderr: stderr.and_then(|t| t.join().ok()).unwrap_or_default(),
+                })
+            }
+        }
+    }
+}
+
+/// wait waits for child to exit, killing it once deadline passes and stopping it on SIGINT,
+/// with its process group if group is set.
+fn wait(child: &mut Child, deadline: Option<Instant>, group: bool) -> io::Result<ExitStatus> {
+    if deadline.is_none() && interrupt::watching().is_none() {
+        return child.wait();
+    }
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if interrupt::interrupted() {
+            interrupt::stop(child, group)?;
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "interrupted while the command ran",
+            ));
+        }
+        let now = Instant::now();
+        let pause = match deadline {
+            Some(deadline) if now >= deadline => {
+                child.kill()?;
+                child.wait()?;
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "deadline exceeded, the command was killed",
+                ));
+            }
+            Some(deadline) => WAIT_POLL.min(deadline - now),
+            None => WAIT_POLL,
+        };
+        thread::sleep(pause);
+    }
+}
+
+/// LoggingRunner logs the commands run by another runner, with their exit code and duration.
+pub struct LoggingRunner<R>(pub R);
+
+impl<R: CommandRunner> CommandRunner for LoggingRunner<R> {
+    fn run(&self, cmd: &mut Command, mode: Mode) -> io::Result<Output> {
+        let command = describe(cmd);
+        info!("Running: {}", command);
+        let started = Instant::now();
+        let output = self.0.run(cmd, mode);
+        match &output {
+            Ok(output) => info!(
+                "Finished in {:.1}s with {}: {}",
+                started.elapsed().as_secs_f64(),
+                output.status,
+                command
+            ),
+            Err(e) => info!("Failed to run {}: {e}", command),
+        }
+        output
+    }
+}
+
+/// RecordingRunner keeps an audit trail of the commands run by another runner, for embedders
+/// and tests replacing the runner of the process.
+#[allow(dead_code)]
+pub struct RecordingRunner<R> {
+    inner: R,
+    recorded: Mutex<Vec<Recorded>>,
+}
+
+#[allow(dead_code)]
+impl<R> RecordingRunner<R> {
+    pub fn new(inner: R) -> Self {
+        RecordingRunner {
+            inner,
+            recorded: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn recorded(&self) -> Vec<Recorded> {
+        self.recorded
+            .lock()
+            .map(|recorded| recorded.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl<R: CommandRunner> CommandRunner for RecordingRunner<R> {
+    fn run(&self, cmd: &mut Command, mode: Mode) -> io::Result<Output> {
+        let mut recorded = Recorded::new(cmd, &mode);
+        let output = self.inner.run(cmd, mode);
+        recorded.code = output.as_ref().ok().and_then(|o| o.status.code());
+        if let Ok(mut all) = self.recorded.lock() {
+            all.push(recorded);
+        }
+        output
+    }
+}
+
+/// DryRunRunner records the commands without running them, each is reported as successful with
+/// no output.
+#[derive(Default)]
+pub struct DryRunRunner {
+    recorded: Mutex<Vec<Recorded>>,
+}
+
+impl DryRunRunner {
+    /// take returns the commands recorded so far and forgets them.
+    pub fn take(&self) -> Vec<Recorded> {
+        self.recorded
+            .lock()
+            .map(|mut recorded| std::mem::take(&mut *recorded))
+            .unwrap_or_default()
+    }
+}
+
+impl CommandRunner for DryRunRunner {
+    fn run(&self, cmd: &mut Command, mode: Mode) -> io::Result<Output> {
+        let recorded = Recorded::new(cmd, &mode);
+        info!("Dry run: {}", recorded.command);
+        if let Ok(mut all) = self.recorded.lock() {
+            all.push(recorded);
+        }
+        Ok(Output {
+            status: ExitStatus::from_raw(0),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+type Replies = VecDeque<(i32, String)>;
+
+/// ScriptedRunner answers the commands whose line starts with a scripted prefix with the
+/// replies scripted for it in turn, the last one repeating, and records every command line.
+/// Other commands fail as if their program were not installed.
+#[cfg(test)]
+#[derive(Default)]
+pub struct ScriptedRunner {
+    /// prefix and the (exit code, stdout) replies to the commands starting with it
+    replies: Mutex<Vec<(String, Replies)>>,
+    commands: Mutex<Vec<String>>,
+}
+
+#[cfg(test)]
+impl ScriptedRunner {
+    /// reply scripts the exit code and stdout of the next command starting with prefix.
+    pub fn reply(self, prefix: &str, code: i32, stdout: &str) -> Self {
+        {
+            let mut replies = self.replies.lock().unwrap();
+            let reply = (code, stdout.to_string());
+            match replies.iter_mut().find(|(p, _)| p == prefix) {
+                Some((_, queue)) => queue.push_back(reply),
+                None => replies.push((prefix.to_string(), VecDeque::from([reply]))),
+            }
+        }
+        self
+    }
+
+    /// commands returns the command lines run so far.
+    pub fn commands(&self) -> Vec<String> {
+        self.commands.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+impl CommandRunner for ScriptedRunner {
+    fn run(&self, cmd: &mut Command, _mode: Mode) -> io::Result<Output> {
+        let line = describe(cmd);
+        self.commands.lock().unwrap().push(line.clone());
+        let mut replies = self.replies.lock().unwrap();
+        let Some((_, queue)) = replies
+            .iter_mut()
+            .find(|(p, _)| line.starts_with(p.as_str()))
+        else {
+            return Err(io::Error::new(io::ErrorKind::NotFound, line));
+        };
+        let (code, stdout) = match queue.len() {
+            1 => queue[0].clone(),
+            _ => queue.pop_front().unwrap_or_default(),
+        };
+        Ok(Output {
+            status: ExitStatus::from_raw(code << 8),
+            stdout: stdout.into_bytes(),
+            stderr: Vec::new(),
+        })
+    }
+}
+
+/// OutputLog keeps the last OUTPUT_LOG_SIZE bytes of output of the commands run for a service,
+/// each preceded by its command line.
+#[derive(Default)]
+pub struct OutputLog {
+    buffer: Mutex<VecDeque<u8>>,
+}
+
+impl OutputLog {
+    fn append(&self, cmd: &Command, output: &io::Result<Output>) {
+        let Ok(mut buffer) = self.buffer.lock() else {
+            return;
+        };
+        buffer.extend(format!("$ {}\n", describe(cmd)).as_bytes());
+        match output {
+            Ok(output) => {
+                buffer.extend(&output.stdout);
+                buffer.extend(&output.stderr);
+                if !output.status.success() {
+                    buffer.extend(format!("[{}]\n", output.status).as_bytes());
+                }
+            }
+            Err(e) => buffer.extend(format!("[failed to run: {e}]\n").as_bytes()),
+        }
+        let excess = buffer.len().saturating_sub(OUTPUT_LOG_SIZE);
+        buffer.drain(..excess);
+    }
+
+    /// text returns the output kept, invalid UTF-8 (e.g. a character cut in half by the ring
+    /// wrapping around) replaced.
+    pub fn text(&self) -> String {
+        self.buffer
+            .lock()
+            .map(|buffer| {
+                String::from_utf8_lossy(&buffer.iter().copied().collect::<Vec<_>>()).to_string()
+            })
+            .unwrap_or_default()
+    }
+}
+
+static RUNNER: RwLock<Option<Arc<dyn CommandRunner>>> = RwLock::new(None);
+
+thread_local! {
+    static SCOPED: RefCell<Option<Arc<dyn CommandRunner>>> = const { RefCell::new(None) };
+    static CAPTURE: RefCell<Option<Arc<OutputLog>>> = const { RefCell::new(None) };
+}
+
+/// set_runner replaces the runner of the whole process, None restores the SystemRunner.
+#[allow(dead_code)]
+pub fn set_runner(runner: Option<Arc<dyn CommandRunner>>) {
+    if let Ok(mut current) = RUNNER.write() {
+        *current = runner;
+    }
+}
+
+/// with_runner runs f with the commands it issues on this thread going to runner.
+pub fn with_runner<T>(runner: Arc<dyn CommandRunner>, f: impl FnOnce() -> T) -> T {
+    let previous = SCOPED.with(|scoped| scoped.replace(Some(runner)));
+    let result = f();
+    SCOPED.with(|scoped| scoped.replace(previous));
+    result
+}
+
+/// with_output_log runs f with the output of the commands it issues on this thread kept in log.
+pub fn with_output_log<T>(log: Arc<OutputLog>, f: impl FnOnce() -> T) -> T {
+    let previous = CAPTURE.with(|capture| capture.replace(Some(log)));
+    let result = f();
+    CAPTURE.with(|capture| capture.replace(previous));
+    result
+}
+
+/// current returns the runner commands issued on this thread go to, by default a SystemRunner
+/// logging the commands.
+pub fn current() -> Arc<dyn CommandRunner> {
+    SCOPED
+        .with(|scoped| scoped.borrow().clone())
+        .or_else(|| RUNNER.read().ok().and_then(|runner| runner.clone()))
+        .unwrap_or_else(|| Arc::new(LoggingRunner(SystemRunner)))
+}
+
+/// run runs cmd with the current runner.
+pub fn run(cmd: &mut Command, mode: Mode) -> io::Result<Output> {
+    let log = CAPTURE.with(|capture| capture.borrow().clone());
+    run_logged(current().as_ref(), log, cmd, mode)
+}
+
+/// run_async runs cmd with the current runner without blocking the runtime workers.
+pub async fn run_async(mut cmd: Command, mode: Mode) -> io::Result<Output> {
+    let runner = current();
+    let log = CAPTURE.with(|capture| capture.borrow().clone());
+    let deadline = deadline::current();
+    let watching = interrupt::watching();
+    tokio::task::spawn_blocking(move || {
+        interrupt::with_watching(watching, || {
+            deadline::with_deadline(deadline, || {
+                run_logged(runner.as_ref(), log, &mut cmd, mode)
+            })
+        })
+    })
+    .await
+    .map_err(io::Error::other)?
+}
+
+fn run_logged(
+    runner: &dyn CommandRunner,
+    log: Option<Arc<OutputLog>>,
+    cmd: &mut Command,
+    mode: Mode,
+) -> io::Result<Output> {
+    let output = runner.run(cmd, mode);
+    if let Some(log) = log {
+        log.append(cmd, &output);
+    }
+    output
+}
+
+/// describe renders cmd as a shell command line. Environment variables are not included, they
+/// only carry secrets the arguments refer to by name.
+pub fn describe(cmd: &Command) -> String {
+    std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|arg| shell_quote(&arg.to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn shell_quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c));
+    match plain {
+        true => arg.to_string(),
+        false => format!("'{}'", arg.replace('\'', "'\\''")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{process::Command, sync::Arc};
+
+    use super::{describe, run, with_output_log, with_runner, DryRunRunner, Mode, OutputLog};
+
+    #[test]
+    fn test_dry_run() {
+        let mut cmd = Command::new("sky");
+        cmd.arg("serve")
+            .arg("up")
+            .arg("-n")
+            .arg("my service")
+            .arg("it's.yaml");
+        assert_eq!(
+            describe(&cmd),
+            "sky serve up -n 'my service' 'it'\\''s.yaml'"
+        );
+
+        let dry = Arc::new(DryRunRunner::default());
+        let log = Arc::new(OutputLog::default());
+        let output = with_output_log(log.clone(), || {
+            with_runner(dry.clone(), || {
+                run(&mut Command::new("kubectl"), Mode::Input(b"{}".to_vec()))?;
+                run(
+                    &mut Command::new("docker"),
+                    Mode::Secret(b"hunter2".to_vec()),
+                )
+            })
+        })
+        .unwrap();
+        assert!(output.status.success());
+
+        let recorded = dry.take();
+        assert_eq!(recorded.len(), 2);
+        assert_eq!(recorded[0].command, "kubectl");
+        assert_eq!(recorded[0].input.as_deref(), Some("{}"));
+        assert_eq!(recorded[1].input.as_deref(), Some("<redacted>"));
+        assert!(dry.take().is_empty());
+        assert_eq!(log.text(), "$ kubectl\n$ docker\n");
+    }
+}
@@ -0,0 +1,220 @@
+//! Scaler module houses the scale-to-zero mode of SkyPilot services: idle services are scaled
+//! down to zero replicas and woken up again by the next request sent to them.
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    process::Command,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use log::{error, info, warn};
+use reqwest::Client;
+use tokio::time::sleep;
+
+use crate::{
+    error::ServicingError,
+    helper, limiter,
+    runner::{self, Mode},
+};
+
+static IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+static WAKE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+static WAKE_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ScaleState {
+    Awake,
+    Asleep,
+    Waking,
+}
+
+/// ScaleToZero is the scale-to-zero setup of one service.
+pub struct ScaleToZero {
+    /// configuration rendered with the regular replica count
+    pub up_config: PathBuf,
+    /// configuration rendered with zero replicas
+    pub zero_config: PathBuf,
+    /// url of the readiness probe, used to tell when a woken service is ready again
+    pub readiness_url: String,
+    pub idle_timeout: Duration,
+    pub up_check: &'static str,
+}
+
+struct Entry {
+    setup: ScaleToZero,
+    state: ScaleState,
+    last_request: Instant,
+}
+
+/// Scaler tracks the services running in scale-to-zero mode.
+#[derive(Default)]
+pub struct Scaler {
+    services: Mutex<HashMap<String, Entry>>,
+}
+
+impl Scaler {
+    /// enable puts the service in scale-to-zero mode and starts watching it for idleness.
+    pub fn enable(
+        self: &Arc<Self>,
+        rt: &tokio::runtime::Runtime,
+        name: &str,
+        setup: ScaleToZero,
+    ) -> Result<(), ServicingError> {
+        let replaced = self.services.lock()?.insert(
+            name.to_string(),
+            Entry {
+                setup,
+                state: ScaleState::Awake,
+                last_request: Instant::now(),
+            },
+        );
+        // the idle watcher of a previous setup is still running
+        if replaced.is_some() {
+            return Ok(());
+        }
+
+        let scaler = self.clone();
+        let name = name.to_string();
+        rt.spawn(async move {
+            loop {
+                sleep(IDLE_CHECK_INTERVAL).await;
+                let zero_config = match scaler.services.lock() {
+                    Ok(mut services) => match services.get_mut(&name) {
+                        Some(entry)
+                            if entry.state == ScaleState::Awake
+                                && entry.last_request.elapsed() >= entry.setup.idle_timeout =>
+                        {
+                            entry.state = ScaleState::Asleep;
+                            entry.setup.zero_config.clone()
+                        }
+                        Some(_) => continue,
+                        None => break,
+                    },
+                    Err(e) => {
+                        error!("Poisoned lock {e}");
+                        break;
+                    }
+                };
+                info!("Service {} is idle, scaling it to zero", name);
+                if let Err(e) = sky_serve_update(&name, &zero_config).await {
+                    warn!("Could not scale service {} to zero: {e}", name);
+                    if let Ok(mut services) = scaler.services.lock() {
+                        if let Some(entry) = services.get_mut(&name) {
+                            entry.state = ScaleState::Awake;
+                            entry.last_request = Instant::now();
+                        }
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+
+    pub fn disable(&self, name: &str) -> Result<bool, ServicingError> {
+        Ok(self.services.lock()?.remove(name).is_some())
+    }
+
+    /// enabled tells whether the service is in scale-to-zero mode, a request then wakes it up
+    /// even though it is not up.
+    pub fn enabled(&self, name: &str) -> bool {
+        self.services
+            .lock()
+            .is_ok_and(|services| services.contains_key(name))
+    }
+
+    /// doze marks the service as scaled to zero, as the idle watcher does.
+    #[cfg(test)]
+    pub fn doze(&self, name: &str) {
+        if let Some(entry) = self.services.lock().unwrap().get_mut(name) {
+            entry.state = ScaleState::Asleep;
+        }
+    }
+
+    /// wake makes sure the service is scaled up before a request is sent to it. A service scaled
+    /// to zero is scaled back up and the request held until the service is ready; requests
+    /// arriving meanwhile wait for the same wake up.
+    pub async fn wake(&self, client: &Client, name: &str) -> Result<(), ServicingError> {
+        let started = Instant::now();
+        loop {
+            let (up_config, readiness_url, up_check) = {
+                let mut services = self.services.lock()?;
+                let Some(entry) = services.get_mut(name) else {
+                    return Ok(());
+                };
+                entry.last_request = Instant::now();
+                match entry.state {
+                    ScaleState::Awake => return Ok(()),
+                    ScaleState::Waking => (None, None, entry.setup.up_check),
+                    ScaleState::Asleep => {
+                        entry.state = ScaleState::Waking;
+                        (
+                            Some(entry.setup.up_config.clone()),
+                            Some(entry.setup.readiness_url.clone()),
+                            entry.setup.up_check,
+                        )
+                    }
+                }
+            };
+
+            let (Some(up_config), Some(readiness_url)) = (up_config, readiness_url) else {
+                // someone else is waking the service
+                if started.elapsed() >= WAKE_TIMEOUT {
+                    return Err(ServicingError::ServiceNotUp(name.to_string()));
+                }
+                sleep(WAKE_CHECK_INTERVAL).await;
+                continue;
+            };
+
+            info!("Waking service {} up", name);
+            let woken = match sky_serve_update(name, &up_config).await {
+                Ok(_) => wait_ready(client, &readiness_url, up_check).await,
+                Err(e) => Err(e),
+            };
+            let mut services = self.services.lock()?;
+            if let Some(entry) = services.get_mut(name) {
+                entry.last_request = Instant::now();
+                entry.state = match woken {
+                    Ok(_) => ScaleState::Awake,
+                    Err(_) => ScaleState::Asleep,
+                };
+            }
+            return woken;
+        }
+    }
+}
+
+async fn wait_ready(client: &Client, url: &str, up_check: &str) -> Result<(), ServicingError> {
+    let started = Instant::now();
+    while started.elapsed() < WAKE_TIMEOUT {
+        if let Ok(body) = helper::fetch(client, url).await {
+            if !body.to_lowercase().contains(up_check) {
+                return Ok(());
+            }
+        }
+        sleep(WAKE_CHECK_INTERVAL).await;
+    }
+    Err(ServicingError::General(format!(
+        "Service at {url} did not become ready after scaling up"
+    )))
+}
+
+async fn sky_serve_update(name: &str, config: &PathBuf) -> Result<(), ServicingError> {
+    let _permit = limiter::acquire_async(&format!("sky serve update {name}")).await?;
+    let mut cmd = Command::new("sky");
+    cmd.arg("serve")
+        .arg("update")
+        .arg(name)
+        .arg(config)
+        .arg("-y");
+    let status = runner::run_async(cmd, Mode::Interactive { echo: true })
+        .await?
+        .status;
+    if !status.success() {
+        return Err(ServicingError::ClusterProvisionError(format!(
+            "Updating service {} failed with code {:?}",
+            name, status
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,130 @@
+//! Shared module houses the runtime and HTTP client shared by every Dispatcher of the process.
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
+};
+
+use reqwest::{Client, ClientBuilder, Proxy};
+use tokio::runtime::{self, Runtime};
+
+use crate::error::ServicingError;
+
+static DEFAULT_THREAD_NAME: &str = "servicing";
+/// managed services a runtime worker is sized for by default
+static SERVICES_PER_WORKER: usize = 8;
+static CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// SHARED keeps the shared runtime alive only as long as a Dispatcher holds it.
+static SHARED: Mutex<Option<(Weak<SharedRuntime>, Client)>> = Mutex::new(None);
+/// PROXIED holds the clients going through a proxy, by proxy URL.
+static PROXIED: Mutex<Option<HashMap<String, Client>>> = Mutex::new(None);
+
+/// SharedRuntime shuts the runtime down in the background once the last Dispatcher using it is
+/// dropped, which unlike dropping a Runtime is allowed from within an async context.
+pub struct SharedRuntime(Option<Runtime>);
+
+impl Deref for SharedRuntime {
+    type Target = Runtime;
+
+    fn deref(&self) -> &Runtime {
+        self.0.as_ref().expect("runtime is only taken on drop")
+    }
+}
+
+impl Drop for SharedRuntime {
+    fn drop(&mut self) {
+        if let Some(rt) = self.0.take() {
+            rt.shutdown_background();
+        }
+    }
+}
+
+/// RuntimeConfig sizes the shared runtime, the fields left None taking their default.
+#[derive(Debug, Default, Clone)]
+pub struct RuntimeConfig {
+    pub worker_threads: Option<usize>,
+    pub thread_name: Option<String>,
+    /// threads running the blocking work: commands, Python callbacks and file I/O
+    pub max_blocking_threads: Option<usize>,
+    /// managed services, sizing the default number of workers
+    pub services: usize,
+}
+
+impl RuntimeConfig {
+    /// workers returns worker_threads or else a worker per 8 managed services, at least one
+    /// and at most one per core, the monitors of every service sharing the workers.
+    pub fn workers(&self) -> usize {
+        let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+        self.worker_threads
+            .unwrap_or_else(|| self.services.div_ceil(SERVICES_PER_WORKER).min(cores))
+            .max(1)
+    }
+}
+
+/// acquire returns the runtime and client shared by the live dispatchers, creating them when
+/// there are none. config only applies when the runtime is created.
+pub fn acquire(config: &RuntimeConfig) -> Result<(Arc<SharedRuntime>, Client), ServicingError> {
+    let mut shared = SHARED.lock()?;
+    if let Some((rt, client)) = shared.as_ref() {
+        if let Some(rt) = rt.upgrade() {
+            return Ok((rt, client.clone()));
+        }
+    }
+
+    let mut runtime = runtime::Builder::new_multi_thread();
+    runtime
+        .worker_threads(config.workers())
+        .thread_name(config.thread_name.as_deref().unwrap_or(DEFAULT_THREAD_NAME))
+        .enable_all();
+    if let Some(max_blocking_threads) = config.max_blocking_threads {
+        runtime.max_blocking_threads(max_blocking_threads.max(1));
+    }
+    let rt = Arc::new(SharedRuntime(Some(runtime.build()?)));
+    let client = builder().build()?;
+    *shared = Some((Arc::downgrade(&rt), client.clone()));
+    Ok((rt, client))
+}
+
+/// proxied returns a client like the shared one sending its requests through proxy, e.g.
+/// socks5h://127.0.0.1:1080 or http://proxy:3128.
+pub fn proxied(proxy: &str) -> Result<Client, ServicingError> {
+    let mut clients = PROXIED.lock()?;
+    let clients = clients.get_or_insert_with(HashMap::new);
+    if let Some(client) = clients.get(proxy) {
+        return Ok(client.clone());
+    }
+    let client = builder().proxy(Proxy::all(proxy)?).build()?;
+    clients.insert(proxy.to_string(), client.clone());
+    Ok(client)
+}
+
+fn builder() -> ClientBuilder {
+    Client::builder()
+        .pool_max_idle_per_host(0)
+        .timeout(CLIENT_TIMEOUT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RuntimeConfig;
+
+    #[test]
+    fn test_workers() {
+        let config = RuntimeConfig::default();
+        assert_eq!(config.workers(), 1);
+        let config = RuntimeConfig {
+            worker_threads: Some(0),
+            services: 100,
+            ..config
+        };
+        assert_eq!(config.workers(), 1);
+        let config = RuntimeConfig {
+            worker_threads: None,
+            services: 9,
+            ..config
+        };
+        assert!((1..=2).contains(&config.workers()));
+    }
+}
@@ -0,0 +1,233 @@
+//! Traffic module houses the client-side rate limiting and circuit breaking applied to every
+//! outbound request to a managed service.
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use log::{info, warn};
+use tokio::time::sleep;
+
+use crate::error::ServicingError;
+
+/// lowest rate limit accepted, one request every 1000 seconds
+static MIN_RATE: f64 = 0.001;
+
+/// TokenBucket allows `rate` requests per second on average with bursts of up to `burst`.
+struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: f64, burst: u32) -> Self {
+        TokenBucket {
+            rate,
+            burst: burst as f64,
+            tokens: burst as f64,
+            last: Instant::now(),
+        }
+    }
+
+    /// take consumes a token, or returns how long to wait until one is available.
+    fn take(&mut self, now: Instant) -> Result<(), Duration> {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.last = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+        let wait = (1.0 - self.tokens) / self.rate;
+        Err(Duration::try_from_secs_f64(wait).unwrap_or(Duration::from_secs_f64(1.0 / MIN_RATE)))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum BreakerState {
+    Closed,
+    Open(Instant),
+    /// one probe request is let through, its outcome decides whether to close again
+    HalfOpen(Instant),
+}
+
+/// CircuitBreaker opens after `threshold` consecutive failures and lets a probe through once
+/// `cooldown` has passed.
+struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    failures: u32,
+    state: BreakerState,
+}
+
+impl CircuitBreaker {
+    fn new(threshold: u32, cooldown: Duration) -> Self {
+        CircuitBreaker {
+            threshold,
+            cooldown,
+            failures: 0,
+            state: BreakerState::Closed,
+        }
+    }
+
+    fn allow(&mut self, now: Instant) -> bool {
+        match self.state {
+            BreakerState::Closed => true,
+            // a probe that never reported back does not keep the circuit half open forever
+            BreakerState::Open(since) | BreakerState::HalfOpen(since)
+                if now.saturating_duration_since(since) >= self.cooldown =>
+            {
+                self.state = BreakerState::HalfOpen(now);
+                true
+            }
+            BreakerState::Open(_) | BreakerState::HalfOpen(_) => false,
+        }
+    }
+
+    fn record(&mut self, success: bool, now: Instant) {
+        if success {
+            self.failures = 0;
+            self.state = BreakerState::Closed;
+            return;
+        }
+        self.failures += 1;
+        if matches!(self.state, BreakerState::HalfOpen(_)) || self.failures >= self.threshold {
+            self.state = BreakerState::Open(now);
+        }
+    }
+}
+
+#[derive(Default)]
+struct ServiceTraffic {
+    bucket: Option<TokenBucket>,
+    breaker: Option<CircuitBreaker>,
+}
+
+/// Traffic tracks the rate limits and circuit breakers of every service.
+#[derive(Default)]
+pub struct Traffic {
+    services: Mutex<HashMap<String, ServiceTraffic>>,
+}
+
+impl Traffic {
+    pub fn set_rate_limit(
+        &self,
+        name: &str,
+        rate: Option<(f64, u32)>,
+    ) -> Result<(), ServicingError> {
+        if let Some((rate, burst)) = rate {
+            // NaN compares false, so it is refused as well
+            if !(rate.is_finite() && rate >= MIN_RATE) {
+                return Err(ServicingError::General(format!(
+                    "Rate limit must be a number of requests per second of at least {MIN_RATE}, \
+                     got {rate}"
+                )));
+            }
+            if burst == 0 {
+                return Err(ServicingError::General(
+                    "Rate limit burst must be at least 1".to_string(),
+                ));
+            }
+        }
+        let mut services = self.services.lock()?;
+        let traffic = services.entry(name.to_string()).or_default();
+        traffic.bucket = rate.map(|(rate, burst)| TokenBucket::new(rate, burst));
+        Ok(())
+    }
+
+    pub fn set_circuit_breaker(
+        &self,
+        name: &str,
+        breaker: Option<(u32, Duration)>,
+    ) -> Result<(), ServicingError> {
+        let mut services = self.services.lock()?;
+        let traffic = services.entry(name.to_string()).or_default();
+        traffic.breaker =
+            breaker.map(|(threshold, cooldown)| CircuitBreaker::new(threshold.max(1), cooldown));
+        Ok(())
+    }
+
+    /// acquire waits for the rate limit of the service and fails fast while its circuit is open.
+    pub async fn acquire(&self, name: &str) -> Result<(), ServicingError> {
+        loop {
+            let wait = {
+                let mut services = self.services.lock()?;
+                let Some(traffic) = services.get_mut(name) else {
+                    return Ok(());
+                };
+                let now = Instant::now();
+                if let Some(breaker) = traffic.breaker.as_mut() {
+                    if !breaker.allow(now) {
+                        return Err(ServicingError::CircuitOpen(name.to_string()));
+                    }
+                }
+                match traffic.bucket.as_mut().map(|b| b.take(now)) {
+                    Some(Err(wait)) => wait,
+                    _ => return Ok(()),
+                }
+            };
+            sleep(wait).await;
+        }
+    }
+
+    /// record feeds the outcome of a request to the circuit breaker of the service.
+    pub fn record(&self, name: &str, success: bool) {
+        let Ok(mut services) = self.services.lock() else {
+            return;
+        };
+        if let Some(breaker) = services.get_mut(name).and_then(|t| t.breaker.as_mut()) {
+            let was_open = breaker.state != BreakerState::Closed;
+            breaker.record(success, Instant::now());
+            match (&breaker.state, was_open) {
+                (BreakerState::Open(_), false) => {
+                    warn!("Circuit breaker opened for service {}", name)
+                }
+                (BreakerState::Closed, true) => {
+                    info!("Circuit breaker closed for service {}", name)
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{BreakerState, CircuitBreaker, TokenBucket, Traffic};
+
+    #[test]
+    fn test_token_bucket_and_breaker() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(2.0, 2);
+        assert!(bucket.take(now).is_ok());
+        assert!(bucket.take(now).is_ok());
+        assert_eq!(bucket.take(now), Err(Duration::from_millis(500)));
+        assert!(bucket.take(now + Duration::from_millis(500)).is_ok());
+
+        let traffic = Traffic::default();
+        for rate in [0.0, -1.0, 0.0001, f64::NAN, f64::INFINITY] {
+            assert!(traffic.set_rate_limit("llm", Some((rate, 1))).is_err());
+        }
+        assert!(traffic.set_rate_limit("llm", Some((2.0, 0))).is_err());
+        assert!(traffic.set_rate_limit("llm", Some((0.001, 1))).is_ok());
+        assert!(traffic.set_rate_limit("llm", None).is_ok());
+
+        let mut breaker = CircuitBreaker::new(2, Duration::from_secs(10));
+        breaker.record(false, now);
+        assert!(breaker.allow(now));
+        breaker.record(false, now);
+        assert!(!breaker.allow(now));
+
+        // after the cooldown a single probe goes through
+        let later = now + Duration::from_secs(10);
+        assert!(breaker.allow(later));
+        assert!(!breaker.allow(later));
+        breaker.record(true, later);
+        assert_eq!(breaker.state, BreakerState::Closed);
+    }
+}
@@ -0,0 +1,144 @@
+//! Tunnel module houses the SSH tunnels through a bastion host, reaching services that only
+//! listen on a private network from the machine running servicing.
+use std::{
+    net::{SocketAddr, TcpListener, TcpStream},
+    process::{Child, Command, Stdio},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::error::ServicingError;
+
+static TUNNEL_OPEN_TIMEOUT: Duration = Duration::from_secs(15);
+static TUNNEL_CHECK_INTERVAL: Duration = Duration::from_millis(200);
+
+/// SshTunnel is the bastion the requests to a service go through.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SshTunnel {
+    pub host: String,
+    pub user: String,
+    /// private key file, the SSH agent and configuration are used without
+    pub key: Option<String>,
+    /// SSH port of the bastion, 22 by default
+    pub port: Option<u16>,
+}
+
+/// OpenTunnel is a running `ssh -L` forwarding a local port to the service, dropping it closes
+/// the tunnel.
+pub struct OpenTunnel {
+    config: SshTunnel,
+    /// host:port of the service, as seen from the bastion
+    target: String,
+    local_port: u16,
+    child: Child,
+}
+
+impl Drop for OpenTunnel {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl OpenTunnel {
+    /// open forwards a free local port to target through the bastion of config, waiting until
+    /// the port accepts connections.
+    pub fn open(config: &SshTunnel, target: &str) -> Result<Self, ServicingError> {
+        let local_port = TcpListener::bind(("127.0.0.1", 0))?.local_addr()?.port();
+        let mut cmd = Command::new("ssh");
+        cmd.arg("-N")
+            .args(["-o", "BatchMode=yes", "-o", "ExitOnForwardFailure=yes"])
+            .arg("-L")
+            .arg(format!("127.0.0.1:{local_port}:{target}"))
+            .arg("-p")
+            .arg(config.port.unwrap_or(22).to_string());
+        if let Some(key) = &config.key {
+            cmd.arg("-i").arg(key);
+        }
+        cmd.arg(format!("{}@{}", config.user, config.host))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        info!(
+            "Opening a tunnel to {} through {}@{}",
+            target, config.user, config.host
+        );
+        let mut tunnel = OpenTunnel {
+            config: config.clone(),
+            target: target.to_string(),
+            local_port,
+            child: cmd.spawn()?,
+        };
+
+        let addr = SocketAddr::from(([127, 0, 0, 1], local_port));
+        let started = Instant::now();
+        while TcpStream::connect_timeout(&addr, TUNNEL_CHECK_INTERVAL).is_err() {
+            if let Some(status) = tunnel.child.try_wait()? {
+                return Err(ServicingError::General(format!(
+                    "SSH tunnel to {target} through {} exited with {status}",
+                    config.host
+                )));
+            }
+            if started.elapsed() > TUNNEL_OPEN_TIMEOUT {
+                return Err(ServicingError::General(format!(
+                    "SSH tunnel to {target} through {} did not open within {}s",
+                    config.host,
+                    TUNNEL_OPEN_TIMEOUT.as_secs()
+                )));
+            }
+            sleep(TUNNEL_CHECK_INTERVAL);
+        }
+        Ok(tunnel)
+    }
+
+    /// serves tells whether the tunnel is still running and forwards to target with config.
+    pub fn serves(&mut self, config: &SshTunnel, target: &str) -> bool {
+        let alive = matches!(self.child.try_wait(), Ok(None));
+        if !alive {
+            warn!("SSH tunnel to {} exited", self.target);
+        }
+        alive && &self.config == config && self.target == target
+    }
+
+    /// rewrite points url at the local end of the tunnel.
+    pub fn rewrite(&self, url: &str) -> String {
+        let (scheme, _, rest) = split_url(url);
+        format!("{scheme}127.0.0.1:{}{rest}", self.local_port)
+    }
+}
+
+/// target returns the host:port url points at, the port defaulting from the scheme.
+pub fn target(url: &str) -> String {
+    let (scheme, authority, _) = split_url(url);
+    match authority.rsplit_once(':') {
+        Some((_, port)) if port.parse::<u16>().is_ok() => authority.to_string(),
+        _ if scheme == "https://" => format!("{authority}:443"),
+        _ => format!("{authority}:80"),
+    }
+}
+
+/// split_url splits url into its scheme (possibly empty), authority and the rest.
+fn split_url(url: &str) -> (&str, &str, &str) {
+    let (scheme, remainder) = match url.find("://") {
+        Some(index) => url.split_at(index + 3),
+        None => ("", url),
+    };
+    let end = remainder.find('/').unwrap_or(remainder.len());
+    let (authority, rest) = remainder.split_at(end);
+    (scheme, authority, rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::target;
+
+    #[test]
+    fn test_target() {
+        assert_eq!(target("10.0.1.5:8080"), "10.0.1.5:8080");
+        assert_eq!(target("http://10.0.1.5/health"), "10.0.1.5:80");
+        assert_eq!(target("https://svc.internal"), "svc.internal:443");
+    }
+}